@@ -54,7 +54,7 @@ impl Frontend for AbstrErlangFrontend {
             let root = self
                 .parser
                 .parse::<Root>(&mut errors.make_into_adapter(), source)?;
-            let ast = lower_abstr(&root);
+            let ast = lower_abstr(&mut errors.make_into_adapter(), &root);
             let eir = lower_module(
                 &mut errors.make_into_adapter(),
                 self.parser.codemap.clone(),
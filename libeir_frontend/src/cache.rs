@@ -0,0 +1,854 @@
+//! Incremental compilation cache for the Erlang frontend.
+//!
+//! Every module compiled from a file is stored in the cache directory as
+//! EIR text, together with a manifest. The manifest records the hash of
+//! the source, a hash identifying the compiler executable and the
+//! `ParseConfig`, every path the parser looked at while resolving
+//! includes and behaviours along with what was found there, and the
+//! diagnostics reported for the module.
+//!
+//! A module is reused only if the source and the configuration are
+//! unchanged and every recorded path still holds what it held. A path
+//! where nothing was found must still be empty, so a new header that
+//! would shadow a recorded one invalidates the entry. The diagnostics of
+//! a reused module are reported again.
+//!
+//! The EIR text holds everything about the module but its source spans.
+//! The spans of a reused module point into the cached text, the file and
+//! line of its `!location`s are those of the Erlang source.
+
+use std::fmt::Write as _;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use libeir_diagnostics::{CodeMap, Diagnostic, FileName, Label, LabelStyle, Severity};
+use libeir_diagnostics::{SourceFile, SourceId, ToDiagnostic};
+use libeir_ir::Module;
+use libeir_syntax_erl::ast::Module as ModuleAst;
+use libeir_syntax_erl::{LookupRecorder, ParserError};
+use libeir_util_parse::{ErrorReceiver, Parse};
+
+use super::eir::EirFrontend;
+use super::erlang::{ErlangFrontend, Error};
+use super::{DynFrontend, Frontend, FrontendErrorReceiver};
+
+/// Bumped whenever the layout of cache entries changes.
+const CACHE_FORMAT: u32 = 2;
+
+/// 64 bit FNV-1a, unlike `DefaultHasher` its output is stable between
+/// builds, so hashes can be persisted.
+struct StableHasher(u64);
+impl StableHasher {
+    fn new() -> Self {
+        StableHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+fn stable_hash<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = StableHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn content_hash(content: &[u8]) -> u64 {
+    let mut hasher = StableHasher::new();
+    hasher.write(content);
+    hasher.finish()
+}
+
+/// Hash of the running executable, it changes with the compiler and
+/// everything linked into it. `None` if the executable can't be read.
+fn compiler_identity() -> Option<u64> {
+    let exe = std::env::current_exe().ok()?;
+    Some(content_hash(&std::fs::read(exe).ok()?))
+}
+
+/// What is at `path`: the hash of the contents of a file, or of the
+/// sorted entry names of a directory. `None` if there is nothing.
+fn fingerprint(codemap: &CodeMap, path: &Path) -> Option<u64> {
+    let metadata = std::fs::metadata(path).ok()?;
+    if metadata.is_dir() {
+        let mut names: Vec<_> = std::fs::read_dir(path)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name())
+            .collect();
+        names.sort();
+        return Some(stable_hash(&names));
+    }
+    // A file the parser read is hashed as it was read, so a change made
+    // while compiling invalidates the entry.
+    match codemap.get_by_name(&FileName::Real(path.to_owned())) {
+        Some(file) => Some(content_hash(file.source().as_bytes())),
+        None => Some(content_hash(&std::fs::read(path).ok()?)),
+    }
+}
+
+/// A file referred to by a cached diagnostic. Real files are read again
+/// when the diagnostic is restored, virtual ones are stored.
+#[derive(Debug, PartialEq, Eq)]
+enum CachedFile {
+    Real(PathBuf),
+    Virtual { name: String, source: String },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct CachedLabel {
+    style: LabelStyle,
+    /// Index into `Manifest::files`.
+    file: usize,
+    start: usize,
+    end: usize,
+    message: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct CachedDiagnostic {
+    /// Whether it was reported as an error rather than a warning.
+    error: bool,
+    severity: Severity,
+    code: Option<String>,
+    message: String,
+    labels: Vec<CachedLabel>,
+    notes: Vec<String>,
+}
+
+/// The parts of a cache entry that are checked before it is reused, and
+/// the diagnostics reported when it is.
+#[derive(Debug, PartialEq, Eq)]
+struct Manifest {
+    source: u64,
+    config: u64,
+    /// Every path looked at, with its fingerprint when the module was
+    /// compiled.
+    lookups: Vec<(PathBuf, Option<u64>)>,
+    files: Vec<CachedFile>,
+    diagnostics: Vec<CachedDiagnostic>,
+}
+
+/// Escapes `string` into a single word without spaces or newlines.
+fn escape(string: &str) -> String {
+    let mut out = String::with_capacity(string.len());
+    for c in string.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            ' ' => out.push_str("\\s"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape(word: &str) -> Option<String> {
+    let mut out = String::with_capacity(word.len());
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            out.push(match chars.next()? {
+                '\\' => '\\',
+                's' => ' ',
+                'n' => '\n',
+                'r' => '\r',
+                _ => return None,
+            });
+        } else {
+            out.push(c);
+        }
+    }
+    Some(out)
+}
+
+fn escape_path(path: &Path) -> Option<String> {
+    path.to_str().map(escape)
+}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Bug => "bug",
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note => "note",
+        Severity::Help => "help",
+    }
+}
+
+fn severity_from_name(name: &str) -> Option<Severity> {
+    match name {
+        "bug" => Some(Severity::Bug),
+        "error" => Some(Severity::Error),
+        "warning" => Some(Severity::Warning),
+        "note" => Some(Severity::Note),
+        "help" => Some(Severity::Help),
+        _ => None,
+    }
+}
+
+impl Manifest {
+    /// `None` if a path is not valid unicode, such a module is not
+    /// cached.
+    fn to_text(&self) -> Option<String> {
+        let mut text = String::new();
+        writeln!(text, "eir-cache {}", CACHE_FORMAT).unwrap();
+        writeln!(text, "source {:016x}", self.source).unwrap();
+        writeln!(text, "config {:016x}", self.config).unwrap();
+        for (path, hash) in self.lookups.iter() {
+            let hash = match hash {
+                Some(hash) => format!("{:016x}", hash),
+                None => "-".to_string(),
+            };
+            writeln!(text, "lookup {} {}", hash, escape_path(path)?).unwrap();
+        }
+        for file in self.files.iter() {
+            match file {
+                CachedFile::Real(path) => writeln!(text, "file real {}", escape_path(path)?),
+                CachedFile::Virtual { name, source } => {
+                    writeln!(text, "file virtual {} {}", escape(name), escape(source))
+                }
+            }
+            .unwrap();
+        }
+        for diagnostic in self.diagnostics.iter() {
+            writeln!(
+                text,
+                "diagnostic {} {} {} {}",
+                if diagnostic.error { "error" } else { "warning" },
+                severity_name(diagnostic.severity),
+                diagnostic
+                    .code
+                    .as_ref()
+                    .map(|code| format!("+{}", escape(code)))
+                    .unwrap_or_else(|| "-".to_string()),
+                escape(&diagnostic.message)
+            )
+            .unwrap();
+            for label in diagnostic.labels.iter() {
+                let style = match label.style {
+                    LabelStyle::Primary => "primary",
+                    LabelStyle::Secondary => "secondary",
+                };
+                writeln!(
+                    text,
+                    "label {} {} {} {} {}",
+                    style,
+                    label.file,
+                    label.start,
+                    label.end,
+                    escape(&label.message)
+                )
+                .unwrap();
+            }
+            for note in diagnostic.notes.iter() {
+                writeln!(text, "note {}", escape(note)).unwrap();
+            }
+        }
+        Some(text)
+    }
+
+    fn from_text(text: &str) -> Option<Manifest> {
+        let mut lines = text.lines();
+        if lines.next()? != format!("eir-cache {}", CACHE_FORMAT) {
+            return None;
+        }
+        let mut hash_line = |prefix: &str| {
+            let line = lines.next()?;
+            u64::from_str_radix(strip_prefix(line, prefix)?, 16).ok()
+        };
+        let source = hash_line("source ")?;
+        let config = hash_line("config ")?;
+
+        let mut manifest = Manifest {
+            source,
+            config,
+            lookups: Vec::new(),
+            files: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+        for line in lines {
+            let words: Vec<&str> = line.split(' ').collect();
+            match words.as_slice() {
+                ["lookup", hash, path] => {
+                    let hash = match *hash {
+                        "-" => None,
+                        hash => Some(u64::from_str_radix(hash, 16).ok()?),
+                    };
+                    manifest
+                        .lookups
+                        .push((PathBuf::from(unescape(path)?), hash));
+                }
+                ["file", "real", path] => {
+                    let path = PathBuf::from(unescape(path)?);
+                    manifest.files.push(CachedFile::Real(path));
+                }
+                ["file", "virtual", name, source] => {
+                    manifest.files.push(CachedFile::Virtual {
+                        name: unescape(name)?,
+                        source: unescape(source)?,
+                    });
+                }
+                ["diagnostic", kind, severity, code, message] => {
+                    let code = match *code {
+                        "-" => None,
+                        code => Some(unescape(strip_prefix(code, "+")?)?),
+                    };
+                    manifest.diagnostics.push(CachedDiagnostic {
+                        error: *kind == "error",
+                        severity: severity_from_name(severity)?,
+                        code,
+                        message: unescape(message)?,
+                        labels: Vec::new(),
+                        notes: Vec::new(),
+                    });
+                }
+                ["label", style, file, start, end, message] => {
+                    let style = match *style {
+                        "primary" => LabelStyle::Primary,
+                        "secondary" => LabelStyle::Secondary,
+                        _ => return None,
+                    };
+                    let file = file.parse().ok()?;
+                    if file >= manifest.files.len() {
+                        return None;
+                    }
+                    manifest.diagnostics.last_mut()?.labels.push(CachedLabel {
+                        style,
+                        file,
+                        start: start.parse().ok()?,
+                        end: end.parse().ok()?,
+                        message: unescape(message)?,
+                    });
+                }
+                ["note", note] => {
+                    let note = unescape(note)?;
+                    manifest.diagnostics.last_mut()?.notes.push(note);
+                }
+                _ => return None,
+            }
+        }
+
+        Some(manifest)
+    }
+
+    /// Whether every recorded path still holds what it held.
+    fn lookups_unchanged(&self, codemap: &CodeMap) -> bool {
+        self.lookups
+            .iter()
+            .all(|(path, hash)| fingerprint(codemap, path) == *hash)
+    }
+
+    /// Records `reported` in the manifest. Returns `false` if a label
+    /// refers to a file not in `codemap`, such a module is not cached.
+    fn record_diagnostics(&mut self, codemap: &CodeMap, reported: &[(bool, Diagnostic)]) -> bool {
+        for (error, diagnostic) in reported.iter() {
+            let mut labels = Vec::with_capacity(diagnostic.labels.len());
+            for label in diagnostic.labels.iter() {
+                let file = match codemap.get(label.file_id) {
+                    Some(file) => file,
+                    None => return false,
+                };
+                let cached = match file.name() {
+                    FileName::Real(path) => CachedFile::Real(path.clone()),
+                    FileName::Virtual(name) => CachedFile::Virtual {
+                        name: name.to_string(),
+                        source: file.source().to_owned(),
+                    },
+                };
+                let index = match self.files.iter().position(|f| *f == cached) {
+                    Some(index) => index,
+                    None => {
+                        self.files.push(cached);
+                        self.files.len() - 1
+                    }
+                };
+                labels.push(CachedLabel {
+                    style: label.style,
+                    file: index,
+                    start: label.range.start,
+                    end: label.range.end,
+                    message: label.message.clone(),
+                });
+            }
+            self.diagnostics.push(CachedDiagnostic {
+                error: *error,
+                severity: diagnostic.severity,
+                code: diagnostic.code.clone(),
+                message: diagnostic.message.clone(),
+                labels,
+                notes: diagnostic.notes.clone(),
+            });
+        }
+        true
+    }
+
+    /// Rebuilds the recorded diagnostics, adding the files they refer to
+    /// to `codemap`. `None` if one of those files can't be read.
+    fn restore_diagnostics(&self, codemap: &CodeMap) -> Option<Vec<(bool, Diagnostic)>> {
+        let mut ids: Vec<SourceId> = Vec::with_capacity(self.files.len());
+        for file in self.files.iter() {
+            let id = match file {
+                CachedFile::Real(path) => {
+                    codemap.add(path.as_path(), std::fs::read_to_string(path).ok()?)
+                }
+                CachedFile::Virtual { name, source } => codemap.add(name.clone(), source.clone()),
+            };
+            ids.push(id);
+        }
+
+        let diagnostics = self
+            .diagnostics
+            .iter()
+            .map(|cached| {
+                let labels = cached
+                    .labels
+                    .iter()
+                    .map(|label| {
+                        Label::new(label.style, ids[label.file], label.start..label.end)
+                            .with_message(label.message.clone())
+                    })
+                    .collect();
+                let mut diagnostic = Diagnostic::new(cached.severity)
+                    .with_message(cached.message.clone())
+                    .with_labels(labels)
+                    .with_notes(cached.notes.clone());
+                diagnostic.code = cached.code.clone();
+                (cached.error, diagnostic)
+            })
+            .collect();
+        Some(diagnostics)
+    }
+}
+
+fn strip_prefix<'a>(line: &'a str, prefix: &str) -> Option<&'a str> {
+    if line.starts_with(prefix) {
+        Some(&line[prefix.len()..])
+    } else {
+        None
+    }
+}
+
+/// Forwards everything reported while compiling a module, keeping the
+/// diagnostics to store them with it.
+struct Recording<'a> {
+    inner: &'a mut FrontendErrorReceiver<'a, Error>,
+    reported: Vec<(bool, Diagnostic)>,
+}
+impl<'a> ErrorReceiver for Recording<'a> {
+    type E = Error;
+    type W = Error;
+
+    fn is_failed(&self) -> bool {
+        self.inner.is_failed()
+    }
+    fn warning(&mut self, warning: Error) {
+        self.reported.push((false, warning.to_diagnostic()));
+        self.inner.warning(warning);
+    }
+    fn error(&mut self, error: Error) {
+        self.reported.push((true, error.to_diagnostic()));
+        self.inner.error(error);
+    }
+}
+
+/// An `ErlangFrontend` that reuses modules compiled by earlier runs when
+/// neither their source, the files the parser looked at nor the compiler
+/// and its configuration changed.
+///
+/// Only `parse_file` is cached, sources given as strings have no
+/// identity to key the cache by.
+pub struct CachingErlangFrontend {
+    frontend: ErlangFrontend,
+    dir: PathBuf,
+    /// `None` if the compiler executable can't be identified, nothing is
+    /// cached then.
+    compiler: Option<u64>,
+    /// Held while compiling, the lookups recorded belong to one module.
+    compiling: Mutex<()>,
+}
+
+impl CachingErlangFrontend {
+    /// Caches modules compiled by `frontend` in `dir`, which is created if
+    /// it does not exist.
+    pub fn new<P: Into<PathBuf>>(mut frontend: ErlangFrontend, dir: P) -> Self {
+        frontend.config_mut().lookups = LookupRecorder::new();
+        CachingErlangFrontend {
+            frontend,
+            dir: dir.into(),
+            compiler: compiler_identity(),
+            compiling: Mutex::new(()),
+        }
+    }
+
+    /// The paths of the module and manifest of the cache entry for the
+    /// source file at `path`.
+    fn entry_paths(&self, path: &Path) -> (PathBuf, PathBuf) {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let name = format!("{}-{:016x}", stem, stable_hash(&path));
+        (
+            self.dir.join(format!("{}.eir", name)),
+            self.dir.join(format!("{}.deps", name)),
+        )
+    }
+
+    fn config_hash(&self, compiler: u64) -> u64 {
+        let mut hasher = StableHasher::new();
+        compiler.hash(&mut hasher);
+        self.frontend.config().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn load(
+        &self,
+        path: &Path,
+        source: u64,
+        config: u64,
+    ) -> Option<(Module, Vec<(bool, Diagnostic)>)> {
+        let (module_path, manifest_path) = self.entry_paths(path);
+        let manifest = Manifest::from_text(&std::fs::read_to_string(manifest_path).ok()?)?;
+        let codemap = self.frontend.codemap();
+        if manifest.source != source
+            || manifest.config != config
+            || !manifest.lookups_unchanged(codemap)
+        {
+            return None;
+        }
+
+        // An entry that fails to parse is treated as missing, it is
+        // overwritten once the module is compiled again.
+        let eir = EirFrontend::new(codemap.clone());
+        let module = eir.parse_file_dyn(&module_path).0.ok()?;
+        let diagnostics = manifest.restore_diagnostics(codemap)?;
+        Some((module, diagnostics))
+    }
+
+    fn store(&self, path: &Path, manifest: &Manifest, module: &Module) -> std::io::Result<()> {
+        let manifest = match manifest.to_text() {
+            Some(manifest) => manifest,
+            None => return Ok(()),
+        };
+        let (module_path, manifest_path) = self.entry_paths(path);
+        std::fs::create_dir_all(&self.dir)?;
+        // The manifest is written last, an interrupted store leaves an
+        // entry that does not validate.
+        let _ = std::fs::remove_file(&manifest_path);
+        std::fs::write(&module_path, module.to_text_standard())?;
+        std::fs::write(&manifest_path, manifest)
+    }
+
+    /// Compiles the file at `path`, recording what is needed to reuse the
+    /// result in a manifest. The manifest is `None` if the module can't
+    /// be cached.
+    fn compile<'a>(
+        &self,
+        errors: &'a mut FrontendErrorReceiver<'a, Error>,
+        path: &Path,
+        content: String,
+    ) -> (Result<Module, ()>, Option<Manifest>) {
+        let lookups = &self.frontend.config().lookups;
+        let _compiling = self.compiling.lock().unwrap();
+        lookups.take();
+
+        let codemap = self.frontend.codemap();
+        let id = codemap.add(path, content);
+        let file = codemap.get(id).unwrap();
+        let source = content_hash(file.source().as_bytes());
+        let mut recording = Recording {
+            inner: errors,
+            reported: Vec::new(),
+        };
+        let res = self.frontend.parse_source(&mut recording, file);
+
+        let mut paths = lookups.take();
+        let mut seen = std::collections::HashSet::new();
+        paths.retain(|path| seen.insert(path.clone()));
+        if res.is_err() || recording.reported.iter().any(|(error, _)| *error) {
+            return (res, None);
+        }
+
+        let mut manifest = Manifest {
+            source,
+            config: self.config_hash(self.compiler.unwrap()),
+            lookups: paths
+                .into_iter()
+                .map(|path| {
+                    let hash = fingerprint(codemap, &path);
+                    (path, hash)
+                })
+                .collect(),
+            files: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+        if !manifest.record_diagnostics(codemap, &recording.reported) {
+            return (res, None);
+        }
+        (res, Some(manifest))
+    }
+}
+
+impl Frontend for CachingErlangFrontend {
+    type Error = Error;
+
+    fn parse_source<'a>(
+        &self,
+        errors: &'a mut FrontendErrorReceiver<'a, Self::Error>,
+        source: Arc<SourceFile>,
+    ) -> Result<Module, ()> {
+        self.frontend.parse_source(errors, source)
+    }
+
+    fn parse_string<'a>(
+        &self,
+        errors: &'a mut FrontendErrorReceiver<'a, Self::Error>,
+        source: &str,
+    ) -> Result<Module, ()> {
+        self.frontend.parse_string(errors, source)
+    }
+
+    fn parse_file<'a>(
+        &self,
+        errors: &'a mut FrontendErrorReceiver<'a, Self::Error>,
+        path: &Path,
+    ) -> Result<Module, ()> {
+        let compiler = match self.compiler {
+            Some(compiler) => compiler,
+            None => return self.frontend.parse_file(errors, path),
+        };
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) => {
+                errors.error(
+                    <ModuleAst as Parse<ModuleAst>>::root_file_error(err, path.to_owned()).into(),
+                );
+                return Err(());
+            }
+        };
+
+        let source = content_hash(content.as_bytes());
+        if let Some((module, diagnostics)) = self.load(path, source, self.config_hash(compiler)) {
+            for (error, diagnostic) in diagnostics {
+                let reported = Error::Parser(ParserError::ShowDiagnostic { diagnostic });
+                if error {
+                    errors.error(reported);
+                } else {
+                    errors.warning(reported);
+                }
+            }
+            return Ok(module);
+        }
+
+        let (res, manifest) = self.compile(errors, path, content);
+        if let (Ok(module), Some(manifest)) = (&res, manifest) {
+            // Failing to write the cache only costs a recompilation later.
+            let _ = self.store(path, &manifest, module);
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    use libeir_diagnostics::{CodeMap, Diagnostic, LabelStyle, Severity};
+    use libeir_syntax_erl::ParseConfig;
+
+    use super::{CachedDiagnostic, CachedFile, CachedLabel, CachingErlangFrontend, Manifest};
+    use crate::erlang::ErlangFrontend;
+    use crate::DynFrontend;
+
+    #[test]
+    fn manifest_roundtrip() {
+        let manifest = Manifest {
+            source: 0x1234,
+            config: 0xdead_beef,
+            lookups: vec![
+                (PathBuf::from("/a/b c.hrl"), Some(7)),
+                (PathBuf::from("/a/missing.hrl"), None),
+            ],
+            files: vec![
+                CachedFile::Real(PathBuf::from("/a/b c.hrl")),
+                CachedFile::Virtual {
+                    name: "-DVALUE".to_string(),
+                    source: "\\n\n ".to_string(),
+                },
+            ],
+            diagnostics: vec![CachedDiagnostic {
+                error: false,
+                severity: Severity::Warning,
+                code: Some(String::new()),
+                message: "a message\nover two lines".to_string(),
+                labels: vec![CachedLabel {
+                    style: LabelStyle::Secondary,
+                    file: 1,
+                    start: 2,
+                    end: 3,
+                    message: String::new(),
+                }],
+                notes: vec!["a note".to_string()],
+            }],
+        };
+        let text = manifest.to_text().unwrap();
+        assert_eq!(Manifest::from_text(&text), Some(manifest));
+        assert_eq!(Manifest::from_text("eir-cache 0\n"), None);
+    }
+
+    struct TempDir(PathBuf);
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("eir_cache_{}_{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn compile(dir: &Path, include_paths: &[PathBuf], source: &Path) -> (String, Vec<Diagnostic>) {
+        let mut config = ParseConfig::default();
+        config.include_paths.extend(include_paths.iter().cloned());
+        let frontend = ErlangFrontend::new(config, Arc::new(CodeMap::new()));
+        let frontend = CachingErlangFrontend::new(frontend, dir.join("cache"));
+        let (res, diagnostics) = frontend.parse_file_dyn(source);
+        (res.unwrap().to_text_standard(), diagnostics)
+    }
+
+    fn contains(text: &str, atom: &str) -> bool {
+        text.contains(&format!("a'{}'", atom))
+    }
+
+    /// Replaces `from` with `to` in the cached modules, to tell a module
+    /// served from the cache apart from a compiled one.
+    fn tamper(dir: &Path, from: &str, to: &str) {
+        for entry in std::fs::read_dir(dir.join("cache")).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().map(|ext| ext == "eir") == Some(true) {
+                let text = std::fs::read_to_string(&path).unwrap();
+                let from = format!("a'{}'", from);
+                let to = format!("a'{}'", to);
+                std::fs::write(&path, text.replace(&from, &to)).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn invalidated_by_header() {
+        let dir = TempDir::new("header");
+        let include = dir.0.join("include");
+        std::fs::create_dir_all(&include).unwrap();
+        std::fs::write(include.join("inner.hrl"), "-define(VALUE, one).\n").unwrap();
+        std::fs::write(include.join("outer.hrl"), "-include(\"inner.hrl\").\n").unwrap();
+        let source = dir.0.join("cached.erl");
+        std::fs::write(
+            &source,
+            "-module(cached).
+-export([value/0]).
+-include(\"outer.hrl\").
+value() -> ?VALUE.
+",
+        )
+        .unwrap();
+        let include_paths = [include.clone()];
+
+        let (first, _) = compile(&dir.0, &include_paths, &source);
+        assert!(contains(&first, "one"));
+
+        tamper(&dir.0, "one", "cached");
+        let (second, _) = compile(&dir.0, &include_paths, &source);
+        assert!(contains(&second, "cached"));
+
+        // Changing a header included through another header invalidates
+        // the entry.
+        std::fs::write(include.join("inner.hrl"), "-define(VALUE, two).\n").unwrap();
+        let (third, _) = compile(&dir.0, &include_paths, &source);
+        assert!(contains(&third, "two"));
+    }
+
+    #[test]
+    fn invalidated_by_shadowing_header() {
+        let dir = TempDir::new("shadow");
+        let first = dir.0.join("first");
+        let second = dir.0.join("second");
+        std::fs::create_dir_all(&first).unwrap();
+        std::fs::create_dir_all(&second).unwrap();
+        std::fs::write(second.join("value.hrl"), "-define(VALUE, second).\n").unwrap();
+        let source = dir.0.join("shadowed.erl");
+        std::fs::write(
+            &source,
+            "-module(shadowed).
+-export([value/0]).
+-include(\"value.hrl\").
+value() -> ?VALUE.
+",
+        )
+        .unwrap();
+        let include_paths = [first.clone(), second.clone()];
+
+        let (text, _) = compile(&dir.0, &include_paths, &source);
+        assert!(contains(&text, "second"));
+        tamper(&dir.0, "second", "cached");
+        let (text, _) = compile(&dir.0, &include_paths, &source);
+        assert!(contains(&text, "cached"));
+
+        // The header in the first include path was looked for but not
+        // found, creating it invalidates the entry.
+        std::fs::write(first.join("value.hrl"), "-define(VALUE, first).\n").unwrap();
+        let (text, _) = compile(&dir.0, &include_paths, &source);
+        assert!(contains(&text, "first"));
+    }
+
+    #[test]
+    fn diagnostics_reported_again() {
+        let dir = TempDir::new("diagnostics");
+        let source = dir.0.join("warned.erl");
+        std::fs::write(
+            &source,
+            "-module(warned).
+-compile([warn_missing_spec]).
+-export([value/0]).
+value() -> fresh.
+",
+        )
+        .unwrap();
+
+        let (text, fresh) = compile(&dir.0, &[], &source);
+        assert!(contains(&text, "fresh"));
+        assert_eq!(fresh.len(), 1);
+
+        tamper(&dir.0, "fresh", "cached");
+        let (text, cached) = compile(&dir.0, &[], &source);
+        assert!(contains(&text, "cached"));
+        assert_eq!(cached.len(), 1);
+
+        let (fresh, cached) = (&fresh[0], &cached[0]);
+        assert_eq!(cached.severity, fresh.severity);
+        assert_eq!(cached.message, fresh.message);
+        assert_eq!(cached.notes, fresh.notes);
+        assert_eq!(cached.labels.len(), fresh.labels.len());
+        for (cached, fresh) in cached.labels.iter().zip(fresh.labels.iter()) {
+            assert_eq!(cached.range, fresh.range);
+            assert_eq!(cached.message, fresh.message);
+        }
+    }
+}
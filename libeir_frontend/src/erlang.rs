@@ -4,9 +4,12 @@ use std::sync::Arc;
 use libeir_diagnostics::*;
 use libeir_ir::Module;
 use libeir_syntax_erl::{
-    ast::Module as ModuleAst, lower_module, LowerError, ParseConfig, ParserError,
+    apply_transforms,
+    ast::{CompileOptions, Module as ModuleAst},
+    check_behaviours, check_deprecations, lower_module, AstTransform, LowerError, ParseConfig,
+    ParserError, TransformRegistry,
 };
-use libeir_util_parse::{error_tee, Parse, Parser};
+use libeir_util_parse::{error_tee, ErrorReceiver, Parse, Parser};
 
 use super::{Frontend, FrontendErrorReceiver};
 
@@ -35,14 +38,61 @@ impl Into<Error> for LowerError {
 
 pub struct ErlangFrontend {
     parser: Parser<ParseConfig>,
+    transforms: TransformRegistry,
 }
 
 impl ErlangFrontend {
     pub fn new(config: ParseConfig, codemap: Arc<CodeMap>) -> Self {
         Self {
             parser: Parser::new(config, codemap),
+            transforms: TransformRegistry::default(),
         }
     }
+
+    pub fn config(&self) -> &ParseConfig {
+        &self.parser.config
+    }
+
+    pub(crate) fn config_mut(&mut self) -> &mut ParseConfig {
+        &mut self.parser.config
+    }
+
+    pub fn codemap(&self) -> &Arc<CodeMap> {
+        &self.parser.codemap
+    }
+
+    /// Makes `transform` available to modules as the parse transform
+    /// `name`, in addition to the built-in ones.
+    pub fn register_transform<T>(&mut self, name: &str, transform: T)
+    where
+        T: AstTransform + 'static,
+    {
+        self.transforms.register(name, transform);
+    }
+
+    /// Parses `source` and runs everything that happens on the AST before
+    /// lowering: parse transforms and the behaviour and deprecation
+    /// checks.
+    pub fn parse_source_ast(
+        &self,
+        errors: &mut dyn ErrorReceiver<E = ParserError, W = ParserError>,
+        source: Arc<SourceFile>,
+    ) -> Result<ModuleAst, ()> {
+        let mut ast = self.parser.parse::<ModuleAst>(errors, source)?;
+
+        // Warning flags given to the compiler apply on top of the ones in
+        // the module.
+        let config = &self.parser.config;
+        if config.warnings_as_errors || config.no_warn {
+            let compile = ast.compile.get_or_insert_with(CompileOptions::default);
+            compile.warnings_as_errors |= config.warnings_as_errors;
+            compile.no_warn |= config.no_warn;
+        }
+        apply_transforms(&self.transforms, errors, &mut ast)?;
+        check_behaviours(&self.parser, errors, &ast);
+        check_deprecations(&self.parser, errors, &ast);
+        Ok(ast)
+    }
 }
 
 impl Frontend for ErlangFrontend {
@@ -54,9 +104,7 @@ impl Frontend for ErlangFrontend {
         source: Arc<SourceFile>,
     ) -> Result<Module, ()> {
         error_tee(errors, |mut errors| {
-            let ast = self
-                .parser
-                .parse::<ModuleAst>(&mut errors.make_into_adapter(), source)?;
+            let ast = self.parse_source_ast(&mut errors.make_into_adapter(), source)?;
             let eir = lower_module(
                 &mut errors.make_into_adapter(),
                 self.parser.codemap.clone(),
@@ -1,5 +1,7 @@
 #[cfg(feature = "frontend_abstr_erlang")]
 pub mod abstr_erlang;
+#[cfg(all(feature = "frontend_erlang", feature = "frontend_eir"))]
+pub mod cache;
 #[cfg(feature = "frontend_eir")]
 pub mod eir;
 #[cfg(feature = "frontend_erlang")]
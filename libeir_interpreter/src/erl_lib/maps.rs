@@ -37,9 +37,51 @@ fn from_list_1(_vm: &VMState, _proc: &mut ProcessContext, args: &[Rc<Term>]) ->
     }
 }
 
+/// Iterators are represented as the list of the remaining entries,
+/// as `{Key, Value}` tuples.
+fn iterator_1(_vm: &VMState, _proc: &mut ProcessContext, args: &[Rc<Term>]) -> NativeReturn {
+    assert!(args.len() == 1);
+    match args[0].as_map() {
+        Some(map) => {
+            let entries: Vec<Rc<Term>> = map
+                .iter()
+                .map(|(key, val)| Term::Tuple(vec![key.clone(), val.clone()]).into())
+                .collect();
+            NativeReturn::Return {
+                term: Term::slice_to_list(&entries, Term::Nil.into()),
+            }
+        }
+        None => NativeReturn::Throw {
+            typ: Term::new_atom("error").into(),
+            reason: Term::Tuple(vec![Term::new_atom("badmap").into(), args[0].clone()]).into(),
+        },
+    }
+}
+
+fn next_1(_vm: &VMState, _proc: &mut ProcessContext, args: &[Rc<Term>]) -> NativeReturn {
+    assert!(args.len() == 1);
+    match &*args[0] {
+        Term::Nil => NativeReturn::Return {
+            term: Term::new_atom("none").into(),
+        },
+        Term::ListCell(head, tail) => {
+            let entry = head.as_tuple().unwrap();
+            NativeReturn::Return {
+                term: Term::Tuple(vec![entry[0].clone(), entry[1].clone(), tail.clone()]).into(),
+            }
+        }
+        _ => NativeReturn::Throw {
+            typ: Term::new_atom("error").into(),
+            reason: Term::new_atom("badarg").into(),
+        },
+    }
+}
+
 pub fn make_maps() -> NativeModule {
     let mut module = NativeModule::new(Symbol::intern("maps"));
     module.add_fun(Symbol::intern("new"), 0, Box::new(new_0));
     module.add_fun(Symbol::intern("from_list"), 1, Box::new(from_list_1));
+    module.add_fun(Symbol::intern("iterator"), 1, Box::new(iterator_1));
+    module.add_fun(Symbol::intern("next"), 1, Box::new(next_1));
     module
 }
@@ -154,35 +154,41 @@ pub fn match_op(
                 };
                 return ret;
             }
-            MatchKind::Binary(BinaryEntrySpecifier::Bytes { unit: 8 }) => match &*unpack_term {
-                Term::Binary(bin) => {
-                    if bin.bit_len() % 8 != 0 {
-                        continue;
-                    }
+            MatchKind::Binary(BinaryEntrySpecifier::Bytes { unit })
+            | MatchKind::Binary(BinaryEntrySpecifier::Bits { unit })
+                if branch_args.len() == 0 =>
+            {
+                let unit = *unit as usize;
+                match &*unpack_term {
+                    Term::Binary(bin) => {
+                        if bin.bit_len() % unit != 0 {
+                            continue;
+                        }
 
-                    return TermCall {
-                        fun: branches_elems[idx].clone(),
-                        args: vec![
-                            unpack_term.clone(),
-                            Term::Binary(BitVec::new().into()).into(),
-                        ],
-                    };
-                }
-                Term::BinarySlice { bit_length, .. } => {
-                    if *bit_length % 8 != 0 {
-                        continue;
+                        return TermCall {
+                            fun: branches_elems[idx].clone(),
+                            args: vec![
+                                unpack_term.clone(),
+                                Term::Binary(BitVec::new().into()).into(),
+                            ],
+                        };
                     }
+                    Term::BinarySlice { bit_length, .. } => {
+                        if *bit_length % unit != 0 {
+                            continue;
+                        }
 
-                    return TermCall {
-                        fun: branches_elems[idx].clone(),
-                        args: vec![
-                            unpack_term.clone(),
-                            Term::Binary(BitVec::new().into()).into(),
-                        ],
-                    };
+                        return TermCall {
+                            fun: branches_elems[idx].clone(),
+                            args: vec![
+                                unpack_term.clone(),
+                                Term::Binary(BitVec::new().into()).into(),
+                            ],
+                        };
+                    }
+                    _ => (),
                 }
-                _ => (),
-            },
+            }
             MatchKind::Wildcard => {
                 assert!(branch_args.len() == 0);
                 return TermCall {
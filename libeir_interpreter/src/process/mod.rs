@@ -426,7 +426,8 @@ impl CallExecutor {
                                     _ => unreachable!(),
                                 }
                             }
-                            BinaryEntrySpecifier::Bytes { unit: 1 } => {
+                            BinaryEntrySpecifier::Bytes { unit: 1 }
+                            | BinaryEntrySpecifier::Bits { unit: 1 } => {
                                 let binary = val_term.as_binary().unwrap();
 
                                 if let Some(size_term) = size_term {
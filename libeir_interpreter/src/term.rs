@@ -99,6 +99,11 @@ impl MapTerm {
     pub fn len(&self) -> usize {
         self.map.len()
     }
+
+    /// Iterates over the entries of the map in key order.
+    pub fn iter(&self) -> std::slice::Iter<(Rc<Term>, Rc<Term>)> {
+        self.sorted.iter()
+    }
 }
 impl PartialEq for MapTerm {
     fn eq(&self, other: &MapTerm) -> bool {
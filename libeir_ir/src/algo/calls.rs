@@ -0,0 +1,180 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use libeir_diagnostics::SourceSpan;
+use libeir_intern::Symbol;
+
+use crate::constant::{AtomicTerm, ConstKind};
+use crate::{Function, FunctionIdent, Module, PrimOpKind, Value, ValueKind};
+
+/// A statically known call target, a function capture where the module,
+/// function and arity are all constants.
+///
+/// Both calls and `fun M:F/A` expressions are lowered to function
+/// captures, as are local calls, which capture a function in the current
+/// module.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CallTarget {
+    pub module: Symbol,
+    pub function: Symbol,
+    pub arity: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub target: CallTarget,
+    /// The location of the capture, `SourceSpan::UNKNOWN` if the IR has
+    /// no location for it.
+    pub span: SourceSpan,
+}
+
+impl Function {
+    /// Returns the statically known call targets reachable from the
+    /// entry block of the function, including those in closures.
+    ///
+    /// Captures with a dynamic module, function or arity are not included.
+    pub fn calls(&self) -> Vec<Call> {
+        let mut seen = BTreeSet::new();
+        let mut calls = Vec::new();
+
+        let graph = self.block_graph();
+        for block in graph.dfs_iter() {
+            self.block_walk_nested_values::<_, ()>(block, &mut |value| {
+                if seen.insert(value) {
+                    if let Some(target) = self.call_target(value) {
+                        let span = self
+                            .value_locations(value)
+                            .and_then(|spans| spans.first().copied())
+                            .unwrap_or(SourceSpan::UNKNOWN);
+                        calls.push(Call { target, span });
+                    }
+                }
+                Ok(())
+            })
+            .unwrap();
+        }
+
+        calls
+    }
+
+    /// If `value` is a function capture with constant operands, returns
+    /// its target.
+    pub fn call_target(&self, value: Value) -> Option<CallTarget> {
+        let primop = match self.value_kind(value) {
+            ValueKind::PrimOp(primop) => primop,
+            _ => return None,
+        };
+        if *self.primop_kind(primop) != PrimOpKind::CaptureFunction {
+            return None;
+        }
+        let reads = self.primop_reads(primop);
+
+        let atomic = |value: Value| {
+            let cons = self.value_const(value)?;
+            match self.const_kind(cons) {
+                ConstKind::Atomic(atomic) => Some(atomic),
+                _ => None,
+            }
+        };
+        let module = match atomic(reads[0])? {
+            AtomicTerm::Atom(atom) => atom.0,
+            _ => return None,
+        };
+        let function = match atomic(reads[1])? {
+            AtomicTerm::Atom(atom) => atom.0,
+            _ => return None,
+        };
+        let arity = match atomic(reads[2])? {
+            AtomicTerm::Int(int) if int.value() >= 0 => int.value() as usize,
+            _ => return None,
+        };
+
+        Some(CallTarget {
+            module,
+            function,
+            arity,
+        })
+    }
+}
+
+/// The statically known calls made by every function in a module.
+#[derive(Debug, Clone)]
+pub struct CallGraph {
+    pub calls: BTreeMap<FunctionIdent, Vec<Call>>,
+}
+
+impl CallGraph {
+    /// Iterates over every call in the module, together with the function
+    /// it is made from.
+    pub fn iter(&self) -> impl Iterator<Item = (&FunctionIdent, &Call)> {
+        self.calls
+            .iter()
+            .flat_map(|(caller, calls)| calls.iter().map(move |call| (caller, call)))
+    }
+
+    /// The set of distinct call targets in the module.
+    pub fn targets(&self) -> BTreeSet<CallTarget> {
+        self.iter().map(|(_, call)| call.target).collect()
+    }
+}
+
+impl Module {
+    pub fn call_graph(&self) -> CallGraph {
+        let calls = self
+            .function_iter()
+            .map(|def| {
+                let fun = def.function();
+                (*fun.ident(), fun.calls())
+            })
+            .collect();
+        CallGraph { calls }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libeir_intern::Symbol;
+
+    use super::CallTarget;
+
+    fn target(module: &str, function: &str, arity: usize) -> CallTarget {
+        CallTarget {
+            module: Symbol::intern(module),
+            function: Symbol::intern(function),
+            arity,
+        }
+    }
+
+    #[test]
+    fn module_call_graph() {
+        let ir = crate::parse_module_unwrap(
+            "
+a'woo' {
+    a'foo'/1 {
+        entry(%ret, %thr, %a):
+            %f = a'lists':a'reverse'/1;
+            %f(%a) => block1 except %thr;
+        block1(%r):
+            %g = a'woo':a'bar'/0;
+            %g() => %ret except %thr;
+    }
+    a'bar'/0 {
+        entry(%ret, %thr):
+            %ret(a'ok');
+    }
+}
+",
+        );
+
+        let graph = ir.call_graph();
+        let targets: Vec<_> = graph.targets().into_iter().collect();
+        let mut expected = vec![target("lists", "reverse", 1), target("woo", "bar", 0)];
+        expected.sort();
+        assert_eq!(targets, expected);
+
+        let callers: Vec<_> = graph
+            .iter()
+            .map(|(caller, _)| caller.name.name.as_str().get().to_string())
+            .collect();
+        assert_eq!(callers, vec!["foo", "foo"]);
+    }
+}
@@ -2,9 +2,11 @@ use std::collections::{BTreeMap, VecDeque};
 
 use snafu::Snafu;
 
-use crate::Function;
+use libeir_intern::{Ident, Symbol};
+
 use crate::ValueKind;
 use crate::{Block, Const, Location, PrimOp, Value};
+use crate::{Function, FunctionIdent, Module};
 
 #[derive(Snafu, Debug, PartialEq, Eq)]
 pub enum EqualityFail {
@@ -47,6 +49,39 @@ pub enum EqualityFail {
         left_desc: String,
         right_desc: String,
     },
+
+    MismatchingModuleName {
+        left: Ident,
+        right: Ident,
+    },
+
+    MismatchingExports {
+        left: Vec<(Symbol, usize)>,
+        right: Vec<(Symbol, usize)>,
+    },
+
+    MismatchingOnLoad {
+        left: Option<(Symbol, usize)>,
+        right: Option<(Symbol, usize)>,
+    },
+
+    MismatchingAttribute {
+        index: usize,
+    },
+
+    MismatchingSpec {
+        name: Symbol,
+        arity: usize,
+    },
+
+    MissingFunction {
+        ident: FunctionIdent,
+    },
+
+    MismatchingFunction {
+        ident: FunctionIdent,
+        fail: Box<EqualityFail>,
+    },
 }
 
 struct EqCtx<'a> {
@@ -186,6 +221,90 @@ impl Function {
     }
 }
 
+impl Module {
+    /// Checks structural equality between two modules. Module metadata
+    /// must be equal, and every function must be equal under
+    /// `Function::graph_eq_opts`.
+    pub fn graph_eq_opts(&self, rhs: &Module, opts: &GraphEqOptions) -> Result<(), EqualityFail> {
+        if self.name().name != rhs.name().name {
+            return Err(EqualityFail::MismatchingModuleName {
+                left: self.name(),
+                right: rhs.name(),
+            });
+        }
+
+        if !self.export_iter().eq(rhs.export_iter()) {
+            return Err(EqualityFail::MismatchingExports {
+                left: self.export_iter().collect(),
+                right: rhs.export_iter().collect(),
+            });
+        }
+
+        if self.on_load() != rhs.on_load() {
+            return Err(EqualityFail::MismatchingOnLoad {
+                left: self.on_load(),
+                right: rhs.on_load(),
+            });
+        }
+
+        let l_attrs = self.attributes();
+        let r_attrs = rhs.attributes();
+        for index in 0..l_attrs.len().max(r_attrs.len()) {
+            let eq = match (l_attrs.get(index), r_attrs.get(index)) {
+                (Some((l_name, l_val)), Some((r_name, r_val))) => {
+                    l_name.name == r_name.name
+                        && self.cons().eq_other(*l_val, rhs.cons(), *r_val)
+                }
+                _ => false,
+            };
+            if !eq {
+                return Err(EqualityFail::MismatchingAttribute { index });
+            }
+        }
+
+        let mut l_specs = self.spec_iter();
+        let mut r_specs = rhs.spec_iter();
+        loop {
+            match (l_specs.next(), r_specs.next()) {
+                (None, None) => break,
+                (Some((l_name, l_spec)), Some((r_name, r_spec)))
+                    if l_name == r_name && l_spec == r_spec => {}
+                (Some(((name, arity), _)), _) | (None, Some(((name, arity), _))) => {
+                    return Err(EqualityFail::MismatchingSpec { name, arity });
+                }
+            }
+        }
+
+        for def in self.function_iter() {
+            let ident = *def.function().ident();
+            if rhs.name_arity_index(ident.name.name, ident.arity).is_none() {
+                return Err(EqualityFail::MissingFunction { ident });
+            }
+        }
+        for def in rhs.function_iter() {
+            let ident = *def.function().ident();
+            match self.name_arity_index(ident.name.name, ident.arity) {
+                None => return Err(EqualityFail::MissingFunction { ident }),
+                Some(idx) => {
+                    let lf = self[idx].function();
+                    let rf = def.function();
+                    lf.graph_eq_opts(lf.block_entry(), rf, rf.block_entry(), opts)
+                        .map_err(|fail| EqualityFail::MismatchingFunction {
+                            ident,
+                            fail: Box::new(fail),
+                        })?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn graph_eq(&self, rhs: &Module) -> Result<(), EqualityFail> {
+        self.graph_eq_opts(rhs, &GraphEqOptions::default())
+    }
+}
+
 fn traverse_value<'a>(ctx: &mut EqCtx<'a>, l: Value, r: Value) -> Result<(), EqualityFail> {
     if let Some(nr) = ctx.map.get(&l) {
         if *nr == r {
@@ -1,3 +1,4 @@
+pub mod calls;
 pub mod equality;
 pub mod func_tree;
 pub mod live;
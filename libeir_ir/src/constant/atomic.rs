@@ -147,7 +147,7 @@ impl From<Number> for AtomicTerm {
 }
 impl Display for FloatTerm {
     fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
-        write!(fmt, "f{}", self.0)
+        write!(fmt, "f'{}'", self.0)
     }
 }
 
@@ -181,7 +181,14 @@ impl PartialEq<str> for AtomTerm {
 }
 impl Display for AtomTerm {
     fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
-        write!(fmt, "a'{}'", self.0) // TODO escape
+        write!(fmt, "a'")?;
+        for c in self.0.as_str().chars() {
+            match c {
+                '\'' | '\\' => write!(fmt, "\\{}", c)?,
+                c => write!(fmt, "{}", c)?,
+            }
+        }
+        write!(fmt, "'")
     }
 }
 
@@ -193,6 +200,15 @@ impl BinaryTerm {
         &self.0
     }
 }
+impl Display for BinaryTerm {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "b'")?;
+        for byte in self.0.iter() {
+            write!(fmt, "{:02x}", byte)?;
+        }
+        write!(fmt, "'")
+    }
+}
 impl From<BinaryTerm> for AtomicTerm {
     fn from(data: BinaryTerm) -> Self {
         AtomicTerm::Binary(data)
@@ -230,7 +246,7 @@ impl Display for AtomicTerm {
             AtomicTerm::Float(float) => write!(fmt, "{}", float),
             AtomicTerm::Atom(atom) => write!(fmt, "{}", atom),
             AtomicTerm::Nil => write!(fmt, "[]"),
-            AtomicTerm::Binary(_bin) => write!(fmt, "bin"),
+            AtomicTerm::Binary(bin) => write!(fmt, "{}", bin),
         }
     }
 }
@@ -232,7 +232,38 @@ impl ConstantContainer {
                 }
                 true
             }
-            l => unimplemented!("{:?}", l),
+            (
+                ConstKind::ListCell { head: h1, tail: t1 },
+                ConstKind::ListCell { head: h2, tail: t2 },
+            ) => self.eq_other(*h1, r_cont, *h2) && self.eq_other(*t1, r_cont, *t2),
+            (
+                ConstKind::Map {
+                    keys: k1,
+                    values: v1,
+                },
+                ConstKind::Map {
+                    keys: k2,
+                    values: v2,
+                },
+            ) => {
+                let k1 = k1.as_slice(&self.const_pool);
+                let k2 = k2.as_slice(&r_cont.const_pool);
+                let v1 = v1.as_slice(&self.const_pool);
+                let v2 = v2.as_slice(&r_cont.const_pool);
+                if k1.len() != k2.len() {
+                    return false;
+                }
+                // Entries are ordered by key constant index, which is
+                // container specific. Match keys up by value instead.
+                k1.iter().zip(v1.iter()).all(|(lk, lv)| {
+                    k2.iter()
+                        .zip(v2.iter())
+                        .find(|(rk, _)| self.eq_other(*lk, r_cont, **rk))
+                        .map(|(_, rv)| self.eq_other(*lv, r_cont, *rv))
+                        .unwrap_or(false)
+                })
+            }
+            _ => false,
         }
     }
 }
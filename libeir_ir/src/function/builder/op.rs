@@ -97,6 +97,33 @@ impl<'a> FunctionBuilder<'a> {
         cont
     }
 
+    pub fn op_trace_construct_next(
+        &mut self,
+        _span: SourceSpan,
+        block: Block,
+        next: Value,
+        trace: Value,
+    ) {
+        let data = self.fun.blocks.get_mut(block).unwrap();
+        assert!(data.op.is_none());
+        assert!(data.reads.is_empty());
+
+        data.op = Some(OpKind::TraceConstruct);
+        data.reads.push(next, &mut self.fun.pool.value);
+        data.reads.push(trace, &mut self.fun.pool.value);
+
+        self.graph_update_block(block);
+    }
+    pub fn op_trace_construct(&mut self, span: SourceSpan, block: Block, trace: Value) -> Block {
+        let cont = self.fun.block_insert();
+        let cont_val = self.value(cont);
+        self.fun.block_arg_insert(cont);
+
+        self.op_trace_construct_next(span, block, cont_val, trace);
+
+        cont
+    }
+
     pub fn op_intrinsic<'b, O: OpBuild>(
         &'b mut self,
         block: Block,
@@ -121,6 +148,15 @@ impl<'a> FunctionBuilder<'a> {
     pub fn op_map_put_build(&mut self, span: SourceSpan, value: Value) -> MapPutBuilder {
         MapPutBuilder::new(span, value, self)
     }
+    pub fn op_map_put_build_next(
+        &mut self,
+        span: SourceSpan,
+        ok: Value,
+        fail: Value,
+        value: Value,
+    ) -> MapPutBuilder {
+        MapPutBuilder::new_next(span, ok, fail, value, self)
+    }
 
     pub fn op_unpack_value_list_next(
         &mut self,
@@ -317,19 +353,16 @@ impl MatchBuilder {
         block
     }
 
-    pub fn push_binary(
+    pub fn push_binary_next(
         &mut self,
+        next: Value,
         specifier: BinaryEntrySpecifier,
         size: Option<Value>,
         b: &mut FunctionBuilder,
-    ) -> Block {
-        let (block, block_val) = b.block_insert_get_val();
-        b.block_arg_insert(block);
-        b.block_arg_insert(block);
-
+    ) {
         self.kinds.push(MatchKind::Binary(specifier));
 
-        self.branches.push(block_val, &mut b.fun.pool.value);
+        self.branches.push(next, &mut b.fun.pool.value);
 
         let args = if let Some(size) = size {
             b.prim_value_list(&[size])
@@ -337,6 +370,18 @@ impl MatchBuilder {
             b.prim_value_list(&[])
         };
         self.branch_args.push(args, &mut b.fun.pool.value);
+    }
+    pub fn push_binary(
+        &mut self,
+        specifier: BinaryEntrySpecifier,
+        size: Option<Value>,
+        b: &mut FunctionBuilder,
+    ) -> Block {
+        let (block, block_val) = b.block_insert_get_val();
+        b.block_arg_insert(block);
+        b.block_arg_insert(block);
+
+        self.push_binary_next(block_val, specifier, size, b);
 
         block
     }
@@ -422,8 +467,7 @@ impl MatchBuilder {
 
 pub struct MapPutBuilder {
     span: SourceSpan,
-    ok: Block,
-    fail: Block,
+    targets: Option<(Block, Block)>,
     reads: EntityList<Value>,
     actions: Vec<MapPutUpdate>,
 }
@@ -435,16 +479,27 @@ impl MapPutBuilder {
         let (fail, fail_val) = b.block_insert_get_val();
         b.block_arg_insert(fail);
 
+        let mut this = Self::new_next(span, ok_val, fail_val, value, b);
+        this.targets = Some((ok, fail));
+        this
+    }
+
+    pub fn new_next(
+        span: SourceSpan,
+        ok: Value,
+        fail: Value,
+        value: Value,
+        b: &mut FunctionBuilder,
+    ) -> Self {
         let mut reads = EntityList::new();
-        reads.push(ok_val, &mut b.fun.pool.value);
-        reads.push(fail_val, &mut b.fun.pool.value);
+        reads.push(ok, &mut b.fun.pool.value);
+        reads.push(fail, &mut b.fun.pool.value);
 
         reads.push(value, &mut b.fun.pool.value);
 
         MapPutBuilder {
             span,
-            ok,
-            fail,
+            targets: None,
             reads,
             actions: Vec::new(),
         }
@@ -463,7 +518,18 @@ impl MapPutBuilder {
         self.reads.push(val, &mut b.fun.pool.value);
     }
 
-    pub fn finish(self, block: Block, b: &mut FunctionBuilder) -> (Block, Block) {
+    /// Finishes a builder created with `new`, returning the ok and fail
+    /// blocks.
+    pub fn finish(mut self, block: Block, b: &mut FunctionBuilder) -> (Block, Block) {
+        let targets = self
+            .targets
+            .take()
+            .expect("finish called on builder created with new_next");
+        self.finish_next(block, b);
+        targets
+    }
+
+    pub fn finish_next(self, block: Block, b: &mut FunctionBuilder) {
         let data = b.fun.blocks.get_mut(block).unwrap();
         assert!(data.op.is_none());
         assert!(data.reads.is_empty());
@@ -474,7 +540,5 @@ impl MapPutBuilder {
         data.reads = self.reads;
 
         b.graph_update_block(block);
-
-        (self.ok, self.fail)
     }
 }
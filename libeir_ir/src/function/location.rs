@@ -7,7 +7,7 @@ use libeir_util_datastructures::{
 };
 
 use cranelift_entity::{entity_impl, EntityList, ListPool};
-use libeir_diagnostics::{CodeMap, SourceSpan};
+use libeir_diagnostics::{CodeMap, SourceId, SourceSpan};
 
 #[derive(Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Location(u32);
@@ -101,6 +101,17 @@ impl LocationContainer {
         locs
     }
 
+    /// Returns the span of the first terminal of the location that
+    /// points into a known source file.
+    pub fn primary_span(&self, location: Location) -> Option<SourceSpan> {
+        self.locations[location]
+            .terminals
+            .as_slice(&self.terminal_pool)
+            .iter()
+            .map(|terminal| self.terminals[*terminal].span)
+            .find(|span| span.source_id() != SourceId::UNKNOWN)
+    }
+
     pub fn location_empty(&mut self) -> Location {
         self.locations.push(
             LocationData {
@@ -273,6 +284,47 @@ impl LocationContainer {
         return true;
     }
 
+    /// Formats the location in the syntax accepted by the `!location`
+    /// meta entry of the text parser.
+    /// Fields that can not be represented in that syntax, like a line
+    /// without a file, are omitted.
+    pub fn format_loc_text(&self, loc: Location) -> String {
+        use std::fmt::Write;
+
+        let loc_inner = &self.locations[loc];
+        let mut out = String::new();
+
+        write!(&mut out, "[").unwrap();
+        for (n, term) in loc_inner
+            .terminals
+            .as_slice(&self.terminal_pool)
+            .iter()
+            .enumerate()
+        {
+            let term_inner = &self.terminals[*term];
+
+            if n != 0 {
+                write!(&mut out, ", ").unwrap();
+            }
+
+            if let Some(module) = &term_inner.module {
+                write!(&mut out, "\"{}\"", module).unwrap();
+                if let Some(entity) = &term_inner.entity {
+                    write!(&mut out, ":\"{}\"", entity).unwrap();
+                }
+            }
+            if let Some(file) = &term_inner.file {
+                write!(&mut out, "@\"{}\"", file).unwrap();
+                if let Some(line) = &term_inner.line {
+                    write!(&mut out, ":{}", line).unwrap();
+                }
+            }
+        }
+        write!(&mut out, "]").unwrap();
+
+        out
+    }
+
     pub fn format_loc(&self, loc: Location) -> String {
         use std::fmt::Write;
 
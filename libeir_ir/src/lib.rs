@@ -17,6 +17,7 @@ pub mod traits;
 
 // Auxiliary utilities
 mod algo;
+pub use algo::calls::{Call, CallGraph, CallTarget};
 pub use algo::equality::GraphEqOptions;
 pub use algo::func_tree::{FunctionEntry, FunctionTree};
 pub use algo::live::LiveValues;
@@ -61,6 +62,9 @@ pub use binary::{BinaryEntrySpecifier, Endianness};
 mod module;
 pub use module::{FunctionDefinition, FunctionIndex, Module};
 
+pub mod spec;
+pub use spec::{FunctionSpec, MapFieldKind, SpecClause, TypeExpr};
+
 #[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, PartialOrd)]
 pub struct FunctionIdent {
     pub module: Ident,
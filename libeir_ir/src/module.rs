@@ -1,8 +1,10 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::ops::{Index, IndexMut};
 
 use cranelift_entity::{entity_impl, PrimaryMap};
 
+use crate::constant::{Const, ConstantContainer};
+use crate::spec::FunctionSpec;
 use crate::{Function, FunctionIdent};
 use libeir_diagnostics::SourceSpan;
 use libeir_intern::{Ident, Symbol};
@@ -34,15 +36,17 @@ pub struct Module {
     span: SourceSpan,
     functions: PrimaryMap<FunctionIndex, FunctionDefinition>,
     name_map: BTreeMap<(Symbol, usize), FunctionIndex>,
+
+    // Module level metadata
+    exports: BTreeSet<(Symbol, usize)>,
+    on_load: Option<(Symbol, usize)>,
+    attributes: Vec<(Ident, Const)>,
+    specs: BTreeMap<(Symbol, usize), FunctionSpec>,
+    constant_container: ConstantContainer,
 }
 impl Module {
     pub fn new(name: Ident) -> Self {
-        Self {
-            name,
-            span: SourceSpan::UNKNOWN,
-            functions: PrimaryMap::new(),
-            name_map: BTreeMap::new(),
-        }
+        Self::new_with_span(name, SourceSpan::UNKNOWN)
     }
 
     pub fn new_with_span(name: Ident, span: SourceSpan) -> Self {
@@ -51,6 +55,11 @@ impl Module {
             span,
             functions: PrimaryMap::new(),
             name_map: BTreeMap::new(),
+            exports: BTreeSet::new(),
+            on_load: None,
+            attributes: Vec::new(),
+            specs: BTreeMap::new(),
+            constant_container: ConstantContainer::new(),
         }
     }
 
@@ -106,6 +115,59 @@ impl Module {
     pub fn index_iter(&self) -> impl Iterator<Item = FunctionIndex> {
         self.functions.keys()
     }
+
+    /// Marks the function with the given name and arity as exported.
+    /// The function is not required to be defined in the module.
+    pub fn add_export(&mut self, name: Symbol, arity: usize) {
+        self.exports.insert((name, arity));
+    }
+    pub fn is_exported(&self, name: Symbol, arity: usize) -> bool {
+        self.exports.contains(&(name, arity))
+    }
+    /// Iterates the exports of the module, ordered by name and arity.
+    pub fn export_iter(&self) -> impl Iterator<Item = (Symbol, usize)> + '_ {
+        self.exports.iter().cloned()
+    }
+
+    pub fn set_on_load(&mut self, on_load: Option<(Symbol, usize)>) {
+        self.on_load = on_load;
+    }
+    pub fn on_load(&self) -> Option<(Symbol, usize)> {
+        self.on_load
+    }
+
+    /// Constant container for module level constants, attribute values
+    /// are stored here.
+    pub fn cons(&self) -> &ConstantContainer {
+        &self.constant_container
+    }
+    pub fn cons_mut(&mut self) -> &mut ConstantContainer {
+        &mut self.constant_container
+    }
+
+    /// Adds a module attribute. The value must be a constant in the
+    /// module constant container. Attributes keep their insertion
+    /// order, and the same name may occur multiple times.
+    pub fn add_attribute(&mut self, name: Ident, value: Const) {
+        self.attributes.push((name, value));
+    }
+    pub fn attributes(&self) -> &[(Ident, Const)] {
+        &self.attributes
+    }
+
+    /// Sets the type specification of the function with the given
+    /// name and arity, replacing any previous one. The function is
+    /// not required to be defined in the module.
+    pub fn set_spec(&mut self, name: Symbol, arity: usize, spec: FunctionSpec) {
+        self.specs.insert((name, arity), spec);
+    }
+    pub fn spec(&self, name: Symbol, arity: usize) -> Option<&FunctionSpec> {
+        self.specs.get(&(name, arity))
+    }
+    /// Iterates the specs of the module, ordered by name and arity.
+    pub fn spec_iter(&self) -> impl Iterator<Item = ((Symbol, usize), &FunctionSpec)> + '_ {
+        self.specs.iter().map(|(key, spec)| (*key, spec))
+    }
 }
 impl Clone for Module {
     fn clone(&self) -> Self {
@@ -126,6 +188,11 @@ impl Clone for Module {
             span: self.span,
             functions,
             name_map,
+            exports: self.exports.clone(),
+            on_load: self.on_load,
+            attributes: self.attributes.clone(),
+            specs: self.specs.clone(),
+            constant_container: self.constant_container.clone(),
         }
     }
 }
@@ -151,3 +218,18 @@ impl Index<&FunctionIdent> for Module {
         &self.functions[idx]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Module;
+    use crate::Function;
+
+    /// Projects are compiled by running the pass pipeline on several
+    /// modules in parallel, moving each module to a worker thread.
+    #[test]
+    fn module_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Module>();
+        assert_send::<Function>();
+    }
+}
@@ -42,11 +42,18 @@
 use std::any::TypeId;
 use std::default::Default;
 
+use libeir_diagnostics::SourceSpan;
+use libeir_intern::Symbol;
 use meta_table::{impl_meta_entry, MetaEntry};
+use pretty::{DocAllocator, RefDoc};
 
 use super::{DynOp, Op, OpBuild};
 use crate::dialect::Dialect;
-use crate::traits::OpBranches;
+use crate::text::ast::DynToken;
+use crate::text::parse_dyn::{binary_specifier, val, ParseCtx};
+use crate::text::printer::binary_specifier_to_doc;
+use crate::text::LowerContext;
+use crate::traits::{FormatOpCtx, OpBranches, OpParser, OpPrinter};
 use crate::{BinaryEntrySpecifier, Block, Function, FunctionBuilder, Value};
 
 pub struct BinaryConstructToken(());
@@ -70,6 +77,48 @@ impl OpBranches for BinaryConstructStart {
     }
 }
 
+/// `@binary_construct_start => %cont;`
+impl OpPrinter for BinaryConstructStart {
+    fn to_doc<'doc>(&self, ctx: &mut dyn FormatOpCtx<'doc>, block: Block) -> RefDoc<'doc, ()> {
+        let reads = ctx.function().block_reads(block).to_vec();
+        let arena = ctx.arena();
+        arena
+            .nil()
+            .append(arena.text("@binary_construct_start"))
+            .append(arena.space())
+            .append(arena.text("=>"))
+            .append(arena.space())
+            .append(ctx.value_use_to_doc(reads[0].into()))
+            .into_doc()
+    }
+}
+
+struct BinaryConstructStartParser;
+impl OpParser for BinaryConstructStartParser {
+    fn parse(
+        &self,
+        context: &mut LowerContext,
+        block: Block,
+        tokens: &[DynToken],
+    ) -> Result<(), ()> {
+        let mut ctx = ParseCtx::new(tokens, SourceSpan::UNKNOWN);
+
+        parser_fail!(context, ctx.tok_fat_arrow());
+        let cont = parser_fail!(context, val(&mut ctx));
+        parser_fail!(context, ctx.eof());
+
+        let cont = context.value(&cont)?;
+
+        context.builder().op_intrinsic(
+            block,
+            BinaryConstructStart,
+            &[cont],
+            BinaryConstructToken(()),
+        );
+        Ok(())
+    }
+}
+
 impl BinaryConstructStart {
     pub fn build(builder: &mut FunctionBuilder, block: Block) -> Block {
         let target = builder.block_insert();
@@ -136,6 +185,85 @@ impl OpBranches for BinaryConstructPush {
     }
 }
 
+/// `@binary_construct_push %bin_ref %value <specifier> [size %size] => %ok except %fail;`
+impl OpPrinter for BinaryConstructPush {
+    fn to_doc<'doc>(&self, ctx: &mut dyn FormatOpCtx<'doc>, block: Block) -> RefDoc<'doc, ()> {
+        let reads = ctx.function().block_reads(block).to_vec();
+        let arena = ctx.arena();
+
+        let mut doc = arena
+            .nil()
+            .append(arena.text("@binary_construct_push"))
+            .append(arena.space())
+            .append(ctx.value_use_to_doc(reads[2].into()))
+            .append(arena.space())
+            .append(ctx.value_use_to_doc(reads[3].into()))
+            .append(arena.space())
+            .append(binary_specifier_to_doc(arena, &self.specifier));
+        if let Some(size) = reads.get(4) {
+            doc = doc
+                .append(arena.space())
+                .append(arena.text("size"))
+                .append(arena.space())
+                .append(ctx.value_use_to_doc((*size).into()));
+        }
+        doc.append(arena.space())
+            .append(arena.text("=>"))
+            .append(arena.space())
+            .append(ctx.value_use_to_doc(reads[0].into()))
+            .append(arena.space())
+            .append(arena.text("except"))
+            .append(arena.space())
+            .append(ctx.value_use_to_doc(reads[1].into()))
+            .into_doc()
+    }
+}
+
+struct BinaryConstructPushParser;
+impl OpParser for BinaryConstructPushParser {
+    fn parse(
+        &self,
+        context: &mut LowerContext,
+        block: Block,
+        tokens: &[DynToken],
+    ) -> Result<(), ()> {
+        let mut ctx = ParseCtx::new(tokens, SourceSpan::UNKNOWN);
+
+        let bin_ref = parser_fail!(context, val(&mut ctx));
+        let value = parser_fail!(context, val(&mut ctx));
+        let specifier = parser_fail!(context, binary_specifier(&mut ctx));
+        let size = if let Some(DynToken::Size(_)) = ctx.peek() {
+            parser_fail!(context, ctx.tok_size());
+            Some(parser_fail!(context, val(&mut ctx)))
+        } else {
+            None
+        };
+        parser_fail!(context, ctx.tok_fat_arrow());
+        let ok = parser_fail!(context, val(&mut ctx));
+        parser_fail!(context, ctx.tok_except());
+        let fail = parser_fail!(context, val(&mut ctx));
+        parser_fail!(context, ctx.eof());
+
+        let mut reads = vec![
+            context.value(&ok)?,
+            context.value(&fail)?,
+            context.value(&bin_ref)?,
+            context.value(&value)?,
+        ];
+        if let Some(size) = size {
+            reads.push(context.value(&size)?);
+        }
+
+        context.builder().op_intrinsic(
+            block,
+            BinaryConstructPush { specifier },
+            &reads,
+            BinaryConstructToken(()),
+        );
+        Ok(())
+    }
+}
+
 impl BinaryConstructPush {
     pub fn build(
         builder: &mut FunctionBuilder,
@@ -219,6 +347,52 @@ impl OpBranches for BinaryConstructFinish {
     }
 }
 
+/// `@binary_construct_finish %bin_ref => %cont;`
+impl OpPrinter for BinaryConstructFinish {
+    fn to_doc<'doc>(&self, ctx: &mut dyn FormatOpCtx<'doc>, block: Block) -> RefDoc<'doc, ()> {
+        let reads = ctx.function().block_reads(block).to_vec();
+        let arena = ctx.arena();
+        arena
+            .nil()
+            .append(arena.text("@binary_construct_finish"))
+            .append(arena.space())
+            .append(ctx.value_use_to_doc(reads[1].into()))
+            .append(arena.space())
+            .append(arena.text("=>"))
+            .append(arena.space())
+            .append(ctx.value_use_to_doc(reads[0].into()))
+            .into_doc()
+    }
+}
+
+struct BinaryConstructFinishParser;
+impl OpParser for BinaryConstructFinishParser {
+    fn parse(
+        &self,
+        context: &mut LowerContext,
+        block: Block,
+        tokens: &[DynToken],
+    ) -> Result<(), ()> {
+        let mut ctx = ParseCtx::new(tokens, SourceSpan::UNKNOWN);
+
+        let bin_ref = parser_fail!(context, val(&mut ctx));
+        parser_fail!(context, ctx.tok_fat_arrow());
+        let cont = parser_fail!(context, val(&mut ctx));
+        parser_fail!(context, ctx.eof());
+
+        let bin_ref = context.value(&bin_ref)?;
+        let cont = context.value(&cont)?;
+
+        context.builder().op_intrinsic(
+            block,
+            BinaryConstructFinish,
+            &[cont, bin_ref],
+            BinaryConstructToken(()),
+        );
+        Ok(())
+    }
+}
+
 impl BinaryConstructFinish {
     pub fn build(builder: &mut FunctionBuilder, block: Block, bin_ref: Value) -> Block {
         let target = builder.block_insert();
@@ -249,10 +423,25 @@ impl OpBuild for BinaryConstructFinish {
 pub fn register(dialect: &mut Dialect) {
     dialect.register_op::<BinaryConstructStart>();
     dialect.register_op_branches_impl(&BinaryConstructStart);
+    dialect.register_op_printer_impl(&BinaryConstructStart);
+    dialect.register_op_parser(
+        Symbol::intern("binary_construct_start"),
+        Box::new(BinaryConstructStartParser),
+    );
 
     dialect.register_op::<BinaryConstructPush>();
     dialect.register_op_branches_impl(&BinaryConstructPush::default());
+    dialect.register_op_printer_impl(&BinaryConstructPush::default());
+    dialect.register_op_parser(
+        Symbol::intern("binary_construct_push"),
+        Box::new(BinaryConstructPushParser),
+    );
 
     dialect.register_op::<BinaryConstructFinish>();
     dialect.register_op_branches_impl(&BinaryConstructFinish);
+    dialect.register_op_printer_impl(&BinaryConstructFinish);
+    dialect.register_op_parser(
+        Symbol::intern("binary_construct_finish"),
+        Box::new(BinaryConstructFinishParser),
+    );
 }
@@ -1,17 +1,21 @@
 use std::any::TypeId;
+use std::collections::{HashMap, HashSet};
 
+use cranelift_entity::EntityRef;
 use libeir_diagnostics::SourceSpan;
 use libeir_intern::Symbol;
 use meta_table::{impl_meta_entry, MetaEntry};
-use pretty::{DocAllocator, RefDoc};
+use pretty::{DocAllocator, DocBuilder, RefDoc};
 
 use super::{DynOp, Op, OpBuild};
-use crate::pattern::{PatternClause, PatternContainer};
+use crate::constant::{AtomicTerm, ConstKind, ConstantContainer};
+use crate::pattern::{PatternClause, PatternContainer, PatternNode, PatternNodeKind, PatternValue};
 use crate::text::ast::DynToken;
 use crate::text::parse_dyn::{DynParserError, ParseCtx};
+use crate::text::printer::{binary_specifier_to_doc, constant_to_doc};
 use crate::text::LowerContext;
 use crate::traits::{FormatOpCtx, OpBranches, OpParser, OpPrinter};
-use crate::{Block, Dialect, Function, FunctionBuilder, Value};
+use crate::{Block, Const, Dialect, Function, FunctionBuilder, Value};
 
 pub struct CaseToken(());
 
@@ -54,6 +58,17 @@ struct Inner {
     clauses: Vec<PatternClause>,
 }
 
+impl Inner {
+    fn eq_with(&self, other: &Inner, const_eq: &dyn Fn(Const, Const) -> bool) -> bool {
+        self.clauses.len() == other.clauses.len()
+            && self
+                .clauses
+                .iter()
+                .zip(other.clauses.iter())
+                .all(|(l, r)| self.container.clause_eq(*l, &other.container, *r, const_eq))
+    }
+}
+
 impl Op for Case {
     fn name(&self) -> &str {
         "case"
@@ -68,8 +83,17 @@ impl Op for Case {
         self
     }
     fn op_eq(&self, other: &dyn Op) -> bool {
-        if let Some(_other_i) = other.downcast_ref::<Self>() {
-            unimplemented!()
+        if let Some(other_i) = other.downcast_ref::<Self>() {
+            self.inner.eq_with(&other_i.inner, &|l, r| l == r)
+        } else {
+            false
+        }
+    }
+    fn op_eq_in(&self, fun: &Function, other: &dyn Op, other_fun: &Function) -> bool {
+        if let Some(other_i) = other.downcast_ref::<Self>() {
+            self.inner.eq_with(&other_i.inner, &|l, r| {
+                fun.cons().eq_other(l, other_fun.cons(), r)
+            })
         } else {
             false
         }
@@ -93,24 +117,225 @@ impl OpBranches for Case {
     }
 }
 
+/// ```ignore
+/// case %match_on {
+///   <n3 @ {a'ok', _}, value %v> guard %guard => %body(n3);
+///   _ => %no_match;
+/// }
+/// ```
+///
+/// Nodes that are bound or referenced by other nodes are named by their
+/// index. External values are printed inline with the `value` prefix.
+/// The traversal order matches the one used when lowering the text
+/// format, which keeps clause values in the same order as the reads.
 impl OpPrinter for Case {
-    fn to_doc<'doc>(&self, ctx: &mut dyn FormatOpCtx<'doc>, _block: Block) -> RefDoc<'doc, ()> {
+    fn to_doc<'doc>(&self, ctx: &mut dyn FormatOpCtx<'doc>, block: Block) -> RefDoc<'doc, ()> {
+        let reads = ctx.function().block_reads(block).to_vec();
+        let read_docs: Vec<_> = reads
+            .iter()
+            .map(|v| ctx.value_use_to_doc((*v).into()))
+            .collect();
+
         let arena = ctx.arena();
+        let cons = ctx.function().cons();
         let inner = &*self.inner;
+        let pat = &inner.container;
 
-        //let block = arena.concat(inner.clauses.iter().map(|clause| {
-        //    let root_nodes = inner.container.clause_root_nodes(clause);
-        //}));
+        let num_clauses = inner.clauses.len();
+        let mut value_offset = 2 + num_clauses * 2;
+
+        let mut entries = Vec::with_capacity(num_clauses + 1);
+        for (n, clause) in inner.clauses.iter().enumerate() {
+            let mut named = HashSet::new();
+            named.extend(pat.clause_binds(*clause).iter().cloned());
+
+            let mut values = HashMap::new();
+            for (val, node) in pat.clause_node_binds_iter(*clause) {
+                named.insert(node);
+                values.insert(val, arena.as_string(node_name(node)).into_doc());
+            }
+            for val in pat.clause_values(*clause) {
+                let doc = arena
+                    .text("value")
+                    .append(arena.space())
+                    .append(read_docs[value_offset].clone())
+                    .into_doc();
+                values.insert(*val, doc);
+                value_offset += 1;
+            }
+
+            let printer = PatternPrinter {
+                arena,
+                cons,
+                pat,
+                named: &named,
+                values: &values,
+            };
+
+            let roots = pat
+                .clause_root_nodes(*clause)
+                .iter()
+                .map(|node| printer.node(*node));
+            let binds = pat
+                .clause_binds(*clause)
+                .iter()
+                .map(|node| arena.as_string(node_name(*node)));
+
+            entries.push(
+                arena
+                    .intersperse(roots, arena.text(",").append(arena.space()))
+                    .enclose("<", ">")
+                    .append(arena.space())
+                    .append(arena.text("guard"))
+                    .append(arena.space())
+                    .append(read_docs[1 + n * 2].clone())
+                    .append(arena.space())
+                    .append(arena.text("=>"))
+                    .append(arena.space())
+                    .append(read_docs[2 + n * 2].clone())
+                    .append(
+                        arena
+                            .intersperse(binds, arena.text(",").append(arena.space()))
+                            .parens(),
+                    )
+                    .append(arena.text(";"))
+                    .indent(2),
+            );
+        }
+        entries.push(
+            arena
+                .text("_")
+                .append(arena.space())
+                .append(arena.text("=>"))
+                .append(arena.space())
+                .append(read_docs[0].clone())
+                .append(arena.text(";"))
+                .indent(2),
+        );
 
         arena
             .nil()
             .append(arena.text("case"))
             .append(arena.space())
-            //.append(block.nest(1).braces())
+            .append(read_docs[1 + num_clauses * 2].clone())
+            .append(arena.space())
+            .append(
+                arena
+                    .hardline()
+                    .append(arena.intersperse(entries, arena.hardline()))
+                    .append(arena.hardline())
+                    .braces(),
+            )
             .into_doc()
     }
 }
 
+fn node_name(node: PatternNode) -> String {
+    format!("n{}", node.index())
+}
+
+struct PatternPrinter<'a, 'doc> {
+    arena: &'doc pretty::Arena<'doc>,
+    cons: &'a ConstantContainer,
+    pat: &'a PatternContainer,
+    named: &'a HashSet<PatternNode>,
+    values: &'a HashMap<PatternValue, RefDoc<'doc, ()>>,
+}
+
+impl<'a, 'doc> PatternPrinter<'a, 'doc> {
+    fn node(&self, node: PatternNode) -> DocBuilder<'doc, pretty::Arena<'doc>, ()> {
+        let arena = self.arena;
+        let doc = self.node_kind(node);
+        if self.named.contains(&node) {
+            arena
+                .as_string(node_name(node))
+                .append(arena.space())
+                .append(arena.text("@"))
+                .append(arena.space())
+                .append(doc)
+        } else {
+            doc
+        }
+    }
+
+    fn value(&self, value: PatternValue) -> DocBuilder<'doc, pretty::Arena<'doc>, ()> {
+        self.arena.nil().append(self.values[&value].clone())
+    }
+
+    fn node_kind(&self, node: PatternNode) -> DocBuilder<'doc, pretty::Arena<'doc>, ()> {
+        let arena = self.arena;
+        match self.pat.node_kind(node) {
+            PatternNodeKind::Wildcard => arena.text("_"),
+            PatternNodeKind::Const(c) => {
+                let doc = constant_to_doc(arena, self.cons, *c);
+                match self.cons.const_kind(*c) {
+                    ConstKind::Atomic(AtomicTerm::Atom(_))
+                    | ConstKind::Atomic(AtomicTerm::Int(_))
+                    | ConstKind::Atomic(AtomicTerm::Nil) => arena.nil().append(doc),
+                    _ => arena.text("const").append(arena.space()).append(doc),
+                }
+            }
+            PatternNodeKind::Value(v) => self.value(*v),
+            PatternNodeKind::Tuple(elems) => {
+                let elems = elems
+                    .as_slice(&self.pat.node_pool)
+                    .iter()
+                    .map(|elem| self.node(*elem));
+                arena
+                    .intersperse(elems, arena.text(",").append(arena.space()))
+                    .braces()
+            }
+            PatternNodeKind::List { head, tail } => arena
+                .text("[")
+                .append(self.node(*head))
+                .append(arena.space())
+                .append(arena.text("|"))
+                .append(arena.space())
+                .append(self.node(*tail))
+                .append(arena.text("]")),
+            PatternNodeKind::Map { keys, values } => {
+                let keys = keys.as_slice(&self.pat.value_pool);
+                let values = values.as_slice(&self.pat.node_pool);
+                let entries = keys.iter().zip(values.iter()).map(|(k, v)| {
+                    self.value(*k)
+                        .append(arena.space())
+                        .append(arena.text("=>"))
+                        .append(arena.space())
+                        .append(self.node(*v))
+                });
+                arena
+                    .intersperse(entries, arena.text(",").append(arena.space()))
+                    .enclose("%{", "}")
+            }
+            PatternNodeKind::Binary {
+                specifier,
+                value,
+                size,
+                remaining,
+            } => {
+                let mut doc = arena
+                    .nil()
+                    .append(binary_specifier_to_doc(arena, specifier));
+                if let Some(size) = size {
+                    doc = doc
+                        .append(arena.space())
+                        .append(arena.text("size"))
+                        .append(arena.space())
+                        .append(self.value(*size));
+                }
+                doc.append(arena.space()).append(
+                    self.node(*value)
+                        .append(arena.space())
+                        .append(arena.text("|"))
+                        .append(arena.space())
+                        .append(self.node(*remaining))
+                        .braces(),
+                )
+            }
+        }
+    }
+}
+
 impl Case {
     pub fn builder() -> CaseBuilder {
         CaseBuilder::default()
@@ -248,18 +473,6 @@ impl OpBuild for Case {
     type Token = CaseToken;
 }
 
-macro_rules! parser_fail {
-    ($context:expr, $value:expr) => {
-        match $value {
-            Ok(value) => value,
-            Err(error) => {
-                $context.error(error);
-                return Err(());
-            }
-        }
-    };
-}
-
 struct CaseParser;
 impl OpParser for CaseParser {
     fn parse(
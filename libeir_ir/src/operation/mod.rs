@@ -7,6 +7,8 @@ use std::raw::TraitObject;
 use meta_table::MetaEntry;
 use stack_dst::Value;
 
+use crate::Function;
+
 macro_rules! impl_op {
     ($typ:ident, $name:expr) => {
         impl Op for $typ {
@@ -26,6 +28,20 @@ macro_rules! impl_op {
     };
 }
 
+/// Unwraps the result of a dyn op parser function, reporting the error
+/// to the lower context on failure.
+macro_rules! parser_fail {
+    ($context:expr, $value:expr) => {
+        match $value {
+            Ok(value) => value,
+            Err(error) => {
+                $context.error(error);
+                return Err(());
+            }
+        }
+    };
+}
+
 pub mod binary_construct;
 pub mod case;
 pub mod receive;
@@ -46,6 +62,13 @@ pub trait Op: MetaEntry + Send {
         self.type_id() == other.type_id()
     }
 
+    /// Tests for semantic equality between two operations that may live in
+    /// different functions. Operations referencing function scoped entities,
+    /// like constants, need to override this. Defaults to `op_eq`.
+    fn op_eq_in(&self, _fun: &Function, other: &dyn Op, _other_fun: &Function) -> bool {
+        self.op_eq(other)
+    }
+
     fn debug_fmt(&self, formatter: &mut Formatter) -> FmtResult {
         write!(formatter, "Op[{}]", self.name())
     }
@@ -27,11 +27,16 @@
 
 use std::any::TypeId;
 
+use libeir_diagnostics::SourceSpan;
+use libeir_intern::Symbol;
 use meta_table::{impl_meta_entry, MetaEntry};
 
 use super::{DynOp, Op, OpBuild};
 use crate::dialect::Dialect;
-use crate::traits::{FormatOpCtx, OpBranches, OpPrinter};
+use crate::text::ast::DynToken;
+use crate::text::parse_dyn::{val, ParseCtx};
+use crate::text::LowerContext;
+use crate::traits::{FormatOpCtx, OpBranches, OpParser, OpPrinter};
 use crate::{Block, Function, FunctionBuilder, Value};
 use pretty::{DocAllocator, RefDoc};
 
@@ -83,18 +88,49 @@ impl OpBranches for ReceiveStart {
     }
 }
 
+/// `@receive_start %timeout => %cont;`
 impl OpPrinter for ReceiveStart {
     fn to_doc<'doc>(&self, ctx: &mut dyn FormatOpCtx<'doc>, block: Block) -> RefDoc<'doc, ()> {
+        let reads = ctx.function().block_reads(block).to_vec();
         let arena = ctx.arena();
         arena
             .nil()
-            .append(arena.text("@"))
-            .append(arena.text("receive_start"))
+            .append(arena.text("@receive_start"))
             .append(arena.space())
+            .append(ctx.value_use_to_doc(reads[1].into()))
+            .append(arena.space())
+            .append(arena.text("=>"))
+            .append(arena.space())
+            .append(ctx.value_use_to_doc(reads[0].into()))
             .into_doc()
     }
 }
 
+struct ReceiveStartParser;
+impl OpParser for ReceiveStartParser {
+    fn parse(
+        &self,
+        context: &mut LowerContext,
+        block: Block,
+        tokens: &[DynToken],
+    ) -> Result<(), ()> {
+        let mut ctx = ParseCtx::new(tokens, SourceSpan::UNKNOWN);
+
+        let timeout = parser_fail!(context, val(&mut ctx));
+        parser_fail!(context, ctx.tok_fat_arrow());
+        let cont = parser_fail!(context, val(&mut ctx));
+        parser_fail!(context, ctx.eof());
+
+        let timeout = context.value(&timeout)?;
+        let cont = context.value(&cont)?;
+
+        context
+            .builder()
+            .op_intrinsic(block, ReceiveStart, &[cont, timeout], ReceiveToken(()));
+        Ok(())
+    }
+}
+
 impl ReceiveStart {
     pub fn build(builder: &mut FunctionBuilder, block: Block, timeout: Value) -> Block {
         let target = builder.block_insert();
@@ -172,6 +208,59 @@ impl OpBranches for ReceiveWait {
     }
 }
 
+/// `@receive_wait %recv_ref => %check_message timeout %timeout;`
+impl OpPrinter for ReceiveWait {
+    fn to_doc<'doc>(&self, ctx: &mut dyn FormatOpCtx<'doc>, block: Block) -> RefDoc<'doc, ()> {
+        let reads = ctx.function().block_reads(block).to_vec();
+        let arena = ctx.arena();
+        arena
+            .nil()
+            .append(arena.text("@receive_wait"))
+            .append(arena.space())
+            .append(ctx.value_use_to_doc(reads[2].into()))
+            .append(arena.space())
+            .append(arena.text("=>"))
+            .append(arena.space())
+            .append(ctx.value_use_to_doc(reads[1].into()))
+            .append(arena.space())
+            .append(arena.text("timeout"))
+            .append(arena.space())
+            .append(ctx.value_use_to_doc(reads[0].into()))
+            .into_doc()
+    }
+}
+
+struct ReceiveWaitParser;
+impl OpParser for ReceiveWaitParser {
+    fn parse(
+        &self,
+        context: &mut LowerContext,
+        block: Block,
+        tokens: &[DynToken],
+    ) -> Result<(), ()> {
+        let mut ctx = ParseCtx::new(tokens, SourceSpan::UNKNOWN);
+
+        let recv_ref = parser_fail!(context, val(&mut ctx));
+        parser_fail!(context, ctx.tok_fat_arrow());
+        let check_message = parser_fail!(context, val(&mut ctx));
+        parser_fail!(context, ctx.tok_ident_named("timeout"));
+        let timeout = parser_fail!(context, val(&mut ctx));
+        parser_fail!(context, ctx.eof());
+
+        let recv_ref = context.value(&recv_ref)?;
+        let check_message = context.value(&check_message)?;
+        let timeout = context.value(&timeout)?;
+
+        context.builder().op_intrinsic(
+            block,
+            ReceiveWait,
+            &[timeout, check_message, recv_ref],
+            ReceiveToken(()),
+        );
+        Ok(())
+    }
+}
+
 impl ReceiveWait {
     pub fn build(builder: &mut FunctionBuilder, block: Block, recv_ref: Value) -> (Block, Block) {
         let timeout = builder.block_insert();
@@ -255,6 +344,67 @@ impl OpBranches for ReceiveDone {
     }
 }
 
+/// `@receive_done %recv_ref (%value, ..) => %next;`
+impl OpPrinter for ReceiveDone {
+    fn to_doc<'doc>(&self, ctx: &mut dyn FormatOpCtx<'doc>, block: Block) -> RefDoc<'doc, ()> {
+        let reads = ctx.function().block_reads(block).to_vec();
+        let arena = ctx.arena();
+        let values: Vec<_> = reads[2..]
+            .iter()
+            .map(|v| ctx.value_use_to_doc((*v).into()))
+            .collect();
+        arena
+            .nil()
+            .append(arena.text("@receive_done"))
+            .append(arena.space())
+            .append(ctx.value_use_to_doc(reads[1].into()))
+            .append(arena.space())
+            .append(
+                arena
+                    .intersperse(values, arena.text(",").append(arena.space()))
+                    .parens(),
+            )
+            .append(arena.space())
+            .append(arena.text("=>"))
+            .append(arena.space())
+            .append(ctx.value_use_to_doc(reads[0].into()))
+            .into_doc()
+    }
+}
+
+struct ReceiveDoneParser;
+impl OpParser for ReceiveDoneParser {
+    fn parse(
+        &self,
+        context: &mut LowerContext,
+        block: Block,
+        tokens: &[DynToken],
+    ) -> Result<(), ()> {
+        let mut ctx = ParseCtx::new(tokens, SourceSpan::UNKNOWN);
+
+        let recv_ref = parser_fail!(context, val(&mut ctx));
+        let (inner, span) = parser_fail!(context, ctx.tok_parens());
+        let mut ictx = ParseCtx::new(inner, span);
+        let values = parser_fail!(context, ictx.comma(val));
+        parser_fail!(context, ictx.eof());
+        parser_fail!(context, ctx.tok_fat_arrow());
+        let next = parser_fail!(context, val(&mut ctx));
+        parser_fail!(context, ctx.eof());
+
+        let mut reads = Vec::with_capacity(values.len() + 2);
+        reads.push(context.value(&next)?);
+        reads.push(context.value(&recv_ref)?);
+        for value in values.iter() {
+            reads.push(context.value(value)?);
+        }
+
+        context
+            .builder()
+            .op_intrinsic(block, ReceiveDone, &reads, ReceiveToken(()));
+        Ok(())
+    }
+}
+
 impl ReceiveDone {
     pub fn build(
         builder: &mut FunctionBuilder,
@@ -296,13 +446,19 @@ impl OpBuild for ReceiveDone {
 pub fn register(dialect: &mut Dialect) {
     dialect.register_op::<ReceiveStart>();
     dialect.register_op_branches_impl(&ReceiveStart);
-    //dialect.register_op_printer_impl(&ReceiveStart);
+    dialect.register_op_printer_impl(&ReceiveStart);
+    dialect.register_op_parser(
+        Symbol::intern("receive_start"),
+        Box::new(ReceiveStartParser),
+    );
 
     dialect.register_op::<ReceiveWait>();
     dialect.register_op_branches_impl(&ReceiveWait);
-    //dialect.register_op_printer_impl(&ReceiveWait);
+    dialect.register_op_printer_impl(&ReceiveWait);
+    dialect.register_op_parser(Symbol::intern("receive_wait"), Box::new(ReceiveWaitParser));
 
     dialect.register_op::<ReceiveDone>();
     dialect.register_op_branches_impl(&ReceiveDone);
-    //dialect.register_op_printer_impl(&ReceiveDone);
+    dialect.register_op_printer_impl(&ReceiveDone);
+    dialect.register_op_parser(Symbol::intern("receive_done"), Box::new(ReceiveDoneParser));
 }
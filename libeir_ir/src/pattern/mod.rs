@@ -391,6 +391,188 @@ impl PatternContainer {
     pub fn node_kind(&self, node: PatternNode) -> &PatternNodeKind {
         self.nodes[node].kind.as_ref().unwrap()
     }
+
+    /// Structurally compares two clauses, which may live in different
+    /// containers. External values are compared by their position in
+    /// the clause value list, constants are compared with `const_eq`.
+    pub fn clause_eq(
+        &self,
+        l: PatternClause,
+        r_cont: &PatternContainer,
+        r: PatternClause,
+        const_eq: &dyn Fn(Const, Const) -> bool,
+    ) -> bool {
+        let mut ctx = ClauseEqCtx {
+            l_cont: self,
+            l_clause: l,
+            r_cont,
+            r_clause: r,
+            const_eq,
+            node_map: HashMap::new(),
+            node_values: Vec::new(),
+        };
+
+        let l_roots = self.clause_root_nodes(l);
+        let r_roots = r_cont.clause_root_nodes(r);
+        if l_roots.len() != r_roots.len() {
+            return false;
+        }
+        if self.clause_values(l).len() != r_cont.clause_values(r).len() {
+            return false;
+        }
+        for (ln, rn) in l_roots.iter().zip(r_roots.iter()) {
+            if !ctx.node_eq(*ln, *rn) {
+                return false;
+            }
+        }
+
+        let l_binds = self.clause_binds(l);
+        let r_binds = r_cont.clause_binds(r);
+        if l_binds.len() != r_binds.len() {
+            return false;
+        }
+        let binds_eq = l_binds
+            .iter()
+            .zip(r_binds.iter())
+            .all(|(ln, rn)| ctx.node_map.get(ln) == Some(rn));
+
+        // Node values may reference nodes that are visited after them,
+        // these are checked once the whole clause is mapped.
+        binds_eq
+            && ctx
+                .node_values
+                .iter()
+                .all(|(ln, rn)| ctx.node_map.get(ln) == Some(rn))
+    }
+}
+
+struct ClauseEqCtx<'a> {
+    l_cont: &'a PatternContainer,
+    l_clause: PatternClause,
+    r_cont: &'a PatternContainer,
+    r_clause: PatternClause,
+    const_eq: &'a dyn Fn(Const, Const) -> bool,
+    node_map: HashMap<PatternNode, PatternNode>,
+    node_values: Vec<(PatternNode, PatternNode)>,
+}
+
+impl<'a> ClauseEqCtx<'a> {
+    fn value_eq(&mut self, l: PatternValue, r: PatternValue) -> bool {
+        let (l_cont, r_cont) = (self.l_cont, self.r_cont);
+
+        let l_pos = l_cont
+            .clause_values(self.l_clause)
+            .iter()
+            .position(|v| *v == l);
+        let r_pos = r_cont
+            .clause_values(self.r_clause)
+            .iter()
+            .position(|v| *v == r);
+
+        match (l_pos, r_pos) {
+            (Some(l_pos), Some(r_pos)) => l_pos == r_pos,
+            (None, None) => {
+                let l_node = l_cont
+                    .clause_node_binds_iter(self.l_clause)
+                    .find(|(v, _)| *v == l)
+                    .map(|(_, n)| n);
+                let r_node = r_cont
+                    .clause_node_binds_iter(self.r_clause)
+                    .find(|(v, _)| *v == r)
+                    .map(|(_, n)| n);
+                match (l_node, r_node) {
+                    (Some(l_node), Some(r_node)) => {
+                        self.node_values.push((l_node, r_node));
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    fn node_eq(&mut self, l: PatternNode, r: PatternNode) -> bool {
+        if let Some(prev) = self.node_map.get(&l) {
+            return *prev == r;
+        }
+        self.node_map.insert(l, r);
+
+        let (l_cont, r_cont) = (self.l_cont, self.r_cont);
+        match (l_cont.node_kind(l), r_cont.node_kind(r)) {
+            (PatternNodeKind::Wildcard, PatternNodeKind::Wildcard) => true,
+            (PatternNodeKind::Const(lc), PatternNodeKind::Const(rc)) => (self.const_eq)(*lc, *rc),
+            (PatternNodeKind::Value(lv), PatternNodeKind::Value(rv)) => self.value_eq(*lv, *rv),
+            (
+                PatternNodeKind::Binary {
+                    specifier: l_spec,
+                    value: l_value,
+                    size: l_size,
+                    remaining: l_rem,
+                },
+                PatternNodeKind::Binary {
+                    specifier: r_spec,
+                    value: r_value,
+                    size: r_size,
+                    remaining: r_rem,
+                },
+            ) => {
+                let size_eq = match (l_size, r_size) {
+                    (Some(ls), Some(rs)) => self.value_eq(*ls, *rs),
+                    (None, None) => true,
+                    _ => false,
+                };
+                l_spec == r_spec
+                    && size_eq
+                    && self.node_eq(*l_value, *r_value)
+                    && self.node_eq(*l_rem, *r_rem)
+            }
+            (PatternNodeKind::Tuple(l_elems), PatternNodeKind::Tuple(r_elems)) => {
+                let l_elems = l_elems.as_slice(&l_cont.node_pool);
+                let r_elems = r_elems.as_slice(&r_cont.node_pool);
+                l_elems.len() == r_elems.len()
+                    && l_elems
+                        .iter()
+                        .zip(r_elems.iter())
+                        .all(|(le, re)| self.node_eq(*le, *re))
+            }
+            (
+                PatternNodeKind::List {
+                    head: l_head,
+                    tail: l_tail,
+                },
+                PatternNodeKind::List {
+                    head: r_head,
+                    tail: r_tail,
+                },
+            ) => self.node_eq(*l_head, *r_head) && self.node_eq(*l_tail, *r_tail),
+            (
+                PatternNodeKind::Map {
+                    keys: l_keys,
+                    values: l_values,
+                },
+                PatternNodeKind::Map {
+                    keys: r_keys,
+                    values: r_values,
+                },
+            ) => {
+                let l_keys = l_keys.as_slice(&l_cont.value_pool);
+                let r_keys = r_keys.as_slice(&r_cont.value_pool);
+                let l_values = l_values.as_slice(&l_cont.node_pool);
+                let r_values = r_values.as_slice(&r_cont.node_pool);
+                l_keys.len() == r_keys.len()
+                    && l_keys
+                        .iter()
+                        .zip(r_keys.iter())
+                        .all(|(lk, rk)| self.value_eq(*lk, *rk))
+                    && l_values
+                        .iter()
+                        .zip(r_values.iter())
+                        .all(|(lv, rv)| self.node_eq(*lv, *rv))
+            }
+            _ => false,
+        }
+    }
 }
 
 fn copy_pattern_node(
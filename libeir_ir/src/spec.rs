@@ -0,0 +1,568 @@
+//! Function type specifications, as declared with `-spec` in the
+//! source. The type expressions are kept close to how they are
+//! written, no resolution of user types or records is done.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use libeir_intern::Symbol;
+
+use crate::constant::{AtomTerm, AtomicTerm, Const, ConstKind, ConstantContainer, IntTerm};
+
+/// The specification of a single function, one clause per
+/// alternative signature.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionSpec {
+    pub clauses: Vec<SpecClause>,
+}
+
+/// A single signature, `(params) -> ret when constraints`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpecClause {
+    pub params: Vec<TypeExpr>,
+    pub ret: TypeExpr,
+    /// Subtype constraints on type variables, `T :: integer()`.
+    pub constraints: Vec<(String, TypeExpr)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TypeExpr {
+    /// Type variable, `T`.
+    Var(String),
+    /// Atom singleton type, `ok`.
+    Atom(String),
+    Integer(i64),
+    /// Integer singleton that does not fit in an `i64`, in decimal.
+    BigInteger(String),
+    Char(char),
+    /// `Name :: Type`
+    Annotated {
+        name: String,
+        ty: Box<TypeExpr>,
+    },
+    /// `A | B | ...`
+    Union(Vec<TypeExpr>),
+    /// `Low..High`
+    Range(Box<TypeExpr>, Box<TypeExpr>),
+    BinaryOp {
+        op: String,
+        lhs: Box<TypeExpr>,
+        rhs: Box<TypeExpr>,
+    },
+    UnaryOp {
+        op: String,
+        operand: Box<TypeExpr>,
+    },
+    /// Builtin or local type, `integer()`, `list(T)`.
+    Local {
+        name: String,
+        params: Vec<TypeExpr>,
+    },
+    /// `module:name(params)`
+    Remote {
+        module: String,
+        name: String,
+        params: Vec<TypeExpr>,
+    },
+    /// `[]`
+    Nil,
+    /// `[T]`
+    List(Box<TypeExpr>),
+    /// `[T, ...]`
+    NonEmptyList(Box<TypeExpr>),
+    /// `#{...}`, the entries are `KeyValuePair`s.
+    Map(Vec<TypeExpr>),
+    Tuple(Vec<TypeExpr>),
+    /// `#name{...}`, the fields are `Field`s.
+    Record {
+        name: String,
+        fields: Vec<TypeExpr>,
+    },
+    /// `<<_:Size, _:_*Unit>>`
+    Binary(Box<TypeExpr>, Box<TypeExpr>),
+    /// `fun()`, or `fun((...) -> Ret)` if the return type is given.
+    AnyFun(Option<Box<TypeExpr>>),
+    /// `fun((Params) -> Ret)`
+    Fun {
+        params: Vec<TypeExpr>,
+        ret: Box<TypeExpr>,
+    },
+    /// `Key => Value` or `Key := Value` in a map type.
+    KeyValuePair(MapFieldKind, Box<TypeExpr>, Box<TypeExpr>),
+    /// `name :: Type` in a record type.
+    Field {
+        name: String,
+        ty: Box<TypeExpr>,
+    },
+}
+
+/// Whether a field of a map type is optional, `=>`, or mandatory, `:=`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MapFieldKind {
+    Assoc,
+    Exact,
+}
+
+fn write_list(f: &mut fmt::Formatter, items: &[TypeExpr]) -> fmt::Result {
+    for (idx, item) in items.iter().enumerate() {
+        if idx != 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for TypeExpr {
+    /// Formats the type in Erlang type syntax.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TypeExpr::Var(name) => write!(f, "{}", name),
+            TypeExpr::Atom(name) => write!(f, "'{}'", name),
+            TypeExpr::Integer(int) => write!(f, "{}", int),
+            TypeExpr::BigInteger(int) => write!(f, "{}", int),
+            TypeExpr::Char(c) => write!(f, "{}", *c as u32),
+            TypeExpr::Annotated { name, ty } => write!(f, "{} :: {}", name, ty),
+            TypeExpr::Union(types) => {
+                for (idx, ty) in types.iter().enumerate() {
+                    if idx != 0 {
+                        write!(f, " | ")?;
+                    }
+                    write!(f, "{}", ty)?;
+                }
+                Ok(())
+            }
+            TypeExpr::Range(low, high) => write!(f, "{}..{}", low, high),
+            TypeExpr::BinaryOp { op, lhs, rhs } => write!(f, "({} {} {})", lhs, op, rhs),
+            TypeExpr::UnaryOp { op, operand } => write!(f, "({} {})", op, operand),
+            TypeExpr::Local { name, params } => {
+                write!(f, "{}(", name)?;
+                write_list(f, params)?;
+                write!(f, ")")
+            }
+            TypeExpr::Remote {
+                module,
+                name,
+                params,
+            } => {
+                write!(f, "{}:{}(", module, name)?;
+                write_list(f, params)?;
+                write!(f, ")")
+            }
+            TypeExpr::Nil => write!(f, "[]"),
+            TypeExpr::List(ty) => write!(f, "[{}]", ty),
+            TypeExpr::NonEmptyList(ty) => write!(f, "[{}, ...]", ty),
+            TypeExpr::Map(entries) => {
+                write!(f, "#{{")?;
+                write_list(f, entries)?;
+                write!(f, "}}")
+            }
+            TypeExpr::Tuple(elems) => {
+                write!(f, "{{")?;
+                write_list(f, elems)?;
+                write!(f, "}}")
+            }
+            TypeExpr::Record { name, fields } => {
+                write!(f, "#{}{{", name)?;
+                write_list(f, fields)?;
+                write!(f, "}}")
+            }
+            TypeExpr::Binary(size, unit) => write!(f, "<<_:{}, _:_*{}>>", size, unit),
+            TypeExpr::AnyFun(None) => write!(f, "fun()"),
+            TypeExpr::AnyFun(Some(ret)) => write!(f, "fun((...) -> {})", ret),
+            TypeExpr::Fun { params, ret } => {
+                write!(f, "fun((")?;
+                write_list(f, params)?;
+                write!(f, ") -> {})", ret)
+            }
+            TypeExpr::KeyValuePair(MapFieldKind::Assoc, key, value) => {
+                write!(f, "{} => {}", key, value)
+            }
+            TypeExpr::KeyValuePair(MapFieldKind::Exact, key, value) => {
+                write!(f, "{} := {}", key, value)
+            }
+            TypeExpr::Field { name, ty } => write!(f, "{} :: {}", name, ty),
+        }
+    }
+}
+
+impl fmt::Display for SpecClause {
+    /// Formats the clause as it would follow the function name in a
+    /// `-spec`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(")?;
+        write_list(f, &self.params)?;
+        write!(f, ") -> {}", self.ret)?;
+        for (idx, (var, ty)) in self.constraints.iter().enumerate() {
+            if idx == 0 {
+                write!(f, " when ")?;
+            } else {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} :: {}", var, ty)?;
+        }
+        Ok(())
+    }
+}
+
+// Specs are written to the text format as constant terms. A spec is a
+// list of clauses, `{Params, Ret, Constraints}`, and every type is a
+// tuple tagged with the kind of the type, except `[]` which is the atom
+// `nil`.
+
+fn atom(c: &mut ConstantContainer, name: &str) -> Const {
+    c.from(Symbol::intern(name))
+}
+
+fn tuple(c: &mut ConstantContainer, elements: &[Const]) -> Const {
+    let mut builder = c.tuple_builder();
+    for element in elements.iter() {
+        builder.push(*element, c);
+    }
+    builder.finish(c)
+}
+
+fn list(c: &mut ConstantContainer, elements: &[Const]) -> Const {
+    let mut acc = c.nil();
+    for element in elements.iter().rev() {
+        acc = c.list_cell(*element, acc);
+    }
+    acc
+}
+
+fn types_to_const(c: &mut ConstantContainer, types: &[TypeExpr]) -> Const {
+    let elements: Vec<_> = types.iter().map(|ty| ty.to_const(c)).collect();
+    list(c, &elements)
+}
+
+fn const_atom(c: &ConstantContainer, term: Const) -> Option<String> {
+    match c.const_kind(term) {
+        ConstKind::Atomic(AtomicTerm::Atom(AtomTerm(name))) => {
+            Some(name.as_str().get().to_string())
+        }
+        _ => None,
+    }
+}
+
+fn const_int(c: &ConstantContainer, term: Const) -> Option<i64> {
+    match c.const_kind(term) {
+        ConstKind::Atomic(AtomicTerm::Int(IntTerm(int))) => Some(*int),
+        _ => None,
+    }
+}
+
+fn const_tuple(c: &ConstantContainer, term: Const) -> Option<&[Const]> {
+    match c.const_kind(term) {
+        ConstKind::Tuple { entries } => Some(entries.as_slice(&c.const_pool)),
+        _ => None,
+    }
+}
+
+fn const_list(c: &ConstantContainer, mut term: Const) -> Option<Vec<Const>> {
+    let mut elements = Vec::new();
+    loop {
+        match c.const_kind(term) {
+            ConstKind::ListCell { head, tail } => {
+                elements.push(*head);
+                term = *tail;
+            }
+            ConstKind::Atomic(AtomicTerm::Nil) => return Some(elements),
+            _ => return None,
+        }
+    }
+}
+
+fn types_from_const(c: &ConstantContainer, term: Const) -> Option<Vec<TypeExpr>> {
+    const_list(c, term)?
+        .iter()
+        .map(|ty| TypeExpr::from_const(c, *ty))
+        .collect()
+}
+
+impl FunctionSpec {
+    /// Encodes the spec as a constant term in `c`, the form it has in
+    /// the text format.
+    pub fn to_const(&self, c: &mut ConstantContainer) -> Const {
+        let clauses: Vec<_> = self
+            .clauses
+            .iter()
+            .map(|clause| clause.to_const(c))
+            .collect();
+        list(c, &clauses)
+    }
+
+    /// Decodes a spec encoded by `to_const`.
+    pub fn from_const(c: &ConstantContainer, term: Const) -> Option<FunctionSpec> {
+        let clauses = const_list(c, term)?
+            .iter()
+            .map(|clause| SpecClause::from_const(c, *clause))
+            .collect::<Option<_>>()?;
+        Some(FunctionSpec { clauses })
+    }
+}
+
+impl SpecClause {
+    fn to_const(&self, c: &mut ConstantContainer) -> Const {
+        let params = types_to_const(c, &self.params);
+        let ret = self.ret.to_const(c);
+        let constraints: Vec<_> = self
+            .constraints
+            .iter()
+            .map(|(var, ty)| {
+                let elements = [atom(c, var), ty.to_const(c)];
+                tuple(c, &elements)
+            })
+            .collect();
+        let constraints = list(c, &constraints);
+        tuple(c, &[params, ret, constraints])
+    }
+
+    fn from_const(c: &ConstantContainer, term: Const) -> Option<SpecClause> {
+        let elements = const_tuple(c, term)?;
+        if elements.len() != 3 {
+            return None;
+        }
+        let constraints = const_list(c, elements[2])?
+            .iter()
+            .map(|constraint| match const_tuple(c, *constraint)? {
+                [var, ty] => Some((const_atom(c, *var)?, TypeExpr::from_const(c, *ty)?)),
+                _ => None,
+            })
+            .collect::<Option<_>>()?;
+        Some(SpecClause {
+            params: types_from_const(c, elements[0])?,
+            ret: TypeExpr::from_const(c, elements[1])?,
+            constraints,
+        })
+    }
+}
+
+impl TypeExpr {
+    fn to_const(&self, c: &mut ConstantContainer) -> Const {
+        let elements = match self {
+            TypeExpr::Nil => return atom(c, "nil"),
+            TypeExpr::Var(name) => vec![atom(c, "var"), atom(c, name)],
+            TypeExpr::Atom(name) => vec![atom(c, "atom"), atom(c, name)],
+            TypeExpr::Integer(int) => vec![atom(c, "integer"), c.from(*int)],
+            TypeExpr::BigInteger(int) => vec![atom(c, "big_integer"), atom(c, int)],
+            TypeExpr::Char(ch) => vec![atom(c, "char"), c.from(*ch as i64)],
+            TypeExpr::Annotated { name, ty } => {
+                vec![atom(c, "annotated"), atom(c, name), ty.to_const(c)]
+            }
+            TypeExpr::Union(types) => vec![atom(c, "union"), types_to_const(c, types)],
+            TypeExpr::Range(low, high) => vec![atom(c, "range"), low.to_const(c), high.to_const(c)],
+            TypeExpr::BinaryOp { op, lhs, rhs } => {
+                vec![atom(c, "op"), atom(c, op), lhs.to_const(c), rhs.to_const(c)]
+            }
+            TypeExpr::UnaryOp { op, operand } => {
+                vec![atom(c, "op"), atom(c, op), operand.to_const(c)]
+            }
+            TypeExpr::Local { name, params } => {
+                vec![atom(c, "type"), atom(c, name), types_to_const(c, params)]
+            }
+            TypeExpr::Remote {
+                module,
+                name,
+                params,
+            } => vec![
+                atom(c, "remote_type"),
+                atom(c, module),
+                atom(c, name),
+                types_to_const(c, params),
+            ],
+            TypeExpr::List(ty) => vec![atom(c, "list"), ty.to_const(c)],
+            TypeExpr::NonEmptyList(ty) => vec![atom(c, "nonempty_list"), ty.to_const(c)],
+            TypeExpr::Map(entries) => vec![atom(c, "map"), types_to_const(c, entries)],
+            TypeExpr::Tuple(elements) => vec![atom(c, "tuple"), types_to_const(c, elements)],
+            TypeExpr::Record { name, fields } => {
+                vec![atom(c, "record"), atom(c, name), types_to_const(c, fields)]
+            }
+            TypeExpr::Binary(size, unit) => {
+                vec![atom(c, "binary"), size.to_const(c), unit.to_const(c)]
+            }
+            TypeExpr::AnyFun(None) => vec![atom(c, "fun")],
+            TypeExpr::AnyFun(Some(ret)) => vec![atom(c, "fun"), ret.to_const(c)],
+            TypeExpr::Fun { params, ret } => {
+                vec![atom(c, "fun"), types_to_const(c, params), ret.to_const(c)]
+            }
+            TypeExpr::KeyValuePair(kind, key, value) => {
+                let tag = match kind {
+                    MapFieldKind::Assoc => "map_field_assoc",
+                    MapFieldKind::Exact => "map_field_exact",
+                };
+                vec![atom(c, tag), key.to_const(c), value.to_const(c)]
+            }
+            TypeExpr::Field { name, ty } => vec![atom(c, "field"), atom(c, name), ty.to_const(c)],
+        };
+        tuple(c, &elements)
+    }
+
+    fn from_const(c: &ConstantContainer, term: Const) -> Option<TypeExpr> {
+        if let Some(name) = const_atom(c, term) {
+            return if name == "nil" {
+                Some(TypeExpr::Nil)
+            } else {
+                None
+            };
+        }
+
+        let elements = const_tuple(c, term)?;
+        let tag = const_atom(c, *elements.get(0)?)?;
+        let name = |idx: usize| const_atom(c, elements[idx]);
+        let ty = |idx: usize| TypeExpr::from_const(c, elements[idx]).map(Box::new);
+        let types = |idx: usize| types_from_const(c, elements[idx]);
+        let expr = match (&*tag, elements.len()) {
+            ("var", 2) => TypeExpr::Var(name(1)?),
+            ("atom", 2) => TypeExpr::Atom(name(1)?),
+            ("integer", 2) => TypeExpr::Integer(const_int(c, elements[1])?),
+            ("big_integer", 2) => TypeExpr::BigInteger(name(1)?),
+            ("char", 2) => {
+                let code = u32::try_from(const_int(c, elements[1])?).ok()?;
+                TypeExpr::Char(std::char::from_u32(code)?)
+            }
+            ("annotated", 3) => TypeExpr::Annotated {
+                name: name(1)?,
+                ty: ty(2)?,
+            },
+            ("union", 2) => TypeExpr::Union(types(1)?),
+            ("range", 3) => TypeExpr::Range(ty(1)?, ty(2)?),
+            ("op", 4) => TypeExpr::BinaryOp {
+                op: name(1)?,
+                lhs: ty(2)?,
+                rhs: ty(3)?,
+            },
+            ("op", 3) => TypeExpr::UnaryOp {
+                op: name(1)?,
+                operand: ty(2)?,
+            },
+            ("type", 3) => TypeExpr::Local {
+                name: name(1)?,
+                params: types(2)?,
+            },
+            ("remote_type", 4) => TypeExpr::Remote {
+                module: name(1)?,
+                name: name(2)?,
+                params: types(3)?,
+            },
+            ("list", 2) => TypeExpr::List(ty(1)?),
+            ("nonempty_list", 2) => TypeExpr::NonEmptyList(ty(1)?),
+            ("map", 2) => TypeExpr::Map(types(1)?),
+            ("tuple", 2) => TypeExpr::Tuple(types(1)?),
+            ("record", 3) => TypeExpr::Record {
+                name: name(1)?,
+                fields: types(2)?,
+            },
+            ("binary", 3) => TypeExpr::Binary(ty(1)?, ty(2)?),
+            ("fun", 1) => TypeExpr::AnyFun(None),
+            ("fun", 2) => TypeExpr::AnyFun(Some(ty(1)?)),
+            ("fun", 3) => TypeExpr::Fun {
+                params: types(1)?,
+                ret: ty(2)?,
+            },
+            ("map_field_assoc", 3) => TypeExpr::KeyValuePair(MapFieldKind::Assoc, ty(1)?, ty(2)?),
+            ("map_field_exact", 3) => TypeExpr::KeyValuePair(MapFieldKind::Exact, ty(1)?, ty(2)?),
+            ("field", 3) => TypeExpr::Field {
+                name: name(1)?,
+                ty: ty(2)?,
+            },
+            _ => return None,
+        };
+        Some(expr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FunctionSpec, MapFieldKind, SpecClause, TypeExpr};
+    use crate::ConstantContainer;
+
+    #[test]
+    fn display() {
+        let clause = SpecClause {
+            params: vec![
+                TypeExpr::Var("T".to_string()),
+                TypeExpr::List(Box::new(TypeExpr::Local {
+                    name: "integer".to_string(),
+                    params: vec![],
+                })),
+            ],
+            ret: TypeExpr::Union(vec![
+                TypeExpr::Tuple(vec![
+                    TypeExpr::Atom("ok".to_string()),
+                    TypeExpr::Var("T".to_string()),
+                ]),
+                TypeExpr::Atom("error".to_string()),
+            ]),
+            constraints: vec![(
+                "T".to_string(),
+                TypeExpr::Range(
+                    Box::new(TypeExpr::Integer(0)),
+                    Box::new(TypeExpr::Integer(10)),
+                ),
+            )],
+        };
+        assert_eq!(
+            clause.to_string(),
+            "(T, [integer()]) -> {'ok', T} | 'error' when T :: 0..10"
+        );
+    }
+
+    #[test]
+    fn const_roundtrip() {
+        let spec = FunctionSpec {
+            clauses: vec![
+                SpecClause {
+                    params: vec![
+                        TypeExpr::Annotated {
+                            name: "Map".to_string(),
+                            ty: Box::new(TypeExpr::Map(vec![
+                                TypeExpr::KeyValuePair(
+                                    MapFieldKind::Assoc,
+                                    Box::new(TypeExpr::Atom("key".to_string())),
+                                    Box::new(TypeExpr::Char('x')),
+                                ),
+                                TypeExpr::KeyValuePair(
+                                    MapFieldKind::Exact,
+                                    Box::new(TypeExpr::Atom("other".to_string())),
+                                    Box::new(TypeExpr::Nil),
+                                ),
+                            ])),
+                        },
+                        TypeExpr::Fun {
+                            params: vec![TypeExpr::Nil],
+                            ret: Box::new(TypeExpr::AnyFun(None)),
+                        },
+                    ],
+                    ret: TypeExpr::Remote {
+                        module: "m".to_string(),
+                        name: "t".to_string(),
+                        params: vec![TypeExpr::BigInteger("123456789012345678901234".to_string())],
+                    },
+                    constraints: vec![("T".to_string(), TypeExpr::Integer(-1))],
+                },
+                SpecClause {
+                    params: vec![],
+                    ret: TypeExpr::UnaryOp {
+                        op: "-".to_string(),
+                        operand: Box::new(TypeExpr::Integer(1)),
+                    },
+                    constraints: vec![],
+                },
+            ],
+        };
+
+        let mut c = ConstantContainer::new();
+        let term = spec.to_const(&mut c);
+        assert_eq!(FunctionSpec::from_const(&c, term), Some(spec));
+
+        let nil = c.nil();
+        assert_eq!(
+            FunctionSpec::from_const(&c, nil),
+            Some(FunctionSpec { clauses: vec![] })
+        );
+        let atom = c.from(libeir_intern::Symbol::intern("bad"));
+        assert_eq!(FunctionSpec::from_const(&c, atom), None);
+    }
+}
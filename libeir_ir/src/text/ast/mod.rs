@@ -2,9 +2,9 @@ use libeir_diagnostics::SourceSpan;
 use libeir_intern::Ident;
 
 use crate::constant::Integer;
-use crate::{BasicType, BinOp, BinaryEntrySpecifier};
+use crate::{BasicType, BinOp, BinaryEntrySpecifier, LogicOp, MapPutUpdate};
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DynToken {
     Parens(Vec<DynToken>, SourceSpan),
     Braces(Vec<DynToken>, SourceSpan),
@@ -18,6 +18,7 @@ pub enum DynToken {
     Atom(Ident),
     Integer(Integer, SourceSpan),
     Float(Ident),
+    Binary(Ident),
     String(Ident),
 
     Percent(SourceSpan),
@@ -45,6 +46,10 @@ pub enum DynToken {
     Case(SourceSpan),
     Guard(SourceSpan),
     Except(SourceSpan),
+    MapPut(SourceSpan),
+    TraceConstruct(SourceSpan),
+    Size(SourceSpan),
+    Const(SourceSpan),
 }
 
 impl DynToken {
@@ -63,6 +68,7 @@ impl DynToken {
             Atom(ident) => ident.span,
             Integer(_, span) => *span,
             Float(ident) => ident.span,
+            Binary(ident) => ident.span,
             String(ident) => ident.span,
 
             Percent(span) => *span,
@@ -90,6 +96,10 @@ impl DynToken {
             Case(span) => *span,
             Guard(span) => *span,
             Except(span) => *span,
+            MapPut(span) => *span,
+            TraceConstruct(span) => *span,
+            Size(span) => *span,
+            Const(span) => *span,
         }
     }
 }
@@ -104,6 +114,33 @@ pub struct Module {
 #[derive(Debug, PartialEq, Eq)]
 pub enum ModuleItem {
     Function(Function),
+    Meta(ModuleMeta),
+}
+
+/// Module header declaration, `!export [a'foo'/1];`,
+/// `!on_load a'init'/0;`, `!attribute a'vsn' = [1];` or
+/// `!spec a'foo'/1 = [...];`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ModuleMeta {
+    pub span: SourceSpan,
+    pub name: Ident,
+    pub value: ModuleMetaValue,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ModuleMetaValue {
+    FunctionNames(Vec<FunctionName>),
+    FunctionName(FunctionName),
+    Attribute(Ident, Value),
+    FunctionAttribute(FunctionName, Value),
+}
+
+/// Reference to a function within the current module.
+#[derive(Debug, PartialEq, Eq)]
+pub struct FunctionName {
+    pub span: SourceSpan,
+    pub name: Ident,
+    pub arity: Integer,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -152,6 +189,8 @@ pub enum Op {
     CallFunction(CallFunctionOp),
     IfBool(IfBoolOp),
     TraceCaptureRaw(TraceCaptureRawOp),
+    TraceConstruct(TraceConstructOp),
+    MapPut(MapPutOp),
     Match(MatchOp),
     Case(CaseOp),
     Unreachable,
@@ -176,6 +215,9 @@ pub struct CaseEntry {
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum CasePattern {
+    /// Matches a constant term, `a'ok'`, `[]` or `const {a'ok', 1}`.
+    Const(Value),
+    /// Matches a value read by the case operation, `value %a`.
     Value(Value),
     Binding {
         name: Ident,
@@ -188,9 +230,27 @@ pub enum CasePattern {
     Tuple {
         elements: Vec<CasePattern>,
     },
+    Map {
+        entries: Vec<(CasePatternValue, CasePattern)>,
+    },
+    Binary {
+        specifier: BinaryEntrySpecifier,
+        size: Option<CasePatternValue>,
+        value: Box<CasePattern>,
+        remaining: Box<CasePattern>,
+    },
     Wildcard,
 }
 
+/// Value input to a pattern, used for map keys and binary sizes.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CasePatternValue {
+    /// Value read by the case operation, `value %a`.
+    Value(Value),
+    /// Value of a named node in the same clause.
+    Node(Ident),
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct MatchOp {
     pub span: SourceSpan,
@@ -252,6 +312,29 @@ pub struct TraceCaptureRawOp {
     pub then: Value,
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub struct TraceConstructOp {
+    pub span: SourceSpan,
+    pub trace: Value,
+    pub then: Value,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct MapPutOp {
+    pub span: SourceSpan,
+    pub map: Value,
+    pub entries: Vec<MapPutEntry>,
+    pub ok: Value,
+    pub fail: Value,
+}
+#[derive(Debug, PartialEq, Eq)]
+pub struct MapPutEntry {
+    pub span: SourceSpan,
+    pub action: MapPutUpdate,
+    pub key: Value,
+    pub value: Value,
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum Value {
     // Atomics
@@ -259,14 +342,20 @@ pub enum Value {
     Block(Ident),
     Atom(Ident),
     Integer(Integer),
+    /// Digits of the float, validated when lowering.
+    Float(Ident),
+    /// Hex encoded bytes, validated when lowering.
+    Binary(Ident),
     Nil,
 
     // Composites
     ValueList(Vec<Value>),
     Tuple(Vec<Value>),
     List(Vec<Value>, Option<Box<Value>>),
+    Map(Vec<(Value, Value)>),
     CaptureFunction(Box<Value>, Box<Value>, Box<Value>),
     BinOp(Box<Value>, BinOp, Box<Value>),
+    LogicOp(LogicOp, Vec<Value>),
 }
 impl Value {
     pub fn value(&self) -> Option<Ident> {
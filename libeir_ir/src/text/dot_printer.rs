@@ -1,8 +1,10 @@
+use std::collections::BTreeMap;
 use std::marker::PhantomData;
 
 use super::printer as pr;
-use super::printer::{FormatState, FunctionFormatData};
-use crate::Function;
+use super::printer::{FormatState, FunctionFormatData, SourceLine};
+use crate::{Block, Function};
+use libeir_diagnostics::CodeMap;
 use pretty::Arena;
 
 use libeir_util_dot_graph::GraphPrinter;
@@ -12,6 +14,29 @@ use petgraph::visit::{Dfs, IntoNeighbors};
 pub fn function_into_graph_printer<O>(fun: &Function, g: &mut GraphPrinter<O>)
 where
     O: std::fmt::Write,
+{
+    function_into_graph_printer_inner(fun, None, g)
+}
+
+/// Like `function_into_graph_printer`, but groups blocks into
+/// clusters by the source line they were lowered from. Each cluster
+/// is labeled with the text of that line, as found in the codemap.
+pub fn function_into_graph_printer_by_source_line<O>(
+    fun: &Function,
+    codemap: &CodeMap,
+    g: &mut GraphPrinter<O>,
+) where
+    O: std::fmt::Write,
+{
+    function_into_graph_printer_inner(fun, Some(codemap), g)
+}
+
+fn function_into_graph_printer_inner<O>(
+    fun: &Function,
+    codemap: Option<&CodeMap>,
+    g: &mut GraphPrinter<O>,
+) where
+    O: std::fmt::Write,
 {
     let mut buf = String::new();
 
@@ -38,14 +63,42 @@ where
     let block_graph = fun.block_graph();
     let mut block_dfs = Dfs::new(&block_graph, fun.block_entry());
 
+    let mut blocks = Vec::new();
     while let Some(block) = block_dfs.next(&block_graph) {
-        let block_val = fun.block_value(block);
+        blocks.push(block);
+    }
 
+    // Blocks without a known source line are not put in any cluster.
+    let mut ungrouped = Vec::new();
+    let mut groups: BTreeMap<SourceLine, Vec<Block>> = BTreeMap::new();
+    for block in blocks.iter().cloned() {
+        match codemap.and_then(|codemap| SourceLine::of_block(fun, codemap, block)) {
+            Some(line) => groups.entry(line).or_default().push(block),
+            None => ungrouped.push(block),
+        }
+    }
+
+    let mut node = |g: &mut GraphPrinter<O>, block: Block| {
         let doc = ctx.block_to_doc(&mut config, &mut state, block);
         buf.clear();
         doc.render_fmt(80, &mut buf).unwrap();
-        g.node(block_val, &buf);
+        g.node(fun.block_value(block), &buf);
+    };
+
+    for block in ungrouped {
+        node(g, block);
+    }
+    for (line, group) in groups.iter() {
+        let label = line.describe(codemap.unwrap()).unwrap_or_else(String::new);
+        g.begin_cluster(&label);
+        for block in group.iter().cloned() {
+            node(g, block);
+        }
+        g.end_cluster();
+    }
 
+    for block in blocks {
+        let block_val = fun.block_value(block);
         for out in block_graph.neighbors(block) {
             let out_val = fun.block_value(out);
             g.edge(block_val, out_val, "");
@@ -58,3 +111,9 @@ pub fn function_to_dot(fun: &Function) -> String {
     function_into_graph_printer(fun, &mut g);
     g.finish().unwrap()
 }
+
+pub fn function_to_dot_by_source_line(fun: &Function, codemap: &CodeMap) -> String {
+    let mut g = GraphPrinter::new();
+    function_into_graph_printer_by_source_line(fun, codemap, &mut g);
+    g.finish().unwrap()
+}
@@ -0,0 +1,265 @@
+//! Self contained html export of function control flow graphs.
+//!
+//! Each function added to the export is shown as a section with the
+//! CFG, followed by the IR text of every block. Rendering the CFG to
+//! svg is left to the caller of `finish`. Hovering a value in the IR
+//! text highlights all other uses of that value in the IR text of
+//! every section, which makes it easy to follow a value through a
+//! pipeline of passes. The rendered graphs are not highlighted.
+
+use libeir_util_dot_graph::{GraphPrinter, StructuredNode};
+use petgraph::visit::{Dfs, IntoNeighbors};
+
+use super::printer as pr;
+use crate::{Block, Function};
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 1em 2em; }
+section { border-top: 1px solid #ccc; padding-top: 1em; }
+.cfg { overflow: auto; max-height: 80vh; border: 1px solid #eee; }
+.blocks pre { background: #f8f8f8; padding: 0.5em; margin: 0.5em 0; }
+.v { cursor: pointer; }
+.v.hl { background: #ffe066; }
+";
+
+const SCRIPT: &str = "
+function highlight(target, on) {
+  var name = target.getAttribute && target.getAttribute('data-value');
+  if (!name) { return; }
+  var elems = document.querySelectorAll('[data-value=\"' + name + '\"]');
+  for (var i = 0; i < elems.length; i++) {
+    elems[i].classList.toggle('hl', on);
+  }
+}
+document.addEventListener('mouseover', function(e) { highlight(e.target, true); });
+document.addEventListener('mouseout', function(e) { highlight(e.target, false); });
+";
+
+pub struct HtmlExport {
+    title: String,
+    sections: Vec<Section>,
+}
+
+struct Section {
+    heading: String,
+    dot: String,
+    blocks: Vec<String>,
+}
+
+impl HtmlExport {
+    pub fn new(title: &str) -> Self {
+        HtmlExport {
+            title: title.to_string(),
+            sections: Vec::new(),
+        }
+    }
+
+    /// Adds a section showing the current state of the function.
+    pub fn push_function(&mut self, heading: &str, fun: &Function) {
+        let mut config = pr::FormatConfig {
+            width: 80,
+            print_locations: false,
+            block_iterator_config: pr::DfsBlockIteratorConfig,
+            value_formatter: pr::StandardValueFormatter,
+            block_value_layout: pr::ReferencePrimopBlockValueLayout::default(),
+        };
+
+        let block_graph = fun.block_graph();
+        let mut block_dfs = Dfs::new(&block_graph, fun.block_entry());
+        let mut blocks: Vec<(Block, String)> = Vec::new();
+        while let Some(block) = block_dfs.next(&block_graph) {
+            blocks.push((block, fun.block_to_text(block, &mut config)));
+        }
+
+        let mut g = GraphPrinter::new();
+        for (block, text) in blocks.iter() {
+            let mut lines = text.splitn(2, '\n');
+            let mut node = StructuredNode::new();
+            node.text(lines.next().unwrap_or(""));
+            if let Some(body) = lines.next() {
+                node.text(format!("{}\n", body));
+            }
+            g.structured_node(fun.block_value(*block), &node);
+        }
+        for (block, _) in blocks.iter() {
+            for out in block_graph.neighbors(*block) {
+                g.edge(fun.block_value(*block), fun.block_value(out), "");
+            }
+        }
+        let dot = g.finish().unwrap();
+
+        self.sections.push(Section {
+            heading: heading.to_string(),
+            dot,
+            blocks: blocks.into_iter().map(|(_block, text)| text).collect(),
+        });
+    }
+
+    /// Finishes the html document. `render_svg` renders the dot source
+    /// of a CFG to svg. Where it returns `None`, the dot source is
+    /// included instead.
+    pub fn finish<F>(self, mut render_svg: F) -> String
+    where
+        F: FnMut(&str) -> Option<String>,
+    {
+        let mut out = String::new();
+        out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>");
+        escape(&self.title, &mut out);
+        out.push_str("</title>\n<style>");
+        out.push_str(STYLE);
+        out.push_str("</style>\n</head>\n<body>\n<h1>");
+        escape(&self.title, &mut out);
+        out.push_str("</h1>\n");
+        for section in self.sections.iter() {
+            out.push_str("<section>\n<h2>");
+            escape(&section.heading, &mut out);
+            out.push_str("</h2>\n<div class=\"cfg\">\n");
+            match render_svg(&section.dot) {
+                Some(svg) => {
+                    // Strip the xml prolog and doctype, they are not valid
+                    // inside a html document.
+                    let start = svg.find("<svg").unwrap_or(0);
+                    out.push_str(&svg[start..]);
+                }
+                None => {
+                    out.push_str("<pre>");
+                    escape(&section.dot, &mut out);
+                    out.push_str("</pre>");
+                }
+            }
+            out.push_str("\n</div>\n<div class=\"blocks\">\n");
+            for text in section.blocks.iter() {
+                out.push_str("<pre>");
+                highlight_values(text, &mut out);
+                out.push_str("</pre>\n");
+            }
+            out.push_str("</div>\n</section>\n");
+        }
+        out.push_str("<script>");
+        out.push_str(SCRIPT);
+        out.push_str("</script>\n</body>\n</html>\n");
+        out
+    }
+}
+
+fn escape(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// Escapes IR text, wrapping every value (`%12`) and block
+/// (`block3`) reference in a span that is highlighted on hover.
+/// Quoted atoms and strings are left alone.
+fn highlight_values(text: &str, out: &mut String) {
+    let mut rest = text;
+    let mut prev_ident = false;
+    let mut quote = None;
+    while let Some(c) = rest.chars().next() {
+        if quote.is_none() && (c == '\'' || c == '"') {
+            quote = Some(c);
+        } else if quote == Some(c) {
+            quote = None;
+        }
+
+        let prefix_len = if quote.is_some() {
+            None
+        } else if c == '%' {
+            Some(1)
+        } else if !prev_ident && rest.starts_with("block") {
+            Some(5)
+        } else {
+            None
+        };
+
+        if let Some(prefix_len) = prefix_len {
+            let digits = rest[prefix_len..]
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len() - prefix_len);
+            let after = rest[prefix_len + digits..].chars().next();
+            let ends_ident = after.map(|c| !is_ident(c)).unwrap_or(true);
+            if digits > 0 && ends_ident {
+                let name = &rest[..prefix_len + digits];
+                out.push_str("<span class=\"v\" data-value=\"");
+                out.push_str(name);
+                out.push_str("\">");
+                out.push_str(name);
+                out.push_str("</span>");
+                rest = &rest[name.len()..];
+                prev_ident = true;
+                continue;
+            }
+        }
+
+        escape(&rest[..c.len_utf8()], out);
+        prev_ident = is_ident(c);
+        rest = &rest[c.len_utf8()..];
+    }
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{highlight_values, HtmlExport};
+
+    #[test]
+    fn highlight() {
+        let mut out = String::new();
+        highlight_values("block2(%3):\n    %ret(%3) <a'block4'>;", &mut out);
+        assert_eq!(
+            out,
+            "<span class=\"v\" data-value=\"block2\">block2</span>(\
+             <span class=\"v\" data-value=\"%3\">%3</span>):\n    \
+             %ret(<span class=\"v\" data-value=\"%3\">%3</span>) \
+             &lt;a'block4'&gt;;"
+        );
+    }
+
+    #[test]
+    fn export() {
+        let ir = crate::parse_function_unwrap(
+            "
+a'woo':a'hoo'/1 {
+    entry(%ret, %thr, %a):
+        %f1 = a'erlang':a'+'/2;
+        %f1(%a, 2) => b2 except %thr;
+    b2(%b):
+        %ret(%b);
+}
+",
+        );
+
+        let mut export = HtmlExport::new("woo:hoo/1");
+        export.push_function("before", &ir);
+        export.push_function("after", &ir);
+        let mut rendered = 0;
+        let html = export.finish(|dot| {
+            assert!(dot.starts_with("digraph"));
+            rendered += 1;
+            if rendered == 1 {
+                Some("<?xml version=\"1.0\"?>\n<svg>graph</svg>".to_string())
+            } else {
+                None
+            }
+        });
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert_eq!(html.matches("<section>").count(), 2);
+        assert!(html.contains("<h2>before</h2>"));
+        assert!(html.contains("data-value=\""));
+        // The svg is embedded without its prolog, unrendered graphs are
+        // shown as dot source.
+        assert!(html.contains("<div class=\"cfg\">\n<svg>graph</svg>"));
+        assert!(!html.contains("<?xml"));
+        assert!(html.contains("<pre>digraph"));
+    }
+}
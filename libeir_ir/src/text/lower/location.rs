@@ -48,7 +48,11 @@ pub fn parse_location(ctx: &mut ParseCtx) -> Result<ParsedLocation, DynParserErr
     let (toks, span) = ctx.tok_square_brackets()?;
     let mut ictx = ParseCtx::new(toks, span);
 
-    let locs = ictx.comma(parse_terminal_location)?;
+    let locs = if toks.is_empty() {
+        vec![]
+    } else {
+        ictx.comma(parse_terminal_location)?
+    };
     ictx.eof()?;
 
     ctx.eof()?;
@@ -1,18 +1,21 @@
 use std::collections::HashMap;
 use std::error::Error;
 
+use cranelift_entity::EntityList;
+
 use libeir_diagnostics::{CodeMap, Diagnostic, Label, SourceSpan, ToDiagnostic};
-use libeir_intern::Ident;
+use libeir_intern::{Ident, Symbol};
 use libeir_util_datastructures::hashmap_stack::HashMapStack;
-use libeir_util_number::ToPrimitive;
+use libeir_util_number::{Float, ToPrimitive};
 use libeir_util_parse::ErrorReceiver;
 
 use snafu::Snafu;
 
+use crate::constant::{Const, ConstKind, ConstantContainer};
 use crate::text::ast;
 use crate::{Block, Value};
-use crate::{Function, FunctionBuilder, FunctionIdent, Module};
-use crate::{PatternContainer, PatternNode};
+use crate::{Function, FunctionBuilder, FunctionIdent, FunctionSpec, Module};
+use crate::{PatternClause, PatternNode, PatternValue};
 
 mod location;
 
@@ -30,6 +33,15 @@ impl<'a, 'b> LowerContext<'a, 'b> {
             diagnostic: diag.to_diagnostic(),
         })
     }
+
+    pub fn builder(&mut self) -> &mut FunctionBuilder<'b> {
+        self.builder
+    }
+
+    /// Lowers a value in the scope of the operation being parsed.
+    pub fn value(&mut self, val: &ast::Value) -> Result<Value, ()> {
+        lower_value(self.errors, self.builder, self.scope, val)
+    }
 }
 
 #[derive(Debug, Snafu)]
@@ -65,6 +77,20 @@ pub enum LowerError {
     UnknownDyn {
         span: SourceSpan,
     },
+
+    InvalidMeta {
+        span: SourceSpan,
+        name: Ident,
+    },
+
+    NonConstantAttribute {
+        span: SourceSpan,
+    },
+
+    InvalidLiteral {
+        span: SourceSpan,
+    },
+
     DynError {
         diagnostic: Diagnostic,
     },
@@ -102,6 +128,18 @@ impl ToDiagnostic for LowerError {
                 .with_message("unknown dynop identifier")
                 .with_labels(vec![Label::primary(span.source_id(), *span)
                     .with_message("no parser exists for dynop in current dialect")]),
+            LowerError::InvalidMeta { span, name } => Diagnostic::error()
+                .with_message("invalid module meta entry")
+                .with_labels(vec![Label::primary(span.source_id(), *span)
+                    .with_message(format!("value has the wrong form for `!{}`", name))]),
+            LowerError::NonConstantAttribute { span } => Diagnostic::error()
+                .with_message("non-constant attribute value")
+                .with_labels(vec![Label::primary(span.source_id(), *span)
+                    .with_message("attribute values must be constant terms")]),
+            LowerError::InvalidLiteral { span } => Diagnostic::error()
+                .with_message("invalid literal")
+                .with_labels(vec![Label::primary(span.source_id(), *span)
+                    .with_message("not a valid float or hex encoded binary")]),
             LowerError::DynError { diagnostic } => diagnostic.clone(),
             _ => Diagnostic::error().with_message(msg),
         }
@@ -129,14 +167,25 @@ impl ast::Module {
         for item in self.items.iter() {
             match item {
                 ast::ModuleItem::Function(fun) => {
-                    let fun_ir = module.add_function(
-                        SourceSpan::UNKNOWN,
-                        fun.name,
-                        fun.arity.to_usize().unwrap(),
-                    );
+                    let arity = fun.arity.to_usize().unwrap();
+                    if let Some(prev) = module.name_arity_index(fun.name.name, arity) {
+                        errors.error(LowerError::DuplicateDefinititon {
+                            previous: module[prev].function().span(),
+                            current: fun.span,
+                        });
+                        return Err(());
+                    }
+                    let fun_ir = module.add_function(fun.span, fun.name, arity);
                     let mut b = fun_ir.function_mut().builder();
                     fun.lower_into(errors, &mut b)?;
                 }
+                ast::ModuleItem::Meta(_) => (),
+            }
+        }
+
+        for item in self.items.iter() {
+            if let ast::ModuleItem::Meta(meta) = item {
+                lower_module_meta(errors, &mut module, meta)?;
             }
         }
 
@@ -144,6 +193,152 @@ impl ast::Module {
     }
 }
 
+/// Like `Module::add_export`, the function named is not required to be
+/// defined in the module.
+fn lower_function_name(name: &ast::FunctionName) -> (Symbol, usize) {
+    (name.name.name, name.arity.to_usize().unwrap())
+}
+
+fn lower_module_meta(
+    errors: ErrCollector,
+    module: &mut Module,
+    meta: &ast::ModuleMeta,
+) -> Result<(), ()> {
+    match (&*meta.name.as_str(), &meta.value) {
+        ("export", ast::ModuleMetaValue::FunctionNames(names)) => {
+            for name in names.iter() {
+                let (name, arity) = lower_function_name(name);
+                module.add_export(name, arity);
+            }
+        }
+        ("on_load", ast::ModuleMetaValue::FunctionName(name)) => {
+            let on_load = lower_function_name(name);
+            module.set_on_load(Some(on_load));
+        }
+        ("attribute", ast::ModuleMetaValue::Attribute(key, value)) => {
+            let value = lower_const(errors, module.cons_mut(), meta.span, value)?;
+            module.add_attribute(*key, value);
+        }
+        ("spec", ast::ModuleMetaValue::FunctionAttribute(name, value)) => {
+            // The spec is decoded from its own container, it is not a
+            // constant of the module.
+            let mut cons = ConstantContainer::new();
+            let term = lower_const(errors, &mut cons, meta.span, value)?;
+            match FunctionSpec::from_const(&cons, term) {
+                Some(spec) => module.set_spec(name.name.name, name.arity.to_usize().unwrap(), spec),
+                None => {
+                    errors.error(LowerError::InvalidMeta {
+                        span: meta.span,
+                        name: meta.name,
+                    });
+                    return Err(());
+                }
+            }
+        }
+        ("export", _) | ("on_load", _) | ("attribute", _) | ("spec", _) => {
+            errors.error(LowerError::InvalidMeta {
+                span: meta.span,
+                name: meta.name,
+            });
+            return Err(());
+        }
+        _ => {
+            errors.error(LowerError::UnknownMeta {
+                span: meta.span,
+                name: meta.name,
+            });
+            return Err(());
+        }
+    }
+    Ok(())
+}
+
+fn lower_const(
+    errors: ErrCollector,
+    c: &mut ConstantContainer,
+    span: SourceSpan,
+    val: &ast::Value,
+) -> Result<Const, ()> {
+    match val {
+        ast::Value::Atom(atom) => Ok(c.from(crate::constant::AtomTerm(atom.name))),
+        ast::Value::Integer(int) => Ok(c.from(int.clone())),
+        ast::Value::Float(float) => Ok(c.from(lower_float(errors, *float)?)),
+        ast::Value::Binary(bin) => Ok(c.from(lower_binary(errors, *bin)?)),
+        ast::Value::Nil => Ok(c.nil()),
+        ast::Value::Tuple(tup) => {
+            let mut builder = c.tuple_builder();
+            for elem in tup.iter() {
+                let elem_c = lower_const(errors, c, span, elem)?;
+                builder.push(elem_c, c);
+            }
+            Ok(builder.finish(c))
+        }
+        ast::Value::List(head, tail) => {
+            let mut acc = tail
+                .as_ref()
+                .map(|v| lower_const(errors, c, span, &*v))
+                .transpose()?
+                .unwrap_or_else(|| c.nil());
+            for v in head.iter().rev() {
+                let new_c = lower_const(errors, c, span, v)?;
+                acc = c.list_cell(new_c, acc);
+            }
+            Ok(acc)
+        }
+        ast::Value::Map(entries) => {
+            let mut pairs = Vec::with_capacity(entries.len());
+            for (key, value) in entries.iter() {
+                let key_c = lower_const(errors, c, span, key)?;
+                let value_c = lower_const(errors, c, span, value)?;
+                pairs.push((key_c, value_c));
+            }
+            // Keys are sorted like in `FunctionBuilder::prim_map`.
+            pairs.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
+
+            let mut keys = EntityList::new();
+            keys.extend(pairs.iter().map(|(k, _)| *k), &mut c.const_pool);
+            let mut values = EntityList::new();
+            values.extend(pairs.iter().map(|(_, v)| *v), &mut c.const_pool);
+
+            Ok(c.from(ConstKind::Map { keys, values }))
+        }
+        _ => {
+            errors.error(LowerError::NonConstantAttribute { span });
+            Err(())
+        }
+    }
+}
+
+fn lower_float(errors: ErrCollector, float: Ident) -> Result<Float, ()> {
+    match float.as_str().parse::<f64>().ok().map(Float::new) {
+        Some(Ok(float)) => Ok(float),
+        _ => {
+            errors.error(LowerError::InvalidLiteral { span: float.span });
+            Err(())
+        }
+    }
+}
+
+fn lower_binary(errors: ErrCollector, bin: Ident) -> Result<Vec<u8>, ()> {
+    let string = bin.as_str();
+    let digits = string.as_bytes();
+    let bytes = if digits.len() % 2 == 0 {
+        digits
+            .chunks(2)
+            .map(|pair| {
+                std::str::from_utf8(pair)
+                    .ok()
+                    .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+            })
+            .collect::<Option<Vec<u8>>>()
+    } else {
+        None
+    };
+    bytes.ok_or_else(|| {
+        errors.error(LowerError::InvalidLiteral { span: bin.span });
+    })
+}
+
 #[derive(Debug, Eq, PartialEq, Hash, Copy, Clone)]
 pub enum Name {
     Value(Ident),
@@ -390,6 +585,24 @@ fn lower_operation(
             let then = lower_value(errors, b, scope, &trace_op.then)?;
             b.op_trace_capture_raw_next(SourceSpan::UNKNOWN, block, then);
         }
+        ast::Op::TraceConstruct(trace_op) => {
+            let trace = lower_value(errors, b, scope, &trace_op.trace)?;
+            let then = lower_value(errors, b, scope, &trace_op.then)?;
+            b.op_trace_construct_next(SourceSpan::UNKNOWN, block, then, trace);
+        }
+        ast::Op::MapPut(map_put) => {
+            let map = lower_value(errors, b, scope, &map_put.map)?;
+            let ok = lower_value(errors, b, scope, &map_put.ok)?;
+            let fail = lower_value(errors, b, scope, &map_put.fail)?;
+
+            let mut builder = b.op_map_put_build_next(SourceSpan::UNKNOWN, ok, fail, map);
+            for entry in map_put.entries.iter() {
+                let key = lower_value(errors, b, scope, &entry.key)?;
+                let value = lower_value(errors, b, scope, &entry.value)?;
+                builder.push_kv(key, value, entry.action, b);
+            }
+            builder.finish_next(block, b);
+        }
         ast::Op::Match(match_op) => {
             let mut builder = b.op_match_build(SourceSpan::UNKNOWN);
             for entry in match_op.entries.iter() {
@@ -415,7 +628,13 @@ fn lower_operation(
                     ast::MatchKind::Tuple(n) => {
                         builder.push_tuple_next(next, *n, b);
                     }
-                    s => unimplemented!("{:?}", s),
+                    ast::MatchKind::Binary(spec, size) => {
+                        let size_v = size
+                            .as_ref()
+                            .map(|v| lower_value(errors, b, scope, v))
+                            .transpose()?;
+                        builder.push_binary_next(next, *spec, size_v, b);
+                    }
                 }
             }
 
@@ -440,7 +659,8 @@ fn lower_operation(
                 for pattern in entry.patterns.iter() {
                     let pat = lower_case_pattern(
                         errors,
-                        &mut case_b.container,
+                        &mut case_b,
+                        clause,
                         b,
                         scope,
                         &mut binds,
@@ -481,7 +701,8 @@ fn lower_operation(
 
 fn lower_case_pattern(
     errors: ErrCollector,
-    pat: &mut PatternContainer,
+    case_b: &mut crate::operation::case::CaseBuilder,
+    clause: PatternClause,
     b: &mut FunctionBuilder,
     scope: &mut HashMapStack<Name, (SourceSpan, Value)>,
     binds: &mut HashMap<Ident, (SourceSpan, PatternNode)>,
@@ -489,7 +710,7 @@ fn lower_case_pattern(
 ) -> Result<PatternNode, ()> {
     match pattern {
         ast::CasePattern::Binding { name, pattern } => {
-            let child = lower_case_pattern(errors, pat, b, scope, binds, pattern)?;
+            let child = lower_case_pattern(errors, case_b, clause, b, scope, binds, pattern)?;
             if binds.contains_key(name) {
                 errors.error(LowerError::DuplicateDefinititon {
                     current: name.span,
@@ -501,11 +722,100 @@ fn lower_case_pattern(
             Ok(child)
         }
         ast::CasePattern::Wildcard => {
-            let node = pat.node_empty(Some(SourceSpan::UNKNOWN));
-            pat.wildcard(node);
+            let node = case_b.container.node_empty(Some(SourceSpan::UNKNOWN));
+            case_b.container.wildcard(node);
             Ok(node)
         }
-        _ => unimplemented!(),
+        ast::CasePattern::Const(val) => {
+            let cons = lower_const(errors, b.cons_mut(), SourceSpan::UNKNOWN, val)?;
+            let node = case_b.container.node_empty(Some(SourceSpan::UNKNOWN));
+            case_b.container.constant(node, cons);
+            Ok(node)
+        }
+        ast::CasePattern::Value(val) => {
+            let val_v = lower_value(errors, b, scope, val)?;
+            case_b.push_value(val_v, b);
+            let pat_val = case_b.container.clause_value(clause);
+            let node = case_b.container.node_empty(Some(SourceSpan::UNKNOWN));
+            case_b.container.value(node, pat_val);
+            Ok(node)
+        }
+        ast::CasePattern::ListCell { head, tail } => {
+            let head_n = lower_case_pattern(errors, case_b, clause, b, scope, binds, head)?;
+            let tail_n = lower_case_pattern(errors, case_b, clause, b, scope, binds, tail)?;
+            let node = case_b.container.node_empty(Some(SourceSpan::UNKNOWN));
+            case_b.container.list(node, head_n, tail_n);
+            Ok(node)
+        }
+        ast::CasePattern::Tuple { elements } => {
+            let node = case_b.container.node_empty(Some(SourceSpan::UNKNOWN));
+            case_b.container.tuple(node);
+            for elem in elements.iter() {
+                let elem_n = lower_case_pattern(errors, case_b, clause, b, scope, binds, elem)?;
+                case_b.container.tuple_elem_push(node, elem_n);
+            }
+            case_b.container.node_finish(node);
+            Ok(node)
+        }
+        ast::CasePattern::Map { entries } => {
+            let node = case_b.container.node_empty(Some(SourceSpan::UNKNOWN));
+            case_b.container.map(node);
+            for (key, value) in entries.iter() {
+                let key_v = lower_case_pattern_value(errors, case_b, clause, b, scope, binds, key)?;
+                let value_n = lower_case_pattern(errors, case_b, clause, b, scope, binds, value)?;
+                case_b.container.map_push(node, key_v, value_n);
+            }
+            case_b.container.node_finish(node);
+            Ok(node)
+        }
+        ast::CasePattern::Binary {
+            specifier,
+            size,
+            value,
+            remaining,
+        } => {
+            let size_v = size
+                .as_ref()
+                .map(|s| lower_case_pattern_value(errors, case_b, clause, b, scope, binds, s))
+                .transpose()?;
+            let value_n = lower_case_pattern(errors, case_b, clause, b, scope, binds, value)?;
+            let remaining_n =
+                lower_case_pattern(errors, case_b, clause, b, scope, binds, remaining)?;
+            let node = case_b.container.node_empty(Some(SourceSpan::UNKNOWN));
+            case_b
+                .container
+                .binary(node, *specifier, value_n, size_v, remaining_n);
+            Ok(node)
+        }
+    }
+}
+
+/// Values referenced by nodes in a clause are either external reads of
+/// the case operation, or the value of a node bound earlier in the same
+/// clause.
+fn lower_case_pattern_value(
+    errors: ErrCollector,
+    case_b: &mut crate::operation::case::CaseBuilder,
+    clause: PatternClause,
+    b: &mut FunctionBuilder,
+    scope: &mut HashMapStack<Name, (SourceSpan, Value)>,
+    binds: &mut HashMap<Ident, (SourceSpan, PatternNode)>,
+    value: &ast::CasePatternValue,
+) -> Result<PatternValue, ()> {
+    match value {
+        ast::CasePatternValue::Value(val) => {
+            let val_v = lower_value(errors, b, scope, val)?;
+            case_b.push_value(val_v, b);
+            Ok(case_b.container.clause_value(clause))
+        }
+        ast::CasePatternValue::Node(name) => {
+            if let Some((_, node)) = binds.get(name) {
+                Ok(case_b.container.clause_node_value(clause, *node))
+            } else {
+                errors.error(LowerError::UndefinedBind { span: name.span });
+                Err(())
+            }
+        }
     }
 }
 
@@ -532,14 +842,19 @@ fn lower_value(
                 return Err(());
             }
         }
-        ast::Value::Atom(atom) => {
-            // TODO escapes
-            Ok(b.value(crate::constant::AtomTerm(atom.name)))
-        }
+        ast::Value::Atom(atom) => Ok(b.value(crate::constant::AtomTerm(atom.name))),
         ast::Value::Integer(int) => match int {
             crate::constant::Integer::Small(int) => Ok(b.value(*int)),
             crate::constant::Integer::Big(int) => Ok(b.value(int.clone())),
         },
+        ast::Value::Float(float) => {
+            let float = lower_float(errors, *float)?;
+            Ok(b.value(float))
+        }
+        ast::Value::Binary(bin) => {
+            let bin = lower_binary(errors, *bin)?;
+            Ok(b.value(bin))
+        }
         ast::Value::Nil => Ok(b.value(crate::constant::NilTerm)),
         ast::Value::ValueList(list) => {
             let v_buf: Result<Vec<Value>, _> = list
@@ -555,6 +870,22 @@ fn lower_value(
                 .collect();
             Ok(b.prim_tuple(SourceSpan::UNKNOWN, &v_buf?))
         }
+        ast::Value::Map(entries) => {
+            let mut keys = Vec::with_capacity(entries.len());
+            let mut values = Vec::with_capacity(entries.len());
+            for (key, value) in entries.iter() {
+                keys.push(lower_value(errors, b, scope, key)?);
+                values.push(lower_value(errors, b, scope, value)?);
+            }
+            Ok(b.prim_map(SourceSpan::UNKNOWN, &keys, &values))
+        }
+        ast::Value::LogicOp(op, operands) => {
+            let v_buf: Result<Vec<Value>, _> = operands
+                .iter()
+                .map(|v| lower_value(errors, b, scope, v))
+                .collect();
+            Ok(b.prim_logic_op(SourceSpan::UNKNOWN, *op, &v_buf?))
+        }
         ast::Value::CaptureFunction(m, f, a) => {
             let m_v = lower_value(errors, b, scope, &*m)?;
             let f_v = lower_value(errors, b, scope, &*f)?;
@@ -4,7 +4,10 @@
 pub mod printer;
 
 pub mod dot_printer;
-pub use dot_printer::function_to_dot;
+pub use dot_printer::{function_to_dot, function_to_dot_by_source_line};
+
+pub mod html_printer;
+pub use html_printer::HtmlExport;
 
 mod lower;
 pub use lower::{LowerContext, LowerError, LowerMap};
@@ -1,8 +1,11 @@
 use libeir_diagnostics::{Diagnostic, Label, SourceSpan, ToDiagnostic};
 use libeir_intern::{Ident, Symbol};
+use libeir_util_number::ToPrimitive;
 use snafu::Snafu;
 
+use crate::binary::{BinaryEntrySpecifier, Endianness};
 use crate::constant::Integer;
+use crate::BasicType;
 
 use super::ast::{DynToken, Value};
 
@@ -34,6 +37,7 @@ pub enum Token {
     Atom(Symbol),
     Integer(Integer),
     Float(Symbol),
+    Binary(Symbol),
     String(Symbol),
 
     Percent,
@@ -61,6 +65,10 @@ pub enum Token {
     Case,
     Guard,
     Except,
+    MapPut,
+    TraceConstruct,
+    Size,
+    Const,
 
     EOF,
 }
@@ -100,6 +108,7 @@ pub fn flatten_dyn_token(tok: &[DynToken]) -> Vec<(Token, SourceSpan)> {
                 DynToken::Atom(ident) => out.push((Token::Atom(ident.name), ident.span)),
                 DynToken::Integer(int, span) => out.push((Token::Integer(int.clone()), *span)),
                 DynToken::Float(ident) => out.push((Token::Float(ident.name), ident.span)),
+                DynToken::Binary(ident) => out.push((Token::Binary(ident.name), ident.span)),
                 DynToken::String(ident) => out.push((Token::String(ident.name), ident.span)),
 
                 DynToken::Percent(span) => out.push((Token::Percent, *span)),
@@ -127,6 +136,10 @@ pub fn flatten_dyn_token(tok: &[DynToken]) -> Vec<(Token, SourceSpan)> {
                 DynToken::Case(span) => out.push((Token::Case, *span)),
                 DynToken::Guard(span) => out.push((Token::Guard, *span)),
                 DynToken::Except(span) => out.push((Token::Except, *span)),
+                DynToken::MapPut(span) => out.push((Token::MapPut, *span)),
+                DynToken::TraceConstruct(span) => out.push((Token::TraceConstruct, *span)),
+                DynToken::Size(span) => out.push((Token::Size, *span)),
+                DynToken::Const(span) => out.push((Token::Const, *span)),
             }
         }
     }
@@ -303,9 +316,8 @@ impl<'a> ParseCtx<'a> {
                 Err(err) => break,
             }
             match self.peek() {
-                Some(&DynToken::Comma(_)) => (),
+                Some(&DynToken::Comma(_)) => self.pos += 1,
                 _ => break,
-                None => break,
             }
         }
         return Ok(res);
@@ -354,6 +366,38 @@ impl<'a> ParseCtx<'a> {
             tok => Err(DynParserError::UnexpectedToken { span: tok.span() }),
         }
     }
+    pub fn tok_fat_arrow(&mut self) -> Result<SourceSpan, DynParserError> {
+        match self.pop()? {
+            DynToken::FatArrow(span) => Ok(*span),
+            tok => Err(DynParserError::UnexpectedToken { span: tok.span() }),
+        }
+    }
+    pub fn tok_except(&mut self) -> Result<SourceSpan, DynParserError> {
+        match self.pop()? {
+            DynToken::Except(span) => Ok(*span),
+            tok => Err(DynParserError::UnexpectedToken { span: tok.span() }),
+        }
+    }
+    pub fn tok_size(&mut self) -> Result<SourceSpan, DynParserError> {
+        match self.pop()? {
+            DynToken::Size(span) => Ok(*span),
+            tok => Err(DynParserError::UnexpectedToken { span: tok.span() }),
+        }
+    }
+    pub fn tok_ident(&mut self) -> Result<Ident, DynParserError> {
+        match self.pop()? {
+            DynToken::Ident(ident) => Ok(*ident),
+            tok => Err(DynParserError::UnexpectedToken { span: tok.span() }),
+        }
+    }
+    /// Expects an identifier with the given name, used for contextual
+    /// keywords in dyn op syntax.
+    pub fn tok_ident_named(&mut self, name: &str) -> Result<SourceSpan, DynParserError> {
+        match self.pop()? {
+            DynToken::Ident(ident) if ident.name == name => Ok(ident.span),
+            tok => Err(DynParserError::UnexpectedToken { span: tok.span() }),
+        }
+    }
 
     container_token!(tok_parens, Parens);
     container_token!(tok_braces, Braces);
@@ -364,15 +408,134 @@ impl<'a> ParseCtx<'a> {
 
 pub struct ParsePos(usize);
 
+/// Parses a binary entry specifier, `integer(signed, big, 8)`.
+pub fn binary_specifier(ctx: &mut ParseCtx) -> Result<BinaryEntrySpecifier, DynParserError> {
+    ctx.try_parse(|ctx| {
+        let name = ctx.tok_ident()?;
+        let (inner, span) = ctx.tok_parens()?;
+
+        let mut ictx = ParseCtx::new(inner, span);
+        let args = ictx.comma(|ctx| match ctx.pop()? {
+            tok @ DynToken::Ident(_) => Ok(tok.clone()),
+            tok @ DynToken::Integer(_, _) => Ok(tok.clone()),
+            tok => UnexpectedToken { span: tok.span() }.fail(),
+        })?;
+        ictx.eof()?;
+
+        binary_specifier_from_tokens(name, &args)
+            .ok_or(DynParserError::UnexpectedToken { span: name.span })
+    })
+}
+
+/// Builds a binary entry specifier from its name and argument tokens.
+/// Arguments are identifiers for signedness and endianness, and
+/// integers for the unit, in the order printed by the text printer.
+pub fn binary_specifier_from_tokens(
+    name: Ident,
+    args: &[DynToken],
+) -> Option<BinaryEntrySpecifier> {
+    fn endianness(tok: &DynToken) -> Option<Endianness> {
+        match tok {
+            DynToken::Ident(i) if i.name == "big" => Some(Endianness::Big),
+            DynToken::Ident(i) if i.name == "little" => Some(Endianness::Little),
+            DynToken::Ident(i) if i.name == "native" => Some(Endianness::Native),
+            _ => None,
+        }
+    }
+    fn signed(tok: &DynToken) -> Option<bool> {
+        match tok {
+            DynToken::Ident(i) if i.name == "signed" => Some(true),
+            DynToken::Ident(i) if i.name == "unsigned" => Some(false),
+            _ => None,
+        }
+    }
+    fn unit(tok: &DynToken) -> Option<i64> {
+        match tok {
+            DynToken::Integer(int, _) => int.to_i64(),
+            _ => None,
+        }
+    }
+
+    match (&*name.as_str(), args) {
+        ("integer", [s, e, u]) => Some(BinaryEntrySpecifier::Integer {
+            signed: signed(s)?,
+            endianness: endianness(e)?,
+            unit: unit(u)?,
+        }),
+        ("float", [e, u]) => Some(BinaryEntrySpecifier::Float {
+            endianness: endianness(e)?,
+            unit: unit(u)?,
+        }),
+        ("bytes", [u]) => Some(BinaryEntrySpecifier::Bytes { unit: unit(u)? }),
+        ("bits", [u]) => Some(BinaryEntrySpecifier::Bits { unit: unit(u)? }),
+        ("utf8", []) => Some(BinaryEntrySpecifier::Utf8),
+        ("utf16", [e]) => Some(BinaryEntrySpecifier::Utf16 {
+            endianness: endianness(e)?,
+        }),
+        ("utf32", [e]) => Some(BinaryEntrySpecifier::Utf32 {
+            endianness: endianness(e)?,
+        }),
+        _ => None,
+    }
+}
+
+/// Resolves the name of a type in a `match` type branch.
+pub fn basic_type_from_ident(ident: Ident) -> Option<BasicType> {
+    match &*ident.as_str() {
+        "list" => Some(BasicType::List),
+        "cons" => Some(BasicType::ListCell),
+        "nil" => Some(BasicType::Nil),
+        "map" => Some(BasicType::Map),
+        "number" => Some(BasicType::Number),
+        "float" => Some(BasicType::Float),
+        "integer" => Some(BasicType::Integer),
+        "smallint" => Some(BasicType::SmallInteger),
+        "bigint" => Some(BasicType::BigInteger),
+        _ => None,
+    }
+}
+
 pub fn val(ctx: &mut ParseCtx) -> Result<Value, DynParserError> {
-    println!("val PEEK {:?}", ctx.peek());
     match ctx.peek() {
         Some(DynToken::SquareBrackets(_, _)) => val_list(ctx),
         Some(DynToken::AngleBrackets(_, _)) => val_value_list(ctx),
+        Some(DynToken::Braces(_, _)) => val_tuple(ctx),
+        Some(DynToken::MapBraces(_, _)) => val_map(ctx),
         _ => val_atomic(ctx),
     }
 }
 
+pub fn val_map(ctx: &mut ParseCtx) -> Result<Value, DynParserError> {
+    ctx.try_parse(|ctx| {
+        let (inner, span) = ctx.tok_map_braces()?;
+
+        let mut ictx = ParseCtx::new(inner, span);
+        let vec = ictx.comma(|ctx| {
+            ctx.try_parse(|ctx| {
+                let key = val(ctx)?;
+                ctx.tok_fat_arrow()?;
+                let value = val(ctx)?;
+                Ok((key, value))
+            })
+        })?;
+        ictx.eof()?;
+
+        Ok(Value::Map(vec))
+    })
+}
+
+pub fn val_tuple(ctx: &mut ParseCtx) -> Result<Value, DynParserError> {
+    ctx.try_parse(|ctx| {
+        let (inner, span) = ctx.tok_braces()?;
+
+        let mut ictx = ParseCtx::new(inner, span);
+        let vec = ictx.comma(val)?;
+        ictx.eof()?;
+
+        Ok(Value::Tuple(vec))
+    })
+}
+
 //pub fn value_max(ctx: &mut ParseCtx) -> Result<Value, DynParserError> {
 //    ctx.try_parse(|ctx| match ctx.pop() {
 //        DynToken::SquareBrackets(inner, span) => unimplemented!(),
@@ -386,12 +549,7 @@ pub fn val_value_list(ctx: &mut ParseCtx) -> Result<Value, DynParserError> {
 
         let mut ictx = ParseCtx::new(inner, span);
         let vec = ictx.comma(val)?;
-
-        println!("{:?}", vec);
-
-        println!("before value list eof");
         ictx.eof()?;
-        println!("after value list eof");
 
         Ok(Value::ValueList(vec))
     })
@@ -417,16 +575,14 @@ pub fn val_list(ctx: &mut ParseCtx) -> Result<Value, DynParserError> {
 }
 
 pub fn val_atomic(ctx: &mut ParseCtx) -> Result<Value, DynParserError> {
-    println!("val_atomic PEEK {:?}", ctx.peek());
     ctx.try_parse(|ctx| match ctx.pop()? {
         DynToken::Atom(atom) => Ok(Value::Atom(*atom)),
         DynToken::Integer(int, span) => Ok(Value::Integer(int.clone())),
+        DynToken::Float(float) => Ok(Value::Float(*float)),
+        DynToken::Binary(bin) => Ok(Value::Binary(*bin)),
         DynToken::Ident(block) => Ok(Value::Block(*block)),
         DynToken::Variable(var) => Ok(Value::Value(*var)),
-        tok => {
-            println!("unexpected tok {:?}", tok);
-            UnexpectedToken { span: tok.span() }.fail()?
-        }
+        tok => UnexpectedToken { span: tok.span() }.fail()?,
     })
 }
 
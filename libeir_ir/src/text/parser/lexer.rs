@@ -83,8 +83,10 @@ pub enum Token {
     Atom(Ident),
     /// 12
     Integer(Integer),
-    /// 12.2
+    /// f'12.2'
     Float(Ident),
+    /// b'0a0b'
+    Binary(Ident),
     String(Ident),
 
     // Symbols
@@ -125,6 +127,12 @@ pub enum Token {
     Case,
     Guard,
     Except,
+    MapPut,
+    TraceConstruct,
+    Size,
+    Const,
+    And,
+    Or,
 }
 
 lazy_static! {
@@ -144,6 +152,12 @@ lazy_static! {
         map.insert(Symbol::intern("case"), Token::Case);
         map.insert(Symbol::intern("except"), Token::Except);
         map.insert(Symbol::intern("guard"), Token::Guard);
+        map.insert(Symbol::intern("map_put"), Token::MapPut);
+        map.insert(Symbol::intern("trace_construct"), Token::TraceConstruct);
+        map.insert(Symbol::intern("size"), Token::Size);
+        map.insert(Symbol::intern("const"), Token::Const);
+        map.insert(Symbol::intern("and"), Token::And);
+        map.insert(Symbol::intern("or"), Token::Or);
         map
     };
 }
@@ -356,11 +370,20 @@ where
             '@' => pop!(self, Token::At),
             '!' => pop!(self, Token::Bang),
             c if c == 'a' => match self.peek() {
-                '\'' => self.lex_atom(),
+                '\'' => Token::Atom(self.lex_quoted()),
+                _ => self.lex_ident(),
+            },
+            c if c == 'f' => match self.peek() {
+                '\'' => Token::Float(self.lex_quoted()),
+                _ => self.lex_ident(),
+            },
+            c if c == 'b' => match self.peek() {
+                '\'' => Token::Binary(self.lex_quoted()),
                 _ => self.lex_ident(),
             },
             c if c.is_alphabetic() => self.lex_ident(),
             c if c.is_numeric() => self.lex_integer(),
+            '-' if self.peek().is_numeric() => self.lex_integer(),
             '"' => self.lex_string(),
             c => unimplemented!("{}", c),
         }
@@ -446,10 +469,15 @@ where
         Token::Integer(int)
     }
 
-    fn lex_atom(&mut self) -> Token {
-        debug_assert!(self.pop() == 'a');
-        debug_assert!(self.pop() == '\'');
+    /// Lexes a single character prefixed, single quoted token like
+    /// `a'foo'`, returning the unescaped contents. Only `\\` and `\'`
+    /// are escapes, see `AtomTerm`'s `Display` implementation.
+    fn lex_quoted(&mut self) -> Ident {
+        self.skip();
+        let c = self.pop();
+        debug_assert!(c == '\'');
 
+        let mut inner = String::new();
         loop {
             match self.read() {
                 '\'' => {
@@ -458,20 +486,17 @@ where
                 }
                 '\\' => {
                     self.skip();
-                    self.skip();
+                    inner.push(self.pop());
                 }
+                '\0' => break,
                 c => {
                     self.skip();
+                    inner.push(c);
                 }
             }
         }
 
-        let slice = self.slice();
-        let inner_slice = &self.slice()[2..(slice.len() - 1)];
-
-        let ident = Ident::new(Symbol::intern(inner_slice), self.span());
-
-        Token::Atom(ident)
+        Ident::new(Symbol::intern(&inner), self.span())
     }
 }
 
@@ -6,16 +6,23 @@ use std::fmt::Write;
 use std::marker::PhantomData;
 
 use cranelift_entity::EntityRef;
+use libeir_diagnostics::CodeMap;
 use petgraph::visit::Dfs;
 use pretty::{Arena, DocAllocator, RefDoc};
 
 use crate::graph::EntityVisitMap;
 use crate::{
-    BinOp, Block, CallKind, Const, Function, LogicOp, Module, OpKind, PrimOpKind, Value, ValueKind,
+    AtomTerm, BinOp, Block, CallKind, Const, ConstantContainer, Function, LogicOp, Module, OpKind,
+    PrimOpKind, Value, ValueKind,
 };
 
 mod constant;
 mod operation;
+mod source_line;
+pub use self::source_line::SourceLine;
+
+pub(crate) use self::constant::constant_to_doc;
+pub(crate) use self::operation::binary_specifier_to_doc;
 
 type DynError = Box<dyn Error>;
 
@@ -274,13 +281,11 @@ where
         let pre;
         if config.print_locations {
             let loc = state.function.block_location(block);
-            let loc_str = state.function.locations.format_loc(loc);
+            let loc_str = state.function.locations.format_loc_text(loc);
             pre = arena
                 .nil()
                 .append(arena.text("!location"))
                 .append(arena.space())
-                .append(arena.as_string(loc))
-                .append(arena.space())
                 .append(arena.as_string(loc_str))
                 .append(arena.text(";"))
                 .append(arena.hardline());
@@ -422,6 +427,20 @@ where
                             arena.text(",").append(arena.space()),
                         )
                         .enclose("or[", "]"),
+                    PrimOpKind::Map => arena
+                        .intersperse(
+                            reads.chunks(2).map(|kv| {
+                                arena
+                                    .nil()
+                                    .append(self.value_use(config, state, kv[0], Some(value)))
+                                    .append(arena.space())
+                                    .append(arena.text("=>"))
+                                    .append(arena.space())
+                                    .append(self.value_use(config, state, kv[1], Some(value)))
+                            }),
+                            arena.text(",").append(arena.space()),
+                        )
+                        .enclose(arena.text("%{"), arena.text("}")),
                     _ => unimplemented!("{:?}", prim_kind),
                 }
             }
@@ -534,9 +553,13 @@ where
     }
 }
 
+/// When a codemap is given, each block is preceded by a comment with
+/// the source line it was lowered from, unless the previous block
+/// was already annotated with the same line.
 fn format_function_body_state<B, V, L, S>(
     config: &mut FormatConfig<B, V, L>,
     state: &mut FormatState,
+    codemap: Option<&CodeMap>,
     sink: &mut S,
 ) -> Result<(), DynError>
 where
@@ -558,7 +581,21 @@ where
 
     let inner_width = config.width - (state.nesting * 2);
 
+    let mut last_source_line = None;
+
     while let Some(block) = block_iter.next(function) {
+        if let Some(codemap) = codemap {
+            let source_line = SourceLine::of_block(function, codemap, block);
+            if source_line.is_some() && source_line != last_source_line {
+                if let Some(text) = source_line.unwrap().describe(codemap) {
+                    sink.write_indent(state.nesting)?;
+                    sink.write_str(&format!("# {}", text))?;
+                    sink.commit_line()?;
+                }
+            }
+            last_source_line = source_line;
+        }
+
         let doc = ctx.block_to_doc(config, state, block);
 
         ctx.buf.clear();
@@ -597,7 +634,30 @@ where
         function,
         nesting: 0,
     };
-    format_function_body_state(config, &mut state, sink)
+    format_function_body_state(config, &mut state, None, sink)
+}
+
+/// Like `format_function_body`, but interleaves the lines of the
+/// original source, as found in the codemap, with the blocks that
+/// were lowered from them. The source lines are written as comments,
+/// so the output can still be parsed.
+pub fn format_function_body_annotated<B, V, L, S>(
+    function: &Function,
+    config: &mut FormatConfig<B, V, L>,
+    codemap: &CodeMap,
+    sink: &mut S,
+) -> Result<(), DynError>
+where
+    B: BlockIteratorConfig,
+    V: ValueFormatter,
+    L: BlockValueLayout,
+    S: BlockFormatSink,
+{
+    let mut state = FormatState {
+        function,
+        nesting: 0,
+    };
+    format_function_body_state(config, &mut state, Some(codemap), sink)
 }
 
 pub fn format_module<B, V, L, S>(
@@ -611,26 +671,166 @@ where
     L: BlockValueLayout,
     S: BlockFormatSink,
 {
-    sink.write_str(&format!("{} {{\n", module.name().name.as_str().get()));
+    format_module_inner(module, config, None, sink)
+}
+
+/// Module counterpart of `format_function_body_annotated`.
+pub fn format_module_annotated<B, V, L, S>(
+    module: &Module,
+    config: &mut FormatConfig<B, V, L>,
+    codemap: &CodeMap,
+    sink: &mut S,
+) -> Result<(), DynError>
+where
+    B: BlockIteratorConfig,
+    V: ValueFormatter,
+    L: BlockValueLayout,
+    S: BlockFormatSink,
+{
+    format_module_inner(module, config, Some(codemap), sink)
+}
+
+fn format_module_inner<B, V, L, S>(
+    module: &Module,
+    config: &mut FormatConfig<B, V, L>,
+    codemap: Option<&CodeMap>,
+    sink: &mut S,
+) -> Result<(), DynError>
+where
+    B: BlockIteratorConfig,
+    V: ValueFormatter,
+    L: BlockValueLayout,
+    S: BlockFormatSink,
+{
+    sink.write_str(&format!("{} {{\n", AtomTerm(module.name().name)))?;
+
+    format_module_header(module, config, sink)?;
 
     let num_functions = module.function_iter().count();
     for (i, fun) in module.function_iter().enumerate() {
         let function = fun.function();
         let ident = function.ident();
-        sink.write_str(&format!("  {}/{} {{\n", &ident.name, ident.arity));
+        sink.write_str(&format!(
+            "  {}/{} {{\n",
+            AtomTerm(ident.name.name),
+            ident.arity
+        ))?;
         let mut state = FormatState {
             function,
             nesting: 2,
         };
-        format_function_body_state(config, &mut state, sink)?;
+        format_function_body_state(config, &mut state, codemap, sink)?;
         if i + 1 < num_functions {
-            sink.write_str("  }\n\n");
+            sink.write_str("  }\n\n")?;
         } else {
-            sink.write_str("  }\n");
+            sink.write_str("  }\n")?;
+        }
+    }
+
+    sink.write_str("}\n")?;
+
+    Ok(())
+}
+
+/// Writes the module level declarations, exports, on_load, attributes
+/// and specs, in the order they are parsed back in.
+fn format_module_header<B, V, L, S>(
+    module: &Module,
+    config: &mut FormatConfig<B, V, L>,
+    sink: &mut S,
+) -> Result<(), DynError>
+where
+    B: BlockIteratorConfig,
+    V: ValueFormatter,
+    L: BlockValueLayout,
+    S: BlockFormatSink,
+{
+    let arena = Arena::new();
+    let mut buf = String::new();
+    let mut any = false;
+
+    if module.export_iter().next().is_some() {
+        let exports = arena
+            .intersperse(
+                module
+                    .export_iter()
+                    .map(|(name, arity)| arena.as_string(format!("{}/{}", AtomTerm(name), arity))),
+                arena.text(",").append(arena.line()),
+            )
+            .nest(2)
+            .group()
+            .brackets();
+        let doc = arena
+            .text("!export")
+            .append(arena.space())
+            .append(exports)
+            .append(arena.text(";"));
+        buf.clear();
+        doc.render_fmt(config.width - 2, &mut buf).unwrap();
+        for line in buf.lines() {
+            sink.write_indent(1)?;
+            sink.write_str(line)?;
+            sink.commit_line()?;
         }
+        any = true;
     }
 
-    sink.write_str("}\n");
+    if let Some((name, arity)) = module.on_load() {
+        sink.write_indent(1)?;
+        sink.write_str(&format!("!on_load {}/{};", AtomTerm(name), arity))?;
+        sink.commit_line()?;
+        any = true;
+    }
+
+    for (name, value) in module.attributes() {
+        let doc = arena
+            .text("!attribute")
+            .append(arena.space())
+            .append(arena.as_string(AtomTerm(name.name)))
+            .append(arena.space())
+            .append(arena.text("="))
+            .append(arena.space())
+            .append(self::constant::constant_to_doc(
+                &arena,
+                module.cons(),
+                *value,
+            ))
+            .append(arena.text(";"));
+        buf.clear();
+        doc.render_fmt(config.width - 2, &mut buf).unwrap();
+        for line in buf.lines() {
+            sink.write_indent(1)?;
+            sink.write_str(line)?;
+            sink.commit_line()?;
+        }
+        any = true;
+    }
+
+    for ((name, arity), spec) in module.spec_iter() {
+        let mut cons = ConstantContainer::new();
+        let value = spec.to_const(&mut cons);
+        let doc = arena
+            .text("!spec")
+            .append(arena.space())
+            .append(arena.as_string(format!("{}/{}", AtomTerm(name), arity)))
+            .append(arena.space())
+            .append(arena.text("="))
+            .append(arena.space())
+            .append(self::constant::constant_to_doc(&arena, &cons, value))
+            .append(arena.text(";"));
+        buf.clear();
+        doc.render_fmt(config.width - 2, &mut buf).unwrap();
+        for line in buf.lines() {
+            sink.write_indent(1)?;
+            sink.write_str(line)?;
+            sink.commit_line()?;
+        }
+        any = true;
+    }
+
+    if any {
+        sink.commit_line()?;
+    }
 
     Ok(())
 }
@@ -651,6 +851,23 @@ impl Function {
         self.to_text(&mut StandardFormatConfig::default())
     }
 
+    /// Prints the function with the source lines each block was
+    /// lowered from interleaved as comments.
+    pub fn to_text_annotated<B, V, L>(
+        &self,
+        config: &mut FormatConfig<B, V, L>,
+        codemap: &CodeMap,
+    ) -> String
+    where
+        B: BlockIteratorConfig,
+        V: ValueFormatter,
+        L: BlockValueLayout,
+    {
+        let mut sink = StringSink::new();
+        format_function_body_annotated(self, config, codemap, &mut sink).unwrap();
+        sink.finalize()
+    }
+
     pub fn block_to_text<B, V, L>(&self, block: Block, config: &mut FormatConfig<B, V, L>) -> String
     where
         B: BlockIteratorConfig,
@@ -695,12 +912,41 @@ impl Module {
     pub fn to_text_standard(&self) -> String {
         self.to_text(&mut StandardFormatConfig::default())
     }
+
+    /// Prints the module with the source lines each block was
+    /// lowered from interleaved as comments.
+    pub fn to_text_annotated<B, V, L>(
+        &self,
+        config: &mut FormatConfig<B, V, L>,
+        codemap: &CodeMap,
+    ) -> String
+    where
+        B: BlockIteratorConfig,
+        V: ValueFormatter,
+        L: BlockValueLayout,
+    {
+        let mut sink = StringSink::new();
+        format_module_annotated(self, config, codemap, &mut sink).unwrap();
+        sink.finalize()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::{format_function_body, FormatConfig, StandardFormatConfig, StringSink};
 
+    use libeir_diagnostics::{ByteOffset, CodeMap, SourceSpan};
+    use libeir_intern::{Ident, Symbol};
+
+    use crate::operation::binary_construct::{
+        BinaryConstructFinish, BinaryConstructPush, BinaryConstructStart,
+    };
+    use crate::operation::case::{Case, CaseBuilder};
+    use crate::operation::receive::{ReceiveDone, ReceiveStart, ReceiveWait};
+    use crate::{AtomicTerm, ConstKind};
+    use crate::{BasicType, BinaryEntrySpecifier, Endianness, FunctionBuilder, MapPutUpdate};
+    use crate::{FunctionSpec, Module, PatternClause, PatternNode, SpecClause, TypeExpr, Value};
+
     #[test]
     fn woo() {
         let ir = crate::parse_function_unwrap(
@@ -718,4 +964,677 @@ a'woo':a'hoo'/1 {
         let text = ir.to_text(&mut StandardFormatConfig::default());
         println!("{}", text);
     }
+
+    #[test]
+    fn module_header_roundtrip() {
+        let ir = crate::parse_module_unwrap(
+            "
+a'woo' {
+    !export [a'hoo'/1];
+    !on_load a'init'/0;
+    !attribute a'vsn' = [1, {a'a', 2}];
+    !attribute a'author' = a'me';
+
+    a'hoo'/1 {
+        entry(%ret, %thr, %a):
+            %ret(%a);
+    }
+    a'init'/0 {
+        entry(%ret, %thr):
+            %ret(a'ok');
+    }
+}
+",
+        );
+        assert!(ir.is_exported(Symbol::intern("hoo"), 1));
+        assert!(!ir.is_exported(Symbol::intern("init"), 0));
+        assert_eq!(ir.on_load(), Some((Symbol::intern("init"), 0)));
+        assert_eq!(ir.attributes().len(), 2);
+
+        let text = ir.to_text_standard();
+        let parsed = crate::parse_module_unwrap(&text);
+        ir.graph_eq(&parsed).unwrap();
+    }
+
+    #[test]
+    fn module_constant_roundtrip() {
+        let ir = crate::parse_module_unwrap(
+            "
+a'woo' {
+    !attribute a'float' = f'-1.5e10';
+    !attribute a'binary' = b'00ff7f';
+    !attribute a'negative' = -12;
+    !attribute a'escaped' = a'it\\'s a \\\\';
+    !attribute a'map' = %{a'a' => [f'0.5'], 1 => b''};
+
+    a'hoo'/1 {
+        entry(%ret, %thr, %a):
+            %m = %{a'k' => %a, f'2.5' => b'0a'};
+            %l = and[%a, %m];
+            %ret({%l, %a, -3, a'\\''});
+    }
+}
+",
+        );
+        let cons = ir.cons();
+        let attribute = |name: &str| {
+            ir.attributes()
+                .iter()
+                .find(|(key, _)| key.name == name)
+                .map(|(_, value)| match cons.const_kind(*value) {
+                    ConstKind::Atomic(atomic) => atomic.clone(),
+                    kind => panic!("{:?}", kind),
+                })
+                .unwrap()
+        };
+        assert_eq!(attribute("float"), AtomicTerm::from(-1.5e10));
+        assert_eq!(
+            attribute("binary"),
+            AtomicTerm::from(vec![0x00u8, 0xff, 0x7f])
+        );
+        assert_eq!(attribute("negative"), AtomicTerm::from(-12));
+        assert_eq!(
+            attribute("escaped"),
+            AtomicTerm::from(Symbol::intern("it's a \\"))
+        );
+
+        let text = ir.to_text_standard();
+        assert!(text.contains("a'it\\'s a \\\\'"));
+        let parsed = crate::parse_module_unwrap(&text);
+        ir.graph_eq(&parsed).unwrap();
+    }
+
+    #[test]
+    fn module_spec_roundtrip() {
+        let mut ir = crate::parse_module_unwrap(
+            "
+a'woo' {
+    !spec a'hoo'/1 = [{[{a'type', a'integer', []}], {a'atom', a'ok'}, []}];
+
+    a'hoo'/1 {
+        entry(%ret, %thr, %a):
+            %ret(a'ok');
+    }
+}
+",
+        );
+        let hoo = Symbol::intern("hoo");
+        let spec = FunctionSpec {
+            clauses: vec![SpecClause {
+                params: vec![TypeExpr::Local {
+                    name: "integer".to_string(),
+                    params: vec![],
+                }],
+                ret: TypeExpr::Atom("ok".to_string()),
+                constraints: vec![],
+            }],
+        };
+        assert_eq!(ir.spec(hoo, 1), Some(&spec));
+
+        // Specs of undefined functions are kept as well
+        let other = FunctionSpec {
+            clauses: vec![SpecClause {
+                params: vec![TypeExpr::Var("T".to_string())],
+                ret: TypeExpr::List(Box::new(TypeExpr::Var("T".to_string()))),
+                constraints: vec![("T".to_string(), TypeExpr::AnyFun(None))],
+            }],
+        };
+        ir.set_spec(Symbol::intern("other"), 2, other.clone());
+
+        let text = ir.to_text_standard();
+        let parsed = crate::parse_module_unwrap(&text);
+        ir.graph_eq(&parsed).unwrap();
+        assert_eq!(parsed.spec(Symbol::intern("other"), 2), Some(&other));
+
+        ir.set_spec(hoo, 1, other);
+        assert!(ir.graph_eq(&parsed).is_err());
+    }
+
+    #[test]
+    fn annotated_source_lines() {
+        let mut ir = crate::parse_function_unwrap(
+            "
+a'woo':a'hoo'/1 {
+    entry(%ret, %thr, %a):
+        %f1 = a'erlang':a'+'/2;
+        %f1(%a, 2) => b2 except %thr;
+    b2(%b):
+        %f2 = a'erlang':a'/'/2;
+        %f2(%b, 2) => %ret except %thr;
+}
+",
+        );
+
+        let codemap = CodeMap::new();
+        let source_id = codemap.add("woo.erl", "hoo(A) ->\n    (A + 2) / 2.\n".to_owned());
+        let file_span = codemap.get(source_id).unwrap().source_span();
+
+        let entry = ir.block_entry();
+        let b2 = ir.block_iter().find(|b| *b != entry).unwrap();
+        {
+            let mut b = FunctionBuilder::new(&mut ir);
+            let entry_loc = b
+                .fun_mut()
+                .locations
+                .from_bytespan(&codemap, file_span, None, None);
+            let b2_loc = b.fun_mut().locations.from_bytespan(
+                &codemap,
+                file_span.shrink_front(ByteOffset(10)),
+                None,
+                None,
+            );
+            b.block_set_location(entry, entry_loc);
+            b.block_set_location(b2, b2_loc);
+        }
+
+        let text = ir.to_text_annotated(&mut StandardFormatConfig::default(), &codemap);
+        let first = text.find("# woo.erl:1: hoo(A) ->").unwrap();
+        let second = text.find("# woo.erl:2: (A + 2) / 2.").unwrap();
+        assert!(first < text.find("entry(").unwrap());
+        assert!(second < text.find("b2(").unwrap());
+
+        // Annotations are comments, the output still parses.
+        let parsed = crate::parse_function_unwrap(&text);
+        ir.graph_eq(entry, &parsed, parsed.block_entry()).unwrap();
+
+        let dot = crate::text::function_to_dot_by_source_line(&ir, &codemap);
+        assert!(dot.contains("subgraph cluster_0"));
+        assert!(dot.contains("subgraph cluster_1"));
+    }
+
+    #[test]
+    fn module_export_undefined_function() {
+        let ir = crate::parse_module_unwrap(
+            "
+a'woo' {
+    !export [a'nope'/1];
+    !on_load a'init'/0;
+}
+",
+        );
+        assert!(ir.is_exported(Symbol::intern("nope"), 1));
+        assert_eq!(ir.on_load(), Some((Symbol::intern("init"), 0)));
+
+        let parsed = crate::parse_module_unwrap(&ir.to_text_standard());
+        ir.graph_eq(&parsed).unwrap();
+    }
+
+    /// Small deterministic PRNG, used to generate modules for the
+    /// roundtrip property test.
+    struct XorShift(u64);
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+        fn pick(&mut self, values: &[Value]) -> Value {
+            values[self.below(values.len())]
+        }
+    }
+
+    fn gen_function(rng: &mut XorShift, module: &mut Module, name: Ident, arity: usize) {
+        let fun_def = module.add_function(SourceSpan::UNKNOWN, name, arity);
+        let mut b = fun_def.function_mut().builder();
+
+        let entry = b.block_insert();
+        b.block_set_entry(entry);
+        let ret = b.block_arg_insert(entry);
+        let thr = b.block_arg_insert(entry);
+
+        let mut pool = Vec::new();
+        for _ in 0..arity {
+            pool.push(b.block_arg_insert(entry));
+        }
+        pool.push(b.value(Symbol::intern("atom")));
+        pool.push(b.value(rng.below(1000) as i64));
+
+        let mut block = entry;
+        for _ in 0..rng.below(6) {
+            match rng.below(4) {
+                0 => {
+                    let num_args = rng.below(3);
+                    let args: Vec<_> = (0..num_args).map(|_| rng.pick(&pool)).collect();
+                    let callee = b.prim_capture_function(
+                        SourceSpan::UNKNOWN,
+                        Symbol::intern("erlang"),
+                        Symbol::intern("foo"),
+                        num_args,
+                    );
+                    let (ok, fail) = b.op_call_function(SourceSpan::UNKNOWN, block, callee, &args);
+                    let fail_args = b.block_args(fail).to_vec();
+                    b.op_call_flow(fail, thr, &fail_args);
+                    pool.push(b.block_args(ok)[0]);
+                    block = ok;
+                }
+                1 => {
+                    let next = b.block_insert();
+                    let arg = b.block_arg_insert(next);
+                    let val = rng.pick(&pool);
+                    b.op_call_flow(block, next, &[val]);
+                    pool.push(arg);
+                    block = next;
+                }
+                2 => {
+                    let val = rng.pick(&pool);
+                    let (tru, fal) = b.op_if_bool_strict(SourceSpan::UNKNOWN, block, val);
+                    let ret_val = rng.pick(&pool);
+                    b.op_call_flow(fal, ret, &[ret_val]);
+                    block = tru;
+                }
+                3 => {
+                    let elems = [rng.pick(&pool), rng.pick(&pool)];
+                    pool.push(b.prim_tuple(SourceSpan::UNKNOWN, &elems));
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        let ret_val = rng.pick(&pool);
+        b.op_call_flow(block, ret, &[ret_val]);
+    }
+
+    fn gen_module(rng: &mut XorShift) -> Module {
+        let mut module = Module::new(Ident::from_str("gen"));
+
+        let num_functions = 1 + rng.below(4);
+        for n in 0..num_functions {
+            let name = Ident::from_str(&format!("fun_{}", n));
+            let arity = rng.below(3);
+            gen_function(rng, &mut module, name, arity);
+            if rng.below(2) == 0 {
+                module.add_export(name.name, arity);
+            }
+            if n == 0 && rng.below(2) == 0 {
+                module.set_on_load(Some((name.name, arity)));
+            }
+        }
+
+        for n in 0..rng.below(3) {
+            let c = module.cons_mut();
+            let nil = c.nil();
+            let int = c.from(n as i64);
+            let list = c.list_cell(int, nil);
+            let atom = c.from(Symbol::intern("attr_value"));
+            let mut tup = c.tuple_builder();
+            tup.push(atom, c);
+            tup.push(list, c);
+            let value = tup.finish(c);
+            module.add_attribute(Ident::from_str(&format!("attr_{}", n)), value);
+        }
+
+        if rng.below(2) == 0 {
+            let spec = FunctionSpec {
+                clauses: vec![SpecClause {
+                    params: vec![TypeExpr::Integer(rng.below(1000) as i64)],
+                    ret: TypeExpr::Atom("ok".to_string()),
+                    constraints: vec![],
+                }],
+            };
+            module.set_spec(Symbol::intern("fun_0"), 1, spec);
+        }
+
+        module
+    }
+
+    fn gen_specifier(rng: &mut XorShift) -> BinaryEntrySpecifier {
+        let endianness = match rng.below(3) {
+            0 => Endianness::Big,
+            1 => Endianness::Little,
+            _ => Endianness::Native,
+        };
+        let unit = 1 + rng.below(16) as i64;
+        match rng.below(7) {
+            0 => BinaryEntrySpecifier::Integer {
+                signed: rng.below(2) == 0,
+                endianness,
+                unit,
+            },
+            1 => BinaryEntrySpecifier::Float { endianness, unit },
+            2 => BinaryEntrySpecifier::Bytes { unit },
+            3 => BinaryEntrySpecifier::Bits { unit },
+            4 => BinaryEntrySpecifier::Utf8,
+            5 => BinaryEntrySpecifier::Utf16 { endianness },
+            _ => BinaryEntrySpecifier::Utf32 { endianness },
+        }
+    }
+
+    /// Generates a case pattern node. External values and node
+    /// references are created in the same order as the text printer
+    /// visits them.
+    fn gen_pattern(
+        rng: &mut XorShift,
+        b: &mut FunctionBuilder,
+        case_b: &mut CaseBuilder,
+        clause: PatternClause,
+        pool: &[Value],
+        done: &mut Vec<PatternNode>,
+        depth: usize,
+    ) -> PatternNode {
+        let choice = if depth == 0 {
+            rng.below(3)
+        } else {
+            rng.below(8)
+        };
+
+        let node = match choice {
+            0 => {
+                let node = case_b.container.node_empty(None);
+                case_b.container.wildcard(node);
+                node
+            }
+            1 => {
+                let c = match rng.below(4) {
+                    0 => b.cons_mut().from(Symbol::intern("pat")),
+                    1 => b.cons_mut().from(rng.below(100) as i64),
+                    2 => b.cons_mut().nil(),
+                    _ => {
+                        let cons = b.cons_mut();
+                        let atom = cons.from(Symbol::intern("pat"));
+                        let int = cons.from(rng.below(100) as i64);
+                        let mut tup = cons.tuple_builder();
+                        tup.push(atom, cons);
+                        tup.push(int, cons);
+                        tup.finish(cons)
+                    }
+                };
+                let node = case_b.container.node_empty(None);
+                case_b.container.constant(node, c);
+                node
+            }
+            2 => {
+                let val = rng.pick(pool);
+                case_b.push_value(val, b);
+                let pat_val = case_b.container.clause_value(clause);
+                let node = case_b.container.node_empty(None);
+                case_b.container.value(node, pat_val);
+                node
+            }
+            3 => {
+                let node = case_b.container.node_empty(None);
+                case_b.container.tuple(node);
+                for _ in 0..rng.below(3) {
+                    let elem = gen_pattern(rng, b, case_b, clause, pool, done, depth - 1);
+                    case_b.container.tuple_elem_push(node, elem);
+                }
+                case_b.container.node_finish(node);
+                node
+            }
+            4 => {
+                let head = gen_pattern(rng, b, case_b, clause, pool, done, depth - 1);
+                let tail = gen_pattern(rng, b, case_b, clause, pool, done, depth - 1);
+                let node = case_b.container.node_empty(None);
+                case_b.container.list(node, head, tail);
+                node
+            }
+            5 => {
+                let node = case_b.container.node_empty(None);
+                case_b.container.map(node);
+                let key = rng.pick(pool);
+                case_b.push_value(key, b);
+                let key_val = case_b.container.clause_value(clause);
+                let value = gen_pattern(rng, b, case_b, clause, pool, done, depth - 1);
+                case_b.container.map_push(node, key_val, value);
+                case_b.container.node_finish(node);
+                node
+            }
+            _ => {
+                let size = match rng.below(3) {
+                    0 => None,
+                    1 if !done.is_empty() => {
+                        let sized = done[rng.below(done.len())];
+                        Some(case_b.container.clause_node_value(clause, sized))
+                    }
+                    _ => {
+                        let val = rng.pick(pool);
+                        case_b.push_value(val, b);
+                        Some(case_b.container.clause_value(clause))
+                    }
+                };
+                let value = gen_pattern(rng, b, case_b, clause, pool, done, depth - 1);
+                let remaining = gen_pattern(rng, b, case_b, clause, pool, done, depth - 1);
+                let node = case_b.container.node_empty(None);
+                let spec = gen_specifier(rng);
+                case_b.container.binary(node, spec, value, size, remaining);
+                node
+            }
+        };
+
+        done.push(node);
+        node
+    }
+
+    /// Generates a function exercising every operation kind, including
+    /// the dyn operations in the normal dialect.
+    fn gen_ops_function(rng: &mut XorShift, module: &mut Module, name: Ident) {
+        let fun_def = module.add_function(SourceSpan::UNKNOWN, name, 1);
+        let mut b = fun_def.function_mut().builder();
+
+        let entry = b.block_insert();
+        b.block_set_entry(entry);
+        let ret = b.block_arg_insert(entry);
+        let thr = b.block_arg_insert(entry);
+
+        let mut pool = vec![b.block_arg_insert(entry)];
+        pool.push(b.value(Symbol::intern("atom")));
+        pool.push(b.value(rng.below(1000) as i64));
+
+        let mut block = entry;
+        for _ in 0..1 + rng.below(4) {
+            match rng.below(10) {
+                0 => {
+                    let val = rng.pick(&pool);
+                    let next = b.op_unpack_value_list(block, val, 2);
+                    pool.extend(b.block_args(next).iter().cloned());
+                    block = next;
+                }
+                1 => {
+                    let val = rng.pick(&pool);
+                    let (tru, fal, or) = b.op_if_bool(SourceSpan::UNKNOWN, block, val);
+                    let ret_val = rng.pick(&pool);
+                    b.op_call_flow(fal, ret, &[ret_val]);
+                    b.op_unreachable(SourceSpan::UNKNOWN, or);
+                    block = tru;
+                }
+                2 => {
+                    let mut match_b = b.op_match_build(SourceSpan::UNKNOWN);
+                    let mut branches = Vec::new();
+                    for _ in 0..1 + rng.below(4) {
+                        let branch = match rng.below(9) {
+                            0 => match_b.push_value(rng.pick(&pool), &mut b),
+                            1 => match_b.push_type(BasicType::Map, &mut b),
+                            2 => match_b.push_type(BasicType::Tuple(rng.below(4)), &mut b),
+                            3 => match_b.push_type(BasicType::Integer, &mut b),
+                            4 => match_b.push_tuple(rng.below(4), &mut b),
+                            5 => match_b.push_list_cell(&mut b),
+                            6 => match_b.push_map_item(rng.pick(&pool), &mut b),
+                            7 => {
+                                let spec = gen_specifier(rng);
+                                let size = if rng.below(2) == 0 {
+                                    Some(rng.pick(&pool))
+                                } else {
+                                    None
+                                };
+                                match_b.push_binary(spec, size, &mut b)
+                            }
+                            _ => match_b.push_wildcard(SourceSpan::UNKNOWN, &mut b),
+                        };
+                        branches.push(branch);
+                    }
+                    let wildcard = match_b.push_wildcard(SourceSpan::UNKNOWN, &mut b);
+                    match_b.finish(block, rng.pick(&pool), &mut b);
+                    for branch in branches {
+                        let ret_val = rng.pick(&pool);
+                        b.op_call_flow(branch, ret, &[ret_val]);
+                    }
+                    block = wildcard;
+                }
+                3 => {
+                    let mut map_b = b.op_map_put_build(SourceSpan::UNKNOWN, rng.pick(&pool));
+                    for _ in 0..1 + rng.below(3) {
+                        let action = if rng.below(2) == 0 {
+                            MapPutUpdate::Put
+                        } else {
+                            MapPutUpdate::Update
+                        };
+                        map_b.push_kv(rng.pick(&pool), rng.pick(&pool), action, &mut b);
+                    }
+                    let (ok, fail) = map_b.finish(block, &mut b);
+                    let fail_args = b.block_args(fail).to_vec();
+                    b.op_call_flow(fail, thr, &fail_args);
+                    pool.push(b.block_args(ok)[0]);
+                    block = ok;
+                }
+                4 => {
+                    let cont = b.op_trace_capture_raw(SourceSpan::UNKNOWN, block);
+                    let trace = b.block_args(cont)[0];
+                    let next = b.op_trace_construct(SourceSpan::UNKNOWN, cont, trace);
+                    pool.push(b.block_args(next)[0]);
+                    block = next;
+                }
+                5 => {
+                    let timeout = b.value(Symbol::intern("infinity"));
+                    let start = ReceiveStart::build(&mut b, block, timeout);
+                    let recv_ref = b.block_args(start)[0];
+                    let (timeout, check) = ReceiveWait::build(&mut b, start, recv_ref);
+                    let ret_val = rng.pick(&pool);
+                    b.op_call_flow(timeout, ret, &[ret_val]);
+                    let message = b.block_args(check)[0];
+                    let next = ReceiveDone::build(&mut b, check, recv_ref, &[message]);
+                    pool.extend(b.block_args(next).iter().cloned());
+                    block = next;
+                }
+                6 => {
+                    let start = BinaryConstructStart::build(&mut b, block);
+                    let bin_ref = b.block_args(start)[0];
+                    let size = if rng.below(2) == 0 {
+                        Some(rng.pick(&pool))
+                    } else {
+                        None
+                    };
+                    let spec = gen_specifier(rng);
+                    let value = rng.pick(&pool);
+                    let (ok, fail) =
+                        BinaryConstructPush::build(&mut b, start, bin_ref, value, spec, size);
+                    b.op_call_flow(fail, thr, &[]);
+                    let bin_ref = b.block_args(ok)[0];
+                    let next = BinaryConstructFinish::build(&mut b, ok, bin_ref);
+                    pool.push(b.block_args(next)[0]);
+                    block = next;
+                }
+                7 => {
+                    let mut case_b = Case::builder();
+                    let mut bodies = Vec::new();
+                    for _ in 0..rng.below(3) {
+                        let clause = case_b.container.clause_start(SourceSpan::UNKNOWN);
+                        let mut done = Vec::new();
+                        for _ in 0..1 + rng.below(2) {
+                            let node =
+                                gen_pattern(rng, &mut b, &mut case_b, clause, &pool, &mut done, 3);
+                            case_b.container.clause_node_push(clause, node);
+                        }
+                        let mut num_binds = 0;
+                        for _ in 0..rng.below(3) {
+                            let node = done[rng.below(done.len())];
+                            case_b.container.clause_bind_push(clause, node);
+                            num_binds += 1;
+                        }
+                        case_b.container.clause_finish(clause);
+
+                        let guard = b.block_insert();
+                        for _ in 0..num_binds {
+                            b.block_arg_insert(guard);
+                        }
+                        let guard_ret = rng.pick(&pool);
+                        b.op_call_flow(guard, ret, &[guard_ret]);
+
+                        let body = b.block_insert();
+                        for _ in 0..num_binds {
+                            b.block_arg_insert(body);
+                        }
+
+                        let guard_val = b.value(guard);
+                        let body_val = b.value(body);
+                        case_b.push_clause(clause, guard_val, body_val, &mut b);
+                        bodies.push(body);
+                    }
+
+                    let no_match = b.block_insert();
+                    case_b.no_match = Some(b.value(no_match));
+                    case_b.match_on = Some(rng.pick(&pool));
+                    case_b.finish(block, &mut b);
+
+                    bodies.push(no_match);
+                    block = bodies.remove(0);
+                    for body in bodies {
+                        let ret_val = rng.pick(&pool);
+                        b.op_call_flow(body, ret, &[ret_val]);
+                    }
+                    pool.extend(b.block_args(block).iter().cloned());
+                }
+                8 => {
+                    let callee = b.prim_capture_function(
+                        SourceSpan::UNKNOWN,
+                        Symbol::intern("erlang"),
+                        Symbol::intern("foo"),
+                        1,
+                    );
+                    let arg = rng.pick(&pool);
+                    let (ok, fail) = b.op_call_function(SourceSpan::UNKNOWN, block, callee, &[arg]);
+                    let fail_args = b.block_args(fail).to_vec();
+                    b.op_call_flow(fail, thr, &fail_args);
+                    pool.push(b.block_args(ok)[0]);
+                    block = ok;
+                }
+                _ => {
+                    let ret_val = rng.pick(&pool);
+                    b.op_call_flow(block, ret, &[ret_val]);
+                    return;
+                }
+            }
+        }
+
+        if rng.below(4) == 0 {
+            b.op_unreachable(SourceSpan::UNKNOWN, block);
+        } else {
+            let ret_val = rng.pick(&pool);
+            b.op_call_flow(block, ret, &[ret_val]);
+        }
+    }
+
+    #[test]
+    fn ops_roundtrip_property() {
+        let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+        for n in 0..300 {
+            let mut module = Module::new(Ident::from_str("gen"));
+            gen_ops_function(
+                &mut rng,
+                &mut module,
+                Ident::from_str(&format!("fun_{}", n)),
+            );
+
+            let text = module.to_text_standard();
+            let parsed = crate::parse_module_unwrap(&text);
+            if let Err(err) = module.graph_eq(&parsed) {
+                panic!("roundtrip failed: {:?}\n{}", err, text);
+            }
+        }
+    }
+
+    #[test]
+    fn module_roundtrip_property() {
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+        for _ in 0..200 {
+            let module = gen_module(&mut rng);
+            let text = module.to_text_standard();
+            let parsed = crate::parse_module_unwrap(&text);
+            if let Err(err) = module.graph_eq(&parsed) {
+                panic!("roundtrip failed: {:?}\n{}", err, text);
+            }
+        }
+    }
 }
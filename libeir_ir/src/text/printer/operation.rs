@@ -1,9 +1,10 @@
-use log::warn;
 use pretty::{DocAllocator, RefDoc};
 
 use crate::binary::{BinaryEntrySpecifier, Endianness};
 use crate::traits::FormatOpCtx;
-use crate::{BasicType, Block, CallKind, DynValue, MatchKind, OpKind, Value};
+use crate::{
+    BasicType, Block, CallKind, DynValue, Function, MapPutUpdate, MatchKind, OpKind, Value,
+};
 
 use super::{
     get_value_list, BlockIteratorConfig, BlockValueLayout, FormatConfig, FormatState,
@@ -31,6 +32,10 @@ where
         self.format_data.arena
     }
 
+    fn function(&self) -> &Function {
+        self.state.function
+    }
+
     fn value_use_to_doc(&mut self, value: DynValue) -> RefDoc<'doc, ()> {
         let val = self.state.function.value_get(value).unwrap();
         self.format_data
@@ -38,7 +43,7 @@ where
     }
 }
 
-fn binary_specifier_to_doc<'a>(
+pub(crate) fn binary_specifier_to_doc<'a>(
     arena: &'a pretty::Arena<'a>,
     spec: &BinaryEntrySpecifier,
 ) -> RefDoc<'a, ()> {
@@ -67,7 +72,7 @@ fn binary_specifier_to_doc<'a>(
                 .text("integer")
                 .append(
                     arena
-                        .intersperse(items.iter().cloned(), arena.text(","))
+                        .intersperse(items.iter().cloned(), arena.text(", "))
                         .parens(),
                 )
                 .into_doc()
@@ -78,7 +83,7 @@ fn binary_specifier_to_doc<'a>(
                 .text("float")
                 .append(
                     arena
-                        .intersperse(items.iter().cloned(), arena.text(","))
+                        .intersperse(items.iter().cloned(), arena.text(", "))
                         .parens(),
                 )
                 .into_doc()
@@ -94,11 +99,11 @@ fn binary_specifier_to_doc<'a>(
         BinaryEntrySpecifier::Utf8 => arena.text("utf8").append(arena.nil().parens()).into_doc(),
         BinaryEntrySpecifier::Utf16 { endianness } => arena
             .text("utf16")
-            .append(f_endianness(endianness))
+            .append(f_endianness(endianness).parens())
             .into_doc(),
         BinaryEntrySpecifier::Utf32 { endianness } => arena
             .text("utf32")
-            .append(f_endianness(endianness))
+            .append(f_endianness(endianness).parens())
             .into_doc(),
     }
 }
@@ -128,7 +133,6 @@ where
         let op_doc = match op {
             OpKind::Match { branches } => {
                 let dests = reads[0];
-                let num_dests = state.function.value_list_length(dests);
                 let num_branches = branches.len();
                 let mut branches_formatted = Vec::with_capacity(num_branches);
                 for (i, kind) in branches.iter().enumerate() {
@@ -140,126 +144,44 @@ where
                     for n in 0..num_args {
                         args.push(state.function.value_list_get_n(args_vl, n).unwrap());
                     }
-                    let formatted = match kind {
-                        MatchKind::Value => {
-                            let val = self.value_use(config, state, args[0], None);
-                            let block_args = arena
-                                .intersperse(
-                                    args.iter()
-                                        .skip(1)
-                                        .map(|v| self.value_use(config, state, *v, None)),
-                                    arena.text(",").append(arena.softline()),
-                                )
-                                .nest(1)
-                                .parens();
-                            let body = arena.nil().append(block_val).append(block_args);
-                            arena
-                                .nil()
-                                .append(arena.text("value"))
-                                .append(arena.space())
-                                .append(val)
-                                .append(arena.space())
-                                .append(arena.text("=>"))
-                                .append(arena.space())
-                                .append(arena.nil().append(body))
-                        }
-                        MatchKind::Type(ty) => {
-                            let block_args = arena
-                                .intersperse(
-                                    args.iter().map(|v| self.value_use(config, state, *v, None)),
-                                    arena.text(",").append(arena.softline()),
-                                )
-                                .nest(1)
-                                .parens();
-                            let body = arena.nil().append(block_val).append(block_args);
-                            arena
-                                .nil()
-                                .append(arena.text("is_type"))
-                                .append(arena.space())
-                                .append(arena.text(type_to_text(ty)))
-                                .append(arena.space())
-                                .append(arena.text("=>"))
-                                .append(arena.space())
-                                .append(arena.nil().append(body))
+                    let pattern = match kind {
+                        MatchKind::Value => arena
+                            .text("value")
+                            .append(arena.space())
+                            .append(self.value_use(config, state, args[0], None)),
+                        MatchKind::Type(BasicType::Map) => arena.text("type %{}"),
+                        MatchKind::Type(BasicType::Tuple(arity)) => {
+                            arena.text(format!("type {{}} arity {}", arity))
                         }
+                        MatchKind::Type(ty) => arena
+                            .text("type")
+                            .append(arena.space())
+                            .append(arena.text(type_to_text(ty))),
                         MatchKind::Binary(ref spec) => {
-                            let doc = binary_specifier_to_doc(arena, spec);
-                            arena.nil().append(doc)
-                        }
-                        MatchKind::Tuple(arity) => {
-                            let block_args = arena
-                                .intersperse(
-                                    args.iter().map(|v| self.value_use(config, state, *v, None)),
-                                    arena.text(",").append(arena.softline()),
-                                )
-                                .nest(1)
-                                .parens();
-                            let body = arena.nil().append(block_val).append(block_args);
-                            arena
-                                .nil()
-                                .append(arena.text("{}"))
-                                .append(arena.space())
-                                .append(arena.text(format!("arity {}", arity)))
-                                .append(arena.space())
-                                .append(arena.text("=>"))
-                                .append(arena.space())
-                                .append(arena.nil().append(body))
-                        }
-                        MatchKind::ListCell => {
-                            let block_args = arena
-                                .intersperse(
-                                    args.iter().map(|v| self.value_use(config, state, *v, None)),
-                                    arena.text(",").append(arena.softline()),
-                                )
-                                .nest(1)
-                                .parens();
-                            let body = arena.nil().append(block_val).append(block_args);
-                            arena
-                                .nil()
-                                .append(arena.text("[]"))
-                                .append(arena.space())
-                                .append(arena.text("=>"))
-                                .append(arena.space())
-                                .append(arena.nil().append(body))
-                        }
-                        MatchKind::MapItem => {
-                            let val = self.value_use(config, state, args[0], None);
-                            let block_args = arena
-                                .intersperse(
-                                    args.iter()
-                                        .skip(1)
-                                        .map(|v| self.value_use(config, state, *v, None)),
-                                    arena.text(",").append(arena.softline()),
-                                )
-                                .nest(1)
-                                .parens();
-                            let body = arena.nil().append(block_val).append(block_args);
-                            arena
-                                .nil()
-                                .append(val)
-                                .append(arena.space())
-                                .append(arena.text("=>"))
-                                .append(arena.space())
-                                .append(arena.nil().append(body))
-                        }
-                        MatchKind::Wildcard => {
-                            let block_args = arena
-                                .intersperse(
-                                    args.iter().map(|v| self.value_use(config, state, *v, None)),
-                                    arena.text(",").append(arena.softline()),
-                                )
-                                .nest(1)
-                                .parens();
-                            let body = arena.nil().append(block_val).append(block_args);
-                            arena
-                                .nil()
-                                .append(arena.text("_"))
-                                .append(arena.space())
-                                .append(arena.text("=>"))
-                                .append(arena.space())
-                                .append(arena.nil().append(body))
+                            let doc = arena.nil().append(binary_specifier_to_doc(arena, spec));
+                            if let Some(size) = args.get(0) {
+                                doc.append(arena.space())
+                                    .append(arena.text("size"))
+                                    .append(arena.space())
+                                    .append(self.value_use(config, state, *size, None))
+                            } else {
+                                doc
+                            }
                         }
+                        MatchKind::Tuple(arity) => arena.text(format!("{{}} arity {}", arity)),
+                        MatchKind::ListCell => arena.text("[]"),
+                        MatchKind::MapItem => arena
+                            .text("%{")
+                            .append(self.value_use(config, state, args[0], None))
+                            .append(arena.text("}")),
+                        MatchKind::Wildcard => arena.text("_"),
                     };
+                    let formatted = pattern
+                        .append(arena.space())
+                        .append(arena.text("=>"))
+                        .append(arena.space())
+                        .append(block_val)
+                        .append(arena.text(";"));
                     branches_formatted.push(formatted.indent(2));
                 }
 
@@ -279,6 +201,65 @@ where
                             .braces(),
                     )
             }
+            OpKind::MapPut { action } => {
+                let ok = self.value_use(config, state, reads[0], None);
+                let fail = self.value_use(config, state, reads[1], None);
+                let map = self.value_use(config, state, reads[2], None);
+
+                let mut entries = Vec::with_capacity(action.len());
+                for (n, action) in action.iter().enumerate() {
+                    let key = self.value_use(config, state, reads[3 + n * 2], None);
+                    let value = self.value_use(config, state, reads[4 + n * 2], None);
+                    let op = match action {
+                        MapPutUpdate::Put => "=>",
+                        MapPutUpdate::Update => ":=",
+                    };
+                    entries.push(
+                        arena
+                            .nil()
+                            .append(key)
+                            .append(arena.space())
+                            .append(arena.text(op))
+                            .append(arena.space())
+                            .append(value),
+                    );
+                }
+
+                arena
+                    .nil()
+                    .append(arena.text("map_put"))
+                    .append(arena.space())
+                    .append(map)
+                    .append(arena.space())
+                    .append(
+                        arena
+                            .intersperse(entries, arena.text(",").append(arena.space()))
+                            .nest(1)
+                            .enclose("%{", "}"),
+                    )
+                    .append(arena.space())
+                    .append(arena.text("=>"))
+                    .append(arena.space())
+                    .append(ok)
+                    .append(arena.space())
+                    .append(arena.text("except"))
+                    .append(arena.space())
+                    .append(fail)
+            }
+            OpKind::TraceConstruct => {
+                assert!(reads.len() == 2);
+                let cont = self.value_use(config, state, reads[0], None);
+                let trace = self.value_use(config, state, reads[1], None);
+                arena
+                    .nil()
+                    .append(arena.text("trace_construct"))
+                    .append(arena.space())
+                    .append(trace)
+                    .append(arena.space())
+                    .append(arena.text("=>"))
+                    .append(arena.space())
+                    .append(cont)
+            }
             OpKind::Call(CallKind::Function) => {
                 let callee_val = self.value_use(config, state, reads[0], None);
                 let call_args = arena
@@ -394,10 +375,6 @@ where
                     arena.as_string(op.name()).append(call_args)
                 }
             }
-            _ => {
-                warn!("Unimplemented printer for OP: {:?}", op);
-                arena.text("unknown")
-            }
         };
 
         op_doc.append(arena.text(";")).into_doc()
@@ -0,0 +1,39 @@
+use libeir_diagnostics::{CodeMap, LineIndex, SourceId};
+
+use crate::{Block, Function};
+
+/// A line in an original source file, used to relate blocks back to
+/// the code they were lowered from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SourceLine {
+    pub source_id: SourceId,
+    pub line: LineIndex,
+}
+
+impl SourceLine {
+    /// The source line the location of the given block starts on.
+    /// Returns `None` if the block has no location pointing into a
+    /// file in the codemap.
+    pub fn of_block(fun: &Function, codemap: &CodeMap, block: Block) -> Option<Self> {
+        let span = fun.locations.primary_span(fun.block_location(block))?;
+        let file = codemap.get(span.source_id())?;
+        Some(SourceLine {
+            source_id: span.source_id(),
+            line: file.line_index(span.start_index()),
+        })
+    }
+
+    /// Formats the line as `file:line: text`, with a one based line
+    /// number and surrounding whitespace trimmed from the text.
+    pub fn describe(&self, codemap: &CodeMap) -> Option<String> {
+        let file = codemap.get(self.source_id)?;
+        let span = file.line_span(self.line).ok()?;
+        let text = file.source_slice(span).ok()?;
+        Some(format!(
+            "{}:{}: {}",
+            file.name(),
+            self.line.number(),
+            text.trim()
+        ))
+    }
+}
@@ -0,0 +1,351 @@
+//! Writing the IR of functions to disk after every pass, used to find
+//! out which pass broke a function.
+//!
+//! For every dumped function a directory named `module.name.arity` is
+//! created in the dump directory. It contains `00_input.eir` with the
+//! function as it was before the pipeline, and a `NN_pass.eir` for
+//! each pass run, `NN` being the position of the pass in the pipeline.
+//! When diffs are enabled, each pass file is accompanied by a
+//! `NN_pass.diff` with a unified diff against the previous file
+//! written.
+
+use std::io;
+use std::path::PathBuf;
+
+use libeir_ir::{Function, FunctionIdent};
+
+pub struct DumpConfig {
+    /// Directory the dumps are written to. Created if missing.
+    pub dir: PathBuf,
+    /// Only functions with these idents are dumped. If empty, every
+    /// function is dumped.
+    pub functions: Vec<FunctionIdent>,
+    /// Only the output of passes with these names is written. If
+    /// empty, the output of every pass is written.
+    pub passes: Vec<String>,
+    /// Write a unified diff against the previously written output next
+    /// to every dumped pass output.
+    pub diff: bool,
+}
+
+impl DumpConfig {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        DumpConfig {
+            dir: dir.into(),
+            functions: Vec::new(),
+            passes: Vec::new(),
+            diff: false,
+        }
+    }
+
+    pub fn dumps_function(&self, ident: &FunctionIdent) -> bool {
+        self.functions.is_empty() || self.functions.contains(ident)
+    }
+
+    pub fn dumps_pass(&self, name: &str) -> bool {
+        self.passes.is_empty() || self.passes.iter().any(|p| p == name)
+    }
+}
+
+/// Dump state for a single function while it runs through the
+/// pipeline.
+pub(crate) struct FunctionDump<'a> {
+    config: &'a DumpConfig,
+    dir: PathBuf,
+    prev_name: String,
+    prev_text: String,
+}
+
+impl<'a> FunctionDump<'a> {
+    pub fn new(config: &'a DumpConfig, fun: &Function) -> io::Result<Self> {
+        let ident = fun.ident();
+        let dir = config.dir.join(format!(
+            "{}.{}.{}",
+            ident.module.name, ident.name.name, ident.arity
+        ));
+        std::fs::create_dir_all(&dir)?;
+
+        let text = fun.to_text_standard();
+        std::fs::write(dir.join("00_input.eir"), &text)?;
+
+        Ok(FunctionDump {
+            config,
+            dir,
+            prev_name: "00_input.eir".to_string(),
+            prev_text: text,
+        })
+    }
+
+    pub fn after_pass(&mut self, num: usize, pass: &str, fun: &Function) -> io::Result<()> {
+        let text = fun.to_text_standard();
+        let name = format!("{:02}_{}", num + 1, pass);
+
+        if self.config.dumps_pass(pass) {
+            let file_name = format!("{}.eir", name);
+            std::fs::write(self.dir.join(&file_name), &text)?;
+
+            if self.config.diff {
+                let diff = unified_diff(&self.prev_name, &file_name, &self.prev_text, &text);
+                std::fs::write(self.dir.join(format!("{}.diff", name)), diff)?;
+            }
+
+            // Diffs are against the previous file written, passes that
+            // are filtered out are folded into the next dumped one.
+            self.prev_name = file_name;
+            self.prev_text = text;
+        }
+
+        Ok(())
+    }
+}
+
+const DIFF_CONTEXT: usize = 3;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum DiffLine {
+    Same(usize, usize),
+    Removed(usize),
+    Added(usize),
+}
+impl DiffLine {
+    fn is_same(self) -> bool {
+        match self {
+            DiffLine::Same(_, _) => true,
+            _ => false,
+        }
+    }
+    fn in_old(self) -> bool {
+        match self {
+            DiffLine::Added(_) => false,
+            _ => true,
+        }
+    }
+    fn in_new(self) -> bool {
+        match self {
+            DiffLine::Removed(_) => false,
+            _ => true,
+        }
+    }
+}
+
+/// Line based diff in the unified format. Empty if the texts are
+/// equal.
+pub fn unified_diff(old_name: &str, new_name: &str, old: &str, new: &str) -> String {
+    use std::fmt::Write;
+
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let ops = diff_lines(&old_lines, &new_lines);
+
+    let mut out = String::new();
+    if ops.iter().all(|op| op.is_same()) {
+        return out;
+    }
+    writeln!(out, "--- {}", old_name).unwrap();
+    writeln!(out, "+++ {}", new_name).unwrap();
+
+    let mut idx = 0;
+    while idx < ops.len() {
+        // Find the next change, and extend the hunk until there is a
+        // run of unchanged lines long enough to split on.
+        let first_change = match ops[idx..].iter().position(|op| !op.is_same()) {
+            Some(pos) => idx + pos,
+            None => break,
+        };
+        let start = first_change.saturating_sub(DIFF_CONTEXT).max(idx);
+
+        let mut end = first_change;
+        let mut same_run = 0;
+        for (pos, op) in ops.iter().enumerate().skip(first_change) {
+            if op.is_same() {
+                same_run += 1;
+                if same_run > DIFF_CONTEXT * 2 {
+                    break;
+                }
+            } else {
+                same_run = 0;
+                end = pos;
+            }
+        }
+        let end = (end + DIFF_CONTEXT + 1).min(ops.len());
+        let hunk = &ops[start..end];
+
+        let old_start = ops[..start].iter().filter(|op| op.in_old()).count();
+        let new_start = ops[..start].iter().filter(|op| op.in_new()).count();
+        let old_len = hunk.iter().filter(|op| op.in_old()).count();
+        let new_len = hunk.iter().filter(|op| op.in_new()).count();
+        writeln!(
+            out,
+            "@@ -{},{} +{},{} @@",
+            old_start + if old_len == 0 { 0 } else { 1 },
+            old_len,
+            new_start + if new_len == 0 { 0 } else { 1 },
+            new_len
+        )
+        .unwrap();
+
+        for op in hunk {
+            match *op {
+                DiffLine::Same(o, _) => writeln!(out, " {}", old_lines[o]).unwrap(),
+                DiffLine::Removed(o) => writeln!(out, "-{}", old_lines[o]).unwrap(),
+                DiffLine::Added(n) => writeln!(out, "+{}", new_lines[n]).unwrap(),
+            }
+        }
+
+        idx = end;
+    }
+
+    out
+}
+
+/// Longest common subsequence diff. Common prefixes and suffixes are
+/// stripped first, passes usually only touch a small part of a
+/// function.
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<DiffLine> {
+    let prefix = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|(o, n)| o == n)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(o, n)| o == n)
+        .count();
+
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    // lcs[i][j] is the length of the common subsequence of
+    // old_mid[i..] and new_mid[j..].
+    let width = new_mid.len() + 1;
+    let mut lcs = vec![0u32; (old_mid.len() + 1) * width];
+    for i in (0..old_mid.len()).rev() {
+        for j in (0..new_mid.len()).rev() {
+            lcs[i * width + j] = if old_mid[i] == new_mid[j] {
+                lcs[(i + 1) * width + j + 1] + 1
+            } else {
+                lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut ops = Vec::with_capacity(old.len().max(new.len()));
+    ops.extend((0..prefix).map(|n| DiffLine::Same(n, n)));
+
+    let (mut i, mut j) = (0, 0);
+    while i < old_mid.len() || j < new_mid.len() {
+        if i < old_mid.len() && j < new_mid.len() && old_mid[i] == new_mid[j] {
+            ops.push(DiffLine::Same(prefix + i, prefix + j));
+            i += 1;
+            j += 1;
+        } else if i < old_mid.len()
+            && (j == new_mid.len() || lcs[(i + 1) * width + j] >= lcs[i * width + j + 1])
+        {
+            ops.push(DiffLine::Removed(prefix + i));
+            i += 1;
+        } else {
+            ops.push(DiffLine::Added(prefix + j));
+            j += 1;
+        }
+    }
+
+    let old_suffix = old.len() - suffix;
+    let new_suffix = new.len() - suffix;
+    ops.extend((0..suffix).map(|n| DiffLine::Same(old_suffix + n, new_suffix + n)));
+
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{unified_diff, DumpConfig, FunctionDump};
+
+    use libeir_ir::parse_function_unwrap;
+
+    #[test]
+    fn diff_skips_filtered_passes() {
+        let input = parse_function_unwrap(
+            "
+a'foo':a'bar'/1 {
+    entry(%ret, %thr, %a):
+        %ret(%a);
+}
+",
+        );
+        let skipped = parse_function_unwrap(
+            "
+a'foo':a'bar'/1 {
+    entry(%ret, %thr, %a):
+        %ret({%a});
+}
+",
+        );
+        let kept = parse_function_unwrap(
+            "
+a'foo':a'bar'/1 {
+    entry(%ret, %thr, %a):
+        %ret({%a, %a});
+}
+",
+        );
+
+        let dir = std::env::temp_dir().join(format!("eir_dump_{}", std::process::id()));
+        let mut config = DumpConfig::new(&dir);
+        config.passes.push("kept".to_string());
+        config.diff = true;
+
+        let mut dump = FunctionDump::new(&config, &input).unwrap();
+        dump.after_pass(0, "skipped", &skipped).unwrap();
+        dump.after_pass(1, "kept", &kept).unwrap();
+
+        let fun_dir = dir.join("foo.bar.1");
+        let diff = std::fs::read_to_string(fun_dir.join("02_kept.diff")).unwrap();
+        assert!(!fun_dir.join("01_skipped.eir").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let expected = unified_diff(
+            "00_input.eir",
+            "02_kept.eir",
+            &input.to_text_standard(),
+            &kept.to_text_standard(),
+        );
+        assert_eq!(diff, expected);
+    }
+
+    #[test]
+    fn equal() {
+        assert_eq!(unified_diff("a", "b", "x\ny\n", "x\ny\n"), "");
+    }
+
+    #[test]
+    fn single_change() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let new = "1\n2\n3\n4\nfive\n6\n7\n8\n9\n";
+        assert_eq!(
+            unified_diff("old", "new", old, new),
+            "--- old\n+++ new\n@@ -2,7 +2,7 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n"
+        );
+    }
+
+    #[test]
+    fn separate_hunks() {
+        let old = "a\n1\n2\n3\n4\n5\n6\n7\n8\nb\n";
+        let new = "A\n1\n2\n3\n4\n5\n6\n7\n8\nB\n";
+        assert_eq!(
+            unified_diff("old", "new", old, new),
+            "--- old\n+++ new\n\
+             @@ -1,4 +1,4 @@\n-a\n+A\n 1\n 2\n 3\n\
+             @@ -7,4 +7,4 @@\n 6\n 7\n 8\n-b\n+B\n"
+        );
+    }
+
+    #[test]
+    fn insert_into_empty() {
+        assert_eq!(
+            unified_diff("old", "new", "", "x\n"),
+            "--- old\n+++ new\n@@ -0,0 +1,1 @@\n+x\n"
+        );
+    }
+}
@@ -1,11 +1,16 @@
 #![deny(warnings)]
 
-use log::{info, trace};
+use log::{info, trace, warn};
 
-use libeir_ir::{FunctionBuilder, Module};
+use libeir_ir::text::HtmlExport;
+use libeir_ir::{Function, FunctionBuilder, Module};
 
 pub mod util;
 
+mod dump;
+use self::dump::FunctionDump;
+pub use self::dump::{unified_diff, DumpConfig};
+
 mod compile_pattern;
 pub use self::compile_pattern::CompilePatternPass;
 
@@ -29,11 +34,15 @@ enum PassType {
 
 pub struct PassManager {
     passes: Vec<PassType>,
+    dump: Option<DumpConfig>,
 }
 
 impl PassManager {
     pub fn new() -> Self {
-        PassManager { passes: Vec::new() }
+        PassManager {
+            passes: Vec::new(),
+            dump: None,
+        }
     }
 
     pub fn push_function_pass<P>(&mut self, pass: P)
@@ -43,24 +52,64 @@ impl PassManager {
         self.passes.push(PassType::Function(Box::new(pass)));
     }
 
+    /// Writes the text of functions to disk before and after every
+    /// pass. See `DumpConfig` for the layout.
+    pub fn set_dump(&mut self, config: DumpConfig) {
+        self.dump = Some(config);
+    }
+
     pub fn run(&mut self, module: &mut Module) {
         for fun_def in module.function_iter_mut() {
             let fun = fun_def.function_mut();
-            let ident = *fun.ident();
+            self.run_function(fun, &mut |_, _| ());
+        }
+    }
 
-            let mut b = FunctionBuilder::new(fun);
-            b.fun().graph_validate_global();
-            trace!("{}", b.fun().to_text_standard());
-            for pass in self.passes.iter_mut() {
-                match pass {
-                    PassType::Function(fun_pass) => {
-                        info!("======== {} FUNCTION_PASS: {}", ident, fun_pass.name());
-                        fun_pass.run_function_pass(&mut b);
-                        trace!("{}", b.fun().to_text_standard());
+    /// Runs the pipeline on a single function, adding the function
+    /// before and after each pass to a html export.
+    pub fn run_html_export(&mut self, fun: &mut Function) -> HtmlExport {
+        let mut export = HtmlExport::new(&format!("{}", fun.ident()));
+        export.push_function("input", fun);
+        self.run_function(fun, &mut |pass_name, fun| {
+            export.push_function(&format!("after {}", pass_name), fun);
+        });
+        export
+    }
+
+    fn run_function(&mut self, fun: &mut Function, after_pass: &mut dyn FnMut(&str, &Function)) {
+        let ident = *fun.ident();
+
+        let mut dump = match &self.dump {
+            Some(config) if config.dumps_function(&ident) => match FunctionDump::new(config, fun) {
+                Ok(dump) => Some(dump),
+                Err(err) => {
+                    warn!("failed to dump {}: {}", ident, err);
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        let mut b = FunctionBuilder::new(fun);
+        b.fun().graph_validate_global();
+        trace!("{}", b.fun().to_text_standard());
+        for (num, pass) in self.passes.iter_mut().enumerate() {
+            match pass {
+                PassType::Function(fun_pass) => {
+                    info!("======== {} FUNCTION_PASS: {}", ident, fun_pass.name());
+                    fun_pass.run_function_pass(&mut b);
+                    trace!("{}", b.fun().to_text_standard());
+                    after_pass(fun_pass.name(), b.fun());
+
+                    if let Some(inner) = &mut dump {
+                        if let Err(err) = inner.after_pass(num, fun_pass.name(), b.fun()) {
+                            warn!("failed to dump {}: {}", ident, err);
+                            dump = None;
+                        }
                     }
                 }
-                b.fun().graph_validate_global();
             }
+            b.fun().graph_validate_global();
         }
     }
 }
@@ -1,15 +1,165 @@
 use libeir_diagnostics::SourceSpan;
-use libeir_intern::Ident;
+use libeir_intern::{Ident, Symbol};
 use libeir_ir::ToPrimitive;
 use libeir_util_number::Float;
-use libeir_util_parse::MessageIgnore;
+use libeir_util_parse::{ErrorReceiver, MessageIgnore};
 
 use std::convert::TryInto;
 
 use crate::ast::Name;
+use crate::lower::LowerError;
 use crate::parser::ast;
 use libeir_util_parse_listing::ast as aast;
 
+struct LowerCtx<'a> {
+    gen: ast::NodeIdGenerator,
+    errors: &'a mut dyn ErrorReceiver<E = LowerError, W = LowerError>,
+}
+impl<'a> LowerCtx<'a> {
+    fn unsupported(&mut self, span: SourceSpan, kind: &'static str, name: &str) {
+        self.errors.error(LowerError::AbstrUnsupported {
+            span,
+            kind,
+            name: name.to_string(),
+        });
+    }
+
+    fn malformed(&mut self, span: SourceSpan, kind: &'static str) {
+        self.errors.error(LowerError::AbstrMalformed { span, kind });
+    }
+
+    /// Stands in for an expression that could not be lowered. An
+    /// error has already been reported for it, so the module will
+    /// never make it past lowering.
+    fn sentinel(&mut self, span: SourceSpan) -> ast::Expr {
+        ast::Expr::Literal(ast::Literal::Atom(
+            self.gen.next(),
+            Ident::new(Symbol::intern("undefined"), span),
+        ))
+    }
+
+    /// Type counterpart of `sentinel`, `any()`.
+    fn sentinel_type(&mut self, span: SourceSpan) -> ast::Type {
+        ast::Type::Generic {
+            span,
+            fun: Ident::new(Symbol::intern("any"), span),
+            params: Vec::new(),
+        }
+    }
+
+    // The `expect_*` accessors report `item` as a malformed `kind` when
+    // it does not have the expected shape. Every `None` they return has
+    // been reported, so callers can give up with `?`.
+
+    /// A tuple of at least `len` entries.
+    fn expect_tuple<'i>(
+        &mut self,
+        item: &'i aast::Item,
+        len: usize,
+        kind: &'static str,
+    ) -> Option<&'i aast::Tuple> {
+        match item.tuple() {
+            Some(tuple) if tuple.entries.len() >= len => Some(tuple),
+            _ => {
+                self.malformed(item.span(), kind);
+                None
+            }
+        }
+    }
+
+    /// A `{Tag, ...}` tuple of at least `len` entries, with its tag.
+    fn expect_form<'i>(
+        &mut self,
+        item: &'i aast::Item,
+        len: usize,
+        kind: &'static str,
+    ) -> Option<(Ident, &'i aast::Tuple)> {
+        let tuple = self.expect_tuple(item, len.max(1), kind)?;
+        let tag = self.expect_atom(&tuple.entries[0], kind)?;
+        Some((tag, tuple))
+    }
+
+    /// Checks that the `kind` form `tuple` has exactly `len` entries.
+    fn expect_len(&mut self, tuple: &aast::Tuple, len: usize, kind: &'static str) -> Option<()> {
+        if tuple.entries.len() == len {
+            Some(())
+        } else {
+            self.malformed(tuple.span, kind);
+            None
+        }
+    }
+
+    fn expect_atom(&mut self, item: &aast::Item, kind: &'static str) -> Option<Ident> {
+        let atom = item.atom();
+        if atom.is_none() {
+            self.malformed(item.span(), kind);
+        }
+        atom
+    }
+
+    /// {atom, L, Atom}
+    fn expect_atom_literal(&mut self, item: &aast::Item, kind: &'static str) -> Option<Ident> {
+        let atom = atom(item);
+        if atom.is_none() {
+            self.malformed(item.span(), kind);
+        }
+        atom
+    }
+
+    fn expect_integer<'i>(
+        &mut self,
+        item: &'i aast::Item,
+        kind: &'static str,
+    ) -> Option<&'i aast::Int> {
+        let int = item.integer();
+        if int.is_none() {
+            self.malformed(item.span(), kind);
+        }
+        int
+    }
+
+    fn expect_arity(&mut self, item: &aast::Item, kind: &'static str) -> Option<usize> {
+        let arity = item.integer().and_then(|int| int.integer.to_usize());
+        if arity.is_none() {
+            self.malformed(item.span(), kind);
+        }
+        arity
+    }
+
+    fn expect_char(&mut self, item: &aast::Item, kind: &'static str) -> Option<char> {
+        let c = item
+            .integer()
+            .and_then(|int| int.integer.to_u32())
+            .and_then(|c| c.try_into().ok());
+        if c.is_none() {
+            self.malformed(item.span(), kind);
+        }
+        c
+    }
+
+    fn expect_float(&mut self, item: &aast::Item, kind: &'static str) -> Option<Float> {
+        let float = item.float().and_then(|float| Float::new(float.float).ok());
+        if float.is_none() {
+            self.malformed(item.span(), kind);
+        }
+        float
+    }
+
+    fn expect_list<'i>(
+        &mut self,
+        item: &'i aast::Item,
+        kind: &'static str,
+    ) -> Option<Vec<&'i aast::Item>> {
+        match item.list_iter() {
+            Some(items) => Some(items.collect()),
+            None => {
+                self.malformed(item.span(), kind);
+                None
+            }
+        }
+    }
+}
+
 fn to_list_expr(
     id_gen: &mut ast::NodeIdGenerator,
     span: SourceSpan,
@@ -27,321 +177,519 @@ fn to_list_expr(
     acc
 }
 
-pub fn lower(root: &aast::Root) -> ast::Module {
-    let mut toplevel: Vec<ast::TopLevel> = Vec::new();
+/// Lowers a module in the abstract format, as printed by
+/// `compile:file(_, ['P'])` or `epp:parse_file/2`, into the AST.
+///
+/// Forms that can not be lowered are reported as errors and replaced,
+/// lowering continues with the rest of the module.
+pub fn lower(
+    errors: &mut dyn ErrorReceiver<E = LowerError, W = LowerError>,
+    root: &aast::Root,
+) -> ast::Module {
+    let mut ctx = LowerCtx {
+        gen: ast::NodeIdGenerator::new(),
+        errors,
+    };
+    let ctx = &mut ctx;
 
-    let mut id_gen = ast::NodeIdGenerator::new();
+    let mut toplevel: Vec<ast::TopLevel> = Vec::new();
 
     let module_span = root.span();
     let mut module_name = None;
     let mut eof = false;
 
     for item in root.items.iter() {
-        let tuple = item.tuple().unwrap();
-        let name_ident = tuple.entries[0].atom().unwrap();
+        let (name_ident, tuple) = match ctx.expect_form(item, 2, "form") {
+            Some(form) => form,
+            None => continue,
+        };
         let name = name_ident.as_str();
 
-        let _line = tuple.entries[1].integer().unwrap();
-
-        assert!(!eof);
+        if eof {
+            ctx.malformed(tuple.span, "form after eof");
+        }
 
         match &*name {
             "attribute" => {
-                let attr_ident = tuple.entries[2].atom().unwrap();
-                let attr = attr_ident.as_str();
-
-                match &*attr {
-                    "file" => (),
-                    "module" => {
-                        module_name = Some(tuple.entries[3].atom().unwrap());
-                    }
-                    "export" => {
-                        let exports = tuple.entries[3]
-                            .list_iter()
-                            .unwrap()
-                            .map(|item| {
-                                let item_tup = item.tuple().unwrap();
-                                let name = item_tup.entries[0].atom().unwrap();
-                                let arity = item_tup.entries[1].integer().unwrap();
-                                ast::PartiallyResolvedFunctionName {
-                                    span: item_tup.span,
-                                    id: id_gen.next(),
-                                    function: name,
-                                    arity: arity.integer.to_usize().unwrap(),
-                                }
-                            })
-                            .collect();
-                        toplevel.push(ast::TopLevel::Attribute(ast::Attribute::Export(
-                            tuple.span, exports,
-                        )));
-                    }
-                    "compile" => {
-                        let opts = tuple.entries[3]
-                            .list_iter()
-                            .unwrap()
-                            .map(|item| match item {
-                                aast::Item::Atom(ident) => {
-                                    ast::Expr::Literal(ast::Literal::Atom(id_gen.next(), *ident))
-                                }
-                                _ => unimplemented!("{:?}", item),
-                            })
-                            .collect();
-                        toplevel.push(ast::TopLevel::Attribute(ast::Attribute::Compile(
-                            tuple.span,
-                            to_list_expr(&mut id_gen, tuple.span, opts),
-                        )))
-                    }
-                    "spec" => {
-                        continue;
-                    }
-                    "dialyzer" => {
-                        continue;
-                    }
-                    "export_type" => {
-                        continue;
-                    }
-                    "type" => {
-                        continue;
-                    }
-                    "opaque" => {
-                        continue;
-                        //let inner_tup = tuple.entries[3].tuple().unwrap();
-                        //let def = ast::TypeDef {
-                        //    span,
-                        //    opaque: true,
-                        //    name: inner_tup.entries[0].atom().unwrap(),
-
-                        //};
-                        //toplevel.push(ast::TopLevel::Attribute(
-                        //    ast::Attribute::Type(def)));
-                    }
-                    "record" => {
-                        let rec_tup = tuple.entries[3].tuple().unwrap();
-
-                        let name = rec_tup.entries[0].atom().unwrap();
-                        let fields = rec_tup.entries[1]
-                            .list_iter()
-                            .unwrap()
-                            .map(|v| lower_record_field(&mut id_gen, v))
-                            .collect();
-
-                        let record = ast::Record {
-                            span: rec_tup.span,
-                            id: id_gen.next(),
-                            name,
-                            fields,
-                        };
-                        toplevel.push(ast::TopLevel::Record(record));
-                    }
-                    "behaviour" => toplevel.push(ast::TopLevel::Attribute(
-                        ast::Attribute::Behaviour(tuple.span, tuple.entries[3].atom().unwrap()),
-                    )),
-                    n => unimplemented!("attribute {} {:?}", n, tuple),
+                if let Some(attr) = lower_attribute(ctx, tuple, &mut module_name) {
+                    toplevel.push(attr);
                 }
             }
             "function" => {
-                let fun_name = tuple.entries[2].atom().unwrap();
-                let fun_arity = tuple.entries[3]
-                    .integer()
-                    .unwrap()
-                    .integer
-                    .to_usize()
-                    .unwrap();
-
-                let clauses = tuple.entries[4]
-                    .list_iter()
-                    .unwrap()
-                    .map(|clause| lower_function_clause(&mut id_gen, clause))
-                    .collect();
-
-                toplevel.push(ast::TopLevel::Function(ast::NamedFunction {
-                    span: tuple.span,
-                    id: id_gen.next(),
-                    name: Name::Atom(fun_name),
-                    arity: fun_arity,
-                    clauses: clauses,
-                    spec: None,
-                }));
+                if let Some(fun) = lower_function(ctx, tuple) {
+                    toplevel.push(fun);
+                }
             }
             "eof" => {
                 eof = true;
             }
-            n => unimplemented!("{}", n),
+            n => ctx.unsupported(tuple.span, "form", n),
         }
     }
 
+    let module_name = match module_name {
+        Some(name) => name,
+        None => {
+            ctx.malformed(module_span, "module without a module attribute");
+            Ident::new(Symbol::intern("undefined"), module_span)
+        }
+    };
+
     let mut errors = MessageIgnore::new();
     let module = ast::Module::new(
         &mut errors,
         module_span,
-        &mut id_gen,
-        module_name.unwrap(),
+        &mut ctx.gen,
+        module_name,
         toplevel,
     );
-    assert!(!errors.failed());
+    // Forms the source frontend would reject, duplicate definitions for
+    // example.
+    if errors.failed() {
+        ctx.malformed(module_span, "module");
+    }
     module
 }
 
-fn lower_record_field(gen: &mut ast::NodeIdGenerator, tup_item: &aast::Item) -> ast::RecordField {
-    let tup = tup_item.tuple().unwrap();
+/// {function, L, Name, Arity, Clauses}
+fn lower_function(ctx: &mut LowerCtx, tuple: &aast::Tuple) -> Option<ast::TopLevel> {
+    ctx.expect_len(tuple, 5, "function")?;
+    let name = ctx.expect_atom(&tuple.entries[2], "function name")?;
+    let arity = ctx.expect_arity(&tuple.entries[3], "function arity")?;
+    let clauses = lower_function_clauses(ctx, None, &tuple.entries[4])?;
+
+    Some(ast::TopLevel::Function(ast::NamedFunction {
+        span: tuple.span,
+        id: ctx.gen.next(),
+        name: Name::Atom(name),
+        arity,
+        clauses,
+        spec: None,
+    }))
+}
 
-    assert!(&*tup.entries[0].atom().unwrap().as_str() == "record_field");
-    assert!(tup.entries.len() == 3 || tup.entries.len() == 4);
+/// {attribute, L, Name, Value}
+fn lower_attribute(
+    ctx: &mut LowerCtx,
+    tuple: &aast::Tuple,
+    module_name: &mut Option<Ident>,
+) -> Option<ast::TopLevel> {
+    ctx.expect_len(tuple, 4, "attribute")?;
+    let attr_ident = ctx.expect_atom(&tuple.entries[2], "attribute name")?;
+    let attr = attr_ident.as_str();
+    let value = &tuple.entries[3];
+    let span = tuple.span;
+
+    let attribute = match &*attr {
+        "file" => return None,
+        // Dropped by the source frontend as well
+        "dialyzer" => return None,
+        "module" => {
+            match value.atom() {
+                Some(name) => *module_name = Some(name),
+                None => ctx.unsupported(value.span(), "attribute", "parameterized module"),
+            }
+            return None;
+        }
+        "export" => ast::Attribute::Export(span, lower_function_names(ctx, value)?),
+        "export_type" => ast::Attribute::ExportType(span, lower_function_names(ctx, value)?),
+        "import" => {
+            let import = ctx.expect_tuple(value, 2, "import")?;
+            let module = ctx.expect_atom(&import.entries[0], "import")?;
+            let names = lower_function_names(ctx, &import.entries[1])?;
+            ast::Attribute::Import(span, module, names)
+        }
+        "compile" => {
+            let opts = match value.list_iter() {
+                Some(items) => items.map(|item| lower_term(ctx, item)).collect(),
+                None => vec![lower_term(ctx, value)],
+            };
+            ast::Attribute::Compile(span, to_list_expr(&mut ctx.gen, span, opts))
+        }
+        "on_load" => ast::Attribute::OnLoad(span, lower_function_name(ctx, value)?),
+        "vsn" => ast::Attribute::Vsn(span, lower_term(ctx, value)),
+        "author" => ast::Attribute::Author(span, lower_term(ctx, value)),
+        "behaviour" | "behavior" => {
+            ast::Attribute::Behaviour(span, ctx.expect_atom(value, "behaviour")?)
+        }
+        "spec" | "callback" => {
+            let spec = ctx.expect_tuple(value, 2, "spec")?;
+            let name = ctx.expect_tuple(&spec.entries[0], 2, "function name")?;
+            let (module, function) = match name.entries.len() {
+                2 => (None, ctx.expect_atom(&name.entries[0], "function name")?),
+                3 => (
+                    Some(ctx.expect_atom(&name.entries[0], "module name")?),
+                    ctx.expect_atom(&name.entries[1], "function name")?,
+                ),
+                _ => {
+                    ctx.malformed(name.span, "function name");
+                    return None;
+                }
+            };
+            let sigs = ctx
+                .expect_list(&spec.entries[1], "spec")?
+                .into_iter()
+                .filter_map(|sig| lower_type_sig(ctx, sig))
+                .collect();
 
-    let name = atom(&tup.entries[2]);
+            if &*attr == "spec" {
+                ast::Attribute::Spec(ast::TypeSpec {
+                    span,
+                    module,
+                    function,
+                    sigs,
+                })
+            } else {
+                ast::Attribute::Callback(ast::Callback {
+                    span,
+                    optional: false,
+                    module,
+                    function,
+                    sigs,
+                })
+            }
+        }
+        "type" | "opaque" => {
+            let def = ctx.expect_tuple(value, 3, "type definition")?;
+            let name = ctx.expect_atom(&def.entries[0], "type name")?;
+            let ty = lower_type(ctx, &def.entries[1]);
+            // {var, L, Name}
+            let params = ctx
+                .expect_list(&def.entries[2], "type parameters")?
+                .into_iter()
+                .filter_map(|param| {
+                    let tup = ctx.expect_tuple(param, 3, "type parameter")?;
+                    let var = ctx.expect_atom(&tup.entries[2], "type parameter")?;
+                    Some(Name::Var(var))
+                })
+                .collect();
+            ast::Attribute::Type(ast::TypeDef {
+                span,
+                opaque: &*attr == "opaque",
+                name,
+                params,
+                ty,
+            })
+        }
+        "record" => {
+            let rec_tup = ctx.expect_tuple(value, 2, "record")?;
+
+            let name = ctx.expect_atom(&rec_tup.entries[0], "record name")?;
+            let fields = ctx
+                .expect_list(&rec_tup.entries[1], "record fields")?
+                .into_iter()
+                .filter_map(|v| lower_record_field(ctx, v))
+                .collect();
+
+            let record = ast::Record {
+                span: rec_tup.span,
+                id: ctx.gen.next(),
+                name,
+                fields,
+            };
+            return Some(ast::TopLevel::Record(record));
+        }
+        // Everything else is kept as a user defined attribute, just
+        // like the source frontend does.
+        _ => ast::Attribute::Custom(ast::UserAttribute {
+            span,
+            name: attr_ident,
+            value: lower_term(ctx, value),
+        }),
+    };
 
-    let value = tup.entries.get(3).map(|v| lower_expr(gen, v));
+    Some(ast::TopLevel::Attribute(attribute))
+}
 
-    ast::RecordField {
-        span: tup.span,
-        id: gen.next(),
-        name,
-        value,
-        ty: None,
+fn lower_function_names(
+    ctx: &mut LowerCtx,
+    list: &aast::Item,
+) -> Option<Vec<ast::PartiallyResolvedFunctionName>> {
+    let names = ctx
+        .expect_list(list, "function names")?
+        .into_iter()
+        .filter_map(|item| lower_function_name(ctx, item))
+        .collect();
+    Some(names)
+}
+
+/// {Name, Arity}
+fn lower_function_name(
+    ctx: &mut LowerCtx,
+    item: &aast::Item,
+) -> Option<ast::PartiallyResolvedFunctionName> {
+    let item_tup = ctx.expect_tuple(item, 2, "function name")?;
+    let name = ctx.expect_atom(&item_tup.entries[0], "function name")?;
+    let arity = ctx.expect_arity(&item_tup.entries[1], "function arity")?;
+    Some(ast::PartiallyResolvedFunctionName {
+        span: item_tup.span,
+        id: ctx.gen.next(),
+        function: name,
+        arity,
+    })
+}
+
+/// Lowers a plain term, as found in attribute values, into the
+/// corresponding literal expression.
+fn lower_term(ctx: &mut LowerCtx, item: &aast::Item) -> ast::Expr {
+    match item {
+        aast::Item::Atom(atom) => ast::Expr::Literal(ast::Literal::Atom(ctx.gen.next(), *atom)),
+        aast::Item::String(string) => {
+            ast::Expr::Literal(ast::Literal::String(ctx.gen.next(), *string))
+        }
+        aast::Item::Int(int) => ast::Expr::Literal(ast::Literal::Integer(
+            int.span,
+            ctx.gen.next(),
+            int.integer.clone(),
+        )),
+        aast::Item::Float(float) => match ctx.expect_float(item, "float") {
+            Some(value) => {
+                ast::Expr::Literal(ast::Literal::Float(float.span, ctx.gen.next(), value))
+            }
+            None => ctx.sentinel(float.span),
+        },
+        aast::Item::Tuple(tuple) => ast::Expr::Tuple(ast::Tuple {
+            span: tuple.span,
+            id: ctx.gen.next(),
+            elements: tuple
+                .entries
+                .iter()
+                .map(|entry| lower_term(ctx, entry))
+                .collect(),
+        }),
+        aast::Item::List(list) => {
+            let mut acc = match &list.tail {
+                Some(tail) => lower_term(ctx, tail),
+                None => ast::Expr::Nil(ast::Nil(list.span, ctx.gen.next())),
+            };
+            for head in list.heads.iter().rev() {
+                acc = ast::Expr::Cons(ast::Cons {
+                    span: list.span,
+                    id: ctx.gen.next(),
+                    head: Box::new(lower_term(ctx, head)),
+                    tail: Box::new(acc),
+                });
+            }
+            acc
+        }
     }
 }
 
-fn lower_function_clause(
-    gen: &mut ast::NodeIdGenerator,
-    clause: &aast::Item,
-) -> ast::FunctionClause {
-    let tup = clause.tuple().unwrap();
+/// {record_field, L, Name} or {record_field, L, Name, Value}, optionally
+/// wrapped in {typed_record_field, Field, Type}
+fn lower_record_field(ctx: &mut LowerCtx, tup_item: &aast::Item) -> Option<ast::RecordField> {
+    let (kind, tup) = ctx.expect_form(tup_item, 3, "record field")?;
 
-    assert!(tup.entries[0].atom().unwrap().as_str() == "clause");
-    let _line = tup.entries[1].integer().unwrap();
+    match &*kind.as_str() {
+        "typed_record_field" => {
+            let mut field = lower_record_field(ctx, &tup.entries[1])?;
+            field.ty = Some(lower_type(ctx, &tup.entries[2]));
+            Some(field)
+        }
+        "record_field" if tup.entries.len() <= 4 => {
+            // `_ = Value` sets the default of all fields not given
+            let name_tup = ctx.expect_tuple(&tup.entries[2], 3, "record field name")?;
+            let name = ctx.expect_atom(&name_tup.entries[2], "record field name")?;
+
+            let value = tup.entries.get(3).map(|v| lower_expr(ctx, v));
+
+            Some(ast::RecordField {
+                span: tup.span,
+                id: ctx.gen.next(),
+                name,
+                value,
+                ty: None,
+            })
+        }
+        _ => {
+            ctx.malformed(tup.span, "record field");
+            None
+        }
+    }
+}
 
-    let params = &tup.entries[2];
-    let guard = &tup.entries[3];
-    let body = &tup.entries[4];
+/// {clause, L, Patterns, Guards, Body}
+fn clause_parts<'i>(
+    ctx: &mut LowerCtx,
+    clause: &'i aast::Item,
+) -> Option<(Vec<&'i aast::Item>, &'i aast::Item, &'i aast::Item)> {
+    let (tag, tup) = ctx.expect_form(clause, 5, "clause")?;
+    if &*tag.as_str() != "clause" {
+        ctx.malformed(tup.span, "clause");
+        return None;
+    }
+    let patterns = ctx.expect_list(&tup.entries[2], "clause patterns")?;
+    Some((patterns, &tup.entries[3], &tup.entries[4]))
+}
+
+/// Lowers the clauses of a function or fun. `None` if there are none,
+/// or if they differ in arity.
+fn lower_function_clauses(
+    ctx: &mut LowerCtx,
+    name: Option<Name>,
+    list: &aast::Item,
+) -> Option<Vec<ast::FunctionClause>> {
+    let clauses: Vec<_> = ctx
+        .expect_list(list, "function clauses")?
+        .into_iter()
+        .filter_map(|clause| lower_function_clause(ctx, name.clone(), clause))
+        .collect();
+    let arity = match clauses.first() {
+        Some(clause) => clause.params.len(),
+        None => {
+            ctx.malformed(list.span(), "function clauses");
+            return None;
+        }
+    };
+    if clauses.iter().any(|clause| clause.params.len() != arity) {
+        ctx.malformed(list.span(), "function clauses");
+        return None;
+    }
+    Some(clauses)
+}
+
+fn lower_function_clause(
+    ctx: &mut LowerCtx,
+    name: Option<Name>,
+    clause: &aast::Item,
+) -> Option<ast::FunctionClause> {
+    let (params, guard, body) = clause_parts(ctx, clause)?;
 
     let params_n: Vec<_> = params
-        .list_iter()
-        .unwrap()
-        .map(|param| lower_expr(gen, param))
+        .into_iter()
+        .map(|param| lower_expr(ctx, param))
         .collect();
 
-    let guard_n = lower_guards(gen, guard);
+    let guard_n = lower_guards(ctx, guard);
 
-    let body_n = lower_body(gen, body);
+    let body_n = lower_body(ctx, body);
 
-    ast::FunctionClause {
+    Some(ast::FunctionClause {
         span: clause.span(),
-        name: None,
+        name,
         params: params_n,
         guard: guard_n,
         body: body_n,
-    }
+    })
 }
 
-fn lower_clause(gen: &mut ast::NodeIdGenerator, clause: &aast::Item) -> ast::Clause {
-    let tup = clause.tuple().unwrap();
-
-    assert!(tup.entries[0].atom().unwrap().as_str() == "clause");
-    let _line = tup.entries[1].integer().unwrap();
+fn lower_clauses(ctx: &mut LowerCtx, list: &aast::Item) -> Vec<ast::Clause> {
+    match ctx.expect_list(list, "clauses") {
+        Some(clauses) => clauses
+            .into_iter()
+            .filter_map(|clause| lower_clause(ctx, clause))
+            .collect(),
+        None => Vec::new(),
+    }
+}
 
-    let pattern = &tup.entries[2];
-    let guard = &tup.entries[3];
-    let body = &tup.entries[4];
+fn lower_clause(ctx: &mut LowerCtx, clause: &aast::Item) -> Option<ast::Clause> {
+    let (patterns, guard, body) = clause_parts(ctx, clause)?;
 
-    let mut patterns = pattern.list_iter().unwrap();
-    let pattern_n = lower_expr(gen, patterns.next().unwrap());
-    assert!(patterns.next().is_none());
+    let pattern = match patterns.as_slice() {
+        [pattern] => *pattern,
+        _ => {
+            ctx.malformed(clause.span(), "clause");
+            return None;
+        }
+    };
+    let pattern_n = lower_expr(ctx, pattern);
 
-    let guard_n = lower_guards(gen, guard);
+    let guard_n = lower_guards(ctx, guard);
 
-    let body_n = lower_body(gen, body);
+    let body_n = lower_body(ctx, body);
 
-    ast::Clause {
+    Some(ast::Clause {
         span: clause.span(),
-        id: gen.next(),
+        id: ctx.gen.next(),
         pattern: pattern_n,
         guard: guard_n,
         body: body_n,
-    }
+    })
 }
 
-fn lower_if_clause(gen: &mut ast::NodeIdGenerator, clause: &aast::Item) -> ast::IfClause {
-    let tup = clause.tuple().unwrap();
-
-    assert!(tup.entries[0].atom().unwrap().as_str() == "clause");
-    let _line = tup.entries[1].integer().unwrap();
+fn lower_if_clause(ctx: &mut LowerCtx, clause: &aast::Item) -> Option<ast::IfClause> {
+    let (patterns, guard, body) = clause_parts(ctx, clause)?;
 
-    assert!(tup.entries[2].list_iter().unwrap().count() == 0);
-    let guard = &tup.entries[3];
-    let body = &tup.entries[4];
-
-    let guard_n = lower_guards(gen, guard);
-    let body_n = lower_body(gen, body);
+    let guard_n = lower_guards(ctx, guard);
+    let guards = match guard_n {
+        Some(guards) if patterns.is_empty() => guards,
+        _ => {
+            ctx.malformed(clause.span(), "if clause");
+            return None;
+        }
+    };
+    let body_n = lower_body(ctx, body);
 
-    ast::IfClause {
+    Some(ast::IfClause {
         span: clause.span(),
-        id: gen.next(),
-        guards: guard_n.unwrap(),
+        id: ctx.gen.next(),
+        guards,
         body: body_n,
-    }
+    })
 }
 
-fn lower_try_clause(gen: &mut ast::NodeIdGenerator, clause: &aast::Item) -> ast::TryClause {
-    let tup = clause.tuple().unwrap();
-
-    assert!(tup.entries[0].atom().unwrap().as_str() == "clause");
-    let _line = tup.entries[1].integer().unwrap();
-
-    let pattern = &tup.entries[2];
-    let guard = &tup.entries[3];
-    let body = &tup.entries[4];
+/// A clause with the single pattern {tuple, L, [Class, Reason, Trace]}
+fn lower_try_clause(ctx: &mut LowerCtx, clause: &aast::Item) -> Option<ast::TryClause> {
+    let (patterns, guard, body) = clause_parts(ctx, clause)?;
 
-    let mut patterns = pattern.list_iter().unwrap();
-    let pattern = patterns.next().unwrap();
-    assert!(patterns.next().is_none());
-
-    let patterns_tup_cont = pattern.tuple().unwrap();
-    assert!(patterns_tup_cont.entries.len() == 3);
-    assert!(patterns_tup_cont.entries[0].atom().unwrap().as_str() == "tuple");
+    let pattern = match patterns.as_slice() {
+        [pattern] => *pattern,
+        _ => {
+            ctx.malformed(clause.span(), "catch clause");
+            return None;
+        }
+    };
 
-    let mut patterns_tup = patterns_tup_cont.entries[2].list_iter().unwrap();
-    let err_kind = patterns_tup.next().unwrap();
-    let err_error = patterns_tup.next().unwrap();
-    let err_trace = patterns_tup.next().unwrap();
-    assert!(patterns_tup.next().is_none());
+    let (pattern_kind, patterns_tup_cont) = ctx.expect_form(pattern, 3, "catch clause")?;
+    let patterns_tup = ctx.expect_list(&patterns_tup_cont.entries[2], "catch clause")?;
+    let (err_kind, err_error, err_trace) = match patterns_tup.as_slice() {
+        [kind, error, trace] if &*pattern_kind.as_str() == "tuple" => (*kind, *error, *trace),
+        _ => {
+            ctx.malformed(patterns_tup_cont.span, "catch clause");
+            return None;
+        }
+    };
 
-    let err_kind_tup = err_kind.tuple().unwrap();
-    let err_kind_name = match &*err_kind_tup.entries[0].atom().unwrap().as_str() {
-        "var" => ast::Name::Var(err_kind_tup.entries[2].atom().unwrap()),
-        "atom" => ast::Name::Atom(err_kind_tup.entries[2].atom().unwrap()),
-        _ => panic!(),
+    let (err_kind_kind, err_kind_tup) = ctx.expect_form(err_kind, 3, "exception class")?;
+    let err_kind_name = match &*err_kind_kind.as_str() {
+        "var" => ast::Name::Var(ctx.expect_atom(&err_kind_tup.entries[2], "exception class")?),
+        "atom" => ast::Name::Atom(ctx.expect_atom(&err_kind_tup.entries[2], "exception class")?),
+        _ => {
+            ctx.malformed(err_kind_tup.span, "exception class");
+            ast::Name::Var(Ident::new(Symbol::intern("_"), err_kind_tup.span))
+        }
     };
 
-    let err_trace_tup = err_trace.tuple().unwrap();
-    assert!(&*err_trace_tup.entries[0].atom().unwrap().as_str() == "var");
-    let err_trace_ident = err_trace_tup.entries[2].atom().unwrap();
+    let (err_trace_kind, err_trace_tup) = ctx.expect_form(err_trace, 3, "stacktrace variable")?;
+    if &*err_trace_kind.as_str() != "var" {
+        ctx.malformed(err_trace_tup.span, "stacktrace variable");
+        return None;
+    }
+    let err_trace_ident = ctx.expect_atom(&err_trace_tup.entries[2], "stacktrace variable")?;
 
-    let guard_n = lower_guards(gen, guard);
+    let guard_n = lower_guards(ctx, guard);
 
-    let body_n = lower_body(gen, body);
+    let body_n = lower_body(ctx, body);
 
-    ast::TryClause {
+    Some(ast::TryClause {
         span: clause.span(),
-        id: gen.next(),
+        id: ctx.gen.next(),
         kind: err_kind_name,
-        error: lower_expr(gen, err_error),
+        error: lower_expr(ctx, err_error),
         trace: err_trace_ident,
         guard: guard_n,
         body: body_n,
-    }
+    })
 }
 
-fn lower_guards(gen: &mut ast::NodeIdGenerator, guard: &aast::Item) -> Option<Vec<ast::Guard>> {
-    let guard_n: Vec<_> = guard
-        .list_iter()
-        .unwrap()
+fn lower_guards(ctx: &mut LowerCtx, guard: &aast::Item) -> Option<Vec<ast::Guard>> {
+    let guard_n: Vec<_> = ctx
+        .expect_list(guard, "guards")?
+        .into_iter()
         .map(|guard| ast::Guard {
             span: guard.span(),
-            conditions: guard
-                .list_iter()
-                .unwrap()
-                .map(|v| lower_expr(gen, v))
-                .collect(),
+            conditions: lower_exprs(ctx, guard),
         })
         .collect();
     if guard_n.len() == 0 {
@@ -351,87 +699,138 @@ fn lower_guards(gen: &mut ast::NodeIdGenerator, guard: &aast::Item) -> Option<Ve
     }
 }
 
-fn lower_body(gen: &mut ast::NodeIdGenerator, body: &aast::Item) -> Vec<ast::Expr> {
-    let body_n: Vec<_> = body
-        .list_iter()
-        .unwrap()
-        .map(|expr| lower_expr(gen, expr))
-        .collect();
-    body_n
+fn lower_body(ctx: &mut LowerCtx, body: &aast::Item) -> Vec<ast::Expr> {
+    lower_exprs(ctx, body)
 }
 
-fn lower_expr(gen: &mut ast::NodeIdGenerator, expr: &aast::Item) -> ast::Expr {
-    let tup = expr.tuple().unwrap();
+fn lower_exprs(ctx: &mut LowerCtx, list: &aast::Item) -> Vec<ast::Expr> {
+    match ctx.expect_list(list, "expressions") {
+        Some(items) => items.into_iter().map(|v| lower_expr(ctx, v)).collect(),
+        None => Vec::new(),
+    }
+}
 
-    let name = tup.entries[0].atom().unwrap().as_str();
-    let _line = tup.entries[1].integer().unwrap();
+fn binary_op(op: &str) -> Option<ast::BinaryOp> {
+    use ast::BinaryOp as B;
+    let op = match op {
+        "!" => B::Send,
+        "orelse" => B::OrElse,
+        "andalso" => B::AndAlso,
+        "==" => B::Equal,
+        "/=" => B::NotEqual,
+        "=<" => B::Lte,
+        "<" => B::Lt,
+        ">=" => B::Gte,
+        ">" => B::Gt,
+        "=:=" => B::StrictEqual,
+        "=/=" => B::StrictNotEqual,
+        "++" => B::Append,
+        "--" => B::Remove,
+        "+" => B::Add,
+        "-" => B::Sub,
+        "bor" => B::Bor,
+        "bxor" => B::Bxor,
+        "bsl" => B::Bsl,
+        "bsr" => B::Bsr,
+        "or" => B::Or,
+        "xor" => B::Xor,
+        "/" => B::Divide,
+        "*" => B::Multiply,
+        "div" => B::Div,
+        "rem" => B::Rem,
+        "band" => B::Band,
+        "and" => B::And,
+        _ => return None,
+    };
+    Some(op)
+}
+
+fn unary_op(op: &str) -> Option<ast::UnaryOp> {
+    let op = match op {
+        "+" => ast::UnaryOp::Plus,
+        "-" => ast::UnaryOp::Minus,
+        "bnot" => ast::UnaryOp::Bnot,
+        "not" => ast::UnaryOp::Not,
+        _ => return None,
+    };
+    Some(op)
+}
+
+/// Lowers `expr`, replacing it with a sentinel if it is malformed.
+fn lower_expr(ctx: &mut LowerCtx, expr: &aast::Item) -> ast::Expr {
+    match try_lower_expr(ctx, expr) {
+        Some(expr_n) => expr_n,
+        None => ctx.sentinel(expr.span()),
+    }
+}
 
+/// {Tag, L, ...}, `None` if the expression is malformed. The error has
+/// been reported then.
+fn try_lower_expr(ctx: &mut LowerCtx, expr: &aast::Item) -> Option<ast::Expr> {
+    let (name, tup) = ctx.expect_form(expr, 2, "expression")?;
+    let name = name.as_str();
     let span = tup.span;
 
-    match &*name {
-        "var" => ast::Expr::Var(ast::Var(gen.next(), tup.entries[2].atom().unwrap())),
+    let expr_n = match &*name {
+        "var" => {
+            ctx.expect_len(tup, 3, "variable")?;
+            let var = ctx.expect_atom(&tup.entries[2], "variable")?;
+            ast::Expr::Var(ast::Var(ctx.gen.next(), var))
+        }
         "op" => {
-            let tup_len = tup.entries.len();
-            let op = tup.entries[2].atom().unwrap().as_str();
-
-            enum ExprKind {
-                Unary(ast::UnaryOp),
-                Binary(ast::BinaryOp),
-            }
-
-            let expr_kind = match (&*op, tup_len) {
-                ("+", 5) => ExprKind::Binary(ast::BinaryOp::Add),
-                ("-", 5) => ExprKind::Binary(ast::BinaryOp::Sub),
-                ("*", 5) => ExprKind::Binary(ast::BinaryOp::Multiply),
-                ("==", 5) => ExprKind::Binary(ast::BinaryOp::Equal),
-                ("=:=", 5) => ExprKind::Binary(ast::BinaryOp::StrictEqual),
-                ("=/=", 5) => ExprKind::Binary(ast::BinaryOp::StrictNotEqual),
-                ("andalso", 5) => ExprKind::Binary(ast::BinaryOp::AndAlso),
-                ("++", 5) => ExprKind::Binary(ast::BinaryOp::Append),
-                (">", 5) => ExprKind::Binary(ast::BinaryOp::Gt),
-                ("<", 5) => ExprKind::Binary(ast::BinaryOp::Lt),
-                ("rem", 5) => ExprKind::Binary(ast::BinaryOp::Rem),
-
-                ("+", 4) => ExprKind::Unary(ast::UnaryOp::Plus),
-                ("-", 4) => ExprKind::Unary(ast::UnaryOp::Minus),
-                ("bnot", 4) => ExprKind::Unary(ast::UnaryOp::Bnot),
-                ("not", 4) => ExprKind::Unary(ast::UnaryOp::Not),
-
-                (n, a) => unimplemented!("{} {}", n, a),
-            };
-
-            match expr_kind {
-                ExprKind::Unary(op) => ast::Expr::UnaryExpr(ast::UnaryExpr {
-                    span,
-                    id: gen.next(),
-                    op: op,
-                    operand: Box::new(lower_expr(gen, &tup.entries[3])),
-                }),
-                ExprKind::Binary(op) => ast::Expr::BinaryExpr(ast::BinaryExpr {
-                    span,
-                    id: gen.next(),
-                    op: op,
-                    lhs: Box::new(lower_expr(gen, &tup.entries[3])),
-                    rhs: Box::new(lower_expr(gen, &tup.entries[4])),
-                }),
+            let len = tup.entries.len();
+            if len != 4 && len != 5 {
+                ctx.malformed(span, "operator");
+                return None;
+            }
+            let op = ctx.expect_atom(&tup.entries[2], "operator")?.as_str();
+            if len == 4 {
+                match unary_op(&op) {
+                    Some(op) => ast::Expr::UnaryExpr(ast::UnaryExpr {
+                        span,
+                        id: ctx.gen.next(),
+                        op: op,
+                        operand: Box::new(lower_expr(ctx, &tup.entries[3])),
+                    }),
+                    None => {
+                        ctx.unsupported(span, "unary operator", &op);
+                        ctx.sentinel(span)
+                    }
+                }
+            } else {
+                match binary_op(&op) {
+                    Some(op) => ast::Expr::BinaryExpr(ast::BinaryExpr {
+                        span,
+                        id: ctx.gen.next(),
+                        op: op,
+                        lhs: Box::new(lower_expr(ctx, &tup.entries[3])),
+                        rhs: Box::new(lower_expr(ctx, &tup.entries[4])),
+                    }),
+                    None => {
+                        ctx.unsupported(span, "binary operator", &op);
+                        ctx.sentinel(span)
+                    }
+                }
             }
         }
         "integer" => {
-            let int = tup.entries[2].integer().unwrap();
-            let lit = ast::Literal::Integer(span, gen.next(), int.integer.clone());
+            ctx.expect_len(tup, 3, "integer")?;
+            let int = ctx.expect_integer(&tup.entries[2], "integer")?;
+            let lit = ast::Literal::Integer(span, ctx.gen.next(), int.integer.clone());
             ast::Expr::Literal(lit)
         }
         "string" => {
+            ctx.expect_len(tup, 3, "string")?;
             if let Some(string) = tup.entries[2].string() {
-                ast::Expr::Literal(ast::Literal::String(gen.next(), string))
+                ast::Expr::Literal(ast::Literal::String(ctx.gen.next(), string))
             } else {
-                let elems: Vec<_> = tup.entries[2].list_iter().unwrap().collect();
-                let mut acc = ast::Expr::Nil(ast::Nil(tup.span, gen.next()));
+                let elems = ctx.expect_list(&tup.entries[2], "string")?;
+                let mut acc = ast::Expr::Nil(ast::Nil(tup.span, ctx.gen.next()));
                 for elem in elems.iter().rev() {
                     acc = ast::Expr::Cons(ast::Cons {
                         span: elem.span(),
-                        id: gen.next(),
-                        head: Box::new(lower_expr(gen, elem)),
+                        id: ctx.gen.next(),
+                        head: Box::new(lower_expr(ctx, elem)),
                         tail: Box::new(acc),
                     });
                 }
@@ -439,280 +838,340 @@ fn lower_expr(gen: &mut ast::NodeIdGenerator, expr: &aast::Item) -> ast::Expr {
             }
         }
         "atom" => {
-            let atom = tup.entries[2].atom().unwrap();
-            ast::Expr::Literal(ast::Literal::Atom(gen.next(), atom))
+            ctx.expect_len(tup, 3, "atom")?;
+            let atom = ctx.expect_atom(&tup.entries[2], "atom")?;
+            ast::Expr::Literal(ast::Literal::Atom(ctx.gen.next(), atom))
+        }
+        "nil" => {
+            ctx.expect_len(tup, 2, "nil")?;
+            ast::Expr::Nil(ast::Nil(span, ctx.gen.next()))
+        }
+        "tuple" => {
+            ctx.expect_len(tup, 3, "tuple")?;
+            ast::Expr::Tuple(ast::Tuple {
+                span,
+                id: ctx.gen.next(),
+                elements: lower_exprs(ctx, &tup.entries[2]),
+            })
         }
-        "nil" => ast::Expr::Nil(ast::Nil(span, gen.next())),
-        "tuple" => ast::Expr::Tuple(ast::Tuple {
-            span,
-            id: gen.next(),
-            elements: tup.entries[2]
-                .list_iter()
-                .unwrap()
-                .map(|e| lower_expr(gen, e))
-                .collect(),
-        }),
         "cons" => {
-            let head = lower_expr(gen, &tup.entries[2]);
-            let tail = lower_expr(gen, &tup.entries[3]);
+            ctx.expect_len(tup, 4, "cons")?;
+            let head = lower_expr(ctx, &tup.entries[2]);
+            let tail = lower_expr(ctx, &tup.entries[3]);
             ast::Expr::Cons(ast::Cons {
                 span,
-                id: gen.next(),
+                id: ctx.gen.next(),
                 head: Box::new(head),
                 tail: Box::new(tail),
             })
         }
         "map" => {
             let tup_len = tup.entries.len();
+            if tup_len != 3 && tup_len != 4 {
+                ctx.malformed(span, "map");
+                return None;
+            }
 
-            let fields = tup.entries[tup_len - 1]
-                .list_iter()
-                .unwrap()
-                .map(|field| {
-                    let field_tup = field.tuple().unwrap();
+            let fields = ctx
+                .expect_list(&tup.entries[tup_len - 1], "map fields")?
+                .into_iter()
+                .filter_map(|field| {
+                    let (op_name, field_tup) = ctx.expect_form(field, 4, "map field")?;
                     let span = field_tup.span;
 
-                    let op_name = field_tup.entries[0].atom().unwrap().as_str();
-
-                    let key = lower_expr(gen, &field_tup.entries[2]);
-                    let value = lower_expr(gen, &field_tup.entries[3]);
+                    let key = lower_expr(ctx, &field_tup.entries[2]);
+                    let value = lower_expr(ctx, &field_tup.entries[3]);
 
-                    match &*op_name {
-                        "map_field_exact" => ast::MapField::Exact {
+                    match &*op_name.as_str() {
+                        "map_field_exact" => Some(ast::MapField::Exact {
                             span,
-                            id: gen.next(),
+                            id: ctx.gen.next(),
                             key,
                             value,
-                        },
-                        "map_field_assoc" => ast::MapField::Assoc {
+                        }),
+                        "map_field_assoc" => Some(ast::MapField::Assoc {
                             span,
-                            id: gen.next(),
+                            id: ctx.gen.next(),
                             key,
                             value,
-                        },
-                        r => panic!("{}", r),
+                        }),
+                        r => {
+                            ctx.unsupported(span, "map field", r);
+                            None
+                        }
                     }
                 })
                 .collect();
 
-            match tup_len {
-                3 => ast::Expr::Map(ast::Map {
+            if tup_len == 3 {
+                ast::Expr::Map(ast::Map {
                     span,
-                    id: gen.next(),
+                    id: ctx.gen.next(),
                     fields,
-                }),
-                4 => ast::Expr::MapUpdate(ast::MapUpdate {
+                })
+            } else {
+                ast::Expr::MapUpdate(ast::MapUpdate {
                     span,
-                    id: gen.next(),
-                    map: Box::new(lower_expr(gen, &tup.entries[2])),
+                    id: ctx.gen.next(),
+                    map: Box::new(lower_expr(ctx, &tup.entries[2])),
                     updates: fields,
-                }),
-                _ => panic!(),
+                })
             }
         }
         "case" => {
-            let expr = lower_expr(gen, &tup.entries[2]);
-            let clauses = tup.entries[3]
-                .list_iter()
-                .unwrap()
-                .map(|c| lower_clause(gen, c))
-                .collect();
+            ctx.expect_len(tup, 4, "case")?;
+            let expr = lower_expr(ctx, &tup.entries[2]);
+            let clauses = lower_clauses(ctx, &tup.entries[3]);
             ast::Expr::Case(ast::Case {
                 span,
-                id: gen.next(),
+                id: ctx.gen.next(),
                 expr: Box::new(expr),
                 clauses,
             })
         }
         "call" => {
-            let target = lower_expr(gen, &tup.entries[2]);
-
-            let args = tup.entries[3]
-                .list_iter()
-                .unwrap()
-                .map(|v| lower_expr(gen, v))
-                .collect();
-
+            ctx.expect_len(tup, 4, "call")?;
+            let target = lower_expr(ctx, &tup.entries[2]);
+            let args = lower_exprs(ctx, &tup.entries[3]);
             ast::Expr::Apply(ast::Apply {
                 span,
-                id: gen.next(),
+                id: ctx.gen.next(),
                 callee: Box::new(target),
                 args,
             })
         }
-        "remote" => ast::Expr::Remote(ast::Remote {
-            span,
-            id: gen.next(),
-            module: Box::new(lower_expr(gen, &tup.entries[2])),
-            function: Box::new(lower_expr(gen, &tup.entries[3])),
-        }),
+        "remote" => {
+            ctx.expect_len(tup, 4, "remote")?;
+            ast::Expr::Remote(ast::Remote {
+                span,
+                id: ctx.gen.next(),
+                module: Box::new(lower_expr(ctx, &tup.entries[2])),
+                function: Box::new(lower_expr(ctx, &tup.entries[3])),
+            })
+        }
         "bin" => {
-            let elements = tup.entries[2]
-                .list_iter()
-                .unwrap()
-                .map(|elem| {
-                    let tup = elem.tuple().unwrap();
-                    assert!(tup.entries[0].atom().unwrap().as_str() == "bin_element");
-
-                    let bit_expr = lower_expr(gen, &tup.entries[2]);
-
-                    let bit_size_v = &tup.entries[3];
-                    let bit_size = if let Some(atom) = bit_size_v.atom() {
-                        assert!(&*atom.as_str() == "default");
-                        None
-                    } else {
-                        Some(lower_expr(gen, bit_size_v))
-                    };
-
-                    let bit_type_v = &tup.entries[4];
-                    let bit_type = if let Some(atom) = bit_type_v.atom() {
-                        assert!(&*atom.as_str() == "default");
-                        None
-                    } else {
-                        let list = bit_type_v
-                            .list_iter()
-                            .unwrap()
-                            .map(|item| {
-                                if let Some(atom) = item.atom() {
-                                    ast::BitType::Name(span, gen.next(), atom)
-                                } else {
-                                    unimplemented!()
-                                }
-                            })
-                            .collect();
-                        Some(list)
-                    };
-
-                    ast::BinaryElement {
-                        span: elem.span(),
-                        id: gen.next(),
-                        bit_expr,
-                        bit_size,
-                        bit_type,
-                    }
-                })
+            ctx.expect_len(tup, 3, "binary")?;
+            let elements = ctx
+                .expect_list(&tup.entries[2], "binary")?
+                .into_iter()
+                .filter_map(|elem| lower_bin_element(ctx, elem))
                 .collect();
             ast::Expr::Binary(ast::Binary {
                 span,
-                id: gen.next(),
+                id: ctx.gen.next(),
                 elements,
             })
         }
         "fun" => {
-            // We expect either a M:F/A or a function definition.
-            let inner = tup.entries[2].tuple().unwrap();
-            let inner_name = inner.entries[0].atom().unwrap();
-            match &*inner_name.as_str() {
-                "function" => {
-                    let module = atom(&inner.entries[1]);
-                    let function = atom(&inner.entries[2]);
-                    let arity = integer(&inner.entries[3]);
-                    ast::Expr::FunctionName(ast::FunctionName::Resolved(
-                        ast::ResolvedFunctionName {
+            ctx.expect_len(tup, 3, "fun")?;
+            // We expect either a local or remote function reference, or a
+            // function definition.
+            let (inner_name, inner) = ctx.expect_form(&tup.entries[2], 2, "fun")?;
+            match (&*inner_name.as_str(), inner.entries.len()) {
+                // {function, Name, Arity}
+                ("function", 3) => {
+                    let function = ctx.expect_atom(&inner.entries[1], "function name")?;
+                    let arity = ctx.expect_arity(&inner.entries[2], "function arity")?;
+                    ast::Expr::FunctionName(ast::FunctionName::PartiallyResolved(
+                        ast::PartiallyResolvedFunctionName {
                             span: inner.span,
-                            id: gen.next(),
-                            module,
+                            id: ctx.gen.next(),
                             function,
-                            arity: arity.integer.to_usize().unwrap(),
+                            arity,
                         },
                     ))
                 }
-                "clauses" => {
-                    let clauses: Vec<_> = inner.entries[1]
-                        .list_iter()
-                        .unwrap()
-                        .map(|v| lower_function_clause(gen, v))
-                        .collect();
-                    let arity = clauses[0].params.len();
-                    for clause in clauses.iter() {
-                        assert!(clause.params.len() == arity);
+                // {function, Module, Name, Arity}
+                ("function", 4) => match (
+                    atom(&inner.entries[1]),
+                    atom(&inner.entries[2]),
+                    integer(&inner.entries[3]),
+                ) {
+                    (Some(module), Some(function), Some(arity)) => {
+                        let arity = match arity.integer.to_usize() {
+                            Some(arity) => arity,
+                            None => {
+                                ctx.malformed(arity.span, "function arity");
+                                return None;
+                            }
+                        };
+                        ast::Expr::FunctionName(ast::FunctionName::Resolved(
+                            ast::ResolvedFunctionName {
+                                span: inner.span,
+                                id: ctx.gen.next(),
+                                module,
+                                function,
+                                arity,
+                            },
+                        ))
+                    }
+                    _ => {
+                        ctx.unsupported(inner.span, "expression", "fun with variable name");
+                        ctx.sentinel(span)
                     }
+                },
+                // {clauses, Clauses}
+                ("clauses", 2) => {
+                    let clauses = lower_function_clauses(ctx, None, &inner.entries[1])?;
+                    let arity = clauses[0].params.len();
                     ast::Expr::Fun(ast::Function::Unnamed(ast::Lambda {
                         span: inner.span,
-                        id: gen.next(),
+                        id: ctx.gen.next(),
                         arity: arity,
                         clauses,
                     }))
                 }
-                v => unimplemented!("{}", v),
+                ("function", _) | ("clauses", _) => {
+                    ctx.malformed(inner.span, "fun");
+                    return None;
+                }
+                (v, _) => {
+                    ctx.unsupported(inner.span, "fun", v);
+                    ctx.sentinel(span)
+                }
             }
         }
+        "named_fun" => {
+            ctx.expect_len(tup, 4, "named fun")?;
+            let name = Name::Var(ctx.expect_atom(&tup.entries[2], "named fun")?);
+            let clauses = lower_function_clauses(ctx, Some(name.clone()), &tup.entries[3])?;
+            let arity = clauses[0].params.len();
+            ast::Expr::Fun(ast::Function::Named(ast::NamedFunction {
+                span,
+                id: ctx.gen.next(),
+                name,
+                arity,
+                clauses,
+                spec: None,
+            }))
+        }
         "match" => {
-            let pattern = lower_expr(gen, &tup.entries[2]);
-            let expr = lower_expr(gen, &tup.entries[3]);
+            ctx.expect_len(tup, 4, "match")?;
+            let pattern = lower_expr(ctx, &tup.entries[2]);
+            let expr = lower_expr(ctx, &tup.entries[3]);
             ast::Expr::Match(ast::Match {
                 span,
-                id: gen.next(),
+                id: ctx.gen.next(),
                 pattern: Box::new(pattern),
                 expr: Box::new(expr),
             })
         }
         "char" => {
-            let int = tup.entries[2].integer().unwrap();
-            ast::Expr::Literal(ast::Literal::Char(
-                span,
-                gen.next(),
-                int.integer.to_u32().unwrap().try_into().unwrap(),
-            ))
+            ctx.expect_len(tup, 3, "character")?;
+            let c = ctx.expect_char(&tup.entries[2], "character")?;
+            ast::Expr::Literal(ast::Literal::Char(span, ctx.gen.next(), c))
         }
         "float" => {
-            let float = tup.entries[2].float().unwrap();
-            ast::Expr::Literal(ast::Literal::Float(
+            ctx.expect_len(tup, 3, "float")?;
+            let float = ctx.expect_float(&tup.entries[2], "float")?;
+            ast::Expr::Literal(ast::Literal::Float(span, ctx.gen.next(), float))
+        }
+        "catch" => {
+            ctx.expect_len(tup, 3, "catch")?;
+            ast::Expr::Catch(ast::Catch {
                 span,
-                gen.next(),
-                Float::new(float.float).unwrap(),
-            ))
+                id: ctx.gen.next(),
+                expr: Box::new(lower_expr(ctx, &tup.entries[2])),
+            })
         }
-        "catch" => ast::Expr::Catch(ast::Catch {
-            span,
-            id: gen.next(),
-            expr: Box::new(lower_expr(gen, &tup.entries[2])),
-        }),
-        "generate" => ast::Expr::Generator(ast::Generator {
-            span,
-            id: gen.next(),
-            pattern: Box::new(lower_expr(gen, &tup.entries[2])),
-            expr: Box::new(lower_expr(gen, &tup.entries[3])),
-        }),
-        "lc" => ast::Expr::ListComprehension(ast::ListComprehension {
-            span,
-            id: gen.next(),
-            body: Box::new(lower_expr(gen, &tup.entries[2])),
-            qualifiers: tup.entries[3]
-                .list_iter()
-                .unwrap()
-                .map(|v| lower_expr(gen, v))
-                .collect(),
-        }),
+        "generate" => {
+            ctx.expect_len(tup, 4, "generator")?;
+            ast::Expr::Generator(ast::Generator {
+                span,
+                id: ctx.gen.next(),
+                pattern: Box::new(lower_expr(ctx, &tup.entries[2])),
+                expr: Box::new(lower_expr(ctx, &tup.entries[3])),
+            })
+        }
+        "b_generate" => {
+            ctx.expect_len(tup, 4, "binary generator")?;
+            ast::Expr::BinaryGenerator(ast::BinaryGenerator {
+                span,
+                id: ctx.gen.next(),
+                pattern_id: ctx.gen.next(),
+                skip_pattern_id: ctx.gen.next(),
+                rest_id: ctx.gen.next(),
+                rest_var_id: ctx.gen.next(),
+                rest_type_id: ctx.gen.next(),
+                pattern: Box::new(lower_expr(ctx, &tup.entries[2])),
+                expr: Box::new(lower_expr(ctx, &tup.entries[3])),
+            })
+        }
+        "m_generate" => {
+            ctx.expect_len(tup, 4, "map generator")?;
+            let field = ctx.expect_tuple(&tup.entries[2], 4, "map generator")?;
+            ast::Expr::MapGenerator(ast::MapGenerator {
+                span,
+                id: ctx.gen.next(),
+                pattern_id: ctx.gen.next(),
+                key: Box::new(lower_expr(ctx, &field.entries[2])),
+                value: Box::new(lower_expr(ctx, &field.entries[3])),
+                expr: Box::new(lower_expr(ctx, &tup.entries[3])),
+            })
+        }
+        "lc" => {
+            ctx.expect_len(tup, 4, "list comprehension")?;
+            ast::Expr::ListComprehension(ast::ListComprehension {
+                span,
+                id: ctx.gen.next(),
+                body: Box::new(lower_expr(ctx, &tup.entries[2])),
+                qualifiers: lower_exprs(ctx, &tup.entries[3]),
+            })
+        }
+        "bc" => {
+            ctx.expect_len(tup, 4, "binary comprehension")?;
+            ast::Expr::BinaryComprehension(ast::BinaryComprehension {
+                span,
+                id: ctx.gen.next(),
+                body_binary_id: ctx.gen.next(),
+                body_element_id: ctx.gen.next(),
+                body_type_id: ctx.gen.next(),
+                body: Box::new(lower_expr(ctx, &tup.entries[2])),
+                qualifiers: lower_exprs(ctx, &tup.entries[3]),
+            })
+        }
+        "mc" => {
+            ctx.expect_len(tup, 4, "map comprehension")?;
+            let field = ctx.expect_tuple(&tup.entries[2], 4, "map comprehension")?;
+            ast::Expr::MapComprehension(ast::MapComprehension {
+                span,
+                id: ctx.gen.next(),
+                key: Box::new(lower_expr(ctx, &field.entries[2])),
+                value: Box::new(lower_expr(ctx, &field.entries[3])),
+                qualifiers: lower_exprs(ctx, &tup.entries[3]),
+            })
+        }
+        // {'receive', L, Clauses} or {'receive', L, Clauses, Timeout, Body}
         "receive" => {
+            let len = tup.entries.len();
+            if len != 3 && len != 5 {
+                ctx.malformed(span, "receive");
+                return None;
+            }
             let mut clauses = Vec::new();
-            let after = None;
-            for clause in tup.entries[2].list_iter().unwrap() {
-                let clause_tup = clause.tuple().unwrap();
-                match &*clause_tup.entries[0].atom().unwrap().as_str() {
-                    "clause" => {
-                        let mut arg_iter = clause_tup.entries[2].list_iter().unwrap();
-                        let arg = arg_iter.next().unwrap();
-                        assert!(arg_iter.next().is_none());
-
-                        let pattern = lower_expr(gen, arg);
-                        let guard = lower_guards(gen, &clause_tup.entries[3]);
-                        let body = lower_body(gen, &clause_tup.entries[4]);
-
-                        clauses.push(ast::Clause {
-                            span: clause_tup.span,
-                            id: gen.next(),
-                            pattern,
-                            guard: guard,
-                            body,
-                        });
-                    }
-                    _ => unimplemented!(),
+            for clause in ctx.expect_list(&tup.entries[2], "receive clauses")? {
+                let (clause_name, clause_tup) = match ctx.expect_form(clause, 1, "receive clause") {
+                    Some(form) => form,
+                    None => continue,
+                };
+                match &*clause_name.as_str() {
+                    "clause" => clauses.extend(lower_clause(ctx, clause)),
+                    n => ctx.unsupported(clause_tup.span, "receive clause", n),
                 }
             }
+            let after = if len == 5 {
+                Some(ast::After {
+                    span,
+                    id: ctx.gen.next(),
+                    timeout: Box::new(lower_expr(ctx, &tup.entries[3])),
+                    body: lower_body(ctx, &tup.entries[4]),
+                })
+            } else {
+                None
+            };
             ast::Expr::Receive(ast::Receive {
                 span,
-                id: gen.next(),
+                id: ctx.gen.next(),
                 clauses: if clauses.len() == 0 {
                     None
                 } else {
@@ -722,74 +1181,102 @@ fn lower_expr(gen: &mut ast::NodeIdGenerator, expr: &aast::Item) -> ast::Expr {
             })
         }
         "block" => {
-            let body = lower_body(gen, &tup.entries[2]);
+            ctx.expect_len(tup, 3, "block")?;
+            let body = lower_body(ctx, &tup.entries[2]);
             ast::Expr::Begin(ast::Begin {
                 span,
-                id: gen.next(),
+                id: ctx.gen.next(),
                 body,
             })
         }
         "if" => {
-            let clauses = tup.entries[2]
-                .list_iter()
-                .unwrap()
-                .map(|v| lower_if_clause(gen, v))
+            ctx.expect_len(tup, 3, "if")?;
+            let clauses = ctx
+                .expect_list(&tup.entries[2], "if clauses")?
+                .into_iter()
+                .filter_map(|v| lower_if_clause(ctx, v))
                 .collect();
             ast::Expr::If(ast::If {
                 span,
-                id: gen.next(),
+                id: ctx.gen.next(),
                 clauses,
             })
         }
         "record" => match tup.entries.len() {
             4 => {
-                let name = tup.entries[2].atom().unwrap();
-                let fields = tup.entries[3]
-                    .list_iter()
-                    .unwrap()
-                    .map(|v| lower_record_field(gen, v))
+                let name = ctx.expect_atom(&tup.entries[2], "record name")?;
+                let fields = ctx
+                    .expect_list(&tup.entries[3], "record fields")?
+                    .into_iter()
+                    .filter_map(|v| lower_record_field(ctx, v))
                     .collect();
                 ast::Expr::Record(ast::Record {
                     span,
-                    id: gen.next(),
+                    id: ctx.gen.next(),
                     name,
                     fields,
                 })
             }
             5 => {
-                let old = lower_expr(gen, &tup.entries[2]);
-                let name = tup.entries[3].atom().unwrap();
-                let fields = tup.entries[4]
-                    .list_iter()
-                    .unwrap()
-                    .map(|v| lower_record_field(gen, v))
+                let old = lower_expr(ctx, &tup.entries[2]);
+                let name = ctx.expect_atom(&tup.entries[3], "record name")?;
+                let fields = ctx
+                    .expect_list(&tup.entries[4], "record fields")?
+                    .into_iter()
+                    .filter_map(|v| lower_record_field(ctx, v))
                     .collect();
                 ast::Expr::RecordUpdate(ast::RecordUpdate {
                     span,
-                    id: gen.next(),
+                    id: ctx.gen.next(),
                     record: Box::new(old),
                     name,
                     updates: fields,
                 })
             }
-            _ => unimplemented!(),
+            _ => {
+                ctx.malformed(span, "record");
+                return None;
+            }
         },
+        // {record_field, L, Expr, Name, Field}
+        "record_field" if tup.entries.len() == 5 => {
+            let record = lower_expr(ctx, &tup.entries[2]);
+            let name = ctx.expect_atom(&tup.entries[3], "record name")?;
+            let field = ctx.expect_atom_literal(&tup.entries[4], "record field name")?;
+            ast::Expr::RecordAccess(ast::RecordAccess {
+                span,
+                id: ctx.gen.next(),
+                record: Box::new(record),
+                name,
+                field,
+            })
+        }
+        // {record_index, L, Name, Field}
+        "record_index" => {
+            ctx.expect_len(tup, 4, "record index")?;
+            let name = ctx.expect_atom(&tup.entries[2], "record name")?;
+            let field = ctx.expect_atom_literal(&tup.entries[3], "record field name")?;
+            ast::Expr::RecordIndex(ast::RecordIndex {
+                span,
+                id: ctx.gen.next(),
+                name,
+                field,
+            })
+        }
+        // {'try', L, Body, Clauses, CatchClauses, After}
         "try" => {
-            let exprs = lower_body(gen, &tup.entries[2]);
-            let clauses: Vec<_> = tup.entries[3]
-                .list_iter()
-                .unwrap()
-                .map(|v| lower_clause(gen, v))
-                .collect();
-            let catch_clauses: Vec<_> = tup.entries[4]
-                .list_iter()
-                .unwrap()
-                .map(|v| lower_try_clause(gen, v))
+            ctx.expect_len(tup, 6, "try")?;
+            let exprs = lower_body(ctx, &tup.entries[2]);
+            let clauses = lower_clauses(ctx, &tup.entries[3]);
+            let catch_clauses: Vec<_> = ctx
+                .expect_list(&tup.entries[4], "catch clauses")?
+                .into_iter()
+                .filter_map(|v| lower_try_clause(ctx, v))
                 .collect();
-            let after = lower_body(gen, &tup.entries[5]);
+            let after = lower_body(ctx, &tup.entries[5]);
             ast::Expr::Try(ast::Try {
                 span,
-                id: gen.next(),
+                id: ctx.gen.next(),
                 exprs,
                 clauses: if clauses.len() == 0 {
                     None
@@ -804,20 +1291,431 @@ fn lower_expr(gen: &mut ast::NodeIdGenerator, expr: &aast::Item) -> ast::Expr {
                 after: if after.len() == 0 { None } else { Some(after) },
             })
         }
-        v => unimplemented!("{}", v),
+        // {'maybe', L, Body} or {'maybe', L, Body, {'else', L, Clauses}}
+        "maybe" => {
+            let len = tup.entries.len();
+            if len != 3 && len != 4 {
+                ctx.malformed(span, "maybe");
+                return None;
+            }
+            let body = lower_body(ctx, &tup.entries[2]);
+            let else_clauses = match tup.entries.get(3) {
+                Some(else_item) => {
+                    let else_tup = ctx.expect_tuple(else_item, 3, "maybe else")?;
+                    Some(lower_clauses(ctx, &else_tup.entries[2]))
+                }
+                None => None,
+            };
+            ast::Expr::Maybe(ast::Maybe {
+                span,
+                id: ctx.gen.next(),
+                body,
+                else_clauses,
+            })
+        }
+        "maybe_match" => {
+            ctx.expect_len(tup, 4, "maybe match")?;
+            ast::Expr::MaybeMatch(ast::MaybeMatch {
+                span,
+                id: ctx.gen.next(),
+                pattern: Box::new(lower_expr(ctx, &tup.entries[2])),
+                expr: Box::new(lower_expr(ctx, &tup.entries[3])),
+            })
+        }
+        v => {
+            ctx.unsupported(span, "expression", v);
+            ctx.sentinel(span)
+        }
+    };
+    Some(expr_n)
+}
+
+/// {bin_element, L, Value, Size, TypeSpecifiers}
+fn lower_bin_element(ctx: &mut LowerCtx, elem: &aast::Item) -> Option<ast::BinaryElement> {
+    let (kind, tup) = ctx.expect_form(elem, 5, "binary element")?;
+    if &*kind.as_str() != "bin_element" {
+        ctx.malformed(tup.span, "binary element");
+        return None;
     }
+
+    let bit_expr = lower_expr(ctx, &tup.entries[2]);
+
+    let bit_size_v = &tup.entries[3];
+    let bit_size = if let Some(atom) = bit_size_v.atom() {
+        if &*atom.as_str() != "default" {
+            ctx.malformed(atom.span, "binary element size");
+        }
+        None
+    } else {
+        Some(lower_expr(ctx, bit_size_v))
+    };
+
+    let bit_type_v = &tup.entries[4];
+    let bit_type = if let Some(atom) = bit_type_v.atom() {
+        if &*atom.as_str() != "default" {
+            ctx.malformed(atom.span, "binary type specifier");
+        }
+        None
+    } else {
+        let list = ctx
+            .expect_list(bit_type_v, "binary type specifiers")?
+            .into_iter()
+            .filter_map(|item| {
+                if let Some(atom) = item.atom() {
+                    return Some(ast::BitType::Name(atom.span, ctx.gen.next(), atom));
+                }
+                // {unit, N}
+                if let Some(sized) = item.tuple().filter(|sized| sized.entries.len() == 2) {
+                    if let (Some(name), Some(size)) =
+                        (sized.entries[0].atom(), sized.entries[1].integer())
+                    {
+                        if let Some(size) = size.integer.to_i64() {
+                            return Some(ast::BitType::Sized(
+                                sized.span,
+                                ctx.gen.next(),
+                                name,
+                                size,
+                            ));
+                        }
+                    }
+                }
+                ctx.malformed(item.span(), "binary type specifier");
+                None
+            })
+            .collect();
+        Some(list)
+    };
+
+    Some(ast::BinaryElement {
+        span: elem.span(),
+        id: ctx.gen.next(),
+        bit_expr,
+        bit_size,
+        bit_type,
+    })
 }
 
-fn atom(item: &aast::Item) -> Ident {
-    let tup = item.tuple().unwrap();
-    assert!(&*tup.entries[0].atom().unwrap().as_str() == "atom");
-    tup.entries[2].atom().unwrap()
+/// {type, L, fun, [{type, L, product, Params}, Ret]} or
+/// {type, L, bounded_fun, [Fun, Constraints]}
+fn lower_type_sig(ctx: &mut LowerCtx, sig: &aast::Item) -> Option<ast::TypeSig> {
+    let tup = ctx.expect_tuple(sig, 4, "function type")?;
+    let span = tup.span;
+    let kind = ctx.expect_atom(&tup.entries[2], "function type")?;
+    let args = ctx.expect_list(&tup.entries[3], "function type")?;
+
+    match (&*kind.as_str(), args.as_slice()) {
+        ("fun", [product, ret]) => {
+            let product = ctx.expect_tuple(product, 4, "function type parameters")?;
+            let params = lower_types(ctx, &product.entries[3]);
+            let ret = lower_type(ctx, ret);
+            Some(ast::TypeSig {
+                span,
+                params,
+                ret: Box::new(ret),
+                guards: None,
+            })
+        }
+        ("bounded_fun", [fun, constraints]) => {
+            let mut inner = lower_type_sig(ctx, fun)?;
+            let guards = ctx
+                .expect_list(constraints, "type constraints")?
+                .into_iter()
+                .filter_map(|constraint| lower_type_guard(ctx, constraint))
+                .collect();
+            inner.span = span;
+            inner.guards = Some(guards);
+            Some(inner)
+        }
+        ("fun", _) | ("bounded_fun", _) => {
+            ctx.malformed(span, "function type");
+            None
+        }
+        (n, _) => {
+            ctx.unsupported(span, "function type", n);
+            None
+        }
+    }
+}
+
+/// {type, L, constraint, [{atom, L, is_subtype}, [Var, Type]]}
+fn lower_type_guard(ctx: &mut LowerCtx, constraint: &aast::Item) -> Option<ast::TypeGuard> {
+    let tup = ctx.expect_tuple(constraint, 4, "type constraint")?;
+    let args = ctx.expect_list(&tup.entries[3], "type constraint")?;
+    let sub = match args.as_slice() {
+        [_is_subtype, sub] => ctx.expect_list(sub, "type constraint")?,
+        _ => {
+            ctx.malformed(tup.span, "type constraint");
+            return None;
+        }
+    };
+    match sub.as_slice() {
+        [var, ty] => {
+            let var_tup = ctx.expect_tuple(var, 3, "type variable")?;
+            let var = ctx.expect_atom(&var_tup.entries[2], "type variable")?;
+            Some(ast::TypeGuard {
+                span: tup.span,
+                var: Name::Var(var),
+                ty: lower_type(ctx, ty),
+            })
+        }
+        _ => {
+            ctx.malformed(tup.span, "type constraint");
+            None
+        }
+    }
 }
 
-fn integer(item: &aast::Item) -> &aast::Int {
-    let tup = item.tuple().unwrap();
-    assert!(&*tup.entries[0].atom().unwrap().as_str() == "integer");
-    tup.entries[2].integer().unwrap()
+fn lower_types(ctx: &mut LowerCtx, list: &aast::Item) -> Vec<ast::Type> {
+    match ctx.expect_list(list, "types") {
+        Some(items) => items.into_iter().map(|v| lower_type(ctx, v)).collect(),
+        None => Vec::new(),
+    }
+}
+
+/// The parameters of a `kind` type that takes exactly two.
+fn lower_type_pair(
+    ctx: &mut LowerCtx,
+    span: SourceSpan,
+    list: &aast::Item,
+    kind: &'static str,
+) -> Option<(Box<ast::Type>, Box<ast::Type>)> {
+    let mut types = lower_types(ctx, list).into_iter();
+    match (types.next(), types.next(), types.next()) {
+        (Some(first), Some(second), None) => Some((Box::new(first), Box::new(second))),
+        _ => {
+            ctx.malformed(span, kind);
+            None
+        }
+    }
+}
+
+/// Lowers `ty`, replacing it with `any()` if it is malformed.
+fn lower_type(ctx: &mut LowerCtx, ty: &aast::Item) -> ast::Type {
+    match try_lower_type(ctx, ty) {
+        Some(ty_n) => ty_n,
+        None => ctx.sentinel_type(ty.span()),
+    }
+}
+
+/// {Tag, L, ...}, `None` if the type is malformed. The error has been
+/// reported then.
+fn try_lower_type(ctx: &mut LowerCtx, ty: &aast::Item) -> Option<ast::Type> {
+    let (kind, tup) = ctx.expect_form(ty, 3, "type")?;
+    let span = tup.span;
+
+    let ty_n = match &*kind.as_str() {
+        "var" => ast::Type::Name(Name::Var(
+            ctx.expect_atom(&tup.entries[2], "type variable")?,
+        )),
+        "atom" => ast::Type::Name(Name::Atom(ctx.expect_atom(&tup.entries[2], "atom type")?)),
+        "integer" => {
+            let int = ctx.expect_integer(&tup.entries[2], "integer type")?;
+            ast::Type::Integer(span, int.integer.clone())
+        }
+        "char" => ast::Type::Char(span, ctx.expect_char(&tup.entries[2], "character type")?),
+        "op" => {
+            let op = ctx.expect_atom(&tup.entries[2], "type operator")?.as_str();
+            match (tup.entries.len(), unary_op(&op), binary_op(&op)) {
+                (4, Some(op), _) => ast::Type::UnaryOp {
+                    span,
+                    op,
+                    rhs: Box::new(lower_type(ctx, &tup.entries[3])),
+                },
+                (5, _, Some(op)) => ast::Type::BinaryOp {
+                    span,
+                    lhs: Box::new(lower_type(ctx, &tup.entries[3])),
+                    op,
+                    rhs: Box::new(lower_type(ctx, &tup.entries[4])),
+                },
+                _ => {
+                    ctx.unsupported(span, "type operator", &op);
+                    ctx.sentinel_type(span)
+                }
+            }
+        }
+        // {ann_type, L, [Var, Type]}
+        "ann_type" => match ctx
+            .expect_list(&tup.entries[2], "annotated type")?
+            .as_slice()
+        {
+            [var, ty] => {
+                let var_tup = ctx.expect_tuple(var, 3, "type variable")?;
+                let var = ctx.expect_atom(&var_tup.entries[2], "type variable")?;
+                ast::Type::Annotated {
+                    span,
+                    name: Name::Var(var),
+                    ty: Box::new(lower_type(ctx, ty)),
+                }
+            }
+            _ => {
+                ctx.malformed(span, "annotated type");
+                return None;
+            }
+        },
+        // {paren_type, L, [Type]}
+        "paren_type" => match ctx
+            .expect_list(&tup.entries[2], "parenthesized type")?
+            .as_slice()
+        {
+            [ty] => lower_type(ctx, ty),
+            _ => {
+                ctx.malformed(span, "parenthesized type");
+                return None;
+            }
+        },
+        // {remote_type, L, [Module, Name, Params]}
+        "remote_type" => match ctx.expect_list(&tup.entries[2], "remote type")?.as_slice() {
+            [module, fun, params] => {
+                let module = ctx.expect_atom_literal(module, "remote type")?;
+                let fun = ctx.expect_atom_literal(fun, "remote type")?;
+                ast::Type::Remote {
+                    span,
+                    module,
+                    fun,
+                    args: lower_types(ctx, params),
+                }
+            }
+            _ => {
+                ctx.malformed(span, "remote type");
+                return None;
+            }
+        },
+        // {user_type, L, Name, Params}
+        "user_type" => {
+            ctx.expect_len(tup, 4, "user type")?;
+            ast::Type::Generic {
+                span,
+                fun: ctx.expect_atom(&tup.entries[2], "user type")?,
+                params: lower_types(ctx, &tup.entries[3]),
+            }
+        }
+        // {type, L, Name, Params}
+        "type" => {
+            ctx.expect_len(tup, 4, "type")?;
+            lower_builtin_type(ctx, tup)?
+        }
+        n => {
+            ctx.unsupported(span, "type", n);
+            ctx.sentinel_type(span)
+        }
+    };
+    Some(ty_n)
+}
+
+fn lower_builtin_type(ctx: &mut LowerCtx, tup: &aast::Tuple) -> Option<ast::Type> {
+    let span = tup.span;
+    let name = ctx.expect_atom(&tup.entries[2], "type name")?;
+    let args = &tup.entries[3];
+    // `tuple()`, `map()` and `fun()` have the atom `any` in place of
+    // the parameters.
+    let is_any = args.atom().is_some();
+
+    let ty = match &*name.as_str() {
+        "union" => ast::Type::Union {
+            span,
+            types: lower_types(ctx, args),
+        },
+        "range" => {
+            let (start, end) = lower_type_pair(ctx, span, args, "range type")?;
+            ast::Type::Range { span, start, end }
+        }
+        "nil" => ast::Type::Nil(span),
+        "tuple" if !is_any => ast::Type::Tuple(span, lower_types(ctx, args)),
+        "map" if !is_any => ast::Type::Map(span, lower_types(ctx, args)),
+        "map_field_assoc" => {
+            let (key, value) = lower_type_pair(ctx, span, args, "map field type")?;
+            ast::Type::KeyValuePair(span, ast::MapFieldKind::Assoc, key, value)
+        }
+        "map_field_exact" => {
+            let (key, value) = lower_type_pair(ctx, span, args, "map field type")?;
+            ast::Type::KeyValuePair(span, ast::MapFieldKind::Exact, key, value)
+        }
+        // [{atom, L, Name} | Fields]
+        "record" => match ctx.expect_list(args, "record type")?.split_first() {
+            Some((record, fields)) => {
+                let record = ctx.expect_atom_literal(record, "record type")?;
+                let fields = fields.iter().map(|field| lower_type(ctx, field)).collect();
+                ast::Type::Record(span, record, fields)
+            }
+            None => {
+                ctx.malformed(span, "record type");
+                return None;
+            }
+        },
+        // [{atom, L, Name}, Type]
+        "field_type" => match ctx.expect_list(args, "record field type")?.as_slice() {
+            [field, ty] => {
+                let field = ctx.expect_atom_literal(field, "record field type")?;
+                ast::Type::Field(span, field, Box::new(lower_type(ctx, ty)))
+            }
+            _ => {
+                ctx.malformed(span, "record field type");
+                return None;
+            }
+        },
+        "binary" => {
+            let (size, unit) = lower_type_pair(ctx, span, args, "binary type")?;
+            ast::Type::Binary(span, size, unit)
+        }
+        "fun" if is_any => ast::Type::AnyFun { span, ret: None },
+        // [{type, L, any}, Ret] or [{type, L, product, Params}, Ret]
+        "fun" => match ctx.expect_list(args, "fun type")?.as_slice() {
+            [params, ret] => {
+                let params = ctx.expect_tuple(params, 3, "fun type parameters")?;
+                let params_kind = ctx.expect_atom(&params.entries[2], "fun type parameters")?;
+                let ret = Box::new(lower_type(ctx, ret));
+                match &*params_kind.as_str() {
+                    // fun((...) -> Ret)
+                    "any" => ast::Type::AnyFun {
+                        span,
+                        ret: Some(ret),
+                    },
+                    _ => {
+                        ctx.expect_len(params, 4, "fun type parameters")?;
+                        ast::Type::Fun {
+                            span,
+                            params: lower_types(ctx, &params.entries[3]),
+                            ret,
+                        }
+                    }
+                }
+            }
+            _ => {
+                ctx.malformed(span, "fun type");
+                return None;
+            }
+        },
+        _ => ast::Type::Generic {
+            span,
+            fun: name,
+            params: if is_any {
+                Vec::new()
+            } else {
+                lower_types(ctx, args)
+            },
+        },
+    };
+    Some(ty)
+}
+
+/// {atom, L, Atom}
+fn atom(item: &aast::Item) -> Option<Ident> {
+    let tup = item.tuple().filter(|tup| tup.entries.len() == 3)?;
+    if &*tup.entries[0].atom()?.as_str() == "atom" {
+        tup.entries[2].atom()
+    } else {
+        None
+    }
+}
+
+/// {integer, L, Integer}
+fn integer(item: &aast::Item) -> Option<&aast::Int> {
+    let tup = item.tuple().filter(|tup| tup.entries.len() == 3)?;
+    if &*tup.entries[0].atom()?.as_str() == "integer" {
+        tup.entries[2].integer()
+    } else {
+        None
+    }
 }
 
 #[cfg(test)]
@@ -916,13 +1814,145 @@ mod test {
 {eof,17}.
 ",
         );
-        super::lower(&root);
+        let mut errors: Errors<LowerError, LowerError> = Errors::new();
+        super::lower(&mut errors, &root);
+        assert!(!errors.failed());
     }
 
     #[test]
     fn maps() {
         let root: Root = parse_file("../test_data/maps.abstr");
-        super::lower(&root);
+        let mut errors: Errors<LowerError, LowerError> = Errors::new();
+        super::lower(&mut errors, &root);
+        assert!(!errors.failed());
+    }
+
+    #[test]
+    fn full_abstract_format() {
+        let root: Root = parse(
+            "
+{attribute,1,module,woo}.
+{attribute,2,export,[{foo,1},{bar,1},{baz,1},{bin,1}]}.
+{attribute,3,export_type,[{pair,1}]}.
+{attribute,4,type,pair,
+    {type,4,tuple,[{var,4,'T'},{var,4,'T'}]},[{var,4,'T'}]}.
+{attribute,5,opaque,opaque,{type,5,map,any},[]}.
+{attribute,6,record,{rec,[{typed_record_field,{record_field,6,{atom,6,a}},
+                                              {type,6,integer,[]}},
+                          {record_field,6,{atom,6,b},{atom,6,undefined}}]}}.
+{attribute,7,spec,
+    {{foo,1},
+    [{type,7,bounded_fun,
+        [{type,7,'fun',[{type,7,product,[{var,7,'T'}]},{user_type,7,pair,[{var,7,'T'}]}]},
+        [{type,7,constraint,[{atom,7,is_subtype},[{var,7,'T'},{type,7,integer,[]}]]}]]}]}}.
+{attribute,8,callback,
+    {{run,0},[{type,8,'fun',[{type,8,product,[]},{remote_type,8,[{atom,8,erlang},{atom,8,term},[]]}]}]}}.
+{attribute,9,my_attribute,{some,[value]}}.
+{function,10,foo,1,
+    [{clause,10,[{var,10,'X'}],[],[{tuple,10,[{var,10,'X'},{var,10,'X'}]}]}]}.
+{function,11,bar,1,
+    [{clause,11,[{var,11,'N'}],[],
+    [{call,11,
+        {named_fun,11,'Fact',
+            [{clause,11,[{integer,11,0}],[],[{integer,11,1}]},
+            {clause,11,[{var,11,'M'}],[],
+                [{op,11,'*',{var,11,'M'},
+                    {call,11,{var,11,'Fact'},[{op,11,'-',{var,11,'M'},{integer,11,1}}]}}]}]},
+        [{var,11,'N'}]}]}]}.
+{function,12,baz,1,
+    [{clause,12,[{var,12,'M'}],[],
+    [{mc,12,{map_field_assoc,12,{var,12,'V'},{var,12,'K'}},
+        [{m_generate,12,{map_field_exact,12,{var,12,'K'},{var,12,'V'}},{var,12,'M'}}]}]}]}.
+{function,13,bin,1,
+    [{clause,13,
+    [{bin,13,[{bin_element,13,{var,13,'Size'},{integer,13,8},default},
+              {bin_element,13,{var,13,'Data'},{var,13,'Size'},[binary,{unit,8}]},
+              {bin_element,13,{var,13,'_'},default,[bitstring]}]}],
+    [],
+    [{bc,13,{bin,13,[{bin_element,13,{var,13,'B'},default,default}]},
+        [{b_generate,13,{bin,13,[{bin_element,13,{var,13,'B'},{integer,13,8},default}]},
+            {var,13,'Data'}}]}]}]}.
+{eof,14}.
+",
+        );
+        let mut errors: Errors<LowerError, LowerError> = Errors::new();
+        let module = super::lower(&mut errors, &root);
+        assert!(!errors.failed());
+        assert_eq!(module.specs.len(), 1);
+        assert_eq!(module.callbacks.len(), 1);
+        assert_eq!(module.types.len(), 2);
+        assert_eq!(module.functions.len(), 4);
+    }
+
+    #[test]
+    fn unsupported_forms() {
+        let root: Root = parse(
+            "
+{attribute,1,module,woo}.
+{attribute,2,export,[{foo,0}]}.
+{warning,{3,epp,{unknown,thing}}}.
+{function,4,foo,0,
+    [{clause,4,[],[],[{some_future_expr,4,{atom,4,a}},{atom,4,ok}]}]}.
+{eof,5}.
+",
+        );
+        let mut errors: Errors<LowerError, LowerError> = Errors::new();
+        let module = super::lower(&mut errors, &root);
+        assert!(errors.failed());
+        assert_eq!(module.functions.len(), 1);
+
+        let messages: Vec<String> = errors.iter_diagnostics().map(|d| d.message).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "unsupported form 'warning' in abstract format".to_string(),
+                "unsupported expression 'some_future_expr' in abstract format".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_forms() {
+        let root: Root = parse(
+            "
+{attribute,1,module,woo}.
+{attribute,2,export,[{foo,0},bar]}.
+{attribute,3,import}.
+{attribute,4,spec,{{foo,0},not_a_list}}.
+{attribute,5,type,{t,{type,5,integer,[]}}}.
+{attribute,6,record,{rec,[{record_field,6}]}}.
+{function,7,foo}.
+{function,8,foo,0,
+    [{clause,8,[],[],
+    [{var,8},{integer,8,nope},{tuple,8,[{atom,8}]},{call,8,{atom,8,f}}]}]}.
+{function,9,bar,0,[{clause,9,[],[]}]}.
+{eof,10}.
+",
+        );
+        let mut errors: Errors<LowerError, LowerError> = Errors::new();
+        let module = super::lower(&mut errors, &root);
+        assert!(errors.failed());
+        assert_eq!(module.functions.len(), 1);
+
+        let messages: Vec<String> = errors.iter_diagnostics().map(|d| d.message).collect();
+        let malformed = |kind: &str| format!("malformed {} in abstract format", kind);
+        assert_eq!(
+            messages,
+            vec![
+                malformed("function name"),
+                malformed("attribute"),
+                malformed("spec"),
+                malformed("type definition"),
+                malformed("record field"),
+                malformed("function"),
+                malformed("variable"),
+                malformed("integer"),
+                malformed("atom"),
+                malformed("call"),
+                malformed("clause"),
+                malformed("function clauses"),
+            ]
+        );
     }
 
     #[test]
@@ -936,7 +1966,7 @@ mod test {
                 "../test_data/match_SUITE.abstr",
             ) {
                 Ok(ast) => {
-                    let module = super::lower(&ast);
+                    let module = super::lower(&mut errors.make_into_adapter(), &ast);
                     crate::lower_module(
                         &mut errors.make_into_adapter(),
                         parser.codemap.clone(),
@@ -35,6 +35,12 @@ pub enum EvalError {
     #[snafu(display("attempted too large bitshift"))]
     TooLargeShift { span: SourceSpan },
 
+    #[snafu(display("boolean operators requires all operands to be booleans"))]
+    InvalidBooleanOperand { span: SourceSpan },
+
+    #[snafu(display("bad argument in call to guard function"))]
+    BadArgument { span: SourceSpan },
+
     #[snafu(display("no record with name"))]
     NoRecord { span: SourceSpan },
 
@@ -65,6 +71,12 @@ impl ToDiagnostic for EvalError {
             EvalError::TooLargeShift { span } => Diagnostic::error()
                 .with_message(msg)
                 .with_labels(vec![Label::primary(span.source_id(), *span)]),
+            EvalError::InvalidBooleanOperand { span } => Diagnostic::error()
+                .with_message(msg)
+                .with_labels(vec![Label::primary(span.source_id(), *span)]),
+            EvalError::BadArgument { span } => Diagnostic::error()
+                .with_message(msg)
+                .with_labels(vec![Label::primary(span.source_id(), *span)]),
             EvalError::NoRecord { span } => Diagnostic::error()
                 .with_message(msg)
                 .with_labels(vec![Label::primary(span.source_id(), *span)]),
@@ -148,6 +160,16 @@ impl From<bool> for Term {
 }
 
 impl Term {
+    /// The value of the term if it is one of the atoms `true` or
+    /// `false`.
+    pub fn boolean(&self) -> Option<bool> {
+        match self {
+            Term::Atom(sym) if *sym == symbols::True => Some(true),
+            Term::Atom(sym) if *sym == symbols::False => Some(false),
+            _ => None,
+        }
+    }
+
     pub fn equals(&self, rhs: &Term, exact: bool) -> bool {
         match (self, rhs) {
             (Term::Atom(l), Term::Atom(r)) => l == r,
@@ -161,6 +183,94 @@ impl Term {
     }
 }
 
+/// Evaluates a call to one of the guard BIFs that do not depend on
+/// the state of the process.
+fn eval_guard_bif(span: SourceSpan, name: Symbol, mut args: Vec<Term>) -> Result<Term, EvalError> {
+    let res = match (&*name.as_str(), args.len()) {
+        ("is_atom", 1) => (match args[0] {
+            Term::Atom(_) => true,
+            _ => false,
+        })
+        .into(),
+        ("is_boolean", 1) => args[0].boolean().is_some().into(),
+        ("is_integer", 1) => (match args[0] {
+            Term::Number(Number::Integer(_)) => true,
+            _ => false,
+        })
+        .into(),
+        ("is_float", 1) => (match args[0] {
+            Term::Number(Number::Float(_)) => true,
+            _ => false,
+        })
+        .into(),
+        ("is_number", 1) => (match args[0] {
+            Term::Number(_) => true,
+            _ => false,
+        })
+        .into(),
+        ("is_list", 1) => (match args[0] {
+            Term::Nil | Term::Cons(_, _) => true,
+            _ => false,
+        })
+        .into(),
+        ("is_tuple", 1) => (match args[0] {
+            Term::Tuple(_) => true,
+            _ => false,
+        })
+        .into(),
+        ("is_map", 1) => (match args[0] {
+            Term::Map(_) => true,
+            _ => false,
+        })
+        .into(),
+        ("abs", 1) => match args.pop().unwrap() {
+            Term::Number(num) if num < Number::Integer(0.into()) => (-num).into(),
+            Term::Number(num) => num.into(),
+            _ => Err(EvalError::BadArgument { span })?,
+        },
+        ("hd", 1) => match args.pop().unwrap() {
+            Term::Cons(head, _) => *head,
+            _ => Err(EvalError::BadArgument { span })?,
+        },
+        ("tl", 1) => match args.pop().unwrap() {
+            Term::Cons(_, tail) => *tail,
+            _ => Err(EvalError::BadArgument { span })?,
+        },
+        ("length", 1) => {
+            let mut len = 0;
+            let mut list = &args[0];
+            loop {
+                match list {
+                    Term::Nil => break,
+                    Term::Cons(_, tail) => {
+                        len += 1;
+                        list = tail;
+                    }
+                    _ => Err(EvalError::BadArgument { span })?,
+                }
+            }
+            Number::Integer(Integer::from(len as i64)).into()
+        }
+        ("tuple_size", 1) => match &args[0] {
+            Term::Tuple(elems) => Number::Integer(elems.len().into()).into(),
+            _ => Err(EvalError::BadArgument { span })?,
+        },
+        ("map_size", 1) => match &args[0] {
+            Term::Map(map) => Number::Integer(map.len().into()).into(),
+            _ => Err(EvalError::BadArgument { span })?,
+        },
+        ("element", 2) => match (&args[0], &args[1]) {
+            (Term::Number(Number::Integer(idx)), Term::Tuple(elems)) => match idx.to_usize() {
+                Some(idx) if idx >= 1 && idx <= elems.len() => elems[idx - 1].clone(),
+                _ => Err(EvalError::BadArgument { span })?,
+            },
+            _ => Err(EvalError::BadArgument { span })?,
+        },
+        _ => Err(EvalError::InvalidConstExpression { span })?,
+    };
+    Ok(res)
+}
+
 pub enum ResolveRecordIndexError {
     NoRecord,
     NoField,
@@ -179,8 +289,15 @@ pub fn eval_expr(
             Literal::Integer(_span, _id, int) => Term::Number(int.clone().into()),
             Literal::Float(_span, _id, float) => Term::Number((*float).into()),
             Literal::Atom(_id, atom) => Term::Atom(atom.name),
-
-            lit => return Err(EvalError::InvalidConstExpression { span: lit.span() }),
+            Literal::Char(_span, _id, c) => Term::Number(Number::Integer((*c).into())),
+            Literal::String(_id, string) => {
+                string.as_str().chars().rev().fold(Term::Nil, |acc, c| {
+                    Term::Cons(
+                        Box::new(Term::Number(Number::Integer(c.into()))),
+                        Box::new(acc),
+                    )
+                })
+            }
         },
 
         Expr::Nil(_) => Term::Nil,
@@ -215,6 +332,19 @@ pub fn eval_expr(
             use BinaryOp as B;
 
             let lhs = eval_expr(&bin_expr.lhs, resolve_record_index)?;
+
+            // Like in a guard, the right operand of `andalso` and
+            // `orelse` is only evaluated if the left one does not decide
+            // the result, and it is the result as is.
+            if let B::AndAlso | B::OrElse = bin_expr.op {
+                return match (bin_expr.op, lhs.boolean()) {
+                    (B::AndAlso, Some(false)) => Ok(false.into()),
+                    (B::OrElse, Some(true)) => Ok(true.into()),
+                    (_, Some(_)) => eval_expr(&bin_expr.rhs, resolve_record_index),
+                    (_, None) => Err(EvalError::InvalidBooleanOperand { span }),
+                };
+            }
+
             let rhs = eval_expr(&bin_expr.rhs, resolve_record_index)?;
 
             match (bin_expr.op, lhs, rhs) {
@@ -269,6 +399,19 @@ pub fn eval_expr(
                 (B::StrictEqual, l, r) => l.equals(&r, true).into(),
                 (B::StrictNotEqual, l, r) => (!l.equals(&r, true)).into(),
 
+                (B::And, l, r) => match (l.boolean(), r.boolean()) {
+                    (Some(l), Some(r)) => (l && r).into(),
+                    _ => Err(EvalError::InvalidBooleanOperand { span })?,
+                },
+                (B::Or, l, r) => match (l.boolean(), r.boolean()) {
+                    (Some(l), Some(r)) => (l || r).into(),
+                    _ => Err(EvalError::InvalidBooleanOperand { span })?,
+                },
+                (B::Xor, l, r) => match (l.boolean(), r.boolean()) {
+                    (Some(l), Some(r)) => (l != r).into(),
+                    _ => Err(EvalError::InvalidBooleanOperand { span })?,
+                },
+
                 (B::Append, _, _) => unimplemented!(),
                 (B::Remove, _, _) => unimplemented!(),
 
@@ -296,6 +439,26 @@ pub fn eval_expr(
             }
         }
 
+        Expr::Apply(apply) => {
+            let name = match &*apply.callee {
+                Expr::Literal(Literal::Atom(_id, name)) => name.name,
+                Expr::Remote(remote) => match (&*remote.module, &*remote.function) {
+                    (
+                        Expr::Literal(Literal::Atom(_, module)),
+                        Expr::Literal(Literal::Atom(_, function)),
+                    ) if &*module.as_str() == "erlang" => function.name,
+                    _ => Err(EvalError::InvalidConstExpression { span })?,
+                },
+                _ => Err(EvalError::InvalidConstExpression { span })?,
+            };
+            let args = apply
+                .args
+                .iter()
+                .map(|arg| eval_expr(arg, resolve_record_index))
+                .collect::<Result<Vec<_>, _>>()?;
+            eval_guard_bif(span, name, args)?
+        }
+
         _ => Err(EvalError::InvalidConstExpression { span })?,
     };
     Ok(res)
@@ -0,0 +1,322 @@
+//! A formatter for Erlang source files, in the spirit of `erlfmt`.
+//!
+//! The source is split into forms at every `.` that ends one, and each
+//! form is parsed on its own and pretty printed from its AST. Comments
+//! are kept in front of the element they precede. Forms that can not be
+//! parsed without the preprocessor, like macro definitions and forms that
+//! use macros, are kept as they are written. Every printed form is parsed
+//! again and compared with the original, and is kept as written if it
+//! does not mean the same.
+
+mod printer;
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use libeir_diagnostics::{CodeMap, SourceFile};
+use libeir_util_parse::{Errors, FileMapSource, Parse as GParse, Scanner};
+
+use crate::lexer::{Lexer, LexicalError, LexicalToken, Token};
+use crate::parser::ast::TopLevel;
+use crate::parser::ParserError;
+use crate::preprocessor::attribute_keyword;
+
+/// Attributes that are handled by the preprocessor.
+const DIRECTIVES: &[&str] = &[
+    "module",
+    "include",
+    "include_lib",
+    "define",
+    "undef",
+    "ifdef",
+    "ifndef",
+    "elif",
+    "endif",
+    "error",
+    "warning",
+    "file",
+    "feature",
+];
+
+#[derive(Debug, Clone)]
+pub struct FormatConfig {
+    /// The number of columns lines are fit into where possible.
+    pub width: usize,
+}
+impl Default for FormatConfig {
+    fn default() -> Self {
+        FormatConfig { width: 100 }
+    }
+}
+
+/// Formats the Erlang source in `file`. Only lexical errors are reported,
+/// anything that fails to parse is kept as it is written.
+pub fn format(config: &FormatConfig, file: Arc<SourceFile>) -> Result<String, LexicalError> {
+    let tokens = lex(&file)?;
+    let text = file.source();
+
+    let mut out = String::new();
+    let mut prev_end = None;
+    for item in split_items(text, &tokens) {
+        let item_tokens = &tokens[item.tokens.clone()];
+        let start = token_range(&item_tokens[0]).start;
+        let end = token_range(&item_tokens[item_tokens.len() - 1]).end;
+
+        if let Some(prev_end) = prev_end {
+            let newlines = text[prev_end..start].matches('\n').count();
+            if item.comment && newlines == 0 {
+                out.push(' ');
+            } else if newlines > 1 {
+                out.push_str("\n\n");
+            } else {
+                out.push('\n');
+            }
+        }
+        if item.comment {
+            out.push_str(text[start..end].trim_end());
+        } else {
+            match format_form(config, &file, item_tokens) {
+                Some(form) => out.push_str(&form),
+                None => out.push_str(&text[start..end]),
+            }
+        }
+        prev_end = Some(end);
+    }
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// A comment outside of any form, or a form, as a range of tokens.
+struct Item {
+    tokens: Range<usize>,
+    comment: bool,
+}
+
+fn lex(file: &Arc<SourceFile>) -> Result<Vec<LexicalToken>, LexicalError> {
+    let lexer = Lexer::new(Scanner::new(FileMapSource::new(file.clone()))).retain_comments();
+    let mut tokens = Vec::new();
+    for token in lexer {
+        let token = token?;
+        if token.1 != Token::EOF {
+            tokens.push(token);
+        }
+    }
+    Ok(tokens)
+}
+
+fn token_range(token: &LexicalToken) -> Range<usize> {
+    token.span().into()
+}
+
+fn is_comment(token: &Token) -> bool {
+    match token {
+        Token::Comment | Token::Edoc => true,
+        _ => false,
+    }
+}
+
+fn split_items(text: &str, tokens: &[LexicalToken]) -> Vec<Item> {
+    let mut items = Vec::new();
+    let mut form_start = None;
+    for (idx, token) in tokens.iter().enumerate() {
+        if form_start.is_none() {
+            if is_comment(&token.1) {
+                items.push(Item {
+                    tokens: idx..idx + 1,
+                    comment: true,
+                });
+                continue;
+            }
+            form_start = Some(idx);
+        }
+
+        // A `.` only ends a form if it is followed by whitespace, a comment
+        // or the end of the file, otherwise it is a record field access.
+        let end = token_range(token).end;
+        let ends_form = match text[end..].chars().next() {
+            Some(c) => c.is_whitespace() || c == '%',
+            None => true,
+        };
+        if token.1 == Token::Dot && ends_form {
+            items.push(Item {
+                tokens: form_start.unwrap()..idx + 1,
+                comment: false,
+            });
+            form_start = None;
+        }
+    }
+    // An unterminated form at the end of the file
+    if let Some(start) = form_start {
+        items.push(Item {
+            tokens: start..tokens.len(),
+            comment: false,
+        });
+    }
+    items
+}
+
+/// Formats a single form. Returns `None` if it is to be kept as written.
+fn format_form(
+    config: &FormatConfig,
+    file: &SourceFile,
+    tokens: &[LexicalToken],
+) -> Option<String> {
+    if !is_formattable(tokens) {
+        return None;
+    }
+    let form = parse_form(tokens)?;
+    let printed = printer::print_form(config.width, file.id(), file.source(), tokens, &form)?;
+
+    // Only keep the printed form if it means the same, and has all the
+    // comments of the original.
+    let codemap = CodeMap::new();
+    let id = codemap.add("formatted", printed.clone());
+    let printed_tokens = lex(&codemap.get(id).unwrap()).ok()?;
+    let comments = |tokens: &[LexicalToken]| tokens.iter().filter(|t| is_comment(&t.1)).count();
+    if comments(&printed_tokens) != comments(tokens) {
+        return None;
+    }
+    if parse_form(&printed_tokens)? != form {
+        return None;
+    }
+    Some(printed)
+}
+
+/// Whether a form can be parsed without the preprocessor.
+fn is_formattable(tokens: &[LexicalToken]) -> bool {
+    let code: Vec<&Token> = tokens
+        .iter()
+        .map(|token| &token.1)
+        .filter(|token| !is_comment(token))
+        .collect();
+    if code.last() != Some(&&Token::Dot) {
+        return false;
+    }
+    let preprocessed = code.iter().any(|token| match token {
+        Token::Question | Token::DoubleQuestion | Token::DelayedSubstitution(_) => true,
+        // Only keywords if the `maybe_expr` feature is enabled.
+        Token::Maybe | Token::Else => true,
+        _ => false,
+    });
+    if preprocessed {
+        return false;
+    }
+    match (code.get(0), code.get(1)) {
+        (Some(Token::Minus), Some(Token::Atom(name))) => !DIRECTIVES.contains(&name.as_str().get()),
+        (Some(Token::Minus), Some(Token::If)) => false,
+        _ => true,
+    }
+}
+
+fn parse_form(tokens: &[LexicalToken]) -> Option<TopLevel> {
+    let mut tokens: Vec<LexicalToken> = tokens
+        .iter()
+        .filter(|token| !is_comment(&token.1))
+        .cloned()
+        .collect();
+    // The preprocessor turns the names of attributes with their own rules
+    // in the grammar into keywords.
+    if tokens.len() > 1 && tokens[0].1 == Token::Minus {
+        let keyword = match &tokens[1].1 {
+            Token::Atom(name) => attribute_keyword(name.as_str().get()),
+            _ => None,
+        };
+        if let Some(keyword) = keyword {
+            tokens[1].1 = keyword;
+        }
+    }
+
+    let mut errors: Errors<ParserError, ParserError> = Errors::new();
+    TopLevel::parse_tokens(
+        &mut errors,
+        tokens.into_iter().map(|token| Ok(token.into())),
+    )
+    .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use libeir_diagnostics::CodeMap;
+    use pretty_assertions::assert_eq;
+
+    use super::{format, FormatConfig};
+
+    fn format_str(input: &str) -> String {
+        let codemap = CodeMap::new();
+        let id = codemap.add("nofile", input.to_string());
+        format(&FormatConfig::default(), codemap.get(id).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn format_functions() {
+        let input = "-module(foo).\n\
+                     -export([bar/1,baz/0]).\n\
+                     \n\
+                     %% Comment\n\
+                     bar(X)->case X of a->1;\n\
+                     % one\n\
+                     b->2 end.\n\
+                     baz() -> ok. % trailing\n";
+        let expected = "-module(foo).\n\
+                        -export([bar/1, baz/0]).\n\
+                        \n\
+                        %% Comment\n\
+                        bar(X) ->\n    \
+                            case X of\n        \
+                                a -> 1;\n        \
+                                % one\n        \
+                                b -> 2\n    \
+                            end.\n\
+                        baz() -> ok. % trailing\n";
+        assert_eq!(format_str(input), expected);
+    }
+
+    #[test]
+    fn format_long_lines() {
+        let input = "f() -> g(aaaaaaaaaaaaaaaaaaaa, bbbbbbbbbbbbbbbbbbbb, cccccccccccccccccccc, \
+                     dddddddddddddddddddd, eeeeeeeeeeeeeeeeeeee).\n";
+        let expected = "f() ->\n    \
+                            g(\n        \
+                                aaaaaaaaaaaaaaaaaaaa,\n        \
+                                bbbbbbbbbbbbbbbbbbbb,\n        \
+                                cccccccccccccccccccc,\n        \
+                                dddddddddddddddddddd,\n        \
+                                eeeeeeeeeeeeeeeeeeee\n    \
+                            ).\n";
+        assert_eq!(format_str(input), expected);
+    }
+
+    #[test]
+    fn keep_preprocessor_forms() {
+        let input = "-define(X,  1).\n\
+                     f() ->   ?X.\n\
+                     g(  ) -> ok\n";
+        assert_eq!(format_str(input), input);
+    }
+
+    #[test]
+    fn idempotent_on_test_data() {
+        let mut paths: Vec<_> = std::fs::read_dir(Path::new("../test_data"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().map(|ext| ext == "erl").unwrap_or(false))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty());
+
+        for path in paths {
+            let input = std::fs::read_to_string(&path).unwrap();
+            let once = format_str(&input);
+            let twice = format_str(&once);
+            assert!(
+                once == twice,
+                "formatting {} is not idempotent",
+                path.display()
+            );
+        }
+    }
+}
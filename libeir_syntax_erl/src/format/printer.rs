@@ -0,0 +1,1381 @@
+//! Pretty printing of a single top-level form.
+//!
+//! Layout decisions are made by `pretty`: a group is put on one line if
+//! it fits, and broken at its `line`s otherwise. Everything that has to
+//! span several lines (clause bodies with more than one expression,
+//! blocks like `case`, comments) is built with explicit hard line breaks
+//! instead, and the groups around it are left out, so `pretty` never has
+//! to decide whether a group containing a hard break fits.
+
+use std::ops::Range;
+
+use libeir_diagnostics::{SourceId, SourceSpan};
+use pretty::{Arena, DocAllocator, DocBuilder};
+
+use crate::lexer::{Ident, LexicalToken, Token};
+use crate::parser::ast::*;
+
+use super::is_comment;
+
+type Doc<'a> = DocBuilder<'a, Arena<'a>>;
+
+/// Binding power of expressions that are not operators.
+const MAX: u32 = 1000;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Mode {
+    Expr,
+    Pattern,
+    /// The value of a user attribute, where a function name is written
+    /// without `fun`.
+    Constant,
+}
+
+/// Prints `form` to a string that is at most `width` columns wide where
+/// possible. Returns `None` if the form can not be printed without losing
+/// any of the comments in `tokens`.
+pub(super) fn print_form(
+    width: usize,
+    source_id: SourceId,
+    text: &str,
+    tokens: &[LexicalToken],
+    form: &TopLevel,
+) -> Option<String> {
+    let arena = Arena::new();
+    let mut printer = Printer::new(&arena, source_id, text, tokens);
+    let doc = printer.form(form)?;
+    if printer.next_comment != printer.comments.len() {
+        return None;
+    }
+
+    let mut out = String::new();
+    doc.render_fmt(width, &mut out).ok()?;
+    let lines: Vec<&str> = out.lines().map(str::trim_end).collect();
+    Some(lines.join("\n"))
+}
+
+struct Printer<'a> {
+    arena: &'a Arena<'a>,
+    source_id: SourceId,
+    text: &'a str,
+    tokens: &'a [LexicalToken],
+    /// The comments of the form, in order.
+    comments: Vec<Range<usize>>,
+    /// The first comment that has not been printed yet.
+    next_comment: usize,
+    /// The number of hard line breaks produced so far. A document is
+    /// multiline if this changed while it was built.
+    breaks: usize,
+}
+
+impl<'a> Printer<'a> {
+    fn new(
+        arena: &'a Arena<'a>,
+        source_id: SourceId,
+        text: &'a str,
+        tokens: &'a [LexicalToken],
+    ) -> Self {
+        let comments = tokens
+            .iter()
+            .filter(|token| is_comment(&token.1))
+            .map(|token| token.span().into())
+            .collect();
+        Printer {
+            arena,
+            source_id,
+            text,
+            tokens,
+            comments,
+            next_comment: 0,
+            breaks: 0,
+        }
+    }
+
+    fn hardline(&mut self) -> Doc<'a> {
+        self.breaks += 1;
+        self.arena.hardline()
+    }
+
+    /// A space, or a line break if the enclosing group does not fit.
+    fn line(&mut self, multiline: bool) -> Doc<'a> {
+        if multiline {
+            self.hardline()
+        } else {
+            self.arena.line()
+        }
+    }
+
+    /// Nothing, or a line break if the enclosing group does not fit.
+    fn line_(&mut self, multiline: bool) -> Doc<'a> {
+        if multiline {
+            self.hardline()
+        } else {
+            self.arena.line_()
+        }
+    }
+
+    fn group(doc: Doc<'a>, multiline: bool) -> Doc<'a> {
+        if multiline {
+            doc
+        } else {
+            doc.group()
+        }
+    }
+
+    fn range(&self, span: SourceSpan) -> Option<Range<usize>> {
+        if span.source_id() != self.source_id {
+            return None;
+        }
+        let range: Range<usize> = span.into();
+        if range.end <= self.text.len() {
+            Some(range)
+        } else {
+            None
+        }
+    }
+
+    fn start(&self, span: SourceSpan) -> usize {
+        self.range(span).map(|range| range.start).unwrap_or(0)
+    }
+
+    fn end(&self, span: SourceSpan) -> usize {
+        self.range(span).map(|range| range.end).unwrap_or(0)
+    }
+
+    /// Whether `inner` is written within `outer`. Parts of the AST that
+    /// the parser fills in with defaults are not.
+    fn within(&self, outer: SourceSpan, inner: SourceSpan) -> bool {
+        match (self.range(outer), self.range(inner)) {
+            (Some(outer), Some(inner)) => inner.start >= outer.start && inner.end <= outer.end,
+            _ => false,
+        }
+    }
+
+    /// The source text of `span`, as it is written.
+    fn slice(&mut self, span: SourceSpan) -> Doc<'a> {
+        let text = self.text;
+        let slice = self
+            .range(span)
+            .and_then(|range| text.get(range))
+            .unwrap_or("");
+        if slice.contains('\n') {
+            self.breaks += 1;
+        }
+        self.arena.text(slice)
+    }
+
+    fn ident(&mut self, ident: Ident) -> Doc<'a> {
+        let text = self.text;
+        match self.range(ident.span).and_then(|range| text.get(range)) {
+            Some(slice) => self.arena.text(slice),
+            None => self.arena.as_string(ident.name),
+        }
+    }
+
+    fn name(&mut self, name: &Name) -> Doc<'a> {
+        match name {
+            Name::Atom(ident) | Name::Var(ident) => self.ident(*ident),
+        }
+    }
+
+    /// Takes the comments that start before `pos`.
+    fn take_comments(&mut self, pos: usize) -> Vec<&'a str> {
+        let text = self.text;
+        let mut comments = Vec::new();
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.start >= pos {
+                break;
+            }
+            comments.push(text[comment.clone()].trim_end());
+            self.next_comment += 1;
+        }
+        comments
+    }
+
+    /// The comments before `pos`, each followed by a line break, to be
+    /// put in front of the element that starts at `pos`.
+    fn leading_comments(&mut self, pos: usize) -> Doc<'a> {
+        let mut doc = self.arena.nil();
+        for comment in self.take_comments(pos) {
+            doc = doc.append(self.arena.text(comment)).append(self.hardline());
+        }
+        doc
+    }
+
+    /// The comments before `pos`, each preceded by a line break, to be
+    /// put after the last element of a block or container that ends at
+    /// `pos`.
+    fn trailing_comments(&mut self, pos: usize) -> Doc<'a> {
+        let mut doc = self.arena.nil();
+        for comment in self.take_comments(pos) {
+            doc = doc.append(self.hardline()).append(self.arena.text(comment));
+        }
+        doc
+    }
+
+    /// Whether the source has an empty line between `from` and `to`.
+    fn blank_line(&self, from: usize, to: usize) -> bool {
+        match self.text.get(from..to) {
+            Some(gap) => {
+                let lines: Vec<&str> = gap.split('\n').collect();
+                lines.len() > 2
+                    && lines[1..lines.len() - 1]
+                        .iter()
+                        .any(|line| line.trim().is_empty())
+            }
+            None => false,
+        }
+    }
+
+    /// `items` separated by `separator` and a line break, keeping the
+    /// empty lines between them and the comments in front of them.
+    fn sequence<T>(
+        &mut self,
+        items: &[T],
+        separator: &'static str,
+        span: fn(&T) -> SourceSpan,
+        print: fn(&mut Self, &T) -> Doc<'a>,
+    ) -> Doc<'a> {
+        let mut doc = self.arena.nil();
+        let mut prev_end = None;
+        for item in items {
+            let span = span(item);
+            let start = self.start(span);
+            if let Some(prev_end) = prev_end {
+                doc = doc.append(separator).append(self.hardline());
+                if self.blank_line(prev_end, start) {
+                    doc = doc.append(self.hardline());
+                }
+            }
+            let comments = self.leading_comments(start);
+            doc = doc.append(comments).append(print(self, item));
+            prev_end = Some(self.end(span));
+        }
+        doc
+    }
+
+    /// `elements` between `open` and `close`, separated by commas. They
+    /// are put on one line if they fit, and each on its own line if not.
+    /// The comments before `end` go after the last element. `before` is
+    /// the number of line breaks before the elements were printed.
+    fn enclose(
+        &mut self,
+        open: &'static str,
+        elements: Vec<Doc<'a>>,
+        close: &'static str,
+        end: Option<usize>,
+        before: usize,
+    ) -> Doc<'a> {
+        let arena = self.arena;
+        let trailing = match end {
+            Some(end) => self.trailing_comments(end),
+            None => arena.nil(),
+        };
+        let multiline = self.breaks != before;
+        if elements.is_empty() && !multiline {
+            return arena.text(open).append(close);
+        }
+
+        let first = if elements.is_empty() {
+            arena.nil()
+        } else {
+            self.line_(multiline)
+        };
+        let separator = arena.text(",").append(self.line(multiline));
+        let inner = first
+            .append(arena.intersperse(elements, separator))
+            .append(trailing)
+            .nest(4);
+        let doc = arena
+            .text(open)
+            .append(inner)
+            .append(self.line_(multiline))
+            .append(close);
+        Self::group(doc, multiline)
+    }
+
+    /// An indented block of lines below a keyword, ending with the
+    /// comments before `end`.
+    fn block(&mut self, doc: Doc<'a>, end: usize) -> Doc<'a> {
+        let trailing = self.trailing_comments(end);
+        self.hardline().append(doc).append(trailing).nest(4)
+    }
+
+    fn form(&mut self, form: &TopLevel) -> Option<Doc<'a>> {
+        let arena = self.arena;
+        let doc = match form {
+            TopLevel::Function(function) => {
+                let clauses =
+                    self.sequence(&function.clauses, ";", |c| c.span, Self::function_clause);
+                clauses.append(".")
+            }
+            TopLevel::Record(record) => {
+                let end = self.end(record.span);
+                let name = self.ident(record.name);
+                let fields = self.record_fields(&record.fields, end, Mode::Expr);
+                arena
+                    .text("-record(")
+                    .append(name)
+                    .append(", ")
+                    .append(fields)
+                    .append(").")
+            }
+            TopLevel::Attribute(attribute) => self.attribute(attribute)?,
+        };
+        Some(doc)
+    }
+
+    fn attribute(&mut self, attribute: &Attribute) -> Option<Doc<'a>> {
+        let arena = self.arena;
+        let doc = match attribute {
+            Attribute::Type(def) => self.type_def(def),
+            Attribute::Spec(spec) => self.spec("-spec ", spec.module, spec.function, &spec.sigs),
+            Attribute::Callback(callback) => {
+                let keyword = if callback.optional {
+                    "-optional_callback "
+                } else {
+                    "-callback "
+                };
+                self.spec(keyword, callback.module, callback.function, &callback.sigs)
+            }
+            Attribute::Custom(attribute) => {
+                let name = self.ident(attribute.name);
+                let value = self.expr(&attribute.value, 0, Mode::Constant);
+                arena
+                    .text("-")
+                    .append(name)
+                    .append("(")
+                    .append(value)
+                    .append(").")
+            }
+            Attribute::Export(span, names) => {
+                let names = self.function_names(names, self.end(*span));
+                arena.text("-export(").append(names).append(").")
+            }
+            Attribute::ExportType(span, names) => {
+                let names = self.function_names(names, self.end(*span));
+                arena.text("-export_type(").append(names).append(").")
+            }
+            Attribute::Import(span, module, names) => {
+                let module = self.ident(*module);
+                let names = self.function_names(names, self.end(*span));
+                arena
+                    .text("-import(")
+                    .append(module)
+                    .append(", ")
+                    .append(names)
+                    .append(").")
+            }
+            Attribute::Removed(span, removed) => {
+                let before = self.breaks;
+                let mut docs = Vec::with_capacity(removed.len());
+                for (name, description) in removed {
+                    let start = self.start(name.span);
+                    let comments = self.leading_comments(start);
+                    let function = self.ident(name.function);
+                    let description = self.string(*description);
+                    docs.push(
+                        comments
+                            .append("{")
+                            .append(function)
+                            .append(", ")
+                            .append(arena.as_string(name.arity))
+                            .append(", ")
+                            .append(description)
+                            .append("}"),
+                    );
+                }
+                let end = self.end(*span);
+                let removed = self.enclose("[", docs, "]", Some(end), before);
+                arena.text("-removed(").append(removed).append(").")
+            }
+            Attribute::Compile(_, value) => {
+                let value = self.expr(value, 0, Mode::Constant);
+                arena.text("-compile(").append(value).append(").")
+            }
+            Attribute::Vsn(_, value) => {
+                let value = self.expr(value, 0, Mode::Constant);
+                arena.text("-vsn(").append(value).append(").")
+            }
+            Attribute::Author(_, value) => {
+                let value = self.expr(value, 0, Mode::Constant);
+                arena.text("-author(").append(value).append(").")
+            }
+            Attribute::OnLoad(_, name) => {
+                let function = self.ident(name.function);
+                arena
+                    .text("-on_load(")
+                    .append(function)
+                    .append("/")
+                    .append(arena.as_string(name.arity))
+                    .append(").")
+            }
+            Attribute::Behaviour(_, name) => {
+                let name = self.ident(*name);
+                arena.text("-behaviour(").append(name).append(").")
+            }
+            // The parsed form does not keep the flag, so it could not be
+            // checked that the printed form means the same.
+            Attribute::Deprecation(_) => return None,
+        };
+        Some(doc)
+    }
+
+    fn function_names(&mut self, names: &[PartiallyResolvedFunctionName], end: usize) -> Doc<'a> {
+        let arena = self.arena;
+        let before = self.breaks;
+        let mut docs = Vec::with_capacity(names.len());
+        for name in names {
+            let start = self.start(name.span);
+            let comments = self.leading_comments(start);
+            let function = self.ident(name.function);
+            docs.push(
+                comments
+                    .append(function)
+                    .append("/")
+                    .append(arena.as_string(name.arity)),
+            );
+        }
+        self.enclose("[", docs, "]", Some(end), before)
+    }
+
+    fn function_clause(&mut self, clause: &FunctionClause) -> Doc<'a> {
+        let before = self.breaks;
+        let head = self.clause_head(clause);
+        self.clause(before, head, &clause.body)
+    }
+
+    /// The name, parameters and guards of a function clause.
+    fn clause_head(&mut self, clause: &FunctionClause) -> Doc<'a> {
+        let name = match &clause.name {
+            Some(name) => self.name(name),
+            None => self.arena.nil(),
+        };
+        let before = self.breaks;
+        let params = self.elements(&clause.params, Mode::Pattern);
+        let params = self.enclose("(", params, ")", None, before);
+        let guards = self.guards(&clause.guard);
+        name.append(params).append(guards)
+    }
+
+    /// `head -> body`, without a group around it. Returns whether it has
+    /// to be printed on several lines.
+    fn clause_parts(&mut self, before: usize, head: Doc<'a>, body: &[Expr]) -> (Doc<'a>, bool) {
+        let body = self.sequence(body, ",", Expr::span, Self::body_expr);
+        let multiline = self.breaks != before;
+        let line = self.line(multiline);
+        (
+            head.append(" ->").append(line.append(body).nest(4)),
+            multiline,
+        )
+    }
+
+    /// `head -> body`, on one line if it fits. `before` is the number of
+    /// line breaks before the head was printed.
+    fn clause(&mut self, before: usize, head: Doc<'a>, body: &[Expr]) -> Doc<'a> {
+        let (doc, multiline) = self.clause_parts(before, head, body);
+        Self::group(doc, multiline)
+    }
+
+    fn body_expr(&mut self, expr: &Expr) -> Doc<'a> {
+        self.expr(expr, 0, Mode::Expr)
+    }
+
+    fn case_clause(&mut self, clause: &Clause) -> Doc<'a> {
+        let before = self.breaks;
+        let pattern = self.expr(&clause.pattern, 0, Mode::Pattern);
+        let guards = self.guards(&clause.guard);
+        self.clause(before, pattern.append(guards), &clause.body)
+    }
+
+    fn if_clause(&mut self, clause: &IfClause) -> Doc<'a> {
+        let before = self.breaks;
+        let guards = self.guard_sequence(&clause.guards);
+        self.clause(before, guards, &clause.body)
+    }
+
+    fn try_clause(&mut self, clause: &TryClause) -> Doc<'a> {
+        let before = self.breaks;
+        let mut head = self.arena.nil();
+        if self.within(clause.span, clause.kind.span()) {
+            head = head.append(self.name(&clause.kind)).append(":");
+        }
+        head = head.append(self.expr(&clause.error, 0, Mode::Pattern));
+        if self.within(clause.span, clause.trace.span) {
+            head = head.append(":").append(self.ident(clause.trace));
+        }
+        let guards = self.guards(&clause.guard);
+        self.clause(before, head.append(guards), &clause.body)
+    }
+
+    /// ` when Guards`, or nothing.
+    fn guards(&mut self, guards: &Option<Vec<Guard>>) -> Doc<'a> {
+        match guards {
+            Some(guards) => self
+                .arena
+                .text(" when ")
+                .append(self.guard_sequence(guards)),
+            None => self.arena.nil(),
+        }
+    }
+
+    fn guard_sequence(&mut self, guards: &[Guard]) -> Doc<'a> {
+        let arena = self.arena;
+        let before = self.breaks;
+        let mut alternatives = Vec::with_capacity(guards.len());
+        for guard in guards {
+            let mut conditions = Vec::with_capacity(guard.conditions.len());
+            for condition in guard.conditions.iter() {
+                conditions.push(self.expr(condition, 0, Mode::Expr));
+            }
+            alternatives.push(conditions);
+        }
+        let multiline = self.breaks != before;
+        let comma = arena.text(",").append(self.line(multiline));
+        let semicolon = arena.text(";").append(self.line(multiline));
+        let doc = arena.intersperse(
+            alternatives
+                .into_iter()
+                .map(|conditions| arena.intersperse(conditions, comma.clone())),
+            semicolon,
+        );
+        Self::group(doc.nest(4), multiline)
+    }
+
+    fn commented_expr(&mut self, expr: &Expr, min: u32, mode: Mode) -> Doc<'a> {
+        let start = self.start(expr.span());
+        let comments = self.leading_comments(start);
+        comments.append(self.expr(expr, min, mode))
+    }
+
+    fn elements(&mut self, exprs: &[Expr], mode: Mode) -> Vec<Doc<'a>> {
+        let mut docs = Vec::with_capacity(exprs.len());
+        for expr in exprs {
+            docs.push(self.commented_expr(expr, 0, mode));
+        }
+        docs
+    }
+
+    /// Prints `expr`, in parentheses if it binds less tightly than `min`.
+    fn expr(&mut self, expr: &Expr, min: u32, mode: Mode) -> Doc<'a> {
+        let doc = self.unparenthesized(expr, mode);
+        if expr_prec(expr) < min {
+            self.arena.text("(").append(doc).append(")")
+        } else {
+            doc
+        }
+    }
+
+    fn unparenthesized(&mut self, expr: &Expr, mode: Mode) -> Doc<'a> {
+        let arena = self.arena;
+        match expr {
+            Expr::Var(Var(_, ident)) => self.ident(*ident),
+            Expr::Literal(literal) => self.literal(literal),
+            Expr::FunctionName(name) => self.function_name(name, mode),
+            Expr::DelayedSubstitution(span, _, _) => self.slice(*span),
+            Expr::Nil(_) => arena.text("[]"),
+            Expr::Cons(cons) => self.list(cons, mode),
+            Expr::Tuple(tuple) => {
+                let before = self.breaks;
+                let elements = self.elements(&tuple.elements, mode);
+                let end = self.end(tuple.span);
+                self.enclose("{", elements, "}", Some(end), before)
+            }
+            Expr::Map(map) => {
+                let before = self.breaks;
+                let fields = self.map_fields(&map.fields, mode);
+                let end = self.end(map.span);
+                self.enclose("#{", fields, "}", Some(end), before)
+            }
+            Expr::MapUpdate(update) => {
+                let map = self.map_base(&update.map, mode);
+                let before = self.breaks;
+                let fields = self.map_fields(&update.updates, mode);
+                let end = self.end(update.span);
+                map.append(self.enclose("#{", fields, "}", Some(end), before))
+            }
+            Expr::MapProjection(projection) => {
+                let map = self.map_base(&projection.map, mode);
+                let before = self.breaks;
+                let fields = self.map_fields(&projection.fields, mode);
+                let end = self.end(projection.span);
+                map.append(self.enclose("#{", fields, "}", Some(end), before))
+            }
+            Expr::Binary(binary) => self.binary(binary, mode),
+            Expr::Record(record) => {
+                let name = self.ident(record.name);
+                let end = self.end(record.span);
+                let fields = self.record_fields(&record.fields, end, mode);
+                arena.text("#").append(name).append(fields)
+            }
+            Expr::RecordAccess(access) => {
+                let record = self.record_base(&access.record, mode);
+                let name = self.ident(access.name);
+                let field = self.ident(access.field);
+                record.append("#").append(name).append(".").append(field)
+            }
+            Expr::RecordIndex(index) => {
+                let name = self.ident(index.name);
+                let field = self.ident(index.field);
+                arena.text("#").append(name).append(".").append(field)
+            }
+            Expr::RecordUpdate(update) => {
+                let record = self.record_base(&update.record, mode);
+                let name = self.ident(update.name);
+                let end = self.end(update.span);
+                let fields = self.record_fields(&update.updates, end, mode);
+                record.append("#").append(name).append(fields)
+            }
+            Expr::ListComprehension(comprehension) => {
+                let before = self.breaks;
+                let body = self.expr(&comprehension.body, 0, mode);
+                self.comprehension("[", body, &comprehension.qualifiers, "]", before)
+            }
+            Expr::BinaryComprehension(comprehension) => {
+                let before = self.breaks;
+                let body = self.expr(&comprehension.body, MAX, mode);
+                self.comprehension("<< ", body, &comprehension.qualifiers, " >>", before)
+            }
+            Expr::MapComprehension(comprehension) => {
+                let before = self.breaks;
+                let key = self.expr(&comprehension.key, 0, mode);
+                let value = self.expr(&comprehension.value, 0, mode);
+                let body = key.append(" => ").append(value);
+                self.comprehension("#{", body, &comprehension.qualifiers, "}", before)
+            }
+            Expr::Generator(generator) => {
+                let pattern = self.expr(&generator.pattern, 0, mode);
+                let expr = self.expr(&generator.expr, 0, mode);
+                pattern.append(" <- ").append(expr)
+            }
+            Expr::BinaryGenerator(generator) => {
+                let pattern = self.expr(&generator.pattern, 0, mode);
+                let expr = self.expr(&generator.expr, 0, mode);
+                pattern.append(" <= ").append(expr)
+            }
+            Expr::MapGenerator(generator) => {
+                let key = self.expr(&generator.key, 0, mode);
+                let value = self.expr(&generator.value, 0, mode);
+                let expr = self.expr(&generator.expr, 0, mode);
+                key.append(" := ").append(value).append(" <- ").append(expr)
+            }
+            Expr::Begin(begin) => {
+                let body = self.sequence(&begin.body, ",", Expr::span, Self::body_expr);
+                let end = self.end(begin.span);
+                let block = self.block(body, end);
+                arena
+                    .text("begin")
+                    .append(block)
+                    .append(self.hardline())
+                    .append("end")
+            }
+            Expr::Apply(apply) => {
+                let callee = self.expr(&apply.callee, 800, mode);
+                let before = self.breaks;
+                let args = self.elements(&apply.args, mode);
+                let end = self.end(apply.span);
+                callee.append(self.enclose("(", args, ")", Some(end), before))
+            }
+            Expr::Remote(remote) => {
+                let module = self.expr(&remote.module, MAX, mode);
+                let function = self.expr(&remote.function, MAX, mode);
+                module.append(":").append(function)
+            }
+            Expr::BinaryExpr(binary) => {
+                let (_, lhs_min, rhs_min) = binary_prec(binary.op);
+                let before = self.breaks;
+                let lhs = self.expr(&binary.lhs, lhs_min, mode);
+                let rhs = self.expr(&binary.rhs, rhs_min, mode);
+                let multiline = self.breaks != before;
+                let line = self.line(multiline);
+                let doc = lhs
+                    .append(" ")
+                    .append(arena.as_string(binary.op))
+                    .append(line.append(rhs).nest(4));
+                Self::group(doc, multiline)
+            }
+            Expr::UnaryExpr(unary) => {
+                let op = match unary.op {
+                    UnaryOp::Plus => "+",
+                    UnaryOp::Minus => "-",
+                    UnaryOp::Bnot => "bnot ",
+                    UnaryOp::Not => "not ",
+                };
+                let operand = self.expr(&unary.operand, 700, mode);
+                arena.text(op).append(operand)
+            }
+            Expr::Match(m) => {
+                // Matches in patterns are right associative, the ones in
+                // expressions left associative.
+                let (lhs_min, rhs_min) = match mode {
+                    Mode::Pattern => (200, 100),
+                    _ => (100, 140),
+                };
+                let pattern = self.expr(&m.pattern, lhs_min, mode);
+                let expr = self.expr(&m.expr, rhs_min, mode);
+                pattern.append(" = ").append(expr)
+            }
+            Expr::MaybeMatch(m) => {
+                let pattern = self.expr(&m.pattern, 100, mode);
+                let expr = self.expr(&m.expr, 0, mode);
+                pattern.append(" ?= ").append(expr)
+            }
+            Expr::Catch(catch) => {
+                let expr = self.expr(&catch.expr, 0, mode);
+                arena.text("catch ").append(expr)
+            }
+            Expr::If(expr) => {
+                let clauses = self.sequence(&expr.clauses, ";", |c| c.span, Self::if_clause);
+                let end = self.end(expr.span);
+                let block = self.block(clauses, end);
+                arena
+                    .text("if")
+                    .append(block)
+                    .append(self.hardline())
+                    .append("end")
+            }
+            Expr::Case(case) => {
+                let input = self.expr(&case.expr, 0, mode);
+                let clauses = self.sequence(&case.clauses, ";", |c| c.span, Self::case_clause);
+                let end = self.end(case.span);
+                let block = self.block(clauses, end);
+                arena
+                    .text("case ")
+                    .append(input)
+                    .append(" of")
+                    .append(block)
+                    .append(self.hardline())
+                    .append("end")
+            }
+            Expr::Receive(receive) => self.receive(receive),
+            Expr::Try(expr) => self.try_expr(expr),
+            Expr::Fun(fun) => self.fun(fun),
+            Expr::Maybe(maybe) => {
+                let else_start = maybe
+                    .else_clauses
+                    .as_ref()
+                    .map(|clauses| self.start(clauses[0].span));
+                let end = self.end(maybe.span);
+                let body = self.sequence(&maybe.body, ",", Expr::span, Self::body_expr);
+                let mut doc = arena
+                    .text("maybe")
+                    .append(self.block(body, else_start.unwrap_or(end)));
+                if let Some(clauses) = &maybe.else_clauses {
+                    let clauses = self.sequence(clauses, ";", |c| c.span, Self::case_clause);
+                    doc = doc
+                        .append(self.hardline())
+                        .append("else")
+                        .append(self.block(clauses, end));
+                }
+                doc.append(self.hardline()).append("end")
+            }
+        }
+    }
+
+    fn literal(&mut self, literal: &Literal) -> Doc<'a> {
+        match literal {
+            Literal::Atom(_, ident) => self.ident(*ident),
+            Literal::String(_, ident) => self.string(*ident),
+            Literal::Char(span, _, _)
+            | Literal::Integer(span, _, _)
+            | Literal::Float(span, _, _) => self.slice(*span),
+        }
+    }
+
+    /// A string literal. Adjacent strings are parsed into one literal, the
+    /// parts are put on their own lines.
+    fn string(&mut self, string: Ident) -> Doc<'a> {
+        let range = match self.range(string.span) {
+            Some(range) => range,
+            None => return self.ident(string),
+        };
+        let parts: Vec<Range<usize>> = self
+            .tokens
+            .iter()
+            .filter(|token| match &token.1 {
+                Token::String(_) => true,
+                _ => false,
+            })
+            .map(|token| token.span().into())
+            .filter(|part: &Range<usize>| part.start >= range.start && part.end <= range.end)
+            .collect();
+        if parts.len() < 2 {
+            return self.slice(string.span);
+        }
+
+        let text = self.text;
+        if parts.iter().any(|part| text[part.clone()].contains('\n')) {
+            self.breaks += 1;
+        }
+        let separator = self.hardline();
+        let arena = self.arena;
+        arena.intersperse(
+            parts.into_iter().map(|part| arena.text(&text[part])),
+            separator,
+        )
+    }
+
+    fn function_name(&mut self, name: &FunctionName, mode: Mode) -> Doc<'a> {
+        let arena = self.arena;
+        let doc = match mode {
+            Mode::Constant => arena.nil(),
+            _ => arena.text("fun "),
+        };
+        match name {
+            FunctionName::PartiallyResolved(name) => doc
+                .append(self.ident(name.function))
+                .append("/")
+                .append(arena.as_string(name.arity)),
+            FunctionName::Resolved(name) => doc
+                .append(self.ident(name.module))
+                .append(":")
+                .append(self.ident(name.function))
+                .append("/")
+                .append(arena.as_string(name.arity)),
+            FunctionName::Unresolved(name) => {
+                let mut doc = doc;
+                if let Some(module) = &name.module {
+                    doc = doc.append(self.name(module)).append(":");
+                }
+                doc = doc.append(self.name(&name.function)).append("/");
+                match &name.arity {
+                    Arity::Int(arity) => doc.append(arena.as_string(arity)),
+                    Arity::Var(var) => doc.append(self.ident(*var)),
+                }
+            }
+        }
+    }
+
+    fn list(&mut self, cons: &Cons, mode: Mode) -> Doc<'a> {
+        let before = self.breaks;
+        let end = self.end(cons.span);
+        let mut elements = Vec::new();
+        let mut cons = cons;
+        loop {
+            elements.push(self.commented_expr(&cons.head, 0, mode));
+            match &*cons.tail {
+                Expr::Cons(tail) => cons = tail,
+                Expr::Nil(_) => break,
+                tail => {
+                    let tail = self.commented_expr(tail, 0, mode);
+                    let last = elements.pop().unwrap();
+                    elements.push(last.append(" | ").append(tail));
+                    break;
+                }
+            }
+        }
+        self.enclose("[", elements, "]", Some(end), before)
+    }
+
+    fn comprehension(
+        &mut self,
+        open: &'static str,
+        body: Doc<'a>,
+        qualifiers: &[Expr],
+        close: &'static str,
+        before: usize,
+    ) -> Doc<'a> {
+        let arena = self.arena;
+        let qualifiers = self.elements(qualifiers, Mode::Expr);
+        let multiline = self.breaks != before;
+        let separator = arena.text(",").append(self.line(multiline));
+        let line = self.line(multiline);
+        let qualifiers = line
+            .append("|| ")
+            .append(arena.intersperse(qualifiers, separator))
+            .nest(4);
+        let doc = arena
+            .text(open)
+            .append(body)
+            .append(qualifiers)
+            .append(close);
+        Self::group(doc, multiline)
+    }
+
+    fn map_base(&mut self, map: &Expr, mode: Mode) -> Doc<'a> {
+        let min = match map {
+            Expr::Map(_) | Expr::MapUpdate(_) | Expr::MapProjection(_) => 0,
+            _ => MAX,
+        };
+        self.expr(map, min, mode)
+    }
+
+    fn record_base(&mut self, record: &Expr, mode: Mode) -> Doc<'a> {
+        let min = match record {
+            Expr::Record(_)
+            | Expr::RecordAccess(_)
+            | Expr::RecordIndex(_)
+            | Expr::RecordUpdate(_) => 0,
+            _ => MAX,
+        };
+        self.expr(record, min, mode)
+    }
+
+    fn map_fields(&mut self, fields: &[MapField], mode: Mode) -> Vec<Doc<'a>> {
+        // Keys of constant maps are expressions.
+        let key_mode = match mode {
+            Mode::Constant => Mode::Expr,
+            mode => mode,
+        };
+        let mut docs = Vec::with_capacity(fields.len());
+        for field in fields {
+            let (key, op, value) = match field {
+                MapField::Assoc { key, value, .. } => (key, " => ", value),
+                MapField::Exact { key, value, .. } => (key, " := ", value),
+            };
+            let start = self.start(field.span());
+            let comments = self.leading_comments(start);
+            let key = self.expr(key, 0, key_mode);
+            let value = self.expr(value, 0, mode);
+            docs.push(comments.append(key).append(op).append(value));
+        }
+        docs
+    }
+
+    fn record_fields(&mut self, fields: &[RecordField], end: usize, mode: Mode) -> Doc<'a> {
+        let before = self.breaks;
+        let mut docs = Vec::with_capacity(fields.len());
+        for field in fields {
+            let start = self.start(field.span);
+            let mut doc = self.leading_comments(start);
+            doc = doc.append(self.ident(field.name));
+            if let Some(value) = &field.value {
+                doc = doc.append(" = ").append(self.expr(value, 0, mode));
+            }
+            if let Some(ty) = &field.ty {
+                doc = doc.append(" :: ").append(self.ty(ty, 0));
+            }
+            docs.push(doc);
+        }
+        self.enclose("{", docs, "}", Some(end), before)
+    }
+
+    fn binary(&mut self, binary: &Binary, mode: Mode) -> Doc<'a> {
+        // A `~b"..."` sigil is parsed into a binary with the span of its
+        // only element.
+        if binary.elements.len() == 1 && binary.elements[0].span == binary.span {
+            return self.slice(binary.span);
+        }
+
+        let before = self.breaks;
+        let mut docs = Vec::with_capacity(binary.elements.len());
+        for element in binary.elements.iter() {
+            let start = self.start(element.span);
+            let comments = self.leading_comments(start);
+            docs.push(comments.append(self.binary_element(element, mode)));
+        }
+        // `<<<<` would be lexed wrong.
+        let (open, close) = match binary.elements.first().map(|e| &e.bit_expr) {
+            Some(Expr::Binary(_)) | Some(Expr::BinaryComprehension(_)) => ("<< ", " >>"),
+            _ => ("<<", ">>"),
+        };
+        let end = self.end(binary.span);
+        self.enclose(open, docs, close, Some(end), before)
+    }
+
+    fn binary_element(&mut self, element: &BinaryElement, mode: Mode) -> Doc<'a> {
+        let arena = self.arena;
+        let min = match &element.bit_expr {
+            Expr::UnaryExpr(unary) if expr_prec(&unary.operand) == MAX => 0,
+            _ => MAX,
+        };
+        let mut doc = self.expr(&element.bit_expr, min, mode);
+        if let Some(size) = &element.bit_size {
+            doc = doc.append(":").append(self.expr(size, MAX, mode));
+        }
+        if let Some(types) = &element.bit_type {
+            let mut docs = Vec::with_capacity(types.len());
+            for ty in types {
+                docs.push(match ty {
+                    BitType::Name(_, _, name) => self.ident(*name),
+                    BitType::Sized(_, _, name, size) => {
+                        self.ident(*name).append(":").append(arena.as_string(size))
+                    }
+                });
+            }
+            doc = doc
+                .append("/")
+                .append(arena.intersperse(docs, arena.text("-")));
+        }
+        doc
+    }
+
+    fn receive(&mut self, receive: &Receive) -> Doc<'a> {
+        let end = self.end(receive.span);
+        let mut doc = self.arena.text("receive");
+        if let Some(clauses) = &receive.clauses {
+            let after_start = receive.after.as_ref().map(|after| self.start(after.span));
+            let clauses = self.sequence(clauses, ";", |c| c.span, Self::case_clause);
+            doc = doc.append(self.block(clauses, after_start.unwrap_or(end)));
+        }
+        if let Some(after) = &receive.after {
+            let before = self.breaks;
+            let timeout = self.commented_expr(&after.timeout, 0, Mode::Expr);
+            let clause = self.clause(before, timeout, &after.body);
+            doc = doc
+                .append(self.hardline())
+                .append("after")
+                .append(self.block(clause, end));
+        }
+        doc.append(self.hardline()).append("end")
+    }
+
+    fn try_expr(&mut self, expr: &Try) -> Doc<'a> {
+        let end = self.end(expr.span);
+        let of_start = expr.clauses.as_ref().map(|c| self.start(c[0].span));
+        let catch_start = expr.catch_clauses.as_ref().map(|c| self.start(c[0].span));
+        let after_start = expr.after.as_ref().map(|a| self.start(a[0].span()));
+
+        let body = self.sequence(&expr.exprs, ",", Expr::span, Self::body_expr);
+        let body_end = of_start.or(catch_start).or(after_start).unwrap_or(end);
+        let mut doc = self.arena.text("try").append(self.block(body, body_end));
+        if let Some(clauses) = &expr.clauses {
+            let clauses = self.sequence(clauses, ";", |c| c.span, Self::case_clause);
+            let clauses_end = catch_start.or(after_start).unwrap_or(end);
+            doc = doc
+                .append(self.hardline())
+                .append("of")
+                .append(self.block(clauses, clauses_end));
+        }
+        if let Some(clauses) = &expr.catch_clauses {
+            let clauses = self.sequence(clauses, ";", |c| c.span, Self::try_clause);
+            let clauses_end = after_start.unwrap_or(end);
+            doc = doc
+                .append(self.hardline())
+                .append("catch")
+                .append(self.block(clauses, clauses_end));
+        }
+        if let Some(after) = &expr.after {
+            let after = self.sequence(after, ",", Expr::span, Self::body_expr);
+            doc = doc
+                .append(self.hardline())
+                .append("after")
+                .append(self.block(after, end));
+        }
+        doc.append(self.hardline()).append("end")
+    }
+
+    fn fun(&mut self, fun: &Function) -> Doc<'a> {
+        let arena = self.arena;
+        let (clauses, span) = match fun {
+            Function::Named(fun) => (&fun.clauses, fun.span),
+            Function::Unnamed(fun) => (&fun.clauses, fun.span),
+        };
+
+        if clauses.len() == 1 {
+            let clause = &clauses[0];
+            let before = self.breaks;
+            let keyword = match clause.name {
+                Some(_) => "fun ",
+                None => "fun",
+            };
+            let head = arena.text(keyword).append(self.clause_head(clause));
+            let (doc, multiline) = self.clause_parts(before, head, &clause.body);
+            let line = self.line(multiline);
+            let doc = doc.append(line).append("end");
+            return Self::group(doc, multiline);
+        }
+
+        let clauses = self.sequence(clauses, ";", |c| c.span, Self::function_clause);
+        let end = self.end(span);
+        let block = self.block(clauses, end);
+        arena
+            .text("fun")
+            .append(block)
+            .append(self.hardline())
+            .append("end")
+    }
+
+    fn type_def(&mut self, def: &TypeDef) -> Doc<'a> {
+        let arena = self.arena;
+        let keyword = if def.opaque { "-opaque " } else { "-type " };
+        let name = self.ident(def.name);
+        let before = self.breaks;
+        let mut params = Vec::with_capacity(def.params.len());
+        for param in def.params.iter() {
+            params.push(self.name(param));
+        }
+        let params = self.enclose("(", params, ")", None, before);
+
+        let before = self.breaks;
+        let ty = self.ty(&def.ty, 0);
+        let multiline = self.breaks != before;
+        let line = self.line(multiline);
+        let doc = arena
+            .text(keyword)
+            .append(name)
+            .append(params)
+            .append(" ::")
+            .append(line.append(ty).nest(4));
+        Self::group(doc, multiline).append(".")
+    }
+
+    fn spec(
+        &mut self,
+        keyword: &'static str,
+        module: Option<Ident>,
+        function: Ident,
+        sigs: &[TypeSig],
+    ) -> Doc<'a> {
+        let arena = self.arena;
+        let mut doc = arena.text(keyword);
+        if let Some(module) = module {
+            doc = doc.append(self.ident(module)).append(":");
+        }
+        doc = doc.append(self.ident(function));
+
+        let mut rest = arena.nil();
+        for (idx, sig) in sigs.iter().enumerate() {
+            let sig = self.type_sig(sig);
+            if idx == 0 {
+                doc = doc.append(sig);
+            } else {
+                rest = rest.append(";").append(self.hardline()).append(sig);
+            }
+        }
+        doc.append(rest.nest(4)).append(".")
+    }
+
+    fn type_sig(&mut self, sig: &TypeSig) -> Doc<'a> {
+        let arena = self.arena;
+        let before = self.breaks;
+        let params = self.types(&sig.params);
+        let params = self.enclose("(", params, ")", None, before);
+        let ret = self.ty(&sig.ret, 0);
+        let doc = params.append(" -> ").append(ret);
+
+        let guards = match &sig.guards {
+            Some(guards) => guards,
+            None => return doc,
+        };
+        let mut docs = Vec::with_capacity(guards.len());
+        for guard in guards {
+            let var = self.name(&guard.var);
+            let ty = self.ty(&guard.ty, 0);
+            docs.push(var.append(" :: ").append(ty));
+        }
+        let multiline = self.breaks != before;
+        let separator = arena.text(",").append(self.line(multiline));
+        let line = self.line(multiline);
+        let doc = doc
+            .append(" when")
+            .append(line.append(arena.intersperse(docs, separator)).nest(4));
+        Self::group(doc, multiline)
+    }
+
+    fn types(&mut self, types: &[Type]) -> Vec<Doc<'a>> {
+        let mut docs = Vec::with_capacity(types.len());
+        for ty in types {
+            docs.push(self.ty(ty, 0));
+        }
+        docs
+    }
+
+    /// Prints `ty`, in parentheses if it binds less tightly than `min`.
+    fn ty(&mut self, ty: &Type, min: u32) -> Doc<'a> {
+        let doc = self.unparenthesized_ty(ty);
+        if type_prec(ty) < min {
+            self.arena.text("(").append(doc).append(")")
+        } else {
+            doc
+        }
+    }
+
+    fn unparenthesized_ty(&mut self, ty: &Type) -> Doc<'a> {
+        let arena = self.arena;
+        match ty {
+            Type::Name(name) => self.name(name),
+            Type::Annotated { name, ty, .. } => {
+                let name = self.name(name);
+                name.append(" :: ").append(self.ty(ty, 200))
+            }
+            Type::Union { types, .. } => {
+                let before = self.breaks;
+                let mut docs = Vec::with_capacity(types.len());
+                for (idx, ty) in types.iter().enumerate() {
+                    docs.push(self.ty(ty, if idx == 0 { 100 } else { 200 }));
+                }
+                let multiline = self.breaks != before;
+                let separator = self.line(multiline).append("| ");
+                Self::group(arena.intersperse(docs, separator).nest(4), multiline)
+            }
+            Type::Range { start, end, .. } => {
+                let start = self.ty(start, 300);
+                start.append("..").append(self.ty(end, 300))
+            }
+            Type::BinaryOp { lhs, op, rhs, .. } => {
+                let (_, lhs_min, rhs_min) = type_binary_prec(*op);
+                let lhs = self.ty(lhs, lhs_min);
+                let rhs = self.ty(rhs, rhs_min);
+                lhs.append(" ")
+                    .append(arena.as_string(op))
+                    .append(" ")
+                    .append(rhs)
+            }
+            Type::UnaryOp { op, rhs, .. } => {
+                let op = match op {
+                    UnaryOp::Plus => "+",
+                    UnaryOp::Minus => "-",
+                    UnaryOp::Bnot => "bnot ",
+                    UnaryOp::Not => "not ",
+                };
+                arena.text(op).append(self.ty(rhs, 600))
+            }
+            Type::Generic { fun, params, .. } => {
+                let fun = self.ident(*fun);
+                let before = self.breaks;
+                let params = self.types(params);
+                fun.append(self.enclose("(", params, ")", None, before))
+            }
+            Type::Remote {
+                module, fun, args, ..
+            } => {
+                let module = self.ident(*module);
+                let fun = self.ident(*fun);
+                let before = self.breaks;
+                let args = self.types(args);
+                module
+                    .append(":")
+                    .append(fun)
+                    .append(self.enclose("(", args, ")", None, before))
+            }
+            Type::Nil(_) => arena.text("[]"),
+            Type::List(_, ty) => arena.text("[").append(self.ty(ty, 0)).append("]"),
+            Type::NonEmptyList(_, ty) => arena.text("[").append(self.ty(ty, 0)).append(", ...]"),
+            Type::Map(_, fields) => {
+                let before = self.breaks;
+                let fields = self.types(fields);
+                self.enclose("#{", fields, "}", None, before)
+            }
+            Type::Tuple(_, elements) => {
+                let before = self.breaks;
+                let elements = self.types(elements);
+                self.enclose("{", elements, "}", None, before)
+            }
+            Type::Record(_, name, fields) => {
+                let name = self.ident(*name);
+                let before = self.breaks;
+                let fields = self.types(fields);
+                arena
+                    .text("#")
+                    .append(name)
+                    .append(self.enclose("{", fields, "}", None, before))
+            }
+            Type::Binary(span, m, n) => {
+                // Sizes that are not written get the span of the binary.
+                let written = |ty: &Type| match ty {
+                    Type::Integer(ty_span, _) => ty_span != span,
+                    _ => true,
+                };
+                let mut doc = arena.text("<<");
+                if written(m) {
+                    doc = doc.append("_:").append(self.ty(m, 0));
+                }
+                if written(n) {
+                    doc = doc.append(", _:").append(self.ty(n, 0));
+                }
+                doc.append(">>")
+            }
+            Type::Integer(span, _) | Type::Char(span, _) => self.slice(*span),
+            Type::AnyFun { ret: None, .. } => arena.text("fun()"),
+            Type::AnyFun { ret: Some(ret), .. } => arena
+                .text("fun((...) -> ")
+                .append(self.ty(ret, 0))
+                .append(")"),
+            Type::Fun { params, ret, .. } => {
+                let before = self.breaks;
+                let params = self.types(params);
+                let params = self.enclose("(", params, ")", None, before);
+                arena
+                    .text("fun(")
+                    .append(params)
+                    .append(" -> ")
+                    .append(self.ty(ret, 0))
+                    .append(")")
+            }
+            Type::KeyValuePair(_, kind, key, value) => {
+                let op = match kind {
+                    MapFieldKind::Assoc => " => ",
+                    MapFieldKind::Exact => " := ",
+                };
+                let key = self.ty(key, 0);
+                key.append(op).append(self.ty(value, 0))
+            }
+            Type::Field(_, name, ty) => {
+                let name = self.ident(*name);
+                name.append(" :: ").append(self.ty(ty, 0))
+            }
+        }
+    }
+}
+
+/// The binding power of `expr`, following the levels of the grammar.
+fn expr_prec(expr: &Expr) -> u32 {
+    match expr {
+        Expr::Catch(_) => 0,
+        Expr::Match(_) | Expr::MaybeMatch(_) => 100,
+        Expr::BinaryExpr(binary) => binary_prec(binary.op).0,
+        Expr::UnaryExpr(_) | Expr::Map(_) | Expr::MapUpdate(_) | Expr::MapProjection(_) => 600,
+        Expr::Apply(_)
+        | Expr::Record(_)
+        | Expr::RecordAccess(_)
+        | Expr::RecordIndex(_)
+        | Expr::RecordUpdate(_) => 700,
+        Expr::Remote(_) => 800,
+        _ => MAX,
+    }
+}
+
+/// The binding power of a binary operator, and the least binding powers
+/// of its operands.
+fn binary_prec(op: BinaryOp) -> (u32, u32, u32) {
+    match op {
+        BinaryOp::Send => (140, 140, 150),
+        BinaryOp::OrElse => (150, 160, 150),
+        BinaryOp::AndAlso => (160, 200, 160),
+        BinaryOp::Equal
+        | BinaryOp::NotEqual
+        | BinaryOp::Lte
+        | BinaryOp::Lt
+        | BinaryOp::Gte
+        | BinaryOp::Gt
+        | BinaryOp::StrictEqual
+        | BinaryOp::StrictNotEqual => (200, 300, 300),
+        BinaryOp::Append | BinaryOp::Remove => (300, 400, 300),
+        BinaryOp::Add
+        | BinaryOp::Sub
+        | BinaryOp::Bor
+        | BinaryOp::Bxor
+        | BinaryOp::Bsl
+        | BinaryOp::Bsr
+        | BinaryOp::Or
+        | BinaryOp::Xor => (400, 400, 500),
+        BinaryOp::Divide
+        | BinaryOp::Multiply
+        | BinaryOp::Div
+        | BinaryOp::Rem
+        | BinaryOp::Band
+        | BinaryOp::And => (500, 500, 600),
+    }
+}
+
+fn type_prec(ty: &Type) -> u32 {
+    match ty {
+        Type::Union { .. } => 0,
+        Type::Annotated { .. } => 100,
+        Type::Range { .. } => 200,
+        Type::BinaryOp { op, .. } => type_binary_prec(*op).0,
+        Type::UnaryOp { .. } => 500,
+        Type::Generic { .. } | Type::Remote { .. } => 600,
+        _ => MAX,
+    }
+}
+
+fn type_binary_prec(op: BinaryOp) -> (u32, u32, u32) {
+    match op {
+        BinaryOp::Divide | BinaryOp::Multiply | BinaryOp::Div | BinaryOp::Rem | BinaryOp::Band => {
+            (400, 400, 500)
+        }
+        _ => (300, 300, 400),
+    }
+}
@@ -26,9 +26,28 @@ pub enum LexicalError {
     #[snafu(display("Unclosed atom literal"))]
     UnclosedAtom { span: SourceSpan },
 
+    /// Occurs when a triple-quoted string is not laid out correctly
+    #[snafu(display("{}", reason))]
+    InvalidTripleQuotedString {
+        span: SourceSpan,
+        reason: &'static str,
+    },
+
+    /// Occurs when a string is prefixed with a sigil we do not know
+    #[snafu(display("unknown sigil '~{}'", sigil))]
+    UnknownSigil { span: SourceSpan, sigil: char },
+
     #[snafu(display("{}", source))]
     EscapeError { source: EscapeStmError<SourceIndex> },
 
+    /// Like EscapeError, but in the contents of a sigil string. The
+    /// sigil is `None` for a bare `~`.
+    #[snafu(display("{}", source))]
+    SigilEscapeError {
+        sigil: Option<char>,
+        source: EscapeStmError<SourceIndex>,
+    },
+
     /// Occurs when we encounter an unexpected character
     #[snafu(display("Encountered unexpected character '{}'", found))]
     UnexpectedCharacter { start: SourceIndex, found: char },
@@ -42,6 +61,9 @@ impl Hash for LexicalError {
             LexicalError::UnclosedAtom { .. } => 3,
             LexicalError::EscapeError { .. } => 4,
             LexicalError::UnexpectedCharacter { .. } => 5,
+            LexicalError::InvalidTripleQuotedString { .. } => 6,
+            LexicalError::UnknownSigil { .. } => 7,
+            LexicalError::SigilEscapeError { .. } => 8,
         };
         id.hash(state);
     }
@@ -61,7 +83,19 @@ impl ToDiagnostic for LexicalError {
                 .with_labels(vec![
                     Label::primary(span.source_id(), span).with_message(msg)
                 ]),
+            LexicalError::InvalidTripleQuotedString { .. } => Diagnostic::error()
+                .with_message("invalid triple-quoted string")
+                .with_labels(vec![
+                    Label::primary(span.source_id(), span).with_message(msg)
+                ]),
+            LexicalError::UnknownSigil { .. } => Diagnostic::error()
+                .with_message(msg)
+                .with_labels(vec![Label::primary(span.source_id(), span)])
+                .with_notes(vec![
+                    "the supported sigils are ~s, ~S, ~b, ~B and ~".to_string()
+                ]),
             LexicalError::EscapeError { source } => source.to_diagnostic(),
+            LexicalError::SigilEscapeError { sigil, source } => source.to_sigil_diagnostic(*sigil),
             LexicalError::UnexpectedCharacter { .. } => Diagnostic::error()
                 .with_message("unexpected character")
                 .with_labels(vec![
@@ -81,7 +115,10 @@ impl LexicalError {
             LexicalError::InvalidRadix { span, .. } => *span,
             LexicalError::UnclosedString { span, .. } => *span,
             LexicalError::UnclosedAtom { span, .. } => *span,
+            LexicalError::InvalidTripleQuotedString { span, .. } => *span,
+            LexicalError::UnknownSigil { span, .. } => *span,
             LexicalError::EscapeError { source } => source.span(),
+            LexicalError::SigilEscapeError { source, .. } => source.span(),
             LexicalError::UnexpectedCharacter { start, .. } => SourceSpan::new(*start, *start),
         }
     }
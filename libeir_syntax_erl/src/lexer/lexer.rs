@@ -1,3 +1,4 @@
+use std::fmt::Write;
 use std::ops::Range;
 use std::str::FromStr;
 
@@ -84,6 +85,10 @@ pub struct Lexer<S> {
     /// produced after that point is Token::EOF, or None, depending on how you are
     /// consuming the lexer
     eof: bool,
+
+    /// Whether comments are produced as `Token::Comment` when iterating,
+    /// instead of being skipped.
+    retain_comments: bool,
 }
 
 impl<S> Lexer<S>
@@ -101,11 +106,20 @@ where
             token_start: start + ByteOffset(0),
             token_end: start + ByteOffset(0),
             eof: false,
+            retain_comments: false,
         };
         lexer.advance();
         lexer
     }
 
+    /// Makes the lexer produce comments as `Token::Comment` tokens, for
+    /// tools like the formatter that need to preserve them. The parser
+    /// does not accept comments, so this is off by default.
+    pub fn retain_comments(mut self) -> Self {
+        self.retain_comments = true;
+        self
+    }
+
     pub fn lex(&mut self) -> Option<<Self as Iterator>::Item> {
         if self.eof && self.token == Token::EOF {
             return None;
@@ -222,6 +236,16 @@ where
         }
     }
 
+    /// Skips spaces and tabs, but not line breaks.
+    fn skip_line_whitespace(&mut self) {
+        loop {
+            match self.read() {
+                ' ' | '\t' => self.skip(),
+                _ => break,
+            }
+        }
+    }
+
     fn tokenize(&mut self) -> Token {
         let c = self.read();
 
@@ -257,6 +281,7 @@ where
             '}' => pop!(self, Token::RBrace),
             '?' => match self.peek() {
                 '?' => pop2!(self, Token::DoubleQuestion),
+                '=' => pop2!(self, Token::QuestionEquals),
                 _ => pop!(self, Token::Question),
             },
             '-' => match self.peek() {
@@ -277,7 +302,21 @@ where
                 }
                 Token::Char(self.pop())
             }
-            '"' => self.lex_string(),
+            '"' => {
+                if self.peek() == '"' && self.peek_next() == '"' {
+                    // Triple-quoted strings are always verbatim
+                    match self.lex_triple_quoted_string() {
+                        Ok((raw, end)) => match self.encode_string_contents(&raw, end, false) {
+                            Ok(symbol) => Token::String(symbol),
+                            Err(err) => Token::Error(err),
+                        },
+                        Err(err) => Token::Error(err),
+                    }
+                } else {
+                    self.lex_string()
+                }
+            }
+            '~' => self.lex_sigil(),
             '\'' => match self.lex_string() {
                 Token::String(s) => Token::Atom(s),
                 other => other,
@@ -399,12 +438,12 @@ where
             return Token::Comment;
         }
 
-        // If no '%', then we should check for an Edoc tag, first skip all whitespace and advance
-        // the token start
-        self.skip_whitespace();
+        // If no '%', then we should check for an Edoc tag, first skip all whitespace on the
+        // line and advance the token start
+        self.skip_line_whitespace();
+        c = self.read();
 
         // See if this is an Edoc tag
-        c = self.read();
         if c == '@' {
             if self.peek().is_ascii_alphabetic() {
                 self.skip();
@@ -412,7 +451,7 @@ where
                 // Get the tag identifier
                 self.lex_identifier();
                 // Skip any leading whitespace in the value
-                self.skip_whitespace();
+                self.skip_line_whitespace();
                 // Get value
                 loop {
                     c = self.read();
@@ -531,6 +570,246 @@ where
         }
     }
 
+    /// Lexes a triple-quoted string. The opening quotes are followed by
+    /// a newline, and the closing quotes are on a line of their own.
+    /// The indentation of the closing line is stripped from every line
+    /// of the string. More than three quotes can be used to delimit a
+    /// string containing three quotes in a row.
+    ///
+    /// Every character of the contents is returned with its index in
+    /// the source, along with the index of the closing quotes.
+    fn lex_triple_quoted_string(
+        &mut self,
+    ) -> Result<(Vec<(SourceIndex, char)>, SourceIndex), LexicalError> {
+        let mut quotes = 0;
+        while self.read() == '"' {
+            self.skip();
+            quotes += 1;
+        }
+        debug_assert!(quotes >= 3);
+
+        loop {
+            match self.read() {
+                '\n' => {
+                    self.skip();
+                    break;
+                }
+                '\0' => return Err(LexicalError::UnclosedString { span: self.span() }),
+                c if c.is_whitespace() => self.skip(),
+                _ => {
+                    return Err(LexicalError::InvalidTripleQuotedString {
+                        span: self.span(),
+                        reason: "the opening quotes must be followed by a newline",
+                    })
+                }
+            }
+        }
+
+        // Each line is kept with the index of the newline ending it.
+        let mut lines = Vec::new();
+        let mut line: Vec<(SourceIndex, char)> = Vec::new();
+        let (indent, end) = loop {
+            let (idx, c) = self.scanner.read();
+            match c {
+                '\0' => return Err(LexicalError::UnclosedString { span: self.span() }),
+                '\n' => {
+                    self.skip();
+                    if line.last().map(|&(_, c)| c) == Some('\r') {
+                        line.pop();
+                    }
+                    lines.push((std::mem::replace(&mut line, Vec::new()), idx));
+                }
+                '"' if line.iter().all(|&(_, c)| c.is_whitespace()) => {
+                    let mut found = Vec::new();
+                    while found.len() < quotes && self.read() == '"' {
+                        found.push((self.index(), '"'));
+                        self.skip();
+                    }
+                    if found.len() == quotes {
+                        break (line, idx);
+                    }
+                    line.extend(found);
+                }
+                c => {
+                    self.skip();
+                    line.push((idx, c));
+                }
+            }
+        };
+
+        let mut raw = Vec::new();
+        let mut newline = None;
+        for (line, line_end) in lines.iter() {
+            if let Some(idx) = newline {
+                raw.push((idx, '\n'));
+            }
+            newline = Some(*line_end);
+
+            let indented = line.len() >= indent.len()
+                && line.iter().zip(indent.iter()).all(|(a, b)| a.1 == b.1);
+            if indented {
+                raw.extend_from_slice(&line[indent.len()..]);
+            } else if !line.iter().all(|&(_, c)| c.is_whitespace()) {
+                return Err(LexicalError::InvalidTripleQuotedString {
+                    span: self.span(),
+                    reason: "every line must start with the indentation of the closing quotes",
+                });
+            }
+        }
+        Ok((raw, end))
+    }
+
+    /// Lexes a string with a sigil prefix. The lowercase `~s` and `~b`
+    /// sigils process escape sequences, while `~S` and `~B` take the
+    /// string verbatim. `~s` and `~S` produce a string, `~b` and `~B`
+    /// a binary. A sigil without a name is `~b`, or `~B` when it is
+    /// followed by a triple-quoted string.
+    fn lex_sigil(&mut self) -> Token {
+        let c = self.pop();
+        debug_assert_eq!(c, '~');
+
+        let sigil = match self.read() {
+            c if c.is_ascii_alphabetic() => {
+                self.skip();
+                Some(c)
+            }
+            _ => None,
+        };
+        let (binary, escapes) = match sigil {
+            None => (true, None),
+            Some('b') => (true, Some(true)),
+            Some('B') => (true, Some(false)),
+            Some('s') => (false, Some(true)),
+            Some('S') => (false, Some(false)),
+            Some(sigil) => {
+                return Token::Error(LexicalError::UnknownSigil {
+                    span: self.span(),
+                    sigil,
+                })
+            }
+        };
+
+        let contents = if self.read() == '"' && self.peek() == '"' && self.peek_next() == '"' {
+            let escapes = escapes.unwrap_or(false);
+            self.lex_triple_quoted_string()
+                .and_then(|(raw, end)| self.encode_string_contents(&raw, end, escapes))
+        } else {
+            let escapes = escapes.unwrap_or(true);
+            self.lex_sigil_contents(escapes)
+                .and_then(|(raw, end)| self.encode_string_contents(&raw, end, escapes))
+        };
+        let contents = contents.map_err(|err| match err {
+            LexicalError::EscapeError { source } => {
+                LexicalError::SigilEscapeError { sigil, source }
+            }
+            err => err,
+        });
+
+        match contents {
+            Ok(symbol) if binary => Token::BinaryString(symbol),
+            Ok(symbol) => Token::String(symbol),
+            Err(err) => Token::Error(err),
+        }
+    }
+
+    /// Reads the contents of a sigil string up to its closing
+    /// delimiter, returning every character with its index in the
+    /// source, along with the index of the delimiter. When escapes are
+    /// processed, the closing delimiter can be escaped with a
+    /// backslash.
+    fn lex_sigil_contents(
+        &mut self,
+        escapes: bool,
+    ) -> Result<(Vec<(SourceIndex, char)>, SourceIndex), LexicalError> {
+        let open = self.read();
+        let close = match open {
+            '(' => ')',
+            '[' => ']',
+            '{' => '}',
+            '<' => '>',
+            '/' | '|' | '\'' | '"' | '`' | '#' => open,
+            found => {
+                return Err(LexicalError::UnexpectedCharacter {
+                    start: self.index(),
+                    found,
+                })
+            }
+        };
+        self.skip();
+
+        let mut raw = Vec::new();
+        let end = loop {
+            let (idx, c) = self.scanner.read();
+            match c {
+                '\0' => return Err(LexicalError::UnclosedString { span: self.span() }),
+                '\\' if escapes => {
+                    self.skip();
+                    let (next_idx, next) = self.scanner.read();
+                    match next {
+                        '\0' => (),
+                        next => {
+                            self.skip();
+                            if next != close {
+                                raw.push((idx, '\\'));
+                            }
+                            raw.push((next_idx, next));
+                        }
+                    }
+                }
+                c if c == close => {
+                    self.skip();
+                    break idx;
+                }
+                c => {
+                    self.skip();
+                    raw.push((idx, c));
+                }
+            }
+        };
+        Ok((raw, end))
+    }
+
+    /// Encodes the contents of a triple-quoted or sigil string as the
+    /// body of a regular string literal, which is what the rest of the
+    /// compiler expects in string tokens. The contents are taken
+    /// verbatim unless `escapes` is set, in which case escape errors
+    /// point at the source indices of the characters in `raw`, or at
+    /// `end` when the contents end in the middle of an escape.
+    fn encode_string_contents(
+        &mut self,
+        raw: &[(SourceIndex, char)],
+        end: SourceIndex,
+        escapes: bool,
+    ) -> Result<Symbol, LexicalError> {
+        let mut buf = String::new();
+        if !escapes {
+            for &(_, c) in raw {
+                push_string_char(&mut buf, c as u64);
+            }
+            return Ok(Symbol::intern(&buf));
+        }
+
+        self.escape.reset();
+        let chars = raw.iter().map(|&(idx, c)| (idx, Some(c)));
+        for (idx, c) in chars.chain(std::iter::once((end, None))) {
+            loop {
+                match self.escape.transition(c, idx) {
+                    Ok((action, result)) => {
+                        if let Some(result) = result {
+                            push_string_char(&mut buf, result.cp);
+                        }
+                        match action {
+                            EscapeStmAction::Next => break,
+                            EscapeStmAction::Again => continue,
+                        }
+                    }
+                    Err(err) => Err(LexicalError::EscapeError { source: err })?,
+                }
+            }
+        }
+        Ok(Symbol::intern(&buf))
+    }
+
     #[inline]
     fn lex_identifier(&mut self) -> Token {
         let c = self.pop();
@@ -781,7 +1060,7 @@ where
         let mut res = self.lex();
         loop {
             match res {
-                Some(Ok(LexicalToken(_, Token::Comment, _))) => {
+                Some(Ok(LexicalToken(_, Token::Comment, _))) if !self.retain_comments => {
                     res = self.lex();
                 }
                 _ => break,
@@ -800,6 +1079,22 @@ fn to_integer_literal(literal: &str, radix: u32) -> Token {
     Token::Integer(int)
 }
 
+/// Appends a character to the body of a string literal, escaping it
+/// if it can not be written as is.
+fn push_string_char(buf: &mut String, cp: u64) {
+    let c = if cp <= std::u32::MAX as u64 {
+        std::char::from_u32(cp as u32)
+    } else {
+        None
+    };
+    match c {
+        Some('\\') => buf.push_str("\\\\"),
+        Some('"') => buf.push_str("\\\""),
+        Some(c) if c == '\n' || c == '\t' || !c.is_control() => buf.push(c),
+        _ => write!(buf, "\\x{{{:X}}}", cp).unwrap(),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use libeir_diagnostics::{ByteIndex, CodeMap, SourceId, SourceIndex, SourceSpan};
@@ -808,6 +1103,7 @@ mod test {
     use pretty_assertions::assert_eq;
 
     use crate::lexer::*;
+    use crate::lower::strings::escape::EscapeStmError;
 
     macro_rules! symbol {
         ($sym:expr) => {
@@ -848,6 +1144,30 @@ mod test {
     fn lex_comment() {
         assert_lex!("% this is a comment", |_| vec![]);
         assert_lex!("% @author Paul", |_| vec![Ok(Token::Edoc)]);
+        assert_lex!("%\nfoo", |_| vec![Ok(Token::Atom(symbol!("foo")))]);
+    }
+
+    #[test]
+    fn lex_retained_comments() {
+        let codemap = CodeMap::new();
+        let id = codemap.add("nofile", "%% a\nfoo % b\n".to_string());
+        let file = codemap.get(id).unwrap();
+        let lexer = Lexer::new(Scanner::new(FileMapSource::new(file.clone()))).retain_comments();
+        let tokens = lexer
+            .map(|result| {
+                let LexicalToken(start, token, end) = result.unwrap();
+                let span = SourceSpan::new(start, end);
+                (token, file.source_slice(span).unwrap().to_string())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                (Token::Comment, "%% a".to_string()),
+                (Token::Atom(symbol!("foo")), "foo".to_string()),
+                (Token::Comment, "% b".to_string()),
+            ]
+        );
     }
 
     macro_rules! f {
@@ -1003,6 +1323,100 @@ mod test {
         )]);
     }
 
+    #[test]
+    fn lex_triple_quoted_string() {
+        assert_lex!(
+            "\"\"\"\n  line one\n    line \"two\"\n\n  \\n\n  \"\"\"",
+            |_| vec![Ok(Token::String(symbol!(
+                "line one\n  line \\\"two\\\"\n\n\\\\n"
+            )))]
+        );
+        assert_lex!("\"\"\"\"\n  \"\"\"\n  \"\"\"\"", |_| vec![Ok(
+            Token::String(symbol!("\\\"\\\"\\\""))
+        )]);
+
+        assert_lex!("\"\"\" a", |source_id| vec![
+            Err(LexicalError::InvalidTripleQuotedString {
+                span: SourceSpan::new(
+                    SourceIndex::new(source_id, ByteIndex(0)),
+                    SourceIndex::new(source_id, ByteIndex(4))
+                ),
+                reason: "the opening quotes must be followed by a newline",
+            }),
+            Ok(Token::Atom(symbol!("a"))),
+        ]);
+        assert_lex!("\"\"\"\n  text\n   \"\"\"", |source_id| vec![Err(
+            LexicalError::InvalidTripleQuotedString {
+                span: SourceSpan::new(
+                    SourceIndex::new(source_id, ByteIndex(0)),
+                    SourceIndex::new(source_id, ByteIndex(17))
+                ),
+                reason: "every line must start with the indentation of the closing quotes",
+            }
+        )]);
+    }
+
+    #[test]
+    fn lex_sigil() {
+        assert_lex!(r#"~"a\tb""#, |_| vec![Ok(Token::BinaryString(symbol!(
+            "a\tb"
+        )))]);
+        assert_lex!(r#"~b(a\)b)"#, |_| vec![Ok(Token::BinaryString(symbol!(
+            "a)b"
+        )))]);
+        assert_lex!(r#"~B[a\tb]"#, |_| vec![Ok(Token::BinaryString(symbol!(
+            "a\\\\tb"
+        )))]);
+        assert_lex!(r#"~s{"a"}"#, |_| vec![Ok(Token::String(symbol!(
+            "\\\"a\\\""
+        )))]);
+        assert_lex!(r#"~S/a\x/"#, |_| vec![Ok(Token::String(symbol!("a\\\\x")))]);
+        assert_lex!("~\"\"\"\n  a\\b\n  \"\"\"", |_| vec![Ok(
+            Token::BinaryString(symbol!("a\\\\b"))
+        )]);
+        assert_lex!("~s\"\"\"\n  a\\tb\n  \"\"\"", |_| vec![Ok(Token::String(
+            symbol!("a\tb")
+        ))]);
+
+        assert_lex!(r#"~x"a""#, |source_id| vec![
+            Err(LexicalError::UnknownSigil {
+                span: SourceSpan::new(
+                    SourceIndex::new(source_id, ByteIndex(0)),
+                    SourceIndex::new(source_id, ByteIndex(2))
+                ),
+                sigil: 'x',
+            }),
+            Ok(Token::String(symbol!("a"))),
+        ]);
+
+        // Escape errors point into the source, past the stripped
+        // indentation and the dropped backslash of an escaped delimiter.
+        assert_lex!("~s\"\"\"\n  a\\qb\n  \"\"\"", |source_id| vec![Err(
+            LexicalError::SigilEscapeError {
+                sigil: Some('s'),
+                source: EscapeStmError::UnknownEscape {
+                    range: (
+                        SourceIndex::new(source_id, ByteIndex(9)),
+                        SourceIndex::new(source_id, ByteIndex(10))
+                    ),
+                    escape_char: 'q',
+                },
+            }
+        )]);
+        assert_lex!(r#"~b(\)\q)"#, |source_id| vec![Err(
+            LexicalError::SigilEscapeError {
+                sigil: Some('b'),
+                source: EscapeStmError::UnknownEscape {
+                    range: (
+                        SourceIndex::new(source_id, ByteIndex(5)),
+                        SourceIndex::new(source_id, ByteIndex(6))
+                    ),
+                    escape_char: 'q',
+                },
+            }
+        )]);
+    }
+
     #[test]
     fn lex_whitespace() {
         assert_lex!("      \n \t", |_| vec![]);
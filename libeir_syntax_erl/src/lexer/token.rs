@@ -87,6 +87,12 @@ impl TryFrom<LexicalToken> for AtomToken {
             LexicalToken(start, Token::If, end) => {
                 return Ok(AtomToken(start, Token::Atom(symbols::If), end));
             }
+            LexicalToken(start, Token::Else, end) => {
+                return Ok(AtomToken(start, Token::Atom(symbols::Else), end));
+            }
+            LexicalToken(start, Token::Maybe, end) => {
+                return Ok(AtomToken(start, Token::Atom(Symbol::intern("maybe")), end));
+            }
             t => Err(TokenConvertError {
                 span: t.span(),
                 token: t.token(),
@@ -247,6 +253,7 @@ impl TryFrom<LexicalToken> for SymbolToken {
             LexicalToken(_, Token::Atom(_), _) => (),
             LexicalToken(_, Token::Ident(_), _) => (),
             LexicalToken(_, Token::String(_), _) => (),
+            LexicalToken(_, Token::BinaryString(_), _) => (),
             LexicalToken(start, token, end) => return Ok(SymbolToken(start, token, end)),
         }
         Err(TokenConvertError {
@@ -290,6 +297,8 @@ pub enum Token {
     Float(Float),
     Atom(Symbol),
     String(Symbol),
+    // A binary string written with a sigil, `~b"..."`
+    BinaryString(Symbol),
     Ident(Symbol),
     // Keywords and Symbols
     LParen,
@@ -318,6 +327,8 @@ pub enum Token {
     If,
     Of,
     Receive,
+    Maybe,
+    Else,
     When,
     // Attributes
     Record,
@@ -393,6 +404,8 @@ pub enum Token {
     DotDotDot,
     Question,
     DoubleQuestion,
+    // ?=
+    QuestionEquals,
 }
 impl PartialEq for Token {
     fn eq(&self, other: &Token) -> bool {
@@ -432,6 +445,11 @@ impl PartialEq for Token {
                     return *s == *s2;
                 }
             }
+            Token::BinaryString(ref s) => {
+                if let Token::BinaryString(s2) = other {
+                    return *s == *s2;
+                }
+            }
             _ => return mem::discriminant(self) == mem::discriminant(other),
         }
         return false;
@@ -446,6 +464,7 @@ impl Hash for Token {
             Token::Atom(ref a) => a.hash(state),
             Token::Ident(ref i) => i.hash(state),
             Token::String(ref s) => s.hash(state),
+            Token::BinaryString(ref s) => s.hash(state),
             Token::Char(c) => c.hash(state),
             ref token => token.to_string().hash(state),
         }
@@ -466,6 +485,8 @@ impl Token {
             "if" => Token::If,
             "of" => Token::Of,
             "receive" => Token::Receive,
+            "maybe" => Token::Maybe,
+            "else" => Token::Else,
             "when" => Token::When,
             "andalso" => Token::AndAlso,
             "orelse" => Token::OrElse,
@@ -513,6 +534,7 @@ impl fmt::Display for Token {
             Token::Float(ref n) => write!(f, "{}", n),
             Token::Atom(ref s) => write!(f, "'{}'", s),
             Token::String(ref s) => write!(f, "\"{}\"", s),
+            Token::BinaryString(ref s) => write!(f, "~b\"{}\"", s),
             Token::Ident(ref s) => write!(f, "{}", s),
             Token::LParen => write!(f, "("),
             Token::RParen => write!(f, ")"),
@@ -539,6 +561,8 @@ impl fmt::Display for Token {
             Token::If => write!(f, "if"),
             Token::Of => write!(f, "of"),
             Token::Receive => write!(f, "receive"),
+            Token::Maybe => write!(f, "maybe"),
+            Token::Else => write!(f, "else"),
             Token::When => write!(f, "when"),
             Token::Record => write!(f, "record"),
             Token::Spec => write!(f, "spec"),
@@ -598,6 +622,7 @@ impl fmt::Display for Token {
             Token::DotDotDot => write!(f, "..."),
             Token::Question => write!(f, "?"),
             Token::DoubleQuestion => write!(f, "??"),
+            Token::QuestionEquals => write!(f, "?="),
         }
     }
 }
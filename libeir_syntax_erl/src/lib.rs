@@ -4,12 +4,14 @@
 
 mod abstr;
 mod evaluator;
+mod format;
 mod lexer;
 mod lower;
 mod parser;
 mod preprocessor;
 
 pub use self::abstr::lower as lower_abstr;
+pub use self::format::{format, FormatConfig};
 pub use self::lexer::*;
 pub use self::lower::{lower_module, LowerError};
 pub use self::parser::*;
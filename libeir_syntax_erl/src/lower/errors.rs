@@ -1,4 +1,5 @@
 use libeir_diagnostics::{Diagnostic, Label, SourceIndex, SourceSpan, ToDiagnostic};
+use libeir_intern::Ident;
 
 use super::expr::BinaryTypeName;
 use crate::lower::strings::StringError;
@@ -35,8 +36,10 @@ pub enum LowerError {
 
     /// Equality in a pattern caused two nodes to be merged,
     /// but merging these two nodes is not supported.
-    /// Happens when trying to merge two binary patterns.
-    #[snafu(display("patterns cannot be merged"))]
+    /// Happens when trying to merge two binary patterns whose
+    /// elements do not line up. The pattern is valid Erlang, this
+    /// is a limitation of the pattern compiler.
+    #[snafu(display("unsupported merge of binary patterns"))]
     UnsupportedPatternUnion {
         left: Option<SourceSpan>,
         right: SourceSpan,
@@ -74,6 +77,40 @@ pub enum LowerError {
         new: SourceSpan,
         old: SourceSpan,
     },
+    /// Variable is bound but never read.
+    #[snafu(display("variable '{}' is unused", name))]
+    UnusedVariable {
+        span: SourceSpan,
+        name: Ident,
+    },
+    /// Variable bound inside a case or if is used after it.
+    #[snafu(display("variable '{}' exported from '{}'", name, construct))]
+    ExportedVariable {
+        span: SourceSpan,
+        bound: SourceSpan,
+        name: Ident,
+        construct: &'static str,
+    },
+
+    // Unused module level definitions
+    #[snafu(display("function {}/{} is unused", name, arity))]
+    UnusedFunction {
+        span: SourceSpan,
+        name: Ident,
+        arity: usize,
+    },
+    #[snafu(display("import {}:{}/{} is unused", module, name, arity))]
+    UnusedImport {
+        span: SourceSpan,
+        module: Ident,
+        name: Ident,
+        arity: usize,
+    },
+    #[snafu(display("record {} is unused", name))]
+    UnusedRecord {
+        span: SourceSpan,
+        name: Ident,
+    },
 
     // Binary specifier parsing
     #[snafu(display("unknown specifier in binary entry"))]
@@ -114,6 +151,19 @@ pub enum LowerError {
     UndefinedRecord {
         span: SourceSpan,
     },
+    #[snafu(display("field {} undefined in record {}", field, record))]
+    UndefinedRecordField {
+        span: SourceSpan,
+        record: Ident,
+        field: Ident,
+    },
+    /// The default value of a record field may only use variables it
+    /// binds itself.
+    #[snafu(display("variable '{}' in record definition", name))]
+    VariableInRecordDefinition {
+        span: SourceSpan,
+        name: Ident,
+    },
 
     // Maps
     #[snafu(display("only map put (=>) allowed in map construction"))]
@@ -125,6 +175,23 @@ pub enum LowerError {
     MapUpdateOnNonMap {
         map: SourceSpan,
     },
+
+    // Abstract format
+    /// A form in an abstract format listing that is well formed, but
+    /// has no equivalent in the AST.
+    #[snafu(display("unsupported {} '{}' in abstract format", kind, name))]
+    AbstrUnsupported {
+        span: SourceSpan,
+        kind: &'static str,
+        name: String,
+    },
+    /// A form in an abstract format listing that does not have the
+    /// expected shape.
+    #[snafu(display("malformed {} in abstract format", kind))]
+    AbstrMalformed {
+        span: SourceSpan,
+        kind: &'static str,
+    },
 }
 
 impl From<StringError> for LowerError {
@@ -178,7 +245,7 @@ impl ToDiagnostic for LowerError {
                 dig.with_labels(labels)
             }
             LowerError::UnsupportedPatternUnion { left, right } => {
-                let dig = Diagnostic::warning().with_message(msg);
+                let dig = Diagnostic::error().with_message(msg);
                 let mut labels = vec![];
                 if let Some(left) = left {
                     labels
@@ -186,7 +253,11 @@ impl ToDiagnostic for LowerError {
                 }
                 labels
                     .push(Label::primary(right.source_id(), *right).with_message("right pattern"));
-                dig.with_labels(labels)
+                dig.with_labels(labels).with_notes(vec![
+                    "binary patterns matched against the same value can only be merged when \
+                     their elements have the same types and sizes, this is not supported yet"
+                        .to_string(),
+                ])
             }
             LowerError::PatternConst { source, .. } => source.to_diagnostic(),
             LowerError::InvalidStringEscape { source, .. } => source.to_diagnostic(),
@@ -209,6 +280,31 @@ impl ToDiagnostic for LowerError {
                     Label::secondary(old.source_id(), *old).with_message("previously bound here"),
                 ])
             }
+            LowerError::UnusedVariable { span, .. } => Diagnostic::warning()
+                .with_message(msg)
+                .with_labels(vec![
+                    Label::primary(span.source_id(), *span).with_message("bound here")
+                ]),
+            LowerError::ExportedVariable { span, bound, .. } => {
+                Diagnostic::warning().with_message(msg).with_labels(vec![
+                    Label::primary(span.source_id(), *span).with_message("used here"),
+                    Label::secondary(bound.source_id(), *bound).with_message("bound here"),
+                ])
+            }
+            LowerError::UnusedFunction { span, .. } => Diagnostic::warning()
+                .with_message(msg)
+                .with_labels(vec![Label::primary(span.source_id(), *span)
+                    .with_message("function is never called")]),
+            LowerError::UnusedImport { span, .. } => Diagnostic::warning()
+                .with_message(msg)
+                .with_labels(vec![
+                    Label::primary(span.source_id(), *span).with_message("imported here")
+                ]),
+            LowerError::UnusedRecord { span, .. } => Diagnostic::warning()
+                .with_message(msg)
+                .with_labels(vec![
+                    Label::primary(span.source_id(), *span).with_message("defined here")
+                ]),
             LowerError::BinaryUnknownSpecifier { span } => Diagnostic::error()
                 .with_message(msg)
                 .with_labels(vec![
@@ -237,7 +333,7 @@ impl ToDiagnostic for LowerError {
             LowerError::String { source } => source.to_diagnostic(),
             LowerError::DuplicateRecordField { new, old } => {
                 Diagnostic::error().with_message(msg).with_labels(vec![
-                    Label::primary(new.source_id(), *old)
+                    Label::primary(new.source_id(), *new)
                         .with_message("duplicate field definition in record"),
                     Label::secondary(old.source_id(), *old).with_message("previously defined here"),
                 ])
@@ -245,6 +341,14 @@ impl ToDiagnostic for LowerError {
             LowerError::UndefinedRecord { span } => Diagnostic::error()
                 .with_message(msg)
                 .with_labels(vec![Label::primary(span.source_id(), *span)]),
+            LowerError::UndefinedRecordField { span, .. } => Diagnostic::error()
+                .with_message(msg)
+                .with_labels(vec![Label::primary(span.source_id(), *span)
+                    .with_message("no such field in the record definition")]),
+            LowerError::VariableInRecordDefinition { span, .. } => Diagnostic::error()
+                .with_message(msg)
+                .with_labels(vec![Label::primary(span.source_id(), *span)
+                    .with_message("unbound in the default value")]),
             LowerError::MapUpdateInConstruction { map, field } => {
                 Diagnostic::error().with_message(msg).with_labels(vec![
                     Label::primary(field.source_id(), *field)
@@ -258,6 +362,16 @@ impl ToDiagnostic for LowerError {
                 .with_labels(vec![Label::primary(map.source_id(), *map).with_message(
                     "updated value is not a map, this will fail at runtime",
                 )]),
+            LowerError::AbstrUnsupported { span, .. } => Diagnostic::error()
+                .with_message(msg)
+                .with_labels(vec![
+                    Label::primary(span.source_id(), *span).with_message("not supported")
+                ]),
+            LowerError::AbstrMalformed { span, .. } => Diagnostic::error()
+                .with_message(msg)
+                .with_labels(vec![
+                    Label::primary(span.source_id(), *span).with_message("unexpected form")
+                ]),
         }
     }
 }
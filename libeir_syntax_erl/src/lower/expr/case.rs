@@ -38,7 +38,7 @@ pub(super) fn lower_case_expr(
 
     let entry_exc_height = ctx.exc_stack.len();
 
-    let mut scope_merge = ScopeMerge::new();
+    let mut scope_merge = ScopeMerge::new("case");
 
     for clause in case.clauses.iter() {
         match lower_clause(
@@ -112,7 +112,7 @@ pub(super) fn lower_if_expr(
 
     let entry_exc_height = ctx.exc_stack.len();
 
-    let mut scope_merge = ScopeMerge::new();
+    let mut scope_merge = ScopeMerge::new("if");
 
     for clause in if_expr.clauses.iter() {
         match lower_clause(
@@ -1,16 +1,22 @@
-use libeir_ir::constant::NilTerm;
+use std::collections::HashSet;
+
+use libeir_diagnostics::SourceSpan;
+use libeir_ir::constant::{EmptyMap, NilTerm};
 use libeir_ir::operation::case::Case;
-use libeir_ir::BinOp;
+use libeir_ir::{BinOp, MapPutUpdate};
 use libeir_ir::{Block as IrBlock, FunctionBuilder, Value as IrValue};
 
 use libeir_intern::{Ident, Symbol};
 
-use crate::parser::ast::{BinaryComprehension, Expr, ListComprehension};
+use crate::parser::ast::{
+    Binary, BinaryComprehension, BinaryElement, BinaryGenerator, BitType, Expr, ListComprehension,
+    Literal, MapComprehension, NodeId, Tuple, Var,
+};
 
 use crate::lower::expr::binary::lower_binary_expr;
 use crate::lower::expr::{lower_single, lower_single_same_scope};
 use crate::lower::pattern::lower_clause;
-use crate::lower::LowerCtx;
+use crate::lower::{LowerCtx, LowerError};
 
 fn lower_qual<F>(
     ctx: &mut LowerCtx,
@@ -127,7 +133,272 @@ where
                     Err(_) => unimplemented!(), // TODO warn/error unreachable pattern
                 }
             }
-            Expr::BinaryGenerator(_gen) => unimplemented!(),
+            Expr::BinaryGenerator(gen) => {
+                let gen_span = gen.span;
+
+                //     loop_block(bin_val, acc)
+                // loop_block(loop_bin_arg, loop_acc_arg):
+                //     match loop_bin_arg on <<Pattern.., Rest/bitstring>>
+                //         do body
+                //         loop_block(Rest, acc)
+                //     no match:
+                //         skip_block
+                // skip_block:
+                //     match loop_bin_arg on <<SkipPattern.., Rest/bitstring>>
+                //         loop_block(Rest, loop_acc_arg)
+                //     no match:
+                //         ret(loop_acc_arg)
+
+                let binary = match &*gen.pattern {
+                    Expr::Binary(binary) => binary,
+                    pattern => {
+                        ctx.error(LowerError::IllegalExpression {
+                            span: pattern.span(),
+                        });
+                        return (block, acc);
+                    }
+                };
+
+                let bin_val = map_block!(block, lower_single(ctx, b, block, &gen.expr));
+
+                // Loop entry block
+                let loop_block = b.block_insert();
+                let loop_bin_arg = b.block_arg_insert(loop_block);
+                let loop_acc_arg = b.block_arg_insert(loop_block);
+
+                b.op_call_flow(block, loop_block, &[bin_val, acc]);
+
+                // Return block, taken when not even the skip pattern
+                // matches what is left of the binary
+                let ret_block = b.block_insert();
+                let ret_val = loop_acc_arg;
+
+                let pattern_span = gen.pattern.span();
+                let rest = Ident::new(Symbol::intern("_Rest"), pattern_span).gensym();
+                let (pattern, skip_pattern) = binary_generator_patterns(ctx, gen, binary, rest);
+
+                let skip_block = b.block_insert();
+
+                // Main pattern
+                block = loop_block;
+
+                let mut case_b = Case::builder();
+                case_b.set_span(pattern_span);
+                case_b.match_on = Some(loop_bin_arg);
+                case_b.no_match = Some(b.value(skip_block));
+
+                match lower_clause(
+                    ctx,
+                    &mut case_b.container,
+                    b,
+                    &mut block,
+                    false,
+                    pattern_span,
+                    [&pattern].iter().map(|i| *i),
+                    None,
+                ) {
+                    Ok(lowered) => {
+                        let (scope_token, body) = lowered.make_body(ctx, b);
+                        let rest_val = ctx.resolve(rest);
+
+                        let body_val = b.value(body);
+                        case_b.push_clause(lowered.clause, lowered.guard, body_val, b);
+                        for value in lowered.values.iter() {
+                            case_b.push_value(*value, b);
+                        }
+
+                        let (cont, cont_val) =
+                            lower_qual(ctx, b, inner, &quals[1..], body, loop_acc_arg);
+                        b.op_call_flow(cont, loop_block, &[rest_val, cont_val]);
+
+                        // Pop scope pushed in lower_clause
+                        ctx.scope.pop(scope_token);
+
+                        case_b.finish(block, b);
+                    }
+                    Err(lowered) => {
+                        b.op_call_flow(block, skip_block, &[]);
+
+                        let (scope_token, body) = lowered.make_body(ctx, b);
+                        let (cont, _cont_val) =
+                            lower_qual(ctx, b, inner, &quals[1..], body, loop_acc_arg);
+                        b.op_unreachable(gen_span, cont);
+
+                        ctx.scope.pop(scope_token);
+                    }
+                }
+
+                // Skip pattern, steps over elements that do not match
+                // the main pattern
+                let mut block = skip_block;
+
+                let mut case_b = Case::builder();
+                case_b.set_span(pattern_span);
+                case_b.match_on = Some(loop_bin_arg);
+                case_b.no_match = Some(b.value(ret_block));
+
+                match lower_clause(
+                    ctx,
+                    &mut case_b.container,
+                    b,
+                    &mut block,
+                    false,
+                    pattern_span,
+                    [&skip_pattern].iter().map(|i| *i),
+                    None,
+                ) {
+                    Ok(lowered) => {
+                        let (scope_token, body) = lowered.make_body(ctx, b);
+                        let rest_val = ctx.resolve(rest);
+
+                        let body_val = b.value(body);
+                        case_b.push_clause(lowered.clause, lowered.guard, body_val, b);
+                        for value in lowered.values.iter() {
+                            case_b.push_value(*value, b);
+                        }
+
+                        b.op_call_flow(body, loop_block, &[rest_val, loop_acc_arg]);
+
+                        ctx.scope.pop(scope_token);
+
+                        case_b.finish(block, b);
+                    }
+                    Err(lowered) => {
+                        b.op_call_flow(block, ret_block, &[]);
+
+                        let (scope_token, body) = lowered.make_body(ctx, b);
+                        b.op_unreachable(gen_span, body);
+
+                        ctx.scope.pop(scope_token);
+                    }
+                }
+
+                (ret_block, ret_val)
+            }
+            Expr::MapGenerator(gen) => {
+                let gen_span = gen.span;
+
+                //     iter = maps:iterator(map_val)
+                //     loop_block(iter, acc)
+                // loop_block(loop_iter_arg, loop_acc_arg):
+                //     next = maps:next(loop_iter_arg)
+                //     match next on
+                //         none:
+                //             ret(loop_acc_arg)
+                //         {Key, Value, NextIter}:
+                //             match {Key, Value} on {KeyPattern, ValuePattern}
+                //                 do body
+                //                 loop_block(NextIter, acc)
+                //             no match:
+                //                 loop_block(NextIter, loop_acc_arg)
+
+                let map_val = map_block!(block, lower_single(ctx, b, block, &gen.expr));
+                let iter_val = map_block!(
+                    block,
+                    ctx.call_function(
+                        b,
+                        block,
+                        gen_span,
+                        Ident::from_str("maps"),
+                        Ident::from_str("iterator"),
+                        &[map_val],
+                    )
+                );
+
+                // Loop entry block
+                let loop_block = b.block_insert();
+                let loop_iter_arg = b.block_arg_insert(loop_block);
+                let loop_acc_arg = b.block_arg_insert(loop_block);
+
+                b.op_call_flow(block, loop_block, &[iter_val, acc]);
+
+                let (next_block, next_val) = ctx.call_function(
+                    b,
+                    loop_block,
+                    gen_span,
+                    Ident::from_str("maps"),
+                    Ident::from_str("next"),
+                    &[loop_iter_arg],
+                );
+
+                // `none` when the iterator is exhausted, otherwise the
+                // next association and the rest of the iterator
+                let none = b.value(Symbol::intern("none"));
+                let mut match_builder = b.op_match_build(gen_span);
+                let ret_block = match_builder.push_value(none, b);
+                let unpack_ok_block = match_builder.push_tuple(3, b);
+                let unpack_fail_block = match_builder.push_wildcard(gen_span, b);
+                match_builder.finish(next_block, next_val, b);
+                b.op_unreachable(gen_span, unpack_fail_block);
+                let ret_val = loop_acc_arg;
+
+                let key_val = b.block_args(unpack_ok_block)[0];
+                let value_val = b.block_args(unpack_ok_block)[1];
+                let next_iter_val = b.block_args(unpack_ok_block)[2];
+
+                // When there is no match, continue iterating
+                let no_match = b.block_insert();
+                b.op_call_flow(no_match, loop_block, &[next_iter_val, loop_acc_arg]);
+
+                // The key and value patterns are matched together as
+                // a tuple
+                let pattern_span = gen.span;
+                let pattern = Expr::Tuple(Tuple {
+                    span: pattern_span,
+                    id: gen.pattern_id,
+                    elements: vec![(*gen.key).clone(), (*gen.value).clone()],
+                });
+                let assoc_val = b.prim_tuple(pattern_span, &[key_val, value_val]);
+
+                block = unpack_ok_block;
+
+                let mut case_b = Case::builder();
+                case_b.set_span(pattern_span);
+                case_b.match_on = Some(assoc_val);
+                case_b.no_match = Some(b.value(no_match));
+
+                match lower_clause(
+                    ctx,
+                    &mut case_b.container,
+                    b,
+                    &mut block,
+                    false,
+                    pattern_span,
+                    [&pattern].iter().map(|i| *i),
+                    None,
+                ) {
+                    Ok(lowered) => {
+                        let (scope_token, body) = lowered.make_body(ctx, b);
+
+                        let body_val = b.value(body);
+                        case_b.push_clause(lowered.clause, lowered.guard, body_val, b);
+                        for value in lowered.values.iter() {
+                            case_b.push_value(*value, b);
+                        }
+
+                        let (cont, cont_val) =
+                            lower_qual(ctx, b, inner, &quals[1..], body, loop_acc_arg);
+                        b.op_call_flow(cont, loop_block, &[next_iter_val, cont_val]);
+
+                        // Pop scope pushed in lower_clause
+                        ctx.scope.pop(scope_token);
+
+                        case_b.finish(block, b);
+                    }
+                    Err(lowered) => {
+                        b.op_call_flow(block, no_match, &[]);
+
+                        let (scope_token, body) = lowered.make_body(ctx, b);
+                        let (cont, _cont_val) =
+                            lower_qual(ctx, b, inner, &quals[1..], body, loop_acc_arg);
+                        b.op_unreachable(gen_span, cont);
+
+                        ctx.scope.pop(scope_token);
+                    }
+                }
+
+                (ret_block, ret_val)
+            }
             expr => {
                 let bool_val = map_block!(block, lower_single_same_scope(ctx, b, block, expr));
                 let span = expr.span();
@@ -182,25 +453,155 @@ pub(super) fn lower_binary_comprehension_expr(
     mut block: IrBlock,
     compr: &BinaryComprehension,
 ) -> (IrBlock, IrValue) {
-    let inner =
-        |ctx: &mut LowerCtx, b: &mut FunctionBuilder, block: IrBlock, acc: IrValue| match &*compr
-            .body
-        {
-            Expr::Binary(bin) => lower_binary_expr(ctx, b, block, Some(acc), bin),
-            _ => panic!(),
-        };
-
-    let nil = b.value(Vec::<u8>::new());
+    // A body that is not a binary construction is appended to the
+    // accumulator as if it was written `<<Body/bitstring>>`.
+    let wrapped;
+    let body = match &*compr.body {
+        Expr::Binary(bin) => bin,
+        body => {
+            let span = body.span();
+            wrapped = Binary {
+                span,
+                id: compr.body_binary_id,
+                elements: vec![BinaryElement {
+                    span,
+                    id: compr.body_element_id,
+                    bit_expr: body.clone(),
+                    bit_size: None,
+                    bit_type: Some(vec![bitstring_type(span, compr.body_type_id)]),
+                }],
+            };
+            &wrapped
+        }
+    };
+
+    let inner = |ctx: &mut LowerCtx, b: &mut FunctionBuilder, block: IrBlock, acc: IrValue| {
+        lower_binary_expr(ctx, b, block, Some(acc), body)
+    };
+
+    let empty = b.value(Vec::<u8>::new());
     let val = map_block!(
         block,
-        lower_qual(ctx, b, &inner, &compr.qualifiers, block, nil)
+        lower_qual(ctx, b, &inner, &compr.qualifiers, block, empty)
     );
-    ctx.call_function(
-        b,
+    (block, val)
+}
+
+pub(super) fn lower_map_comprehension_expr(
+    ctx: &mut LowerCtx,
+    b: &mut FunctionBuilder,
+    mut block: IrBlock,
+    compr: &MapComprehension,
+) -> (IrBlock, IrValue) {
+    let inner = |ctx: &mut LowerCtx, b: &mut FunctionBuilder, mut block: IrBlock, acc: IrValue| {
+        let span = compr.span;
+        let key_val = map_block!(block, lower_single(ctx, b, block, &compr.key));
+        let value_val = map_block!(block, lower_single(ctx, b, block, &compr.value));
+
+        let mut map_builder = b.op_map_put_build(span, acc);
+        map_builder.push_kv(key_val, value_val, MapPutUpdate::Put, b);
+        let (ok, fail) = map_builder.finish(block, b);
+        // Putting into a map can not fail
+        b.op_unreachable(span, fail);
+
+        (ok, b.block_args(ok)[0])
+    };
+
+    let empty_map = b.value(EmptyMap);
+    let val = map_block!(
         block,
-        compr.span,
-        Ident::from_str("lists"),
-        Ident::from_str("reverse"),
-        &[val],
-    )
+        lower_qual(ctx, b, &inner, &compr.qualifiers, block, empty_map)
+    );
+    (block, val)
+}
+
+fn bitstring_type(span: SourceSpan, id: NodeId) -> BitType {
+    BitType::Name(span, id, Ident::new(Symbol::intern("bitstring"), span))
+}
+
+/// Builds the two patterns a binary generator is matched with. The
+/// first is the generator pattern itself, the second matches an
+/// element of the same shape regardless of its value, and is used to
+/// skip elements that do not match the first. Both bind what is left
+/// of the binary to `rest`.
+fn binary_generator_patterns(
+    ctx: &LowerCtx,
+    gen: &BinaryGenerator,
+    binary: &Binary,
+    rest: Ident,
+) -> (Expr, Expr) {
+    let span = binary.span;
+    let rest_elem = BinaryElement {
+        span: rest.span,
+        id: gen.rest_id,
+        bit_expr: Expr::Var(Var(gen.rest_var_id, rest)),
+        bit_size: None,
+        bit_type: Some(vec![bitstring_type(rest.span, gen.rest_type_id)]),
+    };
+
+    // Variables bound by an element and used as the size of a later
+    // one are kept in the skip pattern, everything else that can
+    // differ between elements is replaced by a wildcard.
+    let mut size_vars = HashSet::new();
+    for elem in binary.elements.iter() {
+        if let Some(size) = &elem.bit_size {
+            collect_vars(size, &mut size_vars);
+        }
+    }
+
+    let skip_elements = binary
+        .elements
+        .iter()
+        .map(|elem| {
+            let keep = match &elem.bit_expr {
+                Expr::Var(Var(_id, var)) => {
+                    size_vars.contains(&var.name) && ctx.scope.resolve(*var).is_err()
+                }
+                Expr::Literal(Literal::String(_, _)) => true,
+                _ => false,
+            };
+            let mut elem = elem.clone();
+            if !keep {
+                // The wildcard takes the place of the expression, and
+                // its id
+                let wildcard = Ident::new(Symbol::intern("_"), elem.bit_expr.span());
+                elem.bit_expr = Expr::Var(Var(elem.bit_expr.id(), wildcard));
+            }
+            elem
+        })
+        .chain(std::iter::once(rest_elem.clone()))
+        .collect();
+
+    let elements = binary
+        .elements
+        .iter()
+        .cloned()
+        .chain(std::iter::once(rest_elem))
+        .collect();
+
+    let pattern = Expr::Binary(Binary {
+        span,
+        id: gen.pattern_id,
+        elements,
+    });
+    let skip_pattern = Expr::Binary(Binary {
+        span,
+        id: gen.skip_pattern_id,
+        elements: skip_elements,
+    });
+    (pattern, skip_pattern)
+}
+
+fn collect_vars(expr: &Expr, vars: &mut HashSet<Symbol>) {
+    match expr {
+        Expr::Var(Var(_id, var)) => {
+            vars.insert(var.name);
+        }
+        Expr::BinaryExpr(bin) => {
+            collect_vars(&bin.lhs, vars);
+            collect_vars(&bin.rhs, vars);
+        }
+        Expr::UnaryExpr(un) => collect_vars(&un.operand, vars),
+        _ => (),
+    }
 }
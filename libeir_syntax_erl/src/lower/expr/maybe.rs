@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+
+use libeir_ir::operation::case::Case as CaseOp;
+use libeir_ir::{Block as IrBlock, FunctionBuilder, Value as IrValue};
+
+use libeir_intern::Symbol;
+
+use crate::parser::ast::{Expr, Maybe, MaybeMatch, Var};
+
+use crate::lower::expr::{lower_block_same_scope, lower_expr, lower_single_same_scope};
+use crate::lower::pattern::lower_clause;
+use crate::lower::scope::ScopeMerge;
+use crate::lower::LowerCtx;
+
+/// The body of a `maybe` is lowered in sequence, every `?=` that fails
+/// to match continues to a shared else block with the value that did
+/// not match. Without an `else`, that value is the result of the
+/// whole expression, otherwise it is matched against the `else`
+/// clauses. No variables bound in the body are visible after the
+/// `maybe`.
+pub(super) fn lower_maybe_expr(
+    ctx: &mut LowerCtx,
+    b: &mut FunctionBuilder,
+    block: IrBlock,
+    maybe: &Maybe,
+) -> (IrBlock, IrValue) {
+    let span = maybe.span;
+    let loc = ctx.current_location(b, span);
+
+    let else_block = b.block_insert();
+    b.block_set_location(else_block, loc);
+    let else_val = b.block_arg_insert(else_block);
+
+    let mut scope_merge = ScopeMerge::new("maybe");
+
+    let scope_tok = ctx.scope.push();
+    let mut block = block;
+    let mut value = None;
+    for expr in maybe.body.iter() {
+        assert!(b.fun().block_kind(block).is_none());
+        let (new_block, val) = match expr {
+            Expr::MaybeMatch(mat) => lower_maybe_match(ctx, b, block, mat, else_block),
+            expr => lower_expr(ctx, b, block, expr),
+        };
+        assert!(b.fun().block_kind(new_block).is_none());
+        block = new_block;
+        value = Some(val);
+    }
+    ctx.scope.pop(scope_tok);
+    scope_merge.branch(block, value.unwrap(), HashMap::new());
+
+    match &maybe.else_clauses {
+        None => scope_merge.branch(else_block, else_val, HashMap::new()),
+        Some(clauses) => {
+            let mut block = else_block;
+
+            let no_match = b.block_insert();
+            b.block_set_location(no_match, loc);
+            {
+                let typ_val = b.value(Symbol::intern("error"));
+                let else_clause_val = b.value(Symbol::intern("else_clause"));
+                let err_val = b.prim_tuple(span, &[else_clause_val, else_val]);
+                ctx.exc_stack
+                    .make_error_jump(b, span, no_match, typ_val, err_val);
+            }
+
+            let mut case_b = CaseOp::builder();
+            case_b.set_span(span);
+            case_b.match_on = Some(else_val);
+            case_b.no_match = Some(b.value(no_match));
+
+            let entry_exc_height = ctx.exc_stack.len();
+
+            for clause in clauses.iter() {
+                match lower_clause(
+                    ctx,
+                    &mut case_b.container,
+                    b,
+                    &mut block,
+                    false,
+                    clause.span,
+                    [&clause.pattern].iter().map(|i| *i),
+                    clause.guard.as_ref(),
+                ) {
+                    Ok(lowered) => {
+                        let (scope_token, body) = lowered.make_body(ctx, b);
+
+                        let body_val = b.value(body);
+                        case_b.push_clause(lowered.clause, lowered.guard, body_val, b);
+                        for value in lowered.values.iter() {
+                            case_b.push_value(*value, b);
+                        }
+
+                        let (body_ret_block, body_ret) =
+                            lower_block_same_scope(ctx, b, body, &clause.body);
+
+                        let binds = ctx.scope.pop_take(scope_token);
+                        scope_merge.branch(body_ret_block, body_ret, binds);
+                    }
+                    Err(lowered) => {
+                        let (scope_tok, dummy_body) = lowered.make_body(ctx, b);
+
+                        let (body_ret_block, body_ret) =
+                            lower_block_same_scope(ctx, b, dummy_body, &clause.body);
+
+                        let binds = ctx.scope.pop_take(scope_tok);
+                        scope_merge.branch(body_ret_block, body_ret, binds);
+                    }
+                }
+                assert!(ctx.exc_stack.len() == entry_exc_height)
+            }
+            case_b.finish(block, b);
+        }
+    }
+
+    scope_merge.finish(ctx, b)
+}
+
+/// `Pattern ?= Expr`. Evaluates to the value of the expression if it
+/// matches, otherwise continues to `else_block` with that value.
+fn lower_maybe_match(
+    ctx: &mut LowerCtx,
+    b: &mut FunctionBuilder,
+    mut block: IrBlock,
+    mat: &MaybeMatch,
+    else_block: IrBlock,
+) -> (IrBlock, IrValue) {
+    let match_val = map_block!(block, lower_single_same_scope(ctx, b, block, &mat.expr));
+
+    if let Expr::Var(Var(_id, var)) = *mat.pattern {
+        // An unbound variable always matches
+        if ctx.scope.resolve(var).is_err() {
+            ctx.bind(var, match_val);
+            return (block, match_val);
+        }
+    }
+
+    let loc = ctx.current_location(b, mat.span);
+    let no_match = b.block_insert();
+    b.block_set_location(no_match, loc);
+    b.op_call_flow(no_match, else_block, &[match_val]);
+
+    let mut match_case = CaseOp::builder();
+    match_case.set_span(mat.span);
+
+    match lower_clause(
+        ctx,
+        &mut match_case.container,
+        b,
+        &mut block,
+        false,
+        mat.span,
+        [&mat.pattern].iter().map(|i| &***i),
+        None,
+    ) {
+        Ok(lowered) => {
+            let (_scope_token, body) = lowered.make_body(ctx, b);
+
+            match_case.match_on = Some(match_val);
+            match_case.no_match = Some(b.value(no_match));
+
+            let body_val = b.value(body);
+            match_case.push_clause(lowered.clause, lowered.guard, body_val, b);
+            for value in lowered.values.iter() {
+                match_case.push_value(*value, b);
+            }
+
+            match_case.finish(block, b);
+
+            // The scope pushed in lower_clause is popped together with
+            // the scope of the `maybe` body.
+            (body, match_val)
+        }
+        Err(lowered) => {
+            b.op_call_flow(block, no_match, &[]);
+
+            let (_scope_token, body) = lowered.make_body(ctx, b);
+
+            (body, match_val)
+        }
+    }
+}
@@ -28,7 +28,9 @@ mod catch;
 mod comprehension;
 mod record;
 pub use binary::TypeName as BinaryTypeName;
+pub(super) use record::check_record_definitions;
 mod map;
+mod maybe;
 mod receive;
 
 pub(super) fn lower_block<'a, T>(
@@ -153,16 +155,26 @@ fn lower_expr(
                     };
 
                     let (module, function) = if ctx.module.functions.contains_key(&local) {
+                        ctx.usage.use_local(local);
                         (ctx.module.name, *name)
                     } else {
                         if let Some(resolved) = ctx.module.imports.get(&local) {
                             assert!(resolved.arity == args.len());
+                            ctx.usage.use_import(local);
                             (resolved.module, resolved.function)
                         } else {
                             (ctx.module.name, *name)
                         }
                     };
 
+                    // `is_record/2,3` with a literal record name is a
+                    // use of that record.
+                    if name.name == Symbol::intern("is_record") && args.len() >= 2 {
+                        if let Expr::Literal(Literal::Atom(_, rec_name)) = &args[1] {
+                            ctx.usage.use_record(rec_name.name);
+                        }
+                    }
+
                     let mod_val = b.value(module);
                     let fun_val = b.value(function);
 
@@ -338,8 +350,10 @@ fn lower_expr(
             FunctionName::PartiallyResolved(partial) => {
                 let local = ctx.module.imports.get(&partial.to_local());
                 let resolved = if let Some(fun) = local {
+                    ctx.usage.use_import(partial.to_local());
                     fun.clone()
                 } else {
+                    ctx.usage.use_local(partial.to_local());
                     partial.resolve(ctx.module.name)
                 };
 
@@ -378,6 +392,7 @@ fn lower_expr(
         Expr::Case(case) => case::lower_case_expr(ctx, b, block, case),
         Expr::If(if_expr) => case::lower_if_expr(ctx, b, block, if_expr),
         Expr::Try(try_expr) => catch::lower_try_expr(ctx, b, block, try_expr),
+        Expr::Maybe(maybe) => maybe::lower_maybe_expr(ctx, b, block, maybe),
         Expr::Catch(catch_expr) => catch::lower_catch_expr(ctx, b, block, catch_expr),
         Expr::BinaryExpr(binary_expr) => binary_expr::lower_binary_expr(ctx, b, block, binary_expr),
         Expr::Literal(lit) => lower_literal(ctx, b, block, lit),
@@ -391,6 +406,9 @@ fn lower_expr(
         Expr::BinaryComprehension(compr) => {
             comprehension::lower_binary_comprehension_expr(ctx, b, block, compr)
         }
+        Expr::MapComprehension(compr) => {
+            comprehension::lower_map_comprehension_expr(ctx, b, block, compr)
+        }
         Expr::Binary(bin) => binary::lower_binary_expr(ctx, b, block, None, bin),
         Expr::DelayedSubstitution(_, id, DelayedSubstitution::FunctionName) => {
             lower_literal(ctx, b, block, &Literal::Atom(*id, b.fun().ident().name))
@@ -405,9 +423,14 @@ fn lower_expr(
             ctx.error(LowerError::IllegalExpression { span: rem.span });
             (block, ctx.sentinel())
         }
+        Expr::MaybeMatch(mat) => {
+            ctx.error(LowerError::IllegalExpression { span: mat.span });
+            (block, ctx.sentinel())
+        }
         Expr::MapProjection(_) => unreachable!(),
         Expr::BinaryGenerator(_) => unreachable!(),
         Expr::Generator(_) => unreachable!(),
+        Expr::MapGenerator(_) => unreachable!(),
         //_ => {
         //    unimplemented!("{:?}", expr);
         //}
@@ -1,13 +1,74 @@
+use std::collections::HashMap;
+
 use libeir_diagnostics::SourceSpan;
 use libeir_ir::{BinOp as IrBinOp, Block as IrBlock, FunctionBuilder, Value as IrValue};
 
-use libeir_intern::Symbol;
+use libeir_intern::{Ident, Symbol};
 
+use crate::parser::ast::{DefinedRecord, Module};
 use crate::parser::ast::{Record, RecordAccess, RecordIndex, RecordUpdate};
 
 use crate::lower::expr::lower_single;
+use crate::lower::free_vars::free_variables;
 use crate::lower::{LowerCtx, LowerError};
 
+/// Checks the default values of the fields of the records defined in
+/// `module`, which may only use variables they bind themselves.
+pub(in crate::lower) fn check_record_definitions(module: &Module) -> Vec<LowerError> {
+    let mut records: Vec<&DefinedRecord> = module.records.values().collect();
+    records.sort_by_key(|def| def.record.span);
+
+    let mut errors = Vec::new();
+    for def in records {
+        for field in def.record.fields.iter() {
+            if let Some(value) = field.value.as_ref() {
+                for var in free_variables(value) {
+                    errors.push(LowerError::VariableInRecordDefinition {
+                        span: var.span,
+                        name: var,
+                    });
+                }
+            }
+        }
+    }
+    errors
+}
+
+/// Looks up the definition of the record `name`, reporting an error if
+/// there is none.
+fn record_def<'a>(ctx: &mut LowerCtx<'a>, name: Ident) -> Option<&'a DefinedRecord> {
+    let module = ctx.module;
+    match module.records.get(&name.name) {
+        Some(def) => {
+            ctx.usage.use_record(name.name);
+            Some(def)
+        }
+        None => {
+            ctx.error(LowerError::UndefinedRecord { span: name.span });
+            None
+        }
+    }
+}
+
+/// Looks up the index of `field` in the record, reporting an error if
+/// the record has no such field.
+fn field_idx(
+    ctx: &mut LowerCtx,
+    rec_def: &DefinedRecord,
+    record: Ident,
+    field: Ident,
+) -> Option<usize> {
+    let idx = rec_def.field_idx_map.get(&field).cloned();
+    if idx.is_none() {
+        ctx.error(LowerError::UndefinedRecordField {
+            span: field.span,
+            record,
+            field,
+        });
+    }
+    idx
+}
+
 fn make_rec_fail(
     ctx: &mut LowerCtx,
     b: &mut FunctionBuilder,
@@ -35,11 +96,16 @@ pub(super) fn lower_record_access_expr(
     rec: &RecordAccess,
 ) -> (IrBlock, IrValue) {
     let span = rec.span;
-    let rec_def = &ctx.module.records[&rec.name.name];
+    let rec_def = match record_def(ctx, rec.name) {
+        Some(rec_def) => rec_def,
+        None => return (block, ctx.sentinel()),
+    };
+    let idx = match field_idx(ctx, rec_def, rec.name, rec.field) {
+        Some(idx) => idx,
+        None => return (block, ctx.sentinel()),
+    };
     let recname_val = b.value(rec.name);
 
-    let idx = rec_def.field_idx_map[&rec.field];
-
     let fail_block = make_rec_fail(ctx, b, span, recname_val);
 
     let record_val = map_block!(block, lower_single(ctx, b, block, &rec.record));
@@ -69,8 +135,10 @@ pub(super) fn lower_record_update_expr(
     rec: &RecordUpdate,
 ) -> (IrBlock, IrValue) {
     let span = rec.span;
-    // TODO Warn/error when updates overlap?
-    let rec_def = &ctx.module.records[&rec.name.name];
+    let rec_def = match record_def(ctx, rec.name) {
+        Some(rec_def) => rec_def,
+        None => return (block, ctx.sentinel()),
+    };
     let recname_val = b.value(rec.name);
 
     let num_fields = rec_def.record.fields.len();
@@ -101,8 +169,21 @@ pub(super) fn lower_record_update_expr(
     b.op_call_flow(eq_fail_block, fail_block, &[]);
 
     // Update fields
+    let mut updated: HashMap<Ident, SourceSpan> = HashMap::new();
     for update in rec.updates.iter() {
-        let idx = rec_def.field_idx_map[&update.name];
+        if let Some(old) = updated.get(&update.name) {
+            ctx.error(LowerError::DuplicateRecordField {
+                new: update.name.span,
+                old: *old,
+            });
+            continue;
+        }
+        updated.insert(update.name, update.name.span);
+
+        let idx = match field_idx(ctx, rec_def, rec.name, update.name) {
+            Some(idx) => idx,
+            None => continue,
+        };
         let new_val = map_block!(
             block,
             lower_single(ctx, b, block, update.value.as_ref().unwrap())
@@ -126,7 +207,10 @@ pub(super) fn lower_record_expr(
     rec: &Record,
 ) -> (IrBlock, IrValue) {
     let span = rec.span;
-    let rec_def = &ctx.module.records[&rec.name.name];
+    let rec_def = match record_def(ctx, rec.name) {
+        Some(rec_def) => rec_def,
+        None => return (block, ctx.sentinel()),
+    };
     let recname_val = b.value(rec.name);
 
     let num_fields = rec_def.record.fields.len();
@@ -135,7 +219,10 @@ pub(super) fn lower_record_expr(
 
     // Populate values from expression
     for field in rec.fields.iter() {
-        let idx = rec_def.field_idx_map[&field.name];
+        let idx = match field_idx(ctx, rec_def, rec.name, field.name) {
+            Some(idx) => idx,
+            None => continue,
+        };
 
         if elems[idx].is_some() {
             ctx.error(LowerError::DuplicateRecordField {
@@ -148,6 +235,7 @@ pub(super) fn lower_record_expr(
                     .name
                     .span,
             });
+            continue;
         }
 
         let new_val = map_block!(
@@ -161,8 +249,13 @@ pub(super) fn lower_record_expr(
     for (idx, field) in rec_def.record.fields.iter().enumerate() {
         if elems[idx].is_none() {
             let new_val = if let Some(const_expr) = field.value.as_ref() {
-                // TODO: Allow only constants. This should be a separate lowering method!
-                map_block!(block, lower_single(ctx, b, block, const_expr))
+                // Defaults with unbound variables are reported along
+                // with the record definition.
+                if !free_variables(const_expr).is_empty() {
+                    ctx.sentinel()
+                } else {
+                    map_block!(block, lower_single(ctx, b, block, const_expr))
+                }
             } else {
                 b.value(Symbol::intern("undefined"))
             };
@@ -184,8 +277,14 @@ pub(super) fn lower_record_index(
     block: IrBlock,
     rec: &RecordIndex,
 ) -> (IrBlock, IrValue) {
-    let rec_def = &ctx.module.records[&rec.name.name];
-    let index = rec_def.field_idx_map[&rec.field];
+    let rec_def = match record_def(ctx, rec.name) {
+        Some(rec_def) => rec_def,
+        None => return (block, ctx.sentinel()),
+    };
+    let index = match field_idx(ctx, rec_def, rec.name, rec.field) {
+        Some(index) => index,
+        None => return (block, ctx.sentinel()),
+    };
     let val = b.value(index);
     (block, val)
 }
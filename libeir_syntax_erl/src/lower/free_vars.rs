@@ -0,0 +1,351 @@
+//! Finding the variables an expression uses without binding them.
+//!
+//! Bindings follow the scoping rules of Erlang: variables bound in a
+//! pattern are visible to the rest of the expression sequence, clauses
+//! export the variables bound in all of them, and comprehensions, funs
+//! and guards bind nothing outside of themselves.
+
+use std::collections::HashSet;
+
+use libeir_intern::{Ident, Symbol};
+
+use crate::parser::ast::{
+    Arity, Clause, Expr, Function, FunctionClause, FunctionName, Guard, MapField, Name, RecordField,
+};
+
+/// The variables used in `expr` that are not bound in it, in the
+/// order they occur.
+pub(in crate::lower) fn free_variables(expr: &Expr) -> Vec<Ident> {
+    let mut vars = FreeVars {
+        bound: HashSet::new(),
+        free: Vec::new(),
+    };
+    vars.expr(expr);
+    vars.free
+}
+
+/// One of the alternative branches of an expression.
+enum Branch<'a> {
+    Clause(&'a Clause),
+    Body(&'a [Expr]),
+}
+
+struct FreeVars {
+    bound: HashSet<Symbol>,
+    free: Vec<Ident>,
+}
+
+impl FreeVars {
+    fn use_var(&mut self, var: Ident) {
+        if &*var.as_str() != "_" && !self.bound.contains(&var.name) {
+            self.free.push(var);
+        }
+    }
+
+    fn bind_var(&mut self, var: Ident) {
+        if &*var.as_str() != "_" {
+            self.bound.insert(var.name);
+        }
+    }
+
+    fn use_name(&mut self, name: &Name) {
+        if let Name::Var(var) = name {
+            self.use_var(*var);
+        }
+    }
+
+    /// Runs `fun` with the current bindings and restores them after.
+    fn scoped<F: FnOnce(&mut Self)>(&mut self, fun: F) {
+        let outer = self.bound.clone();
+        fun(self);
+        self.bound = outer;
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) {
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    fn guards(&mut self, guards: &Option<Vec<Guard>>) {
+        self.scoped(|vars| {
+            for guard in guards.iter().flatten() {
+                vars.exprs(&guard.conditions);
+            }
+        });
+    }
+
+    /// Processes alternative clauses. Every clause starts from the
+    /// current bindings, and the variables bound by all of them are
+    /// bound afterwards.
+    fn alternatives<T, F>(&mut self, clauses: &[T], mut clause: F)
+    where
+        F: FnMut(&mut Self, &T),
+    {
+        let outer = self.bound.clone();
+        let mut exported: Option<HashSet<Symbol>> = None;
+        for item in clauses {
+            self.bound = outer.clone();
+            clause(self, item);
+            exported = Some(match exported {
+                None => self.bound.clone(),
+                Some(exported) => exported.intersection(&self.bound).cloned().collect(),
+            });
+        }
+        self.bound = exported.unwrap_or(outer);
+    }
+
+    fn clause(&mut self, clause: &Clause) {
+        self.pattern(&clause.pattern);
+        self.guards(&clause.guard);
+        self.exprs(&clause.body);
+    }
+
+    fn clauses(&mut self, clauses: &[Clause]) {
+        self.alternatives(clauses, |vars, clause| vars.clause(clause));
+    }
+
+    fn function_clauses(&mut self, clauses: &[FunctionClause]) {
+        for clause in clauses {
+            self.scoped(|vars| {
+                for param in clause.params.iter() {
+                    vars.pattern(param);
+                }
+                vars.guards(&clause.guard);
+                vars.exprs(&clause.body);
+            });
+        }
+    }
+
+    fn qualifiers(&mut self, qualifiers: &[Expr]) {
+        for qualifier in qualifiers {
+            match qualifier {
+                Expr::Generator(gen) => {
+                    self.expr(&gen.expr);
+                    self.pattern(&gen.pattern);
+                }
+                Expr::BinaryGenerator(gen) => {
+                    self.expr(&gen.expr);
+                    self.pattern(&gen.pattern);
+                }
+                Expr::MapGenerator(gen) => {
+                    self.expr(&gen.expr);
+                    self.pattern(&gen.key);
+                    self.pattern(&gen.value);
+                }
+                // Variables bound in a filter are visible to the later
+                // qualifiers.
+                filter => self.expr(filter),
+            }
+        }
+    }
+
+    fn map_fields(&mut self, fields: &[MapField]) {
+        for field in fields {
+            self.expr(&field.key());
+            self.expr(&field.value());
+        }
+    }
+
+    fn record_fields(&mut self, fields: &[RecordField]) {
+        for field in fields {
+            if let Some(value) = field.value.as_ref() {
+                self.expr(value);
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Var(var) => self.use_var(var.1),
+            Expr::Literal(_) | Expr::DelayedSubstitution(..) | Expr::Nil(_) => (),
+            Expr::RecordIndex(_) => (),
+            Expr::FunctionName(FunctionName::Unresolved(name)) => {
+                if let Some(module) = name.module.as_ref() {
+                    self.use_name(module);
+                }
+                self.use_name(&name.function);
+                if let Arity::Var(arity) = &name.arity {
+                    self.use_var(*arity);
+                }
+            }
+            Expr::FunctionName(_) => (),
+            Expr::Cons(cons) => {
+                self.expr(&cons.head);
+                self.expr(&cons.tail);
+            }
+            Expr::Tuple(tuple) => self.exprs(&tuple.elements),
+            Expr::Map(map) => self.map_fields(&map.fields),
+            Expr::MapUpdate(map) => {
+                self.expr(&map.map);
+                self.map_fields(&map.updates);
+            }
+            Expr::MapProjection(map) => {
+                self.expr(&map.map);
+                self.map_fields(&map.fields);
+            }
+            Expr::Binary(bin) => {
+                for element in bin.elements.iter() {
+                    self.expr(&element.bit_expr);
+                    if let Some(size) = element.bit_size.as_ref() {
+                        self.expr(size);
+                    }
+                }
+            }
+            Expr::Record(rec) => self.record_fields(&rec.fields),
+            Expr::RecordAccess(rec) => self.expr(&rec.record),
+            Expr::RecordUpdate(rec) => {
+                self.expr(&rec.record);
+                self.record_fields(&rec.updates);
+            }
+            Expr::ListComprehension(compr) => self.scoped(|vars| {
+                vars.qualifiers(&compr.qualifiers);
+                vars.expr(&compr.body);
+            }),
+            Expr::BinaryComprehension(compr) => self.scoped(|vars| {
+                vars.qualifiers(&compr.qualifiers);
+                vars.expr(&compr.body);
+            }),
+            Expr::MapComprehension(compr) => self.scoped(|vars| {
+                vars.qualifiers(&compr.qualifiers);
+                vars.expr(&compr.key);
+                vars.expr(&compr.value);
+            }),
+            // Only valid as qualifiers, handled above.
+            Expr::Generator(_) | Expr::BinaryGenerator(_) | Expr::MapGenerator(_) => {
+                self.qualifiers(std::slice::from_ref(expr))
+            }
+            Expr::Begin(begin) => self.exprs(&begin.body),
+            Expr::Apply(apply) => {
+                self.expr(&apply.callee);
+                self.exprs(&apply.args);
+            }
+            Expr::Remote(remote) => {
+                self.expr(&remote.module);
+                self.expr(&remote.function);
+            }
+            Expr::BinaryExpr(bin) => {
+                self.expr(&bin.lhs);
+                self.expr(&bin.rhs);
+            }
+            Expr::UnaryExpr(un) => self.expr(&un.operand),
+            Expr::Match(m) => {
+                self.expr(&m.expr);
+                self.pattern(&m.pattern);
+            }
+            Expr::If(expr) => self.alternatives(&expr.clauses, |vars, clause| {
+                for guard in clause.guards.iter() {
+                    vars.scoped(|vars| vars.exprs(&guard.conditions));
+                }
+                vars.exprs(&clause.body);
+            }),
+            // Bindings in a catch are unsafe to use after it.
+            Expr::Catch(catch) => self.scoped(|vars| vars.expr(&catch.expr)),
+            Expr::Case(case) => {
+                self.expr(&case.expr);
+                self.clauses(&case.clauses);
+            }
+            Expr::Receive(receive) => {
+                let mut branches: Vec<Branch> = receive
+                    .clauses
+                    .iter()
+                    .flatten()
+                    .map(Branch::Clause)
+                    .collect();
+                if let Some(after) = receive.after.as_ref() {
+                    self.scoped(|vars| vars.expr(&after.timeout));
+                    branches.push(Branch::Body(&after.body));
+                }
+                self.alternatives(&branches, |vars, branch| match branch {
+                    Branch::Clause(clause) => vars.clause(clause),
+                    Branch::Body(body) => vars.exprs(body),
+                });
+            }
+            // Nothing bound in a try is safe to use after it.
+            Expr::Try(expr) => self.scoped(|vars| {
+                vars.scoped(|vars| {
+                    vars.exprs(&expr.exprs);
+                    vars.clauses(expr.clauses.as_ref().map(|c| &c[..]).unwrap_or(&[]));
+                });
+                for clause in expr.catch_clauses.iter().flatten() {
+                    vars.scoped(|vars| {
+                        if let Name::Var(kind) = &clause.kind {
+                            vars.bind_var(*kind);
+                        }
+                        vars.pattern(&clause.error);
+                        vars.bind_var(clause.trace);
+                        vars.guards(&clause.guard);
+                        vars.exprs(&clause.body);
+                    });
+                }
+                if let Some(after) = expr.after.as_ref() {
+                    vars.exprs(after);
+                }
+            }),
+            Expr::Fun(Function::Unnamed(lambda)) => self.function_clauses(&lambda.clauses),
+            Expr::Fun(Function::Named(fun)) => self.scoped(|vars| {
+                if let Name::Var(name) = &fun.name {
+                    vars.bind_var(*name);
+                }
+                vars.function_clauses(&fun.clauses);
+            }),
+            Expr::Maybe(maybe) => self.scoped(|vars| {
+                vars.scoped(|vars| vars.exprs(&maybe.body));
+                vars.clauses(maybe.else_clauses.as_ref().map(|c| &c[..]).unwrap_or(&[]));
+            }),
+            Expr::MaybeMatch(m) => {
+                self.expr(&m.expr);
+                self.pattern(&m.pattern);
+            }
+        }
+    }
+
+    /// Binds the variables of a pattern. Already bound variables, and
+    /// the expressions in a pattern like binary sizes and map keys, are
+    /// uses.
+    fn pattern(&mut self, pattern: &Expr) {
+        match pattern {
+            Expr::Var(var) => self.bind_var(var.1),
+            Expr::Cons(cons) => {
+                self.pattern(&cons.head);
+                self.pattern(&cons.tail);
+            }
+            Expr::Tuple(tuple) => {
+                for element in tuple.elements.iter() {
+                    self.pattern(element);
+                }
+            }
+            Expr::Map(map) => {
+                for field in map.fields.iter() {
+                    self.expr(&field.key());
+                    self.pattern(&field.value());
+                }
+            }
+            Expr::Binary(bin) => {
+                for element in bin.elements.iter() {
+                    if let Some(size) = element.bit_size.as_ref() {
+                        self.expr(size);
+                    }
+                    self.pattern(&element.bit_expr);
+                }
+            }
+            Expr::Record(rec) => {
+                for field in rec.fields.iter() {
+                    if let Some(value) = field.value.as_ref() {
+                        self.pattern(value);
+                    }
+                }
+            }
+            Expr::Match(m) => {
+                self.pattern(&m.pattern);
+                self.pattern(&m.expr);
+            }
+            // `"prefix" ++ Rest`
+            Expr::BinaryExpr(bin) => {
+                self.pattern(&bin.lhs);
+                self.pattern(&bin.rhs);
+            }
+            other => self.expr(other),
+        }
+    }
+}
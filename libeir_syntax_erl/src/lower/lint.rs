@@ -0,0 +1,239 @@
+//! Warnings for module level definitions that are never used: local
+//! functions, imports and records. Uses are recorded while the module
+//! is lowered, the warnings are generated once every function has
+//! been lowered.
+
+use std::collections::{HashMap, HashSet};
+
+use libeir_intern::Symbol;
+
+use crate::parser::ast::{CompileOptions, LocalFunctionName, Module, Type, TypeSig};
+
+use super::LowerError;
+
+pub(super) struct ModuleUsage {
+    /// The top level function currently being lowered.
+    current: Option<LocalFunctionName>,
+    /// Local functions called or captured by each top level function.
+    calls: HashMap<LocalFunctionName, HashSet<LocalFunctionName>>,
+    imports: HashSet<LocalFunctionName>,
+    records: HashSet<Symbol>,
+}
+
+impl ModuleUsage {
+    pub fn new() -> Self {
+        ModuleUsage {
+            current: None,
+            calls: HashMap::new(),
+            imports: HashSet::new(),
+            records: HashSet::new(),
+        }
+    }
+
+    pub fn enter_function(&mut self, name: LocalFunctionName) {
+        self.calls.entry(name).or_insert_with(HashSet::new);
+        self.current = Some(name);
+    }
+
+    pub fn use_local(&mut self, name: LocalFunctionName) {
+        let current = self.current.expect("local call outside of function");
+        self.calls.get_mut(&current).unwrap().insert(name);
+    }
+
+    pub fn use_import(&mut self, name: LocalFunctionName) {
+        self.imports.insert(name);
+    }
+
+    pub fn use_record(&mut self, name: Symbol) {
+        self.records.insert(name);
+    }
+}
+
+pub(super) fn module_warnings(
+    module: &Module,
+    options: &CompileOptions,
+    usage: &ModuleUsage,
+) -> Vec<LowerError> {
+    let mut warnings = Vec::new();
+    if options.warn_unused_function && !options.export_all {
+        unused_functions(module, options, usage, &mut warnings);
+    }
+    if options.warn_unused_import {
+        unused_imports(module, usage, &mut warnings);
+    }
+    if options.warn_unused_record {
+        unused_records(module, usage, &mut warnings);
+    }
+    warnings
+}
+
+/// Functions that are implicitly called by the runtime.
+fn is_pseudolocal(name: &LocalFunctionName) -> bool {
+    match (&*name.function.as_str(), name.arity) {
+        ("module_info", 0) | ("module_info", 1) | ("behaviour_info", 1) => true,
+        _ => false,
+    }
+}
+
+fn unused_functions(
+    module: &Module,
+    options: &CompileOptions,
+    usage: &ModuleUsage,
+    warnings: &mut Vec<LowerError>,
+) {
+    let mut reachable = HashSet::new();
+    let mut stack: Vec<LocalFunctionName> = module
+        .functions
+        .keys()
+        .filter(|&name| {
+            module.exports.contains(name)
+                || module.on_load.as_ref() == Some(name)
+                || is_pseudolocal(name)
+        })
+        .cloned()
+        .collect();
+
+    while let Some(name) = stack.pop() {
+        if !reachable.insert(name) {
+            continue;
+        }
+        if let Some(callees) = usage.calls.get(&name) {
+            stack.extend(callees.iter().filter(|c| !reachable.contains(*c)));
+        }
+    }
+
+    for (name, function) in module.functions.iter() {
+        if reachable.contains(name) || options.no_warn_unused_functions.contains(name) {
+            continue;
+        }
+        warnings.push(LowerError::UnusedFunction {
+            span: function.name.span(),
+            name: name.function,
+            arity: name.arity,
+        });
+    }
+}
+
+fn unused_imports(module: &Module, usage: &ModuleUsage, warnings: &mut Vec<LowerError>) {
+    let mut unused: Vec<_> = module
+        .imports
+        .iter()
+        .filter(|(local, _)| !module.auto_imports.contains(*local))
+        .filter(|(local, _)| !usage.imports.contains(*local))
+        .map(|(_, import)| import)
+        .collect();
+    unused.sort_by_key(|import| import.span);
+
+    for import in unused {
+        warnings.push(LowerError::UnusedImport {
+            span: import.span,
+            module: import.module,
+            name: import.function,
+            arity: import.arity,
+        });
+    }
+}
+
+fn unused_records(module: &Module, usage: &ModuleUsage, warnings: &mut Vec<LowerError>) {
+    // Records referenced from types count as used, even if no
+    // function ever touches them.
+    let mut used = usage.records.clone();
+    for typedef in module.types.values() {
+        type_records(&typedef.ty, &mut used);
+    }
+    for function in module.functions.values() {
+        if let Some(spec) = &function.spec {
+            sigs_records(&spec.sigs, &mut used);
+        }
+    }
+    for callback in module.callbacks.values() {
+        sigs_records(&callback.sigs, &mut used);
+    }
+    for record in module.records.values() {
+        for field in record.record.fields.iter() {
+            if let Some(ty) = &field.ty {
+                type_records(ty, &mut used);
+            }
+        }
+    }
+
+    let mut unused: Vec<_> = module
+        .records
+        .iter()
+        .filter(|(name, _)| !used.contains(*name))
+        .map(|(_, record)| &record.record)
+        .collect();
+    unused.sort_by_key(|record| record.span);
+
+    for record in unused {
+        warnings.push(LowerError::UnusedRecord {
+            span: record.name.span,
+            name: record.name,
+        });
+    }
+}
+
+fn sigs_records(sigs: &[TypeSig], used: &mut HashSet<Symbol>) {
+    for sig in sigs.iter() {
+        for param in sig.params.iter() {
+            type_records(param, used);
+        }
+        type_records(&sig.ret, used);
+        for guard in sig.guards.iter().flatten() {
+            type_records(&guard.ty, used);
+        }
+    }
+}
+
+fn type_records(ty: &Type, used: &mut HashSet<Symbol>) {
+    match ty {
+        Type::Record(_, name, fields) => {
+            used.insert(name.name);
+            for field in fields.iter() {
+                type_records(field, used);
+            }
+        }
+        Type::Annotated { ty, .. } => type_records(ty, used),
+        Type::Union { types, .. } => {
+            for ty in types.iter() {
+                type_records(ty, used);
+            }
+        }
+        Type::Range { start, end, .. } => {
+            type_records(start, used);
+            type_records(end, used);
+        }
+        Type::BinaryOp { lhs, rhs, .. } => {
+            type_records(lhs, used);
+            type_records(rhs, used);
+        }
+        Type::UnaryOp { rhs, .. } => type_records(rhs, used),
+        Type::Generic { params: tys, .. }
+        | Type::Remote { args: tys, .. }
+        | Type::Map(_, tys)
+        | Type::Tuple(_, tys) => {
+            for ty in tys.iter() {
+                type_records(ty, used);
+            }
+        }
+        Type::List(_, ty) | Type::NonEmptyList(_, ty) | Type::Field(_, _, ty) => {
+            type_records(ty, used)
+        }
+        Type::Binary(_, a, b) | Type::KeyValuePair(_, _, a, b) => {
+            type_records(a, used);
+            type_records(b, used);
+        }
+        Type::AnyFun { ret, .. } => {
+            if let Some(ret) = ret {
+                type_records(ret, used);
+            }
+        }
+        Type::Fun { params, ret, .. } => {
+            for param in params.iter() {
+                type_records(param, used);
+            }
+            type_records(ret, used);
+        }
+        Type::Name(_) | Type::Nil(_) | Type::Integer(_, _) | Type::Char(_, _) => (),
+    }
+}
@@ -9,7 +9,7 @@ use libeir_diagnostics::{CodeMap, SourceSpan};
 use libeir_intern::{Ident, Symbol};
 use libeir_util_parse::ErrorReceiver;
 
-use crate::parser::ast::{Function, FunctionClause, Module, NamedFunction};
+use crate::parser::ast::{CompileOptions, Function, FunctionClause, Module, NamedFunction};
 
 macro_rules! map_block {
     ($block:expr, $call:expr) => {{
@@ -23,7 +23,7 @@ mod pattern;
 use pattern::lower_clause;
 
 mod expr;
-use expr::{lower_block, lower_single};
+use expr::{check_record_definitions, lower_block, lower_single};
 
 mod errors;
 pub use errors::LowerError;
@@ -34,6 +34,13 @@ use exception_handler_stack::ExceptionHandlerStack;
 mod scope;
 use scope::ScopeToken;
 
+mod free_vars;
+
+mod lint;
+use lint::ModuleUsage;
+
+mod spec;
+
 #[cfg(test)]
 mod tests;
 
@@ -42,6 +49,7 @@ pub(crate) mod strings;
 pub(crate) struct LowerCtx<'a> {
     codemap: Arc<CodeMap>,
     module: &'a Module,
+    options: CompileOptions,
 
     scope: scope::ScopeTracker,
     exc_stack: ExceptionHandlerStack,
@@ -56,6 +64,8 @@ pub(crate) struct LowerCtx<'a> {
     /// Top is current function name.
     /// Used to generate debug info.
     functions: Vec<String>,
+
+    usage: ModuleUsage,
 }
 
 impl<'a> LowerCtx<'a> {
@@ -76,7 +86,14 @@ impl<'a> LowerCtx<'a> {
     }
 
     pub fn warn(&mut self, err: LowerError) {
-        self.errors.warning(err);
+        if self.options.no_warn {
+            return;
+        }
+        if self.options.warnings_as_errors {
+            self.errors.error(err);
+        } else {
+            self.errors.warning(err);
+        }
     }
 
     pub fn failed(&self) -> bool {
@@ -84,15 +101,32 @@ impl<'a> LowerCtx<'a> {
     }
 
     pub fn resolve(&mut self, ident: Ident) -> IrValue {
-        match self.scope.resolve(ident) {
-            Ok(val) => val,
-            Err(err) => {
-                self.error(err);
+        match self.try_resolve(ident) {
+            Some(val) => val,
+            None => {
+                self.error(LowerError::UnresolvedVariable { span: ident.span });
                 self.sentinel()
             }
         }
     }
 
+    /// Resolves a variable, marking it as used. Returns `None` if the
+    /// variable is not bound.
+    pub fn try_resolve(&mut self, ident: Ident) -> Option<IrValue> {
+        let (val, exported) = self.scope.resolve_use(ident).ok()?;
+        if let Some(exported) = exported {
+            if self.options.warn_export_vars {
+                self.warn(LowerError::ExportedVariable {
+                    span: ident.span,
+                    bound: exported.bound,
+                    name: ident,
+                    construct: exported.construct,
+                });
+            }
+        }
+        Some(val)
+    }
+
     pub fn bind_shadow(&mut self, ident: Ident, val: IrValue) {
         match self.scope.bind_shadow(ident, val) {
             Ok(()) => (),
@@ -170,9 +204,25 @@ pub fn lower_module<'a>(
 
     let mut ir_module = IrModule::new_with_span(module.name, module.span);
 
+    for export in module.exports.iter() {
+        ir_module.add_export(export.function.name, export.arity);
+    }
+    ir_module.set_on_load(
+        module
+            .on_load
+            .as_ref()
+            .map(|name| (name.function.name, name.arity)),
+    );
+    for (name, fun_spec) in module.specs.iter() {
+        if module.functions.contains_key(name) {
+            ir_module.set_spec(name.function.name, name.arity, spec::lower_spec(fun_spec));
+        }
+    }
+
     let mut ctx = LowerCtx {
         codemap,
         module,
+        options: module.compile.clone().unwrap_or_default(),
 
         scope: scope::ScopeTracker::new(),
         exc_stack: ExceptionHandlerStack::new(),
@@ -185,8 +235,14 @@ pub fn lower_module<'a>(
 
         fun_num: 0,
         functions: Vec::new(),
+
+        usage: ModuleUsage::new(),
     };
 
+    for err in check_record_definitions(module) {
+        ctx.error(err);
+    }
+
     for (ident, function) in module.functions.iter() {
         assert!(ctx.scope.height() == 0);
         ctx.fun_num = 0;
@@ -205,7 +261,21 @@ pub fn lower_module<'a>(
         let sentinel_value = builder.block_arg_insert(sentinel_block);
         ctx.sentinel_value = Some(sentinel_value);
 
+        ctx.usage.enter_function(*ident);
         lower_top_function(&mut ctx, &mut builder, function);
+
+        for ident in ctx.scope.take_unused() {
+            if ctx.options.warn_unused_var && !ident.as_str().starts_with('_') {
+                ctx.warn(LowerError::UnusedVariable {
+                    span: ident.span,
+                    name: ident,
+                });
+            }
+        }
+    }
+
+    for warning in lint::module_warnings(module, &ctx.options, &ctx.usage) {
+        ctx.warn(warning);
     }
 
     ctx.exc_stack.finish();
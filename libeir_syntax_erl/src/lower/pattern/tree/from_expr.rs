@@ -1,4 +1,5 @@
 use either::Either;
+use std::cell::RefCell;
 use std::convert::TryInto;
 
 use cranelift_entity::EntityList;
@@ -272,6 +273,7 @@ fn pattern_to_tree_node(
                 return t.nodes.push(TreeNodeKind::Wildcard(span));
             }
 
+            ctx.usage.use_record(rec.name.name);
             let rec_def = &ctx.module.records[&rec.name.name];
 
             let name = b.cons_mut().from(rec.name);
@@ -283,7 +285,17 @@ fn pattern_to_tree_node(
             }
 
             for field in rec.fields.iter() {
-                let idx = rec_def.field_idx_map[&field.name];
+                let idx = match rec_def.field_idx_map.get(&field.name) {
+                    Some(idx) => *idx,
+                    None => {
+                        ctx.error(LowerError::UndefinedRecordField {
+                            span: field.name.span,
+                            record: rec.name,
+                            field: field.name,
+                        });
+                        continue;
+                    }
+                };
 
                 let node =
                     pattern_to_tree_node(ctx, b, pre_block, t, &field.value.as_ref().unwrap());
@@ -428,7 +440,9 @@ fn pattern_to_tree_node(
             })
         }
         expr => {
+            let used_records = RefCell::new(Vec::new());
             let resolve_rec_idx = |name: Ident, field: Ident| {
+                used_records.borrow_mut().push(name.name);
                 let rec = ctx
                     .module
                     .records
@@ -440,7 +454,11 @@ fn pattern_to_tree_node(
                     .ok_or(ResolveRecordIndexError::NoField)?;
                 Ok(*idx)
             };
-            match eval_expr(expr, Some(&resolve_rec_idx)) {
+            let res = eval_expr(expr, Some(&resolve_rec_idx));
+            for name in used_records.into_inner() {
+                ctx.usage.use_record(name);
+            }
+            match res {
                 Ok(term) => {
                     let constant = match term {
                         Term::Number(num) => b.cons_mut().from(num),
@@ -141,7 +141,13 @@ fn lower_tree_node(
             pat.value(p_node, cl_val);
             pat.node_set_span(p_node, *span);
         }
-        TreeNodeKind::Value(_, Either::Right(_node)) => unimplemented!(),
+        TreeNodeKind::Value(span, Either::Right(other)) => {
+            let other_node = map[other];
+            let cl_val = pat.clause_node_value(cl_ctx.pat_clause, other_node);
+
+            pat.value(p_node, cl_val);
+            pat.node_set_span(p_node, *span);
+        }
         TreeNodeKind::Wildcard(_) => {
             pat.wildcard(p_node);
         }
@@ -2,7 +2,11 @@
 
 use std::collections::{BTreeMap, BTreeSet};
 
-use libeir_ir::FunctionBuilder;
+use either::Either;
+
+use libeir_intern::Ident;
+use libeir_ir::constant::{AtomicTerm, BinaryTerm, ConstKind};
+use libeir_ir::{BinaryEntrySpecifier, Const, FunctionBuilder, Value as IrValue};
 
 use cranelift_entity::EntityList;
 
@@ -168,7 +172,9 @@ fn merge_nodes(
                 tail: t_m,
             })
         }
-        (TreeNodeKind::Binary { .. }, TreeNodeKind::Binary { .. }) => unimplemented!(),
+        (TreeNodeKind::Binary { .. }, _) | (_, TreeNodeKind::Binary { .. }) => {
+            merge_binary_nodes(ctx, b, t, left, right)
+        }
         (TreeNodeKind::Map { entries: e_l, span }, TreeNodeKind::Map { entries: e_r, .. }) => {
             // Entries vectors should already be sorted
             debug_assert!(e_l.windows(2).all(|w| w[0].0 < w[1].0));
@@ -227,3 +233,148 @@ fn merge_nodes(
 
     new
 }
+
+/// Merges two nodes where at least one is a binary pattern. Binary
+/// patterns are merged element by element as long as the elements
+/// have the same type and size. An element that matches the rest of
+/// the binary can be merged with any other pattern, which is then
+/// matched on the rest.
+fn merge_binary_nodes(
+    ctx: &mut LowerCtx,
+    b: &mut FunctionBuilder,
+    t: &mut Tree,
+    left: TreeNode,
+    right: TreeNode,
+) -> TreeNode {
+    if let (
+        TreeNodeKind::Binary {
+            span,
+            specifier: s1,
+            size: z1,
+            size_resolved,
+            value: v1,
+            tail: t1,
+        },
+        TreeNodeKind::Binary {
+            specifier: s2,
+            size: z2,
+            value: v2,
+            tail: t2,
+            ..
+        },
+    ) = (t.nodes[left].clone(), t.nodes[right].clone())
+    {
+        if s1 == s2 && same_size(b, z1, z2) {
+            let value = merge_nodes(ctx, b, t, v1, v2);
+            let tail = merge_nodes(ctx, b, t, t1, t2);
+            return t.nodes.push(TreeNodeKind::Binary {
+                span,
+                specifier: s1,
+                size: z1,
+                size_resolved,
+                value,
+                tail,
+            });
+        }
+    }
+
+    if let Some(new) = merge_binary_rest(ctx, b, t, left, right) {
+        return new;
+    }
+    if let Some(new) = merge_binary_rest(ctx, b, t, right, left) {
+        return new;
+    }
+
+    if is_binary(b, t, left) && is_binary(b, t, right) {
+        ctx.error(LowerError::UnsupportedPatternUnion {
+            left: Some(t.node_span(left)),
+            right: t.node_span(right),
+        });
+    } else {
+        ctx.warn(LowerError::DisjointPatternUnionWarning {
+            left: Some(t.node_span(left)),
+            right: Some(t.node_span(right)),
+        });
+    }
+    t.unmatchable = true;
+    t.nodes.push(TreeNodeKind::And { left, right })
+}
+
+/// If `rest` is a binary element that matches all of what is left of
+/// the binary, like `Rest/binary`, merges `other` into its value.
+fn merge_binary_rest(
+    ctx: &mut LowerCtx,
+    b: &mut FunctionBuilder,
+    t: &mut Tree,
+    rest: TreeNode,
+    other: TreeNode,
+) -> Option<TreeNode> {
+    if let TreeNodeKind::Binary {
+        span,
+        specifier,
+        size: None,
+        size_resolved,
+        value,
+        tail,
+    } = t.nodes[rest].clone()
+    {
+        let takes_rest = match specifier {
+            BinaryEntrySpecifier::Bytes { .. } | BinaryEntrySpecifier::Bits { .. } => true,
+            _ => false,
+        };
+        let empty_tail = match &t.nodes[tail] {
+            TreeNodeKind::Atomic(_, cons) => binary_const(b, *cons)
+                .map(|bin| bin.is_empty())
+                .unwrap_or(false),
+            _ => false,
+        };
+        if takes_rest && empty_tail {
+            let value = merge_nodes(ctx, b, t, value, other);
+            return Some(t.nodes.push(TreeNodeKind::Binary {
+                span,
+                specifier,
+                size: None,
+                size_resolved,
+                value,
+                tail,
+            }));
+        }
+    }
+    None
+}
+
+fn same_size(
+    b: &FunctionBuilder,
+    left: Option<Either<Ident, IrValue>>,
+    right: Option<Either<Ident, IrValue>>,
+) -> bool {
+    match (left, right) {
+        (None, None) => true,
+        (Some(Either::Left(l)), Some(Either::Left(r))) => l == r,
+        (Some(Either::Right(l)), Some(Either::Right(r))) => {
+            if l == r {
+                return true;
+            }
+            match (b.fun().value_const(l), b.fun().value_const(r)) {
+                (Some(l), Some(r)) => l == r,
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+fn binary_const(b: &FunctionBuilder, cons: Const) -> Option<&[u8]> {
+    match b.cons().const_kind(cons) {
+        ConstKind::Atomic(AtomicTerm::Binary(BinaryTerm(bin))) => Some(bin),
+        _ => None,
+    }
+}
+
+fn is_binary(b: &FunctionBuilder, t: &Tree, node: TreeNode) -> bool {
+    match &t.nodes[node] {
+        TreeNodeKind::Binary { .. } => true,
+        TreeNodeKind::Atomic(_, cons) => binary_const(b, *cons).is_some(),
+        _ => false,
+    }
+}
@@ -29,18 +29,20 @@ impl<'a, 'b> PromoteCtx<'a, 'b> {
             self.binds_scope.insert(ident, node);
         }
         if self.shadow {
-            if let Some(bound_node) = self.binds.get(&ident) {
-                Some(Either::Left(*bound_node))
+            if let Some(bound_node) = self.binds.get(&ident).cloned() {
+                self.mark_pattern_use(ident);
+                Some(Either::Left(bound_node))
             } else {
                 self.bind(ident, node);
                 None
             }
         } else {
-            if let Ok(prev_bound) = self.ctx.scope.resolve(ident) {
+            if let Some(prev_bound) = self.ctx.try_resolve(ident) {
                 Some(Either::Right(prev_bound))
             } else {
-                if let Some(bound_node) = self.binds.get(&ident) {
-                    Some(Either::Left(*bound_node))
+                if let Some(bound_node) = self.binds.get(&ident).cloned() {
+                    self.mark_pattern_use(ident);
+                    Some(Either::Left(bound_node))
                 } else {
                     self.bind(ident, node);
                     None
@@ -49,11 +51,12 @@ impl<'a, 'b> PromoteCtx<'a, 'b> {
         }
     }
 
-    fn resolve_only(&self, ident: Ident) -> Option<Either<TreeNode, IrValue>> {
-        if let Some(bound_node) = self.binds_scope.get(&ident) {
-            Some(Either::Left(*bound_node))
+    fn resolve_only(&mut self, ident: Ident) -> Option<Either<TreeNode, IrValue>> {
+        if let Some(bound_node) = self.binds_scope.get(&ident).cloned() {
+            self.mark_pattern_use(ident);
+            Some(Either::Left(bound_node))
         } else {
-            if let Ok(prev_bound) = self.ctx.scope.resolve(ident) {
+            if let Some(prev_bound) = self.ctx.try_resolve(ident) {
                 Some(Either::Right(prev_bound))
             } else {
                 None
@@ -61,6 +64,15 @@ impl<'a, 'b> PromoteCtx<'a, 'b> {
         }
     }
 
+    /// A variable occurring more than once in a pattern is compared
+    /// against, which counts as a use of its first occurrence.
+    fn mark_pattern_use(&mut self, ident: Ident) {
+        if let Some(first) = self.binds.keys().find(|bound| **bound == ident) {
+            let first = *first;
+            self.ctx.scope.mark_used(first);
+        }
+    }
+
     fn bind(&mut self, ident: Ident, node: TreeNode) -> Option<TreeNode> {
         let res = if let Some(prev) = self.binds.get(&ident) {
             if *prev == node {
@@ -2,6 +2,7 @@ use std::collections::{HashMap, HashSet};
 
 use libeir_util_datastructures::hashmap_stack::HashMapStack;
 
+use libeir_diagnostics::SourceSpan;
 use libeir_ir::{Block as IrBlock, FunctionBuilder, Value as IrValue};
 
 use libeir_intern::Ident;
@@ -17,17 +18,50 @@ pub struct ScopeToken {
     height: usize,
 }
 
-#[derive(Debug)]
-pub struct ScopeTracker {
+#[derive(Debug, Copy, Clone)]
+pub struct Binding {
     // FIXME: Annoying that we have to store the key in the value.
     // Fix when get_key_value makes it into stable.
-    stack: HashMapStack<Ident, (Ident, IrValue)>,
+    pub ident: Ident,
+    pub value: IrValue,
+    /// If the binding was exported from a case or if, index of the
+    /// merge it was created by.
+    merge: Option<usize>,
+}
+
+#[derive(Debug)]
+struct Merge {
+    construct: &'static str,
+    /// The spans of all the bindings in the branches that were merged.
+    spans: Vec<SourceSpan>,
+}
+
+/// A variable binding that was exported from a case or if, returned
+/// when such a binding is used outside of it.
+#[derive(Debug, Copy, Clone)]
+pub struct ExportedBinding {
+    pub construct: &'static str,
+    pub bound: SourceSpan,
+}
+
+#[derive(Debug)]
+pub struct ScopeTracker {
+    stack: HashMapStack<Ident, Binding>,
+    merges: Vec<Merge>,
+
+    /// Every variable bound since the last call to `take_unused`.
+    bound: Vec<Ident>,
+    /// Spans of bindings that have been read.
+    used: HashSet<SourceSpan>,
 }
 
 impl ScopeTracker {
     pub fn new() -> Self {
         ScopeTracker {
             stack: HashMapStack::new(),
+            merges: Vec::new(),
+            bound: Vec::new(),
+            used: HashSet::new(),
         }
     }
 
@@ -50,14 +84,14 @@ impl ScopeTracker {
         }
     }
 
-    pub fn pop_take(&mut self, token: ScopeToken) -> HashMap<Ident, IrValue> {
+    pub fn pop_take(&mut self, token: ScopeToken) -> HashMap<Ident, Binding> {
         assert!(self.stack.height() >= token.height);
 
         let mut ret = HashMap::new();
         for layer_n in (token.height - 1)..self.stack.height() {
             let layer = self.stack.layer(layer_n);
             for (key, value) in layer.iter() {
-                ret.insert(*key, value.1);
+                ret.insert(*key, *value);
             }
         }
 
@@ -65,14 +99,61 @@ impl ScopeTracker {
         ret
     }
 
+    /// Resolves a variable without marking it as used.
     pub fn resolve(&self, ident: Ident) -> Result<IrValue, LowerError> {
         if let Some(val) = self.stack.get(&ident) {
-            Ok(val.1)
+            Ok(val.value)
         } else {
             Err(LowerError::UnresolvedVariable { span: ident.span })
         }
     }
 
+    /// Resolves a variable and marks its binding as used. If the
+    /// binding was exported from a case or if, that is returned along
+    /// with the value.
+    pub fn resolve_use(
+        &mut self,
+        ident: Ident,
+    ) -> Result<(IrValue, Option<ExportedBinding>), LowerError> {
+        let binding = match self.stack.get(&ident) {
+            Some(binding) => *binding,
+            None => return Err(LowerError::UnresolvedVariable { span: ident.span }),
+        };
+
+        self.used.insert(binding.ident.span);
+        let exported = binding.merge.map(|merge| {
+            let merge = &self.merges[merge];
+            self.used.extend(merge.spans.iter().cloned());
+            ExportedBinding {
+                construct: merge.construct,
+                bound: binding.ident.span,
+            }
+        });
+
+        Ok((binding.value, exported))
+    }
+
+    /// Marks a binding made in the current pattern as used. Used when
+    /// a variable occurs more than once in a pattern.
+    pub fn mark_used(&mut self, ident: Ident) {
+        self.used.insert(ident.span);
+    }
+
+    /// Returns every variable bound since the last call that was
+    /// never read, in binding order.
+    pub fn take_unused(&mut self) -> Vec<Ident> {
+        let mut seen = HashSet::new();
+        let mut unused = Vec::new();
+        for ident in self.bound.drain(..) {
+            if !self.used.contains(&ident.span) && seen.insert(ident.span) {
+                unused.push(ident);
+            }
+        }
+        self.used.clear();
+        self.merges.clear();
+        unused
+    }
+
     pub fn bind(&mut self, ident: Ident, val: IrValue) -> Result<(), LowerError> {
         if is_wildcard(ident) {
             Ok(())
@@ -80,15 +161,41 @@ impl ScopeTracker {
             if let Some(prev_val) = self.stack.get(&ident) {
                 Err(LowerError::AlreadyBound {
                     new: ident.span,
-                    old: prev_val.0.span,
+                    old: prev_val.ident.span,
                 })
             } else {
-                self.stack.insert(ident, (ident, val));
+                self.insert(ident, val, None);
                 Ok(())
             }
         }
     }
 
+    fn bind_merged(&mut self, ident: Ident, val: IrValue, merge: Merge) -> Result<(), LowerError> {
+        let merge_idx = self.merges.len();
+        self.merges.push(merge);
+        if let Some(prev_val) = self.stack.get(&ident) {
+            Err(LowerError::AlreadyBound {
+                new: ident.span,
+                old: prev_val.ident.span,
+            })
+        } else {
+            self.insert(ident, val, Some(merge_idx));
+            Ok(())
+        }
+    }
+
+    fn insert(&mut self, ident: Ident, value: IrValue, merge: Option<usize>) {
+        self.bound.push(ident);
+        self.stack.insert(
+            ident,
+            Binding {
+                ident,
+                value,
+                merge,
+            },
+        );
+    }
+
     pub fn bind_shadow(&mut self, ident: Ident, val: IrValue) -> Result<(), LowerError> {
         if is_wildcard(ident) {
             Ok(())
@@ -96,12 +203,12 @@ impl ScopeTracker {
             let ret = if let Some(prev_val) = self.stack.get(&ident) {
                 Err(LowerError::ShadowingBind {
                     new: ident.span,
-                    old: prev_val.0.span,
+                    old: prev_val.ident.span,
                 })
             } else {
                 Ok(())
             };
-            self.stack.insert(ident, (ident, val));
+            self.insert(ident, val, None);
             ret
         }
     }
@@ -115,7 +222,7 @@ impl ScopeTracker {
 struct Branch {
     cont_block: IrBlock,
     ret: IrValue,
-    binds: HashMap<Ident, IrValue>,
+    binds: HashMap<Ident, Binding>,
 }
 
 /// Utility for performing a scope merge, as is
@@ -124,17 +231,20 @@ struct Branch {
 /// scope if the binding is found to be present in
 /// every branch.
 pub(super) struct ScopeMerge {
+    /// Name of the construct being merged, used in diagnostics.
+    construct: &'static str,
     branches: Vec<Branch>,
 }
 
 impl ScopeMerge {
-    pub fn new() -> Self {
+    pub fn new(construct: &'static str) -> Self {
         ScopeMerge {
+            construct,
             branches: Vec::new(),
         }
     }
 
-    pub fn branch(&mut self, cont: IrBlock, ret: IrValue, binds: HashMap<Ident, IrValue>) {
+    pub fn branch(&mut self, cont: IrBlock, ret: IrValue, binds: HashMap<Ident, Binding>) {
         self.branches.push(Branch {
             cont_block: cont,
             ret,
@@ -163,7 +273,25 @@ impl ScopeMerge {
         // Insert common bindings on join block
         for var in common_vars.iter() {
             let val = b.block_arg_insert(join_block);
-            ctx.bind(*var, val);
+
+            // Reading the merged binding reads the binding in every
+            // branch.
+            let mut spans = Vec::new();
+            for branch in self.branches.iter() {
+                let binding = branch.binds[var];
+                match binding.merge {
+                    Some(merge) => spans.extend(ctx.scope.merges[merge].spans.iter().cloned()),
+                    None => spans.push(binding.ident.span),
+                }
+            }
+            let merge = Merge {
+                construct: self.construct,
+                spans,
+            };
+
+            if let Err(err) = ctx.scope.bind_merged(*var, val, merge) {
+                ctx.error(err);
+            }
         }
 
         // Create calls from all branches to join block
@@ -172,7 +300,7 @@ impl ScopeMerge {
             val_buf.clear();
             val_buf.push(result.ret);
             for var in common_vars.iter() {
-                val_buf.push(result.binds[var]);
+                val_buf.push(result.binds[var].value);
             }
             b.op_call_flow(result.cont_block, join_block, &val_buf);
         }
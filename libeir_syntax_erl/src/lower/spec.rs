@@ -0,0 +1,118 @@
+//! Lowering of `-spec` declarations into the IR type expression form.
+
+use libeir_ir::{FunctionSpec, MapFieldKind, SpecClause, TypeExpr};
+use libeir_util_number::Integer;
+
+use crate::parser::ast::{self, BinaryOp, Name, Type, TypeSpec};
+
+pub(super) fn lower_spec(spec: &TypeSpec) -> FunctionSpec {
+    FunctionSpec {
+        clauses: spec
+            .sigs
+            .iter()
+            .map(|sig| SpecClause {
+                params: lower_types(&sig.params),
+                ret: lower_type(&sig.ret),
+                constraints: sig
+                    .guards
+                    .iter()
+                    .flatten()
+                    .map(|guard| (name_str(&guard.var), lower_type(&guard.ty)))
+                    .collect(),
+            })
+            .collect(),
+    }
+}
+
+fn name_str(name: &Name) -> String {
+    name.symbol().as_str().to_string()
+}
+
+fn is_wildcard(ty: &Type) -> bool {
+    match ty {
+        Type::Name(Name::Var(var)) => var.as_str() == "_",
+        _ => false,
+    }
+}
+
+fn lower_types(types: &[Type]) -> Vec<TypeExpr> {
+    types.iter().map(lower_type).collect()
+}
+
+fn lower_box(ty: &Type) -> Box<TypeExpr> {
+    Box::new(lower_type(ty))
+}
+
+fn lower_type(ty: &Type) -> TypeExpr {
+    match ty {
+        Type::Name(Name::Var(var)) => TypeExpr::Var(var.to_string()),
+        Type::Name(Name::Atom(atom)) => TypeExpr::Atom(atom.to_string()),
+        Type::Annotated { name, ty, .. } => TypeExpr::Annotated {
+            name: name_str(name),
+            ty: lower_box(ty),
+        },
+        Type::Union { types, .. } => TypeExpr::Union(lower_types(types)),
+        Type::Range { start, end, .. } => TypeExpr::Range(lower_box(start), lower_box(end)),
+        Type::BinaryOp { lhs, op, rhs, .. } => TypeExpr::BinaryOp {
+            op: op.to_string(),
+            lhs: lower_box(lhs),
+            rhs: lower_box(rhs),
+        },
+        Type::UnaryOp { op, rhs, .. } => TypeExpr::UnaryOp {
+            op: op.to_string(),
+            operand: lower_box(rhs),
+        },
+        Type::Generic { fun, params, .. } => TypeExpr::Local {
+            name: fun.to_string(),
+            params: lower_types(params),
+        },
+        Type::Remote {
+            module, fun, args, ..
+        } => TypeExpr::Remote {
+            module: module.to_string(),
+            name: fun.to_string(),
+            params: lower_types(args),
+        },
+        Type::Nil(_) => TypeExpr::Nil,
+        Type::List(_, ty) => TypeExpr::List(lower_box(ty)),
+        Type::NonEmptyList(_, ty) => TypeExpr::NonEmptyList(lower_box(ty)),
+        Type::Map(_, entries) => TypeExpr::Map(lower_types(entries)),
+        Type::Tuple(_, elems) => TypeExpr::Tuple(lower_types(elems)),
+        Type::Record(_, name, fields) => TypeExpr::Record {
+            name: name.to_string(),
+            fields: lower_types(fields),
+        },
+        Type::Binary(_, size, unit) => {
+            // The unit in `<<_:M, _:_*N>>` is parsed as the type `_ * N`
+            let unit = match &**unit {
+                Type::BinaryOp {
+                    lhs,
+                    op: BinaryOp::Multiply,
+                    rhs,
+                    ..
+                } if is_wildcard(lhs) => &**rhs,
+                unit => unit,
+            };
+            TypeExpr::Binary(lower_box(size), lower_box(unit))
+        }
+        Type::Integer(_, Integer::Small(int)) => TypeExpr::Integer(*int),
+        Type::Integer(_, Integer::Big(int)) => TypeExpr::BigInteger(int.to_string()),
+        Type::Char(_, c) => TypeExpr::Char(*c),
+        Type::AnyFun { ret, .. } => TypeExpr::AnyFun(ret.as_ref().map(|ret| lower_box(ret))),
+        Type::Fun { params, ret, .. } => TypeExpr::Fun {
+            params: lower_types(params),
+            ret: lower_box(ret),
+        },
+        Type::KeyValuePair(_, kind, key, value) => {
+            let kind = match kind {
+                ast::MapFieldKind::Assoc => MapFieldKind::Assoc,
+                ast::MapFieldKind::Exact => MapFieldKind::Exact,
+            };
+            TypeExpr::KeyValuePair(kind, lower_box(key), lower_box(value))
+        }
+        Type::Field(_, name, ty) => TypeExpr::Field {
+            name: name.to_string(),
+            ty: lower_box(ty),
+        },
+    }
+}
@@ -56,6 +56,20 @@ pub mod escape {
             let (start, end) = self.range();
             SourceSpan::new(*start, *end)
         }
+
+        /// The diagnostic for an escape error in a sigil string, which
+        /// points out the verbatim sigil. `sigil` is `None` for a bare
+        /// `~`, which processes escapes like `~b`.
+        pub fn to_sigil_diagnostic(&self, sigil: Option<char>) -> Diagnostic {
+            let name = sigil
+                .map(|sigil| format!("~{}", sigil))
+                .unwrap_or_else(|| "~".to_string());
+            let verbatim = if sigil == Some('s') { "~S" } else { "~B" };
+            self.to_diagnostic().with_notes(vec![format!(
+                "escape sequences are processed in {} strings, use {} to take the contents verbatim",
+                name, verbatim
+            )])
+        }
     }
 
     impl ToDiagnostic for EscapeStmError<SourceIndex> {
@@ -641,8 +655,10 @@ pub fn string_to_binary(
 
 #[cfg(test)]
 mod tests {
+    use libeir_diagnostics::{SourceIndex, ToDiagnostic};
     use libeir_intern::Ident;
 
+    use super::escape::EscapeStmError;
     use super::{string_to_binary, tokenize_string_to_vec, Encoding, Endianness};
 
     #[test]
@@ -694,4 +710,23 @@ mod tests {
                 == vec![0x61, 0x62, 0x63, 0xc3, 0xa5]
         )
     }
+
+    #[test]
+    fn sigil_escape_note() {
+        let err = EscapeStmError::UnknownEscape {
+            range: (SourceIndex::UNKNOWN, SourceIndex::UNKNOWN),
+            escape_char: 'q',
+        };
+        assert!(err.to_diagnostic().notes.is_empty());
+        assert_eq!(
+            err.to_sigil_diagnostic(Some('s')).notes,
+            vec!["escape sequences are processed in ~s strings, use ~S to take the contents verbatim"]
+        );
+        assert_eq!(
+            err.to_sigil_diagnostic(None).notes,
+            vec![
+                "escape sequences are processed in ~ strings, use ~B to take the contents verbatim"
+            ]
+        );
+    }
 }
@@ -36,6 +36,19 @@ fn lower(input: &str, config: ParseConfig) -> Result<IrModule, ()> {
     res
 }
 
+/// Lowers the module, returning the messages of all diagnostics
+/// emitted while lowering.
+fn lower_messages(input: &str) -> (Result<IrModule, ()>, Vec<String>) {
+    let codemap = Arc::new(CodeMap::new());
+    let parsed: Module = parse(input, ParseConfig::default(), codemap.clone());
+
+    let mut errors = Errors::new();
+    let res = lower_module(&mut errors, codemap.clone(), &parsed);
+    let messages = errors.iter_diagnostics().map(|d| d.message).collect();
+
+    (res, messages)
+}
+
 #[test]
 fn fib_lower() {
     let _result = lower(
@@ -88,6 +101,253 @@ function_arity() -> ?FUNCTION_ARITY.
     .unwrap();
 }
 
+#[test]
+fn unused_variables() {
+    let (res, messages) = lower_messages(
+        "
+-module(test).
+-export([a/1, b/2, c/2]).
+a(X) -> Y = X, ok.
+b(_Z, W) ->
+    case W of
+        {A, B} -> A;
+        _ -> C = 1, C
+    end.
+c(A, A) -> ok.
+",
+    );
+    res.unwrap();
+    assert_eq!(
+        messages,
+        vec!["variable 'Y' is unused", "variable 'B' is unused"]
+    );
+}
+
+#[test]
+fn exported_variables() {
+    let input = "
+-module(test).
+-export([a/1]).
+a(X) ->
+    case X of
+        1 -> Y = 1;
+        _ -> Y = 2
+    end,
+    Y.
+";
+    let (res, messages) = lower_messages(input);
+    res.unwrap();
+    assert!(messages.is_empty());
+
+    let input = input.replace("-export", "-compile(warn_export_vars).\n-export");
+    let (res, messages) = lower_messages(&input);
+    res.unwrap();
+    assert_eq!(messages, vec!["variable 'Y' exported from 'case'"]);
+}
+
+#[test]
+fn unused_module_definitions() {
+    let (res, messages) = lower_messages(
+        "
+-module(test).
+-compile(warn_unused_import).
+-export([a/0]).
+-import(lists, [map/2, reverse/1]).
+-record(used, {a}).
+-record(unused, {a}).
+-record(in_type, {a}).
+-type t() :: #in_type{}.
+a() -> b([#used{}]).
+b(X) -> reverse(X).
+c() -> d().
+d() -> c().
+",
+    );
+    res.unwrap();
+    assert_eq!(
+        messages,
+        vec![
+            "function c/0 is unused",
+            "function d/0 is unused",
+            "import lists:map/2 is unused",
+            "record unused is unused",
+        ]
+    );
+}
+
+#[test]
+fn record_diagnostics() {
+    let (res, messages) = lower_messages(
+        "
+-module(test).
+-export([a/1, b/1, c/0, d/1]).
+-record(r, {a = 1 :: integer(), b = X, c = fun(Y) -> Y end}).
+a(R) -> R#r{a = 1, a = 2}.
+b(R) -> R#r.d.
+c() -> #r.e.
+d(#r{f = 1}) -> ok.
+",
+    );
+    assert!(res.is_err());
+    assert_eq!(
+        messages,
+        vec![
+            "variable 'X' in record definition",
+            "record field specified more than once",
+            "field d undefined in record r",
+            "field e undefined in record r",
+            "field f undefined in record r",
+        ]
+    );
+}
+
+#[test]
+fn record_defaults_binding_variables() {
+    let (res, messages) = lower_messages(
+        "
+-module(test).
+-export([a/1]).
+-record(r, {
+    a = [X || X <- [1, 2]],
+    b = case f() of Y -> Y end,
+    c = begin Z = f(), {Z, Z} end,
+    d = fun(F) -> F end
+}).
+a(V) -> {V, #r{}}.
+f() -> ok.
+",
+    );
+    res.unwrap();
+    assert!(messages.is_empty(), "{:?}", messages);
+
+    let (res, messages) = lower_messages(
+        "
+-module(test).
+-export([a/0]).
+-record(r, {
+    a = [X || _ <- [1, 2]],
+    b = case ok of ok -> Y; _ -> ok end,
+    c = fun() -> Z end
+}).
+a() -> #r{}.
+",
+    );
+    assert!(res.is_err());
+    assert_eq!(
+        messages,
+        vec![
+            "variable 'X' in record definition",
+            "variable 'Y' in record definition",
+            "variable 'Z' in record definition",
+        ]
+    );
+}
+
+#[test]
+fn warning_options() {
+    let (res, messages) = lower_messages(
+        "
+-module(test).
+-compile([nowarn_unused_vars, {nowarn_unused_function, [b/1]}]).
+a(X) -> ok.
+b(X) -> ok.
+",
+    );
+    res.unwrap();
+    assert_eq!(messages, vec!["function a/1 is unused"]);
+
+    let (res, messages) = lower_messages(
+        "
+-module(test).
+-compile(warnings_as_errors).
+-export([a/1]).
+a(X) -> ok.
+",
+    );
+    assert!(res.is_err());
+    assert_eq!(messages, vec!["variable 'X' is unused"]);
+}
+
+#[test]
+fn specs_lowered() {
+    let ir = lower(
+        "
+-module(test).
+-export([a/1, b/1]).
+-spec a(integer()) -> {ok, T} when T :: [atom()].
+a(X) -> b(X).
+b(X) -> X.
+-spec b(#{atom() => 1..10}) -> fun((term()) -> ok) | <<_:8, _:_*4>>.
+",
+        ParseConfig::default(),
+    )
+    .unwrap();
+
+    let spec_a = ir.spec(Symbol::intern("a"), 1).unwrap();
+    assert_eq!(spec_a.clauses.len(), 1);
+    assert_eq!(
+        spec_a.clauses[0].to_string(),
+        "(integer()) -> {'ok', T} when T :: [atom()]"
+    );
+
+    let spec_b = ir.spec(Symbol::intern("b"), 1).unwrap();
+    assert_eq!(
+        spec_b.clauses[0].to_string(),
+        "(#{atom() => 1..10}) -> fun((term()) -> 'ok') | <<_:8, _:_*4>>"
+    );
+}
+
+#[test]
+fn spec_map_field_kinds() {
+    let ir = lower(
+        "
+-module(test).
+-export([a/1]).
+-spec a(#{name := atom(), age => integer()}) -> ok.
+a(_) -> ok.
+",
+        ParseConfig::default(),
+    )
+    .unwrap();
+
+    let spec = ir.spec(Symbol::intern("a"), 1).unwrap();
+    assert_eq!(
+        spec.clauses[0].to_string(),
+        "(#{'name' := atom(), 'age' => integer()}) -> 'ok'"
+    );
+}
+
+#[test]
+fn binary_pattern_merging() {
+    let (res, messages) = lower_messages(
+        "
+-module(test).
+-export([a/1, b/1]).
+a(X) ->
+    <<A:8, _/binary>> = <<B:8, _/binary>> = X,
+    {A, B}.
+b(<<All/binary>> = <<First:8, _/binary>>) -> {All, First}.
+",
+    );
+    res.unwrap();
+    assert!(messages.is_empty());
+
+    // Known limitation: this is valid Erlang, but binary patterns whose
+    // elements do not line up can not be merged, and are rejected with
+    // an error saying so instead of being compiled.
+    let (res, messages) = lower_messages(
+        "
+-module(test).
+-export([a/1]).
+a(<<A:8, _/binary>> = <<B:16>>) -> {A, B}.
+",
+    );
+    assert!(res.is_err());
+    assert!(messages
+        .iter()
+        .any(|m| m == "unsupported merge of binary patterns"));
+}
+
 //#[test]
 //fn compiler_lower() {
 //    let mut config = ParseConfig::default();
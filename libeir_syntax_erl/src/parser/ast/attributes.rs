@@ -97,13 +97,18 @@ impl PartialEq for Callback {
 }
 
 /// Contains type information for a single clause of a function type specification
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct TypeSig {
     pub span: SourceSpan,
     pub params: Vec<Type>,
     pub ret: Box<Type>,
     pub guards: Option<Vec<TypeGuard>>,
 }
+impl PartialEq for TypeSig {
+    fn eq(&self, other: &TypeSig) -> bool {
+        self.params == other.params && self.ret == other.ret && self.guards == other.guards
+    }
+}
 
 /// Contains a single subtype constraint to be applied to a type specification
 #[derive(Debug, Clone)]
@@ -34,8 +34,10 @@ pub enum Expr {
     // Comprehensions
     ListComprehension(ListComprehension),
     BinaryComprehension(BinaryComprehension),
+    MapComprehension(MapComprehension),
     Generator(Generator),
     BinaryGenerator(BinaryGenerator),
+    MapGenerator(MapGenerator),
     // Complex expressions
     Begin(Begin),
     Apply(Apply),
@@ -49,6 +51,8 @@ pub enum Expr {
     Receive(Receive),
     Try(Try),
     Fun(Function),
+    Maybe(Maybe),
+    MaybeMatch(MaybeMatch),
 }
 impl Expr {
     pub fn span(&self) -> SourceSpan {
@@ -70,8 +74,10 @@ impl Expr {
             &Expr::RecordUpdate(RecordUpdate { ref span, .. }) => span.clone(),
             &Expr::ListComprehension(ListComprehension { ref span, .. }) => span.clone(),
             &Expr::BinaryComprehension(BinaryComprehension { ref span, .. }) => span.clone(),
+            &Expr::MapComprehension(MapComprehension { ref span, .. }) => span.clone(),
             &Expr::Generator(Generator { ref span, .. }) => span.clone(),
             &Expr::BinaryGenerator(BinaryGenerator { ref span, .. }) => span.clone(),
+            &Expr::MapGenerator(MapGenerator { ref span, .. }) => span.clone(),
             &Expr::Begin(Begin { ref span, .. }) => span.clone(),
             &Expr::Apply(Apply { ref span, .. }) => span.clone(),
             &Expr::Remote(Remote { ref span, .. }) => span.clone(),
@@ -84,6 +90,8 @@ impl Expr {
             &Expr::Receive(Receive { ref span, .. }) => span.clone(),
             &Expr::Try(Try { ref span, .. }) => span.clone(),
             &Expr::Fun(ref fun) => fun.span(),
+            &Expr::Maybe(Maybe { ref span, .. }) => span.clone(),
+            &Expr::MaybeMatch(MaybeMatch { ref span, .. }) => span.clone(),
         }
     }
     pub fn id(&self) -> NodeId {
@@ -105,8 +113,10 @@ impl Expr {
             Expr::RecordUpdate(rec) => rec.id,
             Expr::ListComprehension(compr) => compr.id,
             Expr::BinaryComprehension(compr) => compr.id,
+            Expr::MapComprehension(compr) => compr.id,
             Expr::Generator(gen) => gen.id,
             Expr::BinaryGenerator(gen) => gen.id,
+            Expr::MapGenerator(gen) => gen.id,
             Expr::Begin(begin) => begin.id,
             Expr::Apply(apply) => apply.id,
             Expr::Remote(rem) => rem.id,
@@ -119,6 +129,8 @@ impl Expr {
             Expr::Receive(rec) => rec.id,
             Expr::Try(tr) => tr.id,
             Expr::Fun(fun) => fun.id(),
+            Expr::Maybe(maybe) => maybe.id,
+            Expr::MaybeMatch(mat) => mat.id,
         }
     }
 }
@@ -511,6 +523,11 @@ impl PartialEq for ListComprehension {
 pub struct BinaryComprehension {
     pub span: SourceSpan,
     pub id: NodeId,
+    /// Ids of the `<<Body/bitstring>>` binary, element and type a body
+    /// that is not a binary is wrapped in when lowering
+    pub body_binary_id: NodeId,
+    pub body_element_id: NodeId,
+    pub body_type_id: NodeId,
     pub body: Box<Expr>,
     pub qualifiers: Vec<Expr>,
 }
@@ -520,6 +537,21 @@ impl PartialEq for BinaryComprehension {
     }
 }
 
+// A map comprehension of the form `#{K => V || Qualifiers}`
+#[derive(Debug, Clone)]
+pub struct MapComprehension {
+    pub span: SourceSpan,
+    pub id: NodeId,
+    pub key: Box<Expr>,
+    pub value: Box<Expr>,
+    pub qualifiers: Vec<Expr>,
+}
+impl PartialEq for MapComprehension {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key && self.value == other.value && self.qualifiers == other.qualifiers
+    }
+}
+
 // A generator of the form `LHS <- RHS`
 #[derive(Debug, Clone)]
 pub struct Generator {
@@ -539,6 +571,16 @@ impl PartialEq for Generator {
 pub struct BinaryGenerator {
     pub span: SourceSpan,
     pub id: NodeId,
+    /// Ids of the two binary patterns the generator is matched with
+    /// when lowering, one for the elements taken and one for those
+    /// skipped
+    pub pattern_id: NodeId,
+    pub skip_pattern_id: NodeId,
+    /// Ids of the `Rest/bitstring` element both patterns end with, and
+    /// of its variable and type
+    pub rest_id: NodeId,
+    pub rest_var_id: NodeId,
+    pub rest_type_id: NodeId,
     pub pattern: Box<Expr>,
     pub expr: Box<Expr>,
 }
@@ -548,6 +590,24 @@ impl PartialEq for BinaryGenerator {
     }
 }
 
+// A generator of the form `K := V <- RHS`
+#[derive(Debug, Clone)]
+pub struct MapGenerator {
+    pub span: SourceSpan,
+    pub id: NodeId,
+    /// Id of the `{K, V}` tuple pattern the key and value are matched
+    /// as when lowering
+    pub pattern_id: NodeId,
+    pub key: Box<Expr>,
+    pub value: Box<Expr>,
+    pub expr: Box<Expr>,
+}
+impl PartialEq for MapGenerator {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key && self.value == other.value && self.expr == other.expr
+    }
+}
+
 // A sequence of expressions, e.g. begin expr1, .., exprN end
 #[derive(Debug, Clone)]
 pub struct Begin {
@@ -711,6 +771,35 @@ impl PartialEq for Try {
     }
 }
 
+/// `maybe ... else ... end`, only available with the `maybe_expr`
+/// feature enabled
+#[derive(Debug, Clone)]
+pub struct Maybe {
+    pub span: SourceSpan,
+    pub id: NodeId,
+    pub body: Vec<Expr>,
+    pub else_clauses: Option<Vec<Clause>>,
+}
+impl PartialEq for Maybe {
+    fn eq(&self, other: &Self) -> bool {
+        self.body == other.body && self.else_clauses == other.else_clauses
+    }
+}
+
+/// `Pattern ?= Expr`, only valid at the top level of a `maybe` body
+#[derive(Debug, Clone)]
+pub struct MaybeMatch {
+    pub span: SourceSpan,
+    pub id: NodeId,
+    pub pattern: Box<Expr>,
+    pub expr: Box<Expr>,
+}
+impl PartialEq for MaybeMatch {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern && self.expr == other.expr
+    }
+}
+
 /// Represents a single `catch` clause in a `try` expression
 #[derive(Debug, Clone)]
 pub struct TryClause {
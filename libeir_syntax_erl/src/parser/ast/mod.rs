@@ -4,6 +4,8 @@ mod functions;
 mod module;
 mod types;
 
+use std::fmt;
+
 use libeir_diagnostics::{SourceIndex, SourceSpan};
 
 pub use self::attributes::*;
@@ -120,6 +122,40 @@ pub enum BinaryOp {
     Band,
     And,
 }
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self {
+            BinaryOp::Send => "!",
+            BinaryOp::OrElse => "orelse",
+            BinaryOp::AndAlso => "andalso",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "/=",
+            BinaryOp::Lte => "=<",
+            BinaryOp::Lt => "<",
+            BinaryOp::Gte => ">=",
+            BinaryOp::Gt => ">",
+            BinaryOp::StrictEqual => "=:=",
+            BinaryOp::StrictNotEqual => "=/=",
+            BinaryOp::Append => "++",
+            BinaryOp::Remove => "--",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Bor => "bor",
+            BinaryOp::Bxor => "bxor",
+            BinaryOp::Bsl => "bsl",
+            BinaryOp::Bsr => "bsr",
+            BinaryOp::Or => "or",
+            BinaryOp::Xor => "xor",
+            BinaryOp::Divide => "/",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Div => "div",
+            BinaryOp::Rem => "rem",
+            BinaryOp::Band => "band",
+            BinaryOp::And => "and",
+        };
+        f.write_str(op)
+    }
+}
 
 /// The set of all unary (prefix) operators which may be used in expressions
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -130,3 +166,14 @@ pub enum UnaryOp {
     Bnot,
     Not,
 }
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self {
+            UnaryOp::Plus => "+",
+            UnaryOp::Minus => "-",
+            UnaryOp::Bnot => "bnot",
+            UnaryOp::Not => "not",
+        };
+        f.write_str(op)
+    }
+}
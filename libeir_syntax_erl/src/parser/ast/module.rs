@@ -57,10 +57,13 @@ pub struct Module {
     pub compile: Option<CompileOptions>,
     pub on_load: Option<LocalFunctionName>,
     pub imports: HashMap<LocalFunctionName, ResolvedFunctionName>,
+    // The subset of `imports` that was implicitly imported from `erlang`
+    pub auto_imports: HashSet<LocalFunctionName>,
     pub exports: HashSet<LocalFunctionName>,
     pub removed: HashMap<LocalFunctionName, (SourceSpan, Ident)>,
     pub types: HashMap<LocalFunctionName, TypeDef>,
     pub exported_types: HashSet<LocalFunctionName>,
+    pub specs: HashMap<LocalFunctionName, TypeSpec>,
     pub behaviours: HashSet<Ident>,
     pub callbacks: HashMap<LocalFunctionName, Callback>,
     pub records: HashMap<Symbol, DefinedRecord>,
@@ -70,6 +73,9 @@ pub struct Module {
     pub deprecation: Option<Deprecation>,
     // Used for function-level deprecation
     pub deprecations: HashSet<Deprecation>,
+    // Continues the node ids of the parsed module, for nodes added to it
+    // afterwards, e.g. by parse transforms
+    pub nid: NodeIdGenerator,
 }
 impl Module {
     /// Called by the parser to create the module once all of the top-level expressions have been
@@ -101,10 +107,12 @@ impl Module {
             on_load: None,
             compile: None,
             imports: HashMap::new(),
+            auto_imports: HashSet::new(),
             exports: HashSet::new(),
             removed: HashMap::new(),
             types: HashMap::new(),
             exported_types: HashSet::new(),
+            specs: HashMap::new(),
             behaviours: HashSet::new(),
             callbacks: HashMap::new(),
             records: HashMap::new(),
@@ -112,14 +120,20 @@ impl Module {
             functions: BTreeMap::new(),
             deprecation: None,
             deprecations: HashSet::new(),
+            nid: NodeIdGenerator::new(),
         };
 
         // Functions will be decorated with their type specs as they are added
         // to the module. To accomplish this, we keep track of seen type specs
         // as they are defined, then later look up the spec for a function when
-        // a definition is encountered
+        // a definition is encountered. The specs are kept on the module once
+        // every top-level expression has been seen
         let mut specs: HashMap<ResolvedFunctionName, TypeSpec> = HashMap::new();
 
+        // Callbacks may be declared after `-optional_callbacks`, so the
+        // attribute is applied once every callback has been seen
+        let mut optional_callbacks: Vec<UserAttribute> = Vec::new();
+
         // Walk every top-level expression and extend our initial module definition accordingly
         for item in body.drain(..) {
             match item {
@@ -567,7 +581,7 @@ impl Module {
                             continue;
                         }
                         "optional_callbacks" => {
-                            //if let Some(callback) = module.callbacks.get_mut()
+                            optional_callbacks.push(attr);
                             continue;
                         }
                         "dialyzer" => {
@@ -711,6 +725,29 @@ impl Module {
             }
         }
 
+        for attr in optional_callbacks.iter() {
+            for entry in to_list_simple(&attr.value) {
+                let callback = match &entry {
+                    Expr::FunctionName(FunctionName::PartiallyResolved(name)) => {
+                        module.callbacks.get_mut(&name.to_local())
+                    }
+                    _ => None,
+                };
+                match callback {
+                    Some(callback) => callback.optional = true,
+                    None => {
+                        let span = entry.span();
+                        errs.warning(ParserError::ShowDiagnostic {
+                            diagnostic: Diagnostic::warning()
+                                .with_message("invalid optional callback")
+                                .with_labels(vec![Label::primary(span.source_id(), span)
+                                    .with_message("expected the name/arity of a callback")]),
+                        });
+                    }
+                }
+            }
+        }
+
         // Ensure internal pseudo-locals are defined
         module.define_pseudolocals(nid);
 
@@ -732,9 +769,22 @@ impl Module {
             }
         }
 
+        module.specs = specs
+            .drain()
+            .map(|(name, spec)| (name.to_local(), spec))
+            .collect();
+
+        // Decorate functions with specs that were declared after the
+        // function itself
+        for (name, function) in module.functions.iter_mut() {
+            if function.spec.is_none() {
+                function.spec = module.specs.get(name).cloned();
+            }
+        }
+
         // Check for orphaned type specs
-        for (spec_name, spec) in &specs {
-            if !module.functions.contains_key(&spec_name.to_local()) {
+        for (spec_name, spec) in &module.specs {
+            if !module.functions.contains_key(spec_name) {
                 errs.warning(ParserError::ShowDiagnostic {
                     diagnostic: Diagnostic::warning()
                         .with_message("type spec for undefined function")
@@ -747,6 +797,7 @@ impl Module {
             }
         }
 
+        module.nid = nid.clone();
         module
     }
 
@@ -936,11 +987,13 @@ impl Module {
             for fun in autos.iter() {
                 if !compile.no_auto_imports.contains(fun) {
                     self.imports.insert(fun.to_local(), fun.clone());
+                    self.auto_imports.insert(fun.to_local());
                 }
             }
         } else {
             for fun in autos.iter() {
                 self.imports.insert(fun.to_local(), fun.clone());
+                self.auto_imports.insert(fun.to_local());
             }
         }
     }
@@ -1033,6 +1086,9 @@ impl PartialEq for Module {
         if self.types != other.types {
             return false;
         }
+        if self.specs != other.specs {
+            return false;
+        }
         if self.exported_types != other.exported_types {
             return false;
         }
@@ -1092,10 +1148,16 @@ pub struct CompileOptions {
     pub warn_unused_var: bool,
     // Warns about unused records
     pub warn_unused_record: bool,
-    // Warns about missing type specs
+    // Warns about missing type specs on exported functions
     pub warn_missing_spec: bool,
+    // Warns about missing or misdefined behaviour callbacks
+    pub warn_behaviours: bool,
+    // Warns about calls to deprecated functions
+    pub warn_deprecated_function: bool,
     // Inlines the given functions
     pub inline_functions: HashSet<ResolvedFunctionName>,
+    // Parse transforms to apply to the module, in the order given
+    pub parse_transforms: Vec<Ident>,
 }
 impl Default for CompileOptions {
     fn default() -> Self {
@@ -1108,15 +1170,18 @@ impl Default for CompileOptions {
             no_auto_import: false,
             no_auto_imports: HashSet::new(),
             warn_export_all: true,
-            warn_export_vars: true,
+            warn_export_vars: false,
             warn_shadow_vars: true,
             warn_unused_function: true,
             no_warn_unused_functions: HashSet::new(),
-            warn_unused_import: true,
+            warn_unused_import: false,
             warn_unused_var: true,
             warn_unused_record: true,
             warn_missing_spec: false,
+            warn_behaviours: true,
+            warn_deprecated_function: true,
             inline_functions: HashSet::new(),
+            parse_transforms: Vec::new(),
         }
     }
 }
@@ -1141,10 +1206,25 @@ impl CompileOptions {
                 match option_name.as_str().get() {
                     "no_native" => (), // Disables hipe compilation, not relevant for us
                     "export_all" => self.export_all = true,
+                    "warnings_as_errors" => self.warnings_as_errors = true,
                     "nowarn_export_all" => self.warn_export_all = false,
+                    "warn_export_vars" => self.warn_export_vars = true,
+                    "nowarn_export_vars" => self.warn_export_vars = false,
                     "nowarn_shadow_vars" => self.warn_shadow_vars = false,
+                    "warn_unused_function" => self.warn_unused_function = true,
                     "nowarn_unused_function" => self.warn_unused_function = false,
+                    "warn_unused_import" => self.warn_unused_import = true,
+                    "nowarn_unused_import" => self.warn_unused_import = false,
+                    "warn_unused_vars" => self.warn_unused_var = true,
                     "nowarn_unused_vars" => self.warn_unused_var = false,
+                    "warn_unused_record" => self.warn_unused_record = true,
+                    "nowarn_unused_record" => self.warn_unused_record = false,
+                    "warn_missing_spec" => self.warn_missing_spec = true,
+                    "nowarn_missing_spec" => self.warn_missing_spec = false,
+                    "warn_behaviours" => self.warn_behaviours = true,
+                    "nowarn_behaviours" => self.warn_behaviours = false,
+                    "warn_deprecated_function" => self.warn_deprecated_function = true,
+                    "nowarn_deprecated_function" => self.warn_deprecated_function = false,
                     "no_auto_import" => self.no_auto_import = true,
                     "inline_list_funcs" => {
                         let funs = [
@@ -1203,6 +1283,22 @@ impl CompileOptions {
                         "inline" => {
                             self.inline_functions(&mut diagnostics, module, &list);
                         }
+                        "parse_transform" => match &elements[1] {
+                            Expr::Literal(Literal::Atom(_id, transform)) => {
+                                self.parse_transforms.push(*transform);
+                            }
+                            other => {
+                                let span = other.span();
+                                diagnostics.push(
+                                    Diagnostic::warning()
+                                        .with_message("invalid compile option")
+                                        .with_labels(vec![Label::primary(span.source_id(), span)
+                                            .with_message(
+                                                "expected the module name of a parse transform",
+                                            )]),
+                                );
+                            }
+                        },
                         "hipe" => {
                             // Should we warn about this? I'm inclined to think
                             // not, since we want to ignore warning spam.
@@ -115,9 +115,17 @@ pub enum Type {
         params: Vec<Type>,
         ret: Box<Type>,
     },
-    KeyValuePair(SourceSpan, Box<Type>, Box<Type>),
+    KeyValuePair(SourceSpan, MapFieldKind, Box<Type>, Box<Type>),
     Field(SourceSpan, Ident, Box<Type>),
 }
+
+/// Whether a field of a map type is optional, `K => V`, or mandatory,
+/// `K := V`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MapFieldKind {
+    Assoc,
+    Exact,
+}
 impl Type {
     pub fn union(span: SourceSpan, lhs: Type, rhs: Type) -> Self {
         let mut types = match lhs {
@@ -155,7 +163,7 @@ impl Type {
             &Type::Char(_, _) => true,
             &Type::AnyFun { .. } => true,
             &Type::Fun { .. } => true,
-            &Type::KeyValuePair(_, _, _) => true,
+            &Type::KeyValuePair(_, _, _, _) => true,
             &Type::Field(_, _, _) => true,
             _ => false,
         }
@@ -280,9 +288,10 @@ impl PartialEq for Type {
                     ..
                 },
             ) => (x1 == y1) && (x2 == y2),
-            (Type::KeyValuePair(_, ref x1, ref x2), Type::KeyValuePair(_, ref y1, ref y2)) => {
-                (x1 == y1) && (x2 == y2)
-            }
+            (
+                Type::KeyValuePair(_, ref k1, ref x1, ref x2),
+                Type::KeyValuePair(_, ref k2, ref y1, ref y2),
+            ) => (k1 == k2) && (x1 == y1) && (x2 == y2),
             (Type::Field(_, ref x1, ref x2), Type::Field(_, ref y1, ref y2)) => {
                 (x1 == y1) && (x2 == y2)
             }
@@ -0,0 +1,342 @@
+//! Checks that a module implements the callbacks of the behaviours it
+//! declares.
+//!
+//! The callbacks of a behaviour are read from the `-callback`
+//! declarations in its source, which is searched for on the code
+//! paths as either `<path>/<behaviour>.erl` or
+//! `<path>/<app>/src/<behaviour>.erl`. If no source is found, the
+//! callbacks of the common OTP behaviours are taken from a built-in
+//! table.
+
+use std::path::PathBuf;
+
+use libeir_diagnostics::{Diagnostic, Label, SourceSpan};
+use libeir_util_parse::{ErrorReceiver, Errors};
+
+use super::ast::{CompileOptions, Ident, LocalFunctionName, Module};
+use super::{ParseConfig, Parser, ParserError};
+
+struct BehaviourCallback {
+    name: String,
+    arity: usize,
+    optional: bool,
+}
+
+/// `(behaviour, [(callback, arity, optional)])`
+const BUILTIN_BEHAVIOURS: &[(&str, &[(&str, usize, bool)])] = &[
+    (
+        "gen_server",
+        &[
+            ("init", 1, false),
+            ("handle_call", 3, false),
+            ("handle_cast", 2, false),
+            ("handle_info", 2, true),
+            ("handle_continue", 2, true),
+            ("terminate", 2, true),
+            ("code_change", 3, true),
+            ("format_status", 1, true),
+            ("format_status", 2, true),
+        ],
+    ),
+    (
+        "gen_statem",
+        &[
+            ("init", 1, false),
+            ("callback_mode", 0, false),
+            ("handle_event", 4, true),
+            ("terminate", 3, true),
+            ("code_change", 4, true),
+            ("format_status", 1, true),
+            ("format_status", 2, true),
+        ],
+    ),
+    ("supervisor", &[("init", 1, false)]),
+    (
+        "application",
+        &[
+            ("start", 2, false),
+            ("stop", 1, false),
+            ("prep_stop", 1, true),
+            ("start_phase", 3, true),
+            ("config_change", 3, true),
+        ],
+    ),
+];
+
+/// Warns about required behaviour callbacks that are not exported by
+/// the module, and about callbacks that are exported with an arity the
+/// behaviour does not define.
+pub fn check_behaviours(
+    parser: &Parser,
+    errs: &mut dyn ErrorReceiver<E = ParserError, W = ParserError>,
+    module: &Module,
+) {
+    let default_options = CompileOptions::default();
+    let options = module.compile.as_ref().unwrap_or(&default_options);
+    if options.no_warn || !options.warn_behaviours {
+        return;
+    }
+
+    let exported: Vec<LocalFunctionName> = if options.export_all {
+        module.functions.keys().cloned().collect()
+    } else {
+        module.exports.iter().cloned().collect()
+    };
+
+    let mut behaviours: Vec<&Ident> = module.behaviours.iter().collect();
+    behaviours.sort_by_key(|behaviour| behaviour.span);
+
+    for behaviour in behaviours {
+        let callbacks = match resolve_behaviour(parser, behaviour) {
+            Some(callbacks) => callbacks,
+            None => {
+                emit(
+                    errs,
+                    options,
+                    behaviour.span,
+                    format!("behaviour {} undefined", behaviour),
+                    None,
+                );
+                continue;
+            }
+        };
+
+        for callback in callbacks.iter() {
+            let is_exported = |arity: usize| {
+                exported
+                    .iter()
+                    .any(|e| e.function.as_str() == callback.name.as_str() && e.arity == arity)
+            };
+            if is_exported(callback.arity) {
+                continue;
+            }
+
+            // An export of the same name with an arity that no callback
+            // of the behaviour has is most likely meant to be this one.
+            let mut wrong_arity: Vec<&LocalFunctionName> = exported
+                .iter()
+                .filter(|e| e.function.as_str() == callback.name.as_str())
+                .filter(|e| {
+                    !callbacks
+                        .iter()
+                        .any(|c| c.name == callback.name && c.arity == e.arity)
+                })
+                .collect();
+            wrong_arity.sort_by_key(|e| e.arity);
+
+            if let Some(found) = wrong_arity.first() {
+                let span = module
+                    .functions
+                    .get(*found)
+                    .map(|fun| fun.span)
+                    .unwrap_or(behaviour.span);
+                emit(
+                    errs,
+                    options,
+                    span,
+                    format!(
+                        "callback function {}/{} has the wrong arity (behaviour '{}' expects {}/{})",
+                        callback.name, found.arity, behaviour, callback.name, callback.arity
+                    ),
+                    Some(behaviour.span),
+                );
+            } else if !callback.optional {
+                emit(
+                    errs,
+                    options,
+                    behaviour.span,
+                    format!(
+                        "undefined callback function {}/{} (behaviour '{}')",
+                        callback.name, callback.arity, behaviour
+                    ),
+                    None,
+                );
+            }
+        }
+    }
+}
+
+fn emit(
+    errs: &mut dyn ErrorReceiver<E = ParserError, W = ParserError>,
+    options: &CompileOptions,
+    span: SourceSpan,
+    message: String,
+    behaviour: Option<SourceSpan>,
+) {
+    let diagnostic = if options.warnings_as_errors {
+        Diagnostic::error()
+    } else {
+        Diagnostic::warning()
+    };
+    let mut labels = vec![Label::primary(span.source_id(), span)];
+    if let Some(behaviour) = behaviour {
+        labels.push(
+            Label::secondary(behaviour.source_id(), behaviour)
+                .with_message("behaviour declared here"),
+        );
+    }
+    let err = ParserError::ShowDiagnostic {
+        diagnostic: diagnostic.with_message(message).with_labels(labels),
+    };
+    if options.warnings_as_errors {
+        errs.error(err);
+    } else {
+        errs.warning(err);
+    }
+}
+
+fn resolve_behaviour(parser: &Parser, behaviour: &Ident) -> Option<Vec<BehaviourCallback>> {
+    let name = behaviour.as_str();
+    if let Some(path) = find_source(&parser.config, &name) {
+        // Problems in the behaviour module are reported when it is
+        // compiled itself, not as part of this module.
+        let mut errors: Errors<ParserError, ParserError> = Errors::new();
+        if let Ok(source) = parser.parse_file::<Module, _>(&mut errors, &path) {
+            let mut callbacks: Vec<BehaviourCallback> = source
+                .callbacks
+                .iter()
+                .map(|(name, callback)| BehaviourCallback {
+                    name: name.function.to_string(),
+                    arity: name.arity,
+                    optional: callback.optional,
+                })
+                .collect();
+            callbacks.sort_by(|a, b| (&a.name, a.arity).cmp(&(&b.name, b.arity)));
+            return Some(callbacks);
+        }
+    }
+
+    BUILTIN_BEHAVIOURS
+        .iter()
+        .find(|(builtin, _)| *builtin == &*name)
+        .map(|(_, callbacks)| {
+            callbacks
+                .iter()
+                .map(|(name, arity, optional)| BehaviourCallback {
+                    name: name.to_string(),
+                    arity: *arity,
+                    optional: *optional,
+                })
+                .collect()
+        })
+}
+
+fn find_source(config: &ParseConfig, name: &str) -> Option<PathBuf> {
+    let file_name = format!("{}.erl", name);
+    for root in config.code_paths.iter() {
+        let path = root.join(&file_name);
+        config.lookups.record(&path);
+        if path.is_file() {
+            return Some(path);
+        }
+        // The listing of the root decides which applications are
+        // searched, so the root itself is a lookup as well.
+        config.lookups.record(root);
+        if let Ok(entries) = std::fs::read_dir(root) {
+            let mut apps: Vec<PathBuf> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+            apps.sort();
+            for app in apps {
+                let path = app.join("src").join(&file_name);
+                config.lookups.record(&path);
+                if path.is_file() {
+                    return Some(path);
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use libeir_diagnostics::CodeMap;
+    use libeir_util_parse::Errors;
+
+    use super::check_behaviours;
+    use crate::parser::ast::Module;
+    use crate::parser::{ParseConfig, Parser, ParserError};
+
+    fn check(config: ParseConfig, input: &str) -> Vec<String> {
+        let parser = Parser::new(config, Arc::new(CodeMap::new()));
+        let mut errors: Errors<ParserError, ParserError> = Errors::new();
+        let module = parser
+            .parse_string::<Module, _>(&mut errors, input)
+            .unwrap();
+
+        let mut errors: Errors<ParserError, ParserError> = Errors::new();
+        check_behaviours(&parser, &mut errors, &module);
+        errors.iter_diagnostics().map(|d| d.message).collect()
+    }
+
+    #[test]
+    fn builtin_behaviour() {
+        let messages = check(
+            ParseConfig::default(),
+            "-module(foo).
+-behaviour(gen_server).
+-export([init/1, handle_call/3, handle_cast/3, handle_info/2]).
+init(_) -> ok.
+handle_call(_, _, S) -> S.
+handle_cast(_, _, S) -> S.
+handle_info(_, S) -> S.
+",
+        );
+        assert_eq!(
+            messages,
+            vec!["callback function handle_cast/3 has the wrong arity \
+                 (behaviour 'gen_server' expects handle_cast/2)"
+                .to_string()]
+        );
+
+        let messages = check(
+            ParseConfig::default(),
+            "-module(foo).
+-behaviour(supervisor).
+-behaviour(no_such_behaviour).
+",
+        );
+        assert_eq!(
+            messages,
+            vec![
+                "undefined callback function init/1 (behaviour 'supervisor')".to_string(),
+                "behaviour no_such_behaviour undefined".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn behaviour_from_source() {
+        let dir = std::env::temp_dir().join(format!("eir_behaviours_{}", std::process::id()));
+        let src = dir.join("my_app").join("src");
+        std::fs::create_dir_all(&src).unwrap();
+        std::fs::write(
+            src.join("my_behaviour.erl"),
+            "-module(my_behaviour).
+-callback run(term()) -> ok.
+-callback stop() -> ok.
+-callback info(term(), term()) -> ok.
+-optional_callbacks([info/2]).
+",
+        )
+        .unwrap();
+
+        let mut config = ParseConfig::default();
+        config.code_paths.push_back(dir.clone());
+        let messages = check(
+            config,
+            "-module(foo).
+-behaviour(my_behaviour).
+-export([run/1]).
+run(_) -> ok.
+",
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            messages,
+            vec!["undefined callback function stop/0 (behaviour 'my_behaviour')".to_string()]
+        );
+    }
+}
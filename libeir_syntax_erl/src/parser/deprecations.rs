@@ -0,0 +1,561 @@
+//! Warns about calls to deprecated functions, and reports calls to
+//! removed functions as errors.
+//!
+//! Local calls are checked against the `-deprecated` and `-removed`
+//! attributes of the module itself. Remote calls, and calls to imported
+//! functions, are checked against the `DeprecatedFunctions` of the
+//! `ParseConfig`, which by default contains a selection of the functions
+//! OTP lists as deprecated or removed in `otp_internal`.
+//!
+//! That selection is not the full `otp_internal:obsolete/3` table. It
+//! covers the commonly called functions of `erlang`, `calendar`,
+//! `filename`, `gen_fsm`, `slave`, `code`, `crypto`, `erl_lint`,
+//! `http_uri`, `pg2`, `random` and `ssl`. Everything else in the table,
+//! the other deprecated functions of those modules included, is not
+//! reported unless added to the database with
+//! `DeprecatedFunctions::deprecate` or `DeprecatedFunctions::remove`.
+
+use std::collections::BTreeMap;
+
+use libeir_diagnostics::{Diagnostic, Label, SourceSpan};
+use libeir_util_parse::ErrorReceiver;
+
+use super::ast::{
+    CompileOptions, DeprecatedFlag, Deprecation, Expr, FunctionName, Ident, Literal,
+    LocalFunctionName, Module, Remote,
+};
+use super::{visit_module_exprs_ref, Parser, ParserError};
+
+/// How a function in `DeprecatedFunctions` is to be reported.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DeprecatedFunction {
+    /// Calls are warned about, with the given advice.
+    Deprecated(String),
+    /// Calls are errors, with the given advice.
+    Removed(String),
+}
+
+/// A database of the deprecated and removed functions of other modules.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DeprecatedFunctions {
+    /// Keyed by module, function and arity, where an arity of `None`
+    /// covers all arities of the function.
+    functions: BTreeMap<(String, String, Option<usize>), DeprecatedFunction>,
+}
+impl DeprecatedFunctions {
+    /// Creates a database without any functions.
+    pub fn new() -> Self {
+        DeprecatedFunctions {
+            functions: BTreeMap::new(),
+        }
+    }
+
+    /// Creates a database containing the commonly used functions that
+    /// OTP deprecated or removed. This is a subset of
+    /// `otp_internal:obsolete/3`, see the module documentation.
+    pub fn otp() -> Self {
+        let mut db = Self::new();
+        for (module, function, arity, advice) in OTP_DEPRECATED.iter() {
+            db.deprecate(*module, *function, *arity, *advice);
+        }
+        for (module, function, arity, advice) in OTP_REMOVED.iter() {
+            db.remove(*module, *function, *arity, *advice);
+        }
+        db
+    }
+
+    /// Marks `module:function/arity` as deprecated, or all arities of
+    /// the function if `arity` is `None`.
+    pub fn deprecate<M, F, A>(&mut self, module: M, function: F, arity: Option<usize>, advice: A)
+    where
+        M: Into<String>,
+        F: Into<String>,
+        A: Into<String>,
+    {
+        self.functions.insert(
+            (module.into(), function.into(), arity),
+            DeprecatedFunction::Deprecated(advice.into()),
+        );
+    }
+
+    /// Marks `module:function/arity` as removed, or all arities of the
+    /// function if `arity` is `None`.
+    pub fn remove<M, F, A>(&mut self, module: M, function: F, arity: Option<usize>, advice: A)
+    where
+        M: Into<String>,
+        F: Into<String>,
+        A: Into<String>,
+    {
+        self.functions.insert(
+            (module.into(), function.into(), arity),
+            DeprecatedFunction::Removed(advice.into()),
+        );
+    }
+
+    /// Looks up `module:function/arity`, falling back to an entry for
+    /// all arities of the function.
+    pub fn get(&self, module: &str, function: &str, arity: usize) -> Option<&DeprecatedFunction> {
+        let mut key = (module.to_string(), function.to_string(), Some(arity));
+        if let Some(found) = self.functions.get(&key) {
+            return Some(found);
+        }
+        key.2 = None;
+        self.functions.get(&key)
+    }
+}
+impl Default for DeprecatedFunctions {
+    fn default() -> Self {
+        Self::otp()
+    }
+}
+
+/// `(module, function, arity, advice)`, from `otp_internal:obsolete/3`.
+const OTP_DEPRECATED: &[(&str, &str, Option<usize>, &str)] = &[
+    (
+        "calendar",
+        "local_time_to_universal_time",
+        Some(1),
+        "use calendar:local_time_to_universal_time_dst/1 instead",
+    ),
+    (
+        "erlang",
+        "now",
+        Some(0),
+        "see the \"Time and Time Correction in Erlang\" chapter of the ERTS User's Guide \
+         for more information",
+    ),
+    ("erlang", "phash", Some(2), "use erlang:phash2/2 instead"),
+    (
+        "filename",
+        "find_src",
+        None,
+        "use filelib:find_source/1,3 instead",
+    ),
+    (
+        "gen_fsm",
+        "start",
+        None,
+        "use the 'gen_statem' module instead",
+    ),
+    (
+        "gen_fsm",
+        "start_link",
+        None,
+        "use the 'gen_statem' module instead",
+    ),
+    (
+        "gen_fsm",
+        "send_event",
+        Some(2),
+        "use the 'gen_statem' module instead",
+    ),
+    (
+        "gen_fsm",
+        "sync_send_event",
+        None,
+        "use the 'gen_statem' module instead",
+    ),
+    (
+        "gen_fsm",
+        "send_all_state_event",
+        Some(2),
+        "use the 'gen_statem' module instead",
+    ),
+    (
+        "gen_fsm",
+        "sync_send_all_state_event",
+        None,
+        "use the 'gen_statem' module instead",
+    ),
+    (
+        "gen_fsm",
+        "reply",
+        Some(2),
+        "use the 'gen_statem' module instead",
+    ),
+    (
+        "gen_fsm",
+        "enter_loop",
+        None,
+        "use the 'gen_statem' module instead",
+    ),
+    ("slave", "start", None, "use the 'peer' module instead"),
+    ("slave", "start_link", None, "use the 'peer' module instead"),
+    ("slave", "stop", Some(1), "use the 'peer' module instead"),
+];
+
+/// `(module, function, arity, advice)`, from `otp_internal:obsolete/3`.
+const OTP_REMOVED: &[(&str, &str, Option<usize>, &str)] = &[
+    ("code", "is_module_native", Some(1), "HiPE has been removed"),
+    (
+        "code",
+        "rehash",
+        Some(0),
+        "the code path cache feature has been removed",
+    ),
+    (
+        "crypto",
+        "block_decrypt",
+        None,
+        "use crypto:crypto_one_time/4 or crypto:crypto_init/3 + crypto:crypto_update/2 + \
+         crypto:crypto_final/1 instead",
+    ),
+    (
+        "crypto",
+        "block_encrypt",
+        None,
+        "use crypto:crypto_one_time/4 or crypto:crypto_init/3 + crypto:crypto_update/2 + \
+         crypto:crypto_final/1 instead",
+    ),
+    ("crypto", "hmac", None, "use crypto:mac/4 instead"),
+    (
+        "crypto",
+        "rand_bytes",
+        Some(1),
+        "use crypto:strong_rand_bytes/1 instead",
+    ),
+    (
+        "crypto",
+        "rand_uniform",
+        Some(2),
+        "use rand:uniform/1 instead",
+    ),
+    (
+        "erl_lint",
+        "modify_line",
+        Some(2),
+        "use erl_parse:map_anno/2 instead",
+    ),
+    (
+        "erlang",
+        "get_stacktrace",
+        Some(0),
+        "use the new try/catch syntax for retrieving the stack backtrace",
+    ),
+    ("erlang", "hash", Some(2), "use erlang:phash2/2 instead"),
+    (
+        "http_uri",
+        "parse",
+        None,
+        "use uri_string functions instead",
+    ),
+    (
+        "pg2",
+        "create",
+        Some(1),
+        "this module was removed in OTP 24. Use 'pg' instead",
+    ),
+    (
+        "pg2",
+        "delete",
+        Some(1),
+        "this module was removed in OTP 24. Use 'pg' instead",
+    ),
+    (
+        "pg2",
+        "get_members",
+        Some(1),
+        "this module was removed in OTP 24. Use 'pg' instead",
+    ),
+    (
+        "pg2",
+        "join",
+        Some(2),
+        "this module was removed in OTP 24. Use 'pg' instead",
+    ),
+    (
+        "pg2",
+        "leave",
+        Some(2),
+        "this module was removed in OTP 24. Use 'pg' instead",
+    ),
+    ("random", "seed", None, "use the 'rand' module instead"),
+    ("random", "seed0", Some(0), "use the 'rand' module instead"),
+    ("random", "uniform", None, "use the 'rand' module instead"),
+    ("random", "uniform_s", None, "use the 'rand' module instead"),
+    ("ssl", "ssl_accept", None, "use ssl:handshake/1,2,3 instead"),
+];
+
+struct Call {
+    span: SourceSpan,
+    /// `None` for local calls
+    module: Option<Ident>,
+    function: Ident,
+    arity: usize,
+}
+
+/// Warns about calls to deprecated functions and reports calls to
+/// removed functions, including implicit calls through `fun F/A`.
+pub fn check_deprecations(
+    parser: &Parser,
+    errs: &mut dyn ErrorReceiver<E = ParserError, W = ParserError>,
+    module: &Module,
+) {
+    let default_options = CompileOptions::default();
+    let options = module.compile.as_ref().unwrap_or(&default_options);
+
+    let mut calls = Vec::new();
+    visit_module_exprs_ref(module, &mut |expr| collect_call(expr, &mut calls));
+    calls.sort_by_key(|call| call.span);
+
+    let db = &parser.config.deprecated_functions;
+    for call in calls {
+        let local = LocalFunctionName {
+            span: call.span,
+            function: call.function,
+            arity: call.arity,
+        };
+        match call.module {
+            Some(remote) if remote != module.name => {
+                check_remote(errs, options, db, &call, remote);
+            }
+            _ => {
+                if let Some((_, advice)) = module.removed.get(&local) {
+                    removed(
+                        errs,
+                        call.span,
+                        format!("{}/{} is removed; {}", call.function, call.arity, advice),
+                    );
+                } else if module.functions.contains_key(&local) {
+                    check_local(errs, options, module, &call);
+                } else if call.module.is_none() {
+                    if let Some(resolved) = module.imports.get(&local) {
+                        check_remote(errs, options, db, &call, resolved.module);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn collect_call(expr: &Expr, calls: &mut Vec<Call>) {
+    match expr {
+        Expr::Apply(apply) => match &*apply.callee {
+            Expr::Remote(Remote {
+                span,
+                module,
+                function,
+                ..
+            }) => match (&**module, &**function) {
+                (
+                    Expr::Literal(Literal::Atom(_, module)),
+                    Expr::Literal(Literal::Atom(_, function)),
+                ) => calls.push(Call {
+                    span: *span,
+                    module: Some(*module),
+                    function: *function,
+                    arity: apply.args.len(),
+                }),
+                _ => (),
+            },
+            Expr::Literal(Literal::Atom(_, function)) => calls.push(Call {
+                span: apply.callee.span(),
+                module: None,
+                function: *function,
+                arity: apply.args.len(),
+            }),
+            _ => (),
+        },
+        Expr::FunctionName(FunctionName::Resolved(name)) => calls.push(Call {
+            span: name.span,
+            module: Some(name.module),
+            function: name.function,
+            arity: name.arity,
+        }),
+        Expr::FunctionName(FunctionName::PartiallyResolved(name)) => calls.push(Call {
+            span: name.span,
+            module: None,
+            function: name.function,
+            arity: name.arity,
+        }),
+        _ => (),
+    }
+}
+
+fn check_local(
+    errs: &mut dyn ErrorReceiver<E = ParserError, W = ParserError>,
+    options: &CompileOptions,
+    module: &Module,
+    call: &Call,
+) {
+    let flag = module
+        .deprecations
+        .iter()
+        .find_map(|deprecation| match deprecation {
+            Deprecation::Function { function, flag, .. }
+                if function.function == call.function && function.arity == call.arity =>
+            {
+                Some(flag)
+            }
+            _ => None,
+        });
+    let message = match flag {
+        None => return,
+        Some(DeprecatedFlag::Description(advice)) => {
+            format!("{}/{} is deprecated; {}", call.function, call.arity, advice)
+        }
+        Some(flag) => format!(
+            "{}/{} is deprecated and will be removed {}",
+            call.function, call.arity, flag
+        ),
+    };
+    deprecated(errs, options, call.span, message);
+}
+
+fn check_remote(
+    errs: &mut dyn ErrorReceiver<E = ParserError, W = ParserError>,
+    options: &CompileOptions,
+    db: &DeprecatedFunctions,
+    call: &Call,
+    module: Ident,
+) {
+    let name = format!("{}:{}/{}", module, call.function, call.arity);
+    match db.get(&module.as_str(), &call.function.as_str(), call.arity) {
+        None => (),
+        Some(DeprecatedFunction::Deprecated(advice)) => deprecated(
+            errs,
+            options,
+            call.span,
+            format!("{} is deprecated; {}", name, advice),
+        ),
+        Some(DeprecatedFunction::Removed(advice)) => {
+            removed(errs, call.span, format!("{} is removed; {}", name, advice))
+        }
+    }
+}
+
+fn deprecated(
+    errs: &mut dyn ErrorReceiver<E = ParserError, W = ParserError>,
+    options: &CompileOptions,
+    span: SourceSpan,
+    message: String,
+) {
+    if options.no_warn || !options.warn_deprecated_function {
+        return;
+    }
+    let diagnostic = if options.warnings_as_errors {
+        Diagnostic::error()
+    } else {
+        Diagnostic::warning()
+    };
+    let err = ParserError::ShowDiagnostic {
+        diagnostic: diagnostic
+            .with_message(message)
+            .with_labels(vec![Label::primary(span.source_id(), span)
+                .with_message("deprecated function called here")]),
+    };
+    if options.warnings_as_errors {
+        errs.error(err);
+    } else {
+        errs.warning(err);
+    }
+}
+
+fn removed(
+    errs: &mut dyn ErrorReceiver<E = ParserError, W = ParserError>,
+    span: SourceSpan,
+    message: String,
+) {
+    errs.error(ParserError::ShowDiagnostic {
+        diagnostic: Diagnostic::error()
+            .with_message(message)
+            .with_labels(vec![
+                Label::primary(span.source_id(), span).with_message("removed function called here")
+            ]),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use libeir_diagnostics::CodeMap;
+    use libeir_util_parse::Errors;
+
+    use super::check_deprecations;
+    use crate::parser::ast::Module;
+    use crate::parser::{ParseConfig, Parser, ParserError};
+
+    fn check(config: ParseConfig, input: &str) -> Vec<String> {
+        let parser = Parser::new(config, Arc::new(CodeMap::new()));
+        let mut errors: Errors<ParserError, ParserError> = Errors::new();
+        let module = parser
+            .parse_string::<Module, _>(&mut errors, input)
+            .unwrap();
+
+        let mut errors: Errors<ParserError, ParserError> = Errors::new();
+        check_deprecations(&parser, &mut errors, &module);
+        errors.iter_diagnostics().map(|d| d.message).collect()
+    }
+
+    #[test]
+    fn local_deprecations() {
+        let messages = check(
+            ParseConfig::default(),
+            "-module(foo).
+-export([run/0]).
+-deprecated([{old, 0, next_major_release}, {older, 1, \"use new/1 instead\"}]).
+-removed([{gone, 0, \"use new/1 instead\"}]).
+run() -> old(), older(1), F = fun older/1, F(2), foo:old(), gone().
+old() -> ok.
+older(_) -> ok.
+",
+        );
+        assert_eq!(
+            messages,
+            vec![
+                "old/0 is deprecated and will be removed in the next major release",
+                "older/1 is deprecated; use new/1 instead",
+                "older/1 is deprecated; use new/1 instead",
+                "old/0 is deprecated and will be removed in the next major release",
+                "gone/0 is removed; use new/1 instead",
+            ]
+        );
+    }
+
+    #[test]
+    fn remote_deprecations() {
+        let mut config = ParseConfig::default();
+        config.deprecated_functions.deprecate(
+            "mylib",
+            "legacy",
+            None,
+            "use mylib:modern/1 instead",
+        );
+        let messages = check(
+            config,
+            "-module(foo).
+-export([run/0]).
+run() -> erlang:now(), now(), mylib:legacy(1), erlang:get_stacktrace(), lists:map(1, 2).
+",
+        );
+        assert_eq!(
+            messages,
+            vec![
+                "erlang:now/0 is deprecated; see the \"Time and Time Correction in Erlang\" \
+                 chapter of the ERTS User's Guide for more information",
+                "erlang:now/0 is deprecated; see the \"Time and Time Correction in Erlang\" \
+                 chapter of the ERTS User's Guide for more information",
+                "mylib:legacy/1 is deprecated; use mylib:modern/1 instead",
+                "erlang:get_stacktrace/0 is removed; use the new try/catch syntax for \
+                 retrieving the stack backtrace",
+            ]
+        );
+
+        let messages = check(
+            ParseConfig::default(),
+            "-module(foo).
+-compile(nowarn_deprecated_function).
+-export([run/0]).
+run() -> erlang:now(), erlang:get_stacktrace().
+",
+        );
+        assert_eq!(
+            messages,
+            vec![
+                "erlang:get_stacktrace/0 is removed; use the new try/catch syntax for \
+                 retrieving the stack backtrace"
+            ]
+        );
+    }
+}
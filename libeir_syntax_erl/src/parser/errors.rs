@@ -4,7 +4,7 @@ use libeir_diagnostics::*;
 use libeir_util_parse::SourceError;
 use snafu::Snafu;
 
-use crate::lexer::Token;
+use crate::lexer::{Ident, Token};
 use crate::preprocessor::PreprocessorError;
 
 pub type ParseError = lalrpop_util::ParseError<SourceIndex, Token, ()>;
@@ -43,6 +43,16 @@ pub enum ParserError {
         location: SourceIndex,
         expected: Vec<String>,
     },
+
+    #[snafu(display("unknown parse transform '{}'", name))]
+    UnknownParseTransform { span: SourceSpan, name: Ident },
+
+    #[snafu(display("{}", message))]
+    ParseTransform {
+        span: SourceSpan,
+        transform: Ident,
+        message: String,
+    },
 }
 impl From<ParseError> for ParserError {
     fn from(err: ParseError) -> Self {
@@ -106,6 +116,22 @@ impl ToDiagnostic for ParserError {
                 .with_message("unexpected token")
                 .with_labels(vec![Label::primary(span.source_id(), *span)
                     .with_message("did not expect this token")]),
+            Self::UnknownParseTransform { span, .. } => Diagnostic::error()
+                .with_message(self.to_string())
+                .with_labels(vec![Label::primary(span.source_id(), *span)
+                    .with_message("no transform with this name is registered")])
+                .with_notes(vec![
+                    "parse transforms are not run from Erlang code, only the built-in \
+                     transforms and those registered with the frontend are available"
+                        .to_string(),
+                ]),
+            Self::ParseTransform {
+                span, transform, ..
+            } => Diagnostic::error()
+                .with_message(format!("parse transform {} failed", transform))
+                .with_labels(vec![
+                    Label::primary(span.source_id(), *span).with_message(self.to_string())
+                ]),
         }
     }
 }
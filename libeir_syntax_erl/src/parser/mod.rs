@@ -36,39 +36,129 @@ pub(crate) mod grammar {
 mod macros;
 
 pub mod ast;
+mod behaviours;
+mod deprecations;
 mod errors;
+mod transform;
 
-use std::collections::VecDeque;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
-use libeir_util_parse::{error_tee, ErrorReceiver, Scanner, Source, SourceError};
+use libeir_util_parse::{error_tee, ErrorReceiver, FileMapSource, Scanner, Source, SourceError};
 use libeir_util_parse::{Parse as GParse, Parser as GParser};
 
 pub type Parser = GParser<ParseConfig>;
 pub trait Parse<T> = GParse<T, Config = ParseConfig, Error = ParserError>;
 
 use crate::lexer::Lexer;
-use crate::preprocessor::{MacroContainer, Preprocessed, Preprocessor};
+use crate::preprocessor::{Preprocessed, Preprocessor};
 
 pub use self::ast::{NodeId, NodeIdGenerator};
+pub use self::behaviours::check_behaviours;
+pub use self::deprecations::{check_deprecations, DeprecatedFunction, DeprecatedFunctions};
 pub use self::errors::*;
+pub use self::transform::{
+    apply_transforms, visit_expr, visit_expr_ref, visit_module_exprs, visit_module_exprs_ref,
+};
+pub use self::transform::{AstTransform, MsTransform, TransformRegistry};
 
 /// The type of result returned from parsing functions
 pub type ParseResult<T> = Result<T, Vec<ParserError>>;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// The OTP release emulated unless configured otherwise.
+pub const DEFAULT_OTP_RELEASE: u32 = 26;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ParseConfig {
     pub warnings_as_errors: bool,
     pub no_warn: bool,
     pub include_paths: VecDeque<PathBuf>,
     pub code_paths: VecDeque<PathBuf>,
-    pub macros: Option<MacroContainer>,
+    /// Macros that are defined before the first token of the module,
+    /// like with `erlc -D`.
+    pub macros: BTreeMap<String, MacroValue>,
+    /// The OTP release that is emulated, `?OTP_RELEASE` expands to this.
+    pub otp_release: u32,
+    /// Functions of other modules that calls are warned about, or
+    /// rejected if they were removed.
+    pub deprecated_functions: DeprecatedFunctions,
+    /// Receives every path looked at while resolving includes and
+    /// behaviours.
+    pub lookups: LookupRecorder,
 }
 impl ParseConfig {
     pub fn new() -> Self {
         ParseConfig::default()
     }
+
+    /// Defines a macro as `erlc -DName` does if `value` is `None`,
+    /// otherwise as `erlc -DName=Value`.
+    pub fn define_macro<N: Into<String>>(&mut self, name: N, value: Option<String>) {
+        let value = match value {
+            None => MacroValue::Defined,
+            Some(tokens) => MacroValue::Tokens(tokens),
+        };
+        self.macros.insert(name.into(), value);
+    }
+
+    /// Removes a macro defined with `define_macro`, as `erlc -UName`.
+    pub fn undefine_macro(&mut self, name: &str) {
+        self.macros.remove(name);
+    }
+}
+
+/// The value of a macro in `ParseConfig::macros`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MacroValue {
+    /// The macro expands to `true`.
+    Defined,
+    /// The macro expands to the tokens of the given Erlang source.
+    Tokens(String),
+}
+/// Records the paths the parser looks at to find files: the candidates
+/// for `-include` and `-include_lib`, and the sources and directories
+/// searched for behaviour modules. A path is recorded whether or not
+/// anything was found there, so that a file created later that would
+/// change the result can be noticed.
+///
+/// Nothing is recorded by the default recorder. A recorder does not
+/// change how modules are compiled, all recorders compare equal and it
+/// does not contribute to the hash of a `ParseConfig`.
+#[derive(Debug, Clone, Default)]
+pub struct LookupRecorder(Option<Arc<Mutex<Vec<PathBuf>>>>);
+impl LookupRecorder {
+    /// A recorder that keeps the recorded paths until they are taken.
+    pub fn new() -> Self {
+        LookupRecorder(Some(Arc::new(Mutex::new(Vec::new()))))
+    }
+
+    pub fn record(&self, path: &Path) {
+        if let Some(paths) = &self.0 {
+            paths.lock().unwrap().push(path.to_owned());
+        }
+    }
+
+    /// Returns the paths recorded since the last call, in the order they
+    /// were looked at.
+    pub fn take(&self) -> Vec<PathBuf> {
+        match &self.0 {
+            Some(paths) => std::mem::replace(&mut *paths.lock().unwrap(), Vec::new()),
+            None => Vec::new(),
+        }
+    }
 }
+impl PartialEq for LookupRecorder {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+impl Eq for LookupRecorder {}
+impl Hash for LookupRecorder {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
 impl Default for ParseConfig {
     fn default() -> Self {
         ParseConfig {
@@ -76,7 +166,10 @@ impl Default for ParseConfig {
             no_warn: false,
             include_paths: VecDeque::new(),
             code_paths: VecDeque::new(),
-            macros: None,
+            macros: BTreeMap::new(),
+            otp_release: DEFAULT_OTP_RELEASE,
+            deprecated_functions: DeprecatedFunctions::default(),
+            lookups: LookupRecorder::default(),
         }
     }
 }
@@ -153,6 +246,85 @@ impl GParse for ast::Expr {
     }
 }
 
+impl GParse for ast::TopLevel {
+    type Parser = grammar::TopLevelParser;
+    type Error = ParserError;
+    type Config = ParseConfig;
+    type Token = Preprocessed;
+
+    fn root_file_error(source: std::io::Error, path: std::path::PathBuf) -> Self::Error {
+        ParserError::RootFile { source, path }
+    }
+
+    fn parse<S>(parser: &Parser, err: &mut ParserErrorReceiver, source: S) -> Result<Self, ()>
+    where
+        S: Source,
+    {
+        error_tee(err, |mut errors| {
+            let scanner = Scanner::new(source);
+            let lexer = Lexer::new(scanner);
+            error_tee(&mut errors.clone().make_into_adapter(), |preproc_errors| {
+                let tokens = Preprocessor::new(parser, lexer, preproc_errors);
+                Self::parse_tokens(&mut errors, tokens)
+            })
+        })
+    }
+
+    fn parse_tokens<S: IntoIterator<Item = Preprocessed>>(
+        err: &mut ParserErrorReceiver,
+        tokens: S,
+    ) -> Result<Self, ()> {
+        let mut nid = NodeIdGenerator::new();
+        let result = Self::Parser::new().parse(err, &mut nid, tokens);
+        to_parse_result(err, result)
+    }
+}
+
+/// Runs only the preprocessor over a source, and renders the expanded
+/// token stream back to Erlang source, like `erlc -P`.
+pub fn preprocess<S>(
+    parser: &Parser,
+    err: &mut ParserErrorReceiver,
+    source: S,
+) -> Result<String, ()>
+where
+    S: Source,
+{
+    error_tee(err, |errors| {
+        let scanner = Scanner::new(source);
+        let lexer = Lexer::new(scanner);
+        error_tee(&mut errors.clone().make_into_adapter(), |preproc_errors| {
+            Preprocessor::new(parser, lexer, preproc_errors).render()
+        })
+    })
+}
+
+/// Preprocesses the file at `path`, see `preprocess`.
+pub fn preprocess_file<P>(
+    parser: &Parser,
+    err: &mut ParserErrorReceiver,
+    path: P,
+) -> Result<String, ()>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    match std::fs::read_to_string(path) {
+        Err(source) => {
+            err.error(ParserError::RootFile {
+                source,
+                path: path.to_owned(),
+            });
+            Err(())
+        }
+        Ok(content) => {
+            let id = parser.codemap.add(path, content);
+            let file = parser.codemap.get(id).unwrap();
+            preprocess(parser, err, FileMapSource::new(file))
+        }
+    }
+}
+
 fn to_parse_result<T>(
     errs: &mut ParserErrorReceiver,
     result: Result<T, ParseError>,
@@ -660,6 +832,321 @@ system_version() ->
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn parse_preprocessor_otp_macros() {
+        let codemap = Arc::new(CodeMap::new());
+        let mut config = ParseConfig::default();
+        config.otp_release = 24;
+        let result: Module = parse(
+            config,
+            codemap.clone(),
+            "-module(foo).
+-export([release/0, features/0, name/0]).
+
+-if(?OTP_RELEASE >= 25 andalso defined(TEST)).
+release() -> new.
+-elif(?OTP_RELEASE >= 21 orelse is_atom(?OTP_RELEASE)).
+release() -> otp21.
+-elif(true).
+release() -> already_taken.
+-else.
+release() -> old.
+-endif.
+
+-if(?FEATURE_AVAILABLE(maybe_expr) and not ?FEATURE_ENABLED(maybe_expr)).
+features() -> disabled.
+-endif.
+
+-if(?MODULE_STRING == \"foo\" andalso defined(MODULE) andalso not defined(TEST)).
+name() -> ?MODULE.
+-endif.
+",
+        );
+
+        let body = |name: &str| {
+            let (_, fun) = result
+                .functions
+                .iter()
+                .find(|(fun, _)| &*fun.function.as_str() == name)
+                .unwrap();
+            assert_eq!(fun.clauses.len(), 1);
+            match &fun.clauses[0].body[..] {
+                [Expr::Literal(Literal::Atom(_, atom))] => atom.to_string(),
+                other => panic!("unexpected body {:?}", other),
+            }
+        };
+        assert_eq!(body("release"), "otp21");
+        assert_eq!(body("features"), "disabled");
+        assert_eq!(body("name"), "foo");
+    }
+
+    /// A header shaped like `stdlib/include/assert.hrl`, selecting the
+    /// `?assert` flavour with a condition that only holds up because
+    /// `andalso` does not evaluate its right operand for a non-integer
+    /// `?ASSERT_LEVEL`.
+    const ASSERT_HRL: &str = "-ifndef(ASSERT_HRL).
+-define(ASSERT_HRL, true).
+
+-ifdef(ASSERT).
+-undef(NOASSERT).
+-endif.
+
+-ifndef(ASSERT_LEVEL).
+-define(ASSERT_LEVEL, full).
+-endif.
+
+-ifdef(NOASSERT).
+-define(assert(BoolExpr), ok).
+-elif(is_integer(?ASSERT_LEVEL) andalso ?ASSERT_LEVEL + 1 > 2).
+-define(assert(BoolExpr), verbose_assert(BoolExpr, ?MODULE, ?LINE)).
+-elif(?OTP_RELEASE >= 21 orelse ?OTP_RELEASE + legacy).
+-define(assert(BoolExpr),
+        case (BoolExpr) of
+            true -> ok;
+            __V -> erlang:error({assert, [{module, ?MODULE}, {line, ?LINE}, {value, __V}]})
+        end).
+-endif.
+
+-endif.
+";
+
+    #[test]
+    fn parse_preprocessor_assert_hrl() {
+        let dir = std::env::temp_dir().join(format!("eir_assert_hrl_{}", std::process::id()));
+        let include = dir.join("stdlib").join("include");
+        std::fs::create_dir_all(&include).unwrap();
+        std::fs::write(include.join("assert.hrl"), ASSERT_HRL).unwrap();
+
+        let check = |level: Option<&str>| {
+            let codemap = Arc::new(CodeMap::new());
+            let mut config = ParseConfig::default();
+            config.code_paths.push_front(dir.clone());
+            if let Some(level) = level {
+                config.define_macro("ASSERT_LEVEL", Some(level.to_string()));
+            }
+            let result: Module = parse(
+                config,
+                codemap,
+                "-module(foo).
+-include_lib(\"stdlib/include/assert.hrl\").
+-include_lib(\"stdlib/include/assert.hrl\").
+check(X) -> ?assert(X).
+",
+            );
+            let (_, fun) = result.functions.iter().next().unwrap();
+            match &fun.clauses[0].body[..] {
+                [Expr::Case(_)] => "case",
+                [Expr::Apply(_)] => "verbose",
+                other => panic!("unexpected body {:?}", other),
+            }
+        };
+        let default = check(None);
+        let verbose = check(Some("2"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(default, "case");
+        assert_eq!(verbose, "verbose");
+    }
+
+    #[test]
+    fn parse_config_macros() {
+        let codemap = Arc::new(CodeMap::new());
+        let mut config = ParseConfig::default();
+        config.define_macro("TEST", None);
+        config.define_macro("VSN", Some("\"1.0\"".to_string()));
+        config.define_macro("REMOVED", None);
+        config.undefine_macro("REMOVED");
+        let result: Module = parse(
+            config,
+            codemap.clone(),
+            "-module(foo).
+
+-ifdef(TEST).
+vsn() -> {?TEST, ?VSN}.
+-endif.
+
+-ifdef(REMOVED).
+removed() -> ok.
+-endif.
+",
+        );
+
+        assert_eq!(result.functions.len(), 1);
+        let (name, fun) = result.functions.iter().next().unwrap();
+        assert_eq!(&*name.function.as_str(), "vsn");
+        match &fun.clauses[0].body[..] {
+            [Expr::Tuple(Tuple { elements, .. })] => match &elements[..] {
+                [Expr::Literal(Literal::Atom(_, test)), Expr::Literal(Literal::String(_, vsn))] => {
+                    assert_eq!(&*test.as_str(), "true");
+                    assert_eq!(&*vsn.as_str(), "1.0");
+                }
+                other => panic!("unexpected elements {:?}", other),
+            },
+            other => panic!("unexpected body {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_sigils_and_triple_quoted_strings() {
+        let result: Module = parse(
+            ParseConfig::default(),
+            Arc::new(CodeMap::new()),
+            "-module(foo).
+
+strings() ->
+    {~\"bin\", ~s(str), \"\"\"
+        text
+        \"\"\"}.
+",
+        );
+
+        let (_, fun) = result.functions.iter().next().unwrap();
+        match &fun.clauses[0].body[..] {
+            [Expr::Tuple(Tuple { elements, .. })] => match &elements[..] {
+                [Expr::Binary(bin), Expr::Literal(Literal::String(_, string)), Expr::Literal(Literal::String(_, text))] =>
+                {
+                    match &bin.elements[..] {
+                        [BinaryElement {
+                            bit_expr: Expr::Literal(Literal::String(_, bin)),
+                            bit_type: Some(bit_type),
+                            ..
+                        }] => {
+                            assert_eq!(&*bin.as_str(), "bin");
+                            match &bit_type[..] {
+                                [BitType::Name(_, _, name)] => assert_eq!(&*name.as_str(), "utf8"),
+                                other => panic!("unexpected bit type {:?}", other),
+                            }
+                        }
+                        other => panic!("unexpected binary elements {:?}", other),
+                    }
+                    assert_eq!(&*string.as_str(), "str");
+                    assert_eq!(&*text.as_str(), "text");
+                }
+                other => panic!("unexpected elements {:?}", other),
+            },
+            other => panic!("unexpected body {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_map_comprehension() {
+        let result: Module = parse(
+            ParseConfig::default(),
+            Arc::new(CodeMap::new()),
+            "-module(foo).
+
+swap(M) -> #{V => K || K := V <- M, is_atom(K)}.
+",
+        );
+
+        let (_, fun) = result.functions.iter().next().unwrap();
+        match &fun.clauses[0].body[..] {
+            [Expr::MapComprehension(compr)] => {
+                match (&*compr.key, &*compr.value) {
+                    (Expr::Var(Var(_, key)), Expr::Var(Var(_, value))) => {
+                        assert_eq!(&*key.as_str(), "V");
+                        assert_eq!(&*value.as_str(), "K");
+                    }
+                    other => panic!("unexpected association {:?}", other),
+                }
+                match &compr.qualifiers[..] {
+                    [Expr::MapGenerator(gen), Expr::Apply(_)] => {
+                        match (&*gen.key, &*gen.value, &*gen.expr) {
+                            (Expr::Var(_), Expr::Var(_), Expr::Var(Var(_, map))) => {
+                                assert_eq!(&*map.as_str(), "M")
+                            }
+                            other => panic!("unexpected generator {:?}", other),
+                        }
+                    }
+                    other => panic!("unexpected qualifiers {:?}", other),
+                }
+            }
+            other => panic!("unexpected body {:?}", other),
+        }
+    }
+
+    #[test]
+    fn preprocess_with_includes() {
+        let dir = std::env::temp_dir().join(format!("eir_preprocess_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let include = dir.join("defs.hrl");
+        let file = dir.join("foo.erl");
+        std::fs::write(
+            &include,
+            "-define(GREETING, \"hello\").\n-record(state, {count = 0}).\n",
+        )
+        .unwrap();
+        std::fs::write(
+            &file,
+            "-module(foo).\n-include(\"defs.hrl\").\n-define(TWICE(X), {X, X}).\n\n\
+             greet() -> ?TWICE(?GREETING).\n",
+        )
+        .unwrap();
+
+        let codemap = Arc::new(CodeMap::new());
+        let mut config = ParseConfig::default();
+        config.include_paths.push_front(dir.clone());
+        let parser = Parser::new(config, codemap);
+        let mut errors = Errors::new();
+        let result = preprocess_file(&parser, &mut errors, &file);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let rendered = match result {
+            Ok(rendered) => rendered,
+            Err(()) => fail_with(&errors, &parser.codemap, "preprocess failed"),
+        };
+        assert_eq!(
+            rendered,
+            format!(
+                "-file(\"{file}\", 1).\n\
+                 -module(foo).\n\
+                 \n\
+                 -file(\"{include}\", 2).\n\
+                 -record(state, {{count = 0}}).\n\
+                 \n\
+                 -file(\"{file}\", 5).\n\
+                 greet() -> {{\"hello\", \"hello\"}}.\n",
+                file = file.display(),
+                include = include.display(),
+            )
+        );
+    }
+
+    #[test]
+    fn preprocess_function_macros() {
+        let dir = std::env::temp_dir().join(format!("eir_preprocess_fun_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("foo.erl");
+        std::fs::write(
+            &file,
+            "-module(foo).\n-define(WHERE, {?MODULE, ?FUNCTION_NAME, ?FUNCTION_ARITY}).\n\
+             f({A, B}, [C]) -> ?WHERE.\ng() -> ?FUNCTION_ARITY.\n",
+        )
+        .unwrap();
+
+        let codemap = Arc::new(CodeMap::new());
+        let parser = Parser::new(ParseConfig::default(), codemap);
+        let mut errors = Errors::new();
+        let result = preprocess_file(&parser, &mut errors, &file);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let rendered = match result {
+            Ok(rendered) => rendered,
+            Err(()) => fail_with(&errors, &parser.codemap, "preprocess failed"),
+        };
+        assert_eq!(
+            rendered,
+            format!(
+                "-file(\"{file}\", 1).\n\
+                 -module(foo).\n\
+                 \n\
+                 f({{A, B}}, [C]) -> {{foo, f, 2}}.\n\
+                 g() -> 0.\n",
+                file = file.display(),
+            )
+        );
+    }
+
     #[test]
     fn parse_preprocessor_warning_error() {
         // NOTE: Warnings are not printed with cfg(test), as we
@@ -889,6 +1376,34 @@ woo() -> 2.
         );
     }
 
+    #[test]
+    fn parse_typed_record_fields() {
+        let result: Module = parse(
+            ParseConfig::default(),
+            Arc::new(CodeMap::new()),
+            "-module(foo).
+-record(person, {name :: string(), age = 0 :: non_neg_integer(), city}).
+",
+        );
+
+        let def = &result.records[&Symbol::intern("person")];
+        let fields: Vec<(String, bool)> = def
+            .record
+            .fields
+            .iter()
+            .map(|field| (field.name.to_string(), field.ty.is_some()))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("name".to_string(), true),
+                ("age".to_string(), true),
+                ("city".to_string(), false),
+            ]
+        );
+        assert_eq!(def.field_idx_map[&ident!("age")], 1);
+    }
+
     #[test]
     fn parse_removed_attribute() {
         let _result: Module = parse(
@@ -0,0 +1,111 @@
+//! Parse transforms, run on the AST of a module before it is lowered.
+//!
+//! Erlang parse transforms are modules that export `parse_transform/2`
+//! and are run by the compiler on the abstract format. Since we do not
+//! run Erlang code during compilation, transforms are implemented in
+//! Rust instead through `AstTransform`, and a module's
+//! `-compile({parse_transform, Name}).` options select them by name
+//! from a `TransformRegistry`.
+
+mod ms_transform;
+mod visit;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use libeir_util_parse::ErrorReceiver;
+
+use super::ast::Module;
+use super::ParserError;
+use crate::lexer::Symbol;
+
+pub use self::ms_transform::MsTransform;
+pub use self::visit::{visit_expr, visit_expr_ref, visit_module_exprs, visit_module_exprs_ref};
+
+/// A transform of the AST of a module, the Rust counterpart of an Erlang
+/// parse transform.
+pub trait AstTransform: Send + Sync {
+    /// Rewrites `module` in place. Errors are reported through `errs`,
+    /// returning `Err(())` stops compilation of the module.
+    fn transform(
+        &self,
+        errs: &mut dyn ErrorReceiver<E = ParserError, W = ParserError>,
+        module: &mut Module,
+    ) -> Result<(), ()>;
+}
+
+/// The parse transforms available to modules, keyed by the name of the
+/// Erlang module they stand in for.
+#[derive(Clone)]
+pub struct TransformRegistry {
+    transforms: HashMap<Symbol, Arc<dyn AstTransform>>,
+}
+impl TransformRegistry {
+    /// Creates a registry without any transforms, not even the built-in ones.
+    pub fn new() -> Self {
+        Self {
+            transforms: HashMap::new(),
+        }
+    }
+
+    /// Registers `transform` under `name`, replacing any transform
+    /// previously registered under it.
+    pub fn register<T>(&mut self, name: &str, transform: T)
+    where
+        T: AstTransform + 'static,
+    {
+        self.transforms
+            .insert(Symbol::intern(name), Arc::new(transform));
+    }
+
+    pub fn get(&self, name: Symbol) -> Option<&dyn AstTransform> {
+        self.transforms.get(&name).map(|transform| &**transform)
+    }
+}
+impl Default for TransformRegistry {
+    /// A registry containing the built-in transforms.
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register("ms_transform", MsTransform);
+        registry
+    }
+}
+
+/// Applies the parse transforms given in the compile options of `module`,
+/// in order.
+///
+/// All transforms are looked up before any of them is run, so that an
+/// unknown transform does not leave the module partially transformed.
+pub fn apply_transforms(
+    registry: &TransformRegistry,
+    errs: &mut dyn ErrorReceiver<E = ParserError, W = ParserError>,
+    module: &mut Module,
+) -> Result<(), ()> {
+    let names = match module.compile.as_ref() {
+        Some(options) => options.parse_transforms.clone(),
+        None => return Ok(()),
+    };
+
+    let mut transforms = Vec::with_capacity(names.len());
+    let mut failed = false;
+    for name in names.iter() {
+        match registry.get(name.name) {
+            Some(transform) => transforms.push(transform),
+            None => {
+                errs.error(ParserError::UnknownParseTransform {
+                    span: name.span,
+                    name: *name,
+                });
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        return Err(());
+    }
+
+    for transform in transforms {
+        transform.transform(errs, module)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,596 @@
+//! The built-in `ms_transform`, which turns the literal funs given to
+//! `ets:fun2ms/1` and `dbg:fun2ms/1` into match specifications at
+//! compile time.
+//!
+//! Variables bound in the head of the fun become `'$N'` match variables,
+//! guards become match conditions, one match function per guard
+//! sequence, and variables bound outside the fun are inserted as
+//! `{const, Var}`.
+
+use std::collections::HashMap;
+
+use libeir_diagnostics::SourceSpan;
+use libeir_util_number::Integer;
+use libeir_util_parse::ErrorReceiver;
+
+use super::super::ast::*;
+use super::{visit_module_exprs, AstTransform};
+use crate::parser::ParserError;
+
+pub struct MsTransform;
+
+impl AstTransform for MsTransform {
+    fn transform(
+        &self,
+        errs: &mut dyn ErrorReceiver<E = ParserError, W = ParserError>,
+        module: &mut Module,
+    ) -> Result<(), ()> {
+        // The records are only read, but the walk borrows the whole module.
+        // For the same reason the node id generator is handed back after.
+        let records = module.records.clone();
+        let mut nid = module.nid.clone();
+
+        let mut failed = false;
+        let mut visit = |expr: &mut Expr| {
+            let (kind, lambda) = match fun2ms_call(expr) {
+                None => return,
+                Some(Err(span)) => {
+                    errs.error(ParserError::ParseTransform {
+                        span,
+                        transform: ident!("ms_transform"),
+                        message: "the argument to fun2ms/1 must be a literal fun".to_string(),
+                    });
+                    failed = true;
+                    return;
+                }
+                Some(Ok(call)) => call,
+            };
+            let mut translator = Translator {
+                kind,
+                records: &records,
+                nid: &mut nid,
+            };
+            match translator.translate(lambda) {
+                Ok(spec) => *expr = spec,
+                Err((span, message)) => {
+                    errs.error(ParserError::ParseTransform {
+                        span,
+                        transform: ident!("ms_transform"),
+                        message,
+                    });
+                    failed = true;
+                }
+            }
+        };
+
+        visit_module_exprs(module, &mut visit);
+        module.nid = nid;
+
+        if failed {
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Kind {
+    /// `ets:fun2ms/1`, the fun takes the object being matched
+    Ets,
+    /// `dbg:fun2ms/1`, the fun takes the list of arguments of a call
+    Dbg,
+}
+
+/// Recognizes calls to `ets:fun2ms/1` and `dbg:fun2ms/1`, returning the
+/// span of the argument if it is not a literal fun.
+fn fun2ms_call(expr: &Expr) -> Option<Result<(Kind, Lambda), SourceSpan>> {
+    let apply = match expr {
+        Expr::Apply(apply) if apply.args.len() == 1 => apply,
+        _ => return None,
+    };
+    let remote = match &*apply.callee {
+        Expr::Remote(remote) => remote,
+        _ => return None,
+    };
+    let (module, function) = match (&*remote.module, &*remote.function) {
+        (Expr::Literal(Literal::Atom(_, module)), Expr::Literal(Literal::Atom(_, function))) => {
+            (module, function)
+        }
+        _ => return None,
+    };
+    if &*function.as_str() != "fun2ms" {
+        return None;
+    }
+    let kind = match &*module.as_str() {
+        "ets" => Kind::Ets,
+        "dbg" => Kind::Dbg,
+        _ => return None,
+    };
+    match &apply.args[0] {
+        Expr::Fun(Function::Unnamed(lambda)) => Some(Ok((kind, lambda.clone()))),
+        other => Some(Err(other.span())),
+    }
+}
+
+type TranslateResult<T> = Result<T, (SourceSpan, String)>;
+
+/// The match variables of the head of a clause.
+struct Bindings {
+    vars: HashMap<Symbol, Symbol>,
+    next: usize,
+}
+impl Bindings {
+    fn new() -> Self {
+        Bindings {
+            vars: HashMap::new(),
+            next: 1,
+        }
+    }
+
+    fn bind(&mut self, var: Symbol) -> Symbol {
+        let next = &mut self.next;
+        *self.vars.entry(var).or_insert_with(|| {
+            let name = Symbol::intern(&format!("${}", next));
+            *next += 1;
+            name
+        })
+    }
+}
+
+struct Translator<'a> {
+    kind: Kind,
+    records: &'a HashMap<Symbol, DefinedRecord>,
+    nid: &'a mut NodeIdGenerator,
+}
+impl<'a> Translator<'a> {
+    /// Produces the match specification for `lambda`, a list of
+    /// `{Head, Conditions, Body}` tuples.
+    fn translate(&mut self, lambda: Lambda) -> TranslateResult<Expr> {
+        let mut functions = Vec::new();
+        for clause in lambda.clauses.iter() {
+            if clause.params.len() != 1 {
+                return Err((
+                    clause.span,
+                    "the fun given to fun2ms/1 must take exactly one parameter".to_string(),
+                ));
+            }
+
+            let mut bindings = Bindings::new();
+            let head = self.head(&clause.params[0], &mut bindings)?;
+            let mut body = Vec::with_capacity(clause.body.len());
+            for expr in clause.body.iter() {
+                body.push(self.expr(expr, &bindings)?);
+            }
+            let body = self.list(body);
+
+            match &clause.guard {
+                None => {
+                    let conditions = nil!(self.nid);
+                    functions.push(tuple!(self.nid, head, conditions, body));
+                }
+                Some(guards) => {
+                    for guard in guards.iter() {
+                        let mut conditions = Vec::with_capacity(guard.conditions.len());
+                        for condition in guard.conditions.iter() {
+                            conditions.push(self.expr(condition, &bindings)?);
+                        }
+                        let conditions = self.list(conditions);
+                        functions.push(tuple!(self.nid, head.clone(), conditions, body.clone()));
+                    }
+                }
+            }
+        }
+        Ok(self.list(functions))
+    }
+
+    fn head(&mut self, param: &Expr, bindings: &mut Bindings) -> TranslateResult<Expr> {
+        match (self.kind, param) {
+            // `Var = Pattern` binds the variable to the whole object, `'$_'`
+            (Kind::Ets, Expr::Match(mat)) => match (&*mat.pattern, &*mat.expr) {
+                (Expr::Var(Var(_, var)), pattern) | (pattern, Expr::Var(Var(_, var))) => {
+                    bindings.vars.insert(var.name, Symbol::intern("$_"));
+                    self.head(pattern, bindings)
+                }
+                _ => Err((
+                    mat.span,
+                    "only a variable may be matched against the parameter of the fun".to_string(),
+                )),
+            },
+            (Kind::Ets, Expr::Var(_))
+            | (Kind::Ets, Expr::Tuple(_))
+            | (Kind::Ets, Expr::Record(_))
+            | (Kind::Dbg, Expr::Var(_))
+            | (Kind::Dbg, Expr::Cons(_))
+            | (Kind::Dbg, Expr::Nil(_)) => self.pattern(param, bindings),
+            (Kind::Ets, other) => Err((
+                other.span(),
+                "the parameter of the fun given to ets:fun2ms/1 must be a variable, \
+                 tuple or record"
+                    .to_string(),
+            )),
+            (Kind::Dbg, other) => Err((
+                other.span(),
+                "the parameter of the fun given to dbg:fun2ms/1 must be a variable or list"
+                    .to_string(),
+            )),
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Expr, bindings: &mut Bindings) -> TranslateResult<Expr> {
+        match pattern {
+            Expr::Var(Var(_, var)) if &*var.as_str() == "_" => Ok(atom!(self.nid, "_")),
+            Expr::Var(Var(_, var)) => {
+                let name = bindings.bind(var.name);
+                Ok(atom_from_sym!(self.nid, name))
+            }
+            Expr::Literal(_) | Expr::Nil(_) => Ok(pattern.clone()),
+            Expr::Tuple(tuple) => {
+                let mut elements = Vec::with_capacity(tuple.elements.len());
+                for element in tuple.elements.iter() {
+                    elements.push(self.pattern(element, bindings)?);
+                }
+                Ok(Expr::Tuple(Tuple {
+                    span: tuple.span,
+                    id: self.nid.next(),
+                    elements,
+                }))
+            }
+            Expr::Cons(cons) => {
+                let head = self.pattern(&cons.head, bindings)?;
+                let tail = self.pattern(&cons.tail, bindings)?;
+                Ok(cons!(self.nid, head, tail))
+            }
+            Expr::Record(rec) => {
+                let def = self.record(rec.name)?;
+                // `_ = Pattern` gives the pattern of all fields not named
+                let mut default = atom!(self.nid, "_");
+                for field in rec.fields.iter() {
+                    if &*field.name.as_str() == "_" {
+                        if let Some(value) = field.value.as_ref() {
+                            default = self.pattern(value, bindings)?;
+                        }
+                    } else if !def.field_idx_map.contains_key(&field.name) {
+                        return Err(undefined_field(rec.name, field.name));
+                    }
+                }
+
+                let mut elements = vec![atom_from_sym!(self.nid, rec.name.name)];
+                for def_field in def.record.fields.iter() {
+                    let field = rec.fields.iter().find(|f| f.name == def_field.name);
+                    match field.and_then(|f| f.value.as_ref()) {
+                        Some(value) => elements.push(self.pattern(value, bindings)?),
+                        None => elements.push(default.clone()),
+                    }
+                }
+                Ok(Expr::Tuple(Tuple {
+                    span: rec.span,
+                    id: self.nid.next(),
+                    elements,
+                }))
+            }
+            other => Err((
+                other.span(),
+                "this pattern is not supported in match specifications".to_string(),
+            )),
+        }
+    }
+
+    /// Translates an expression of a guard or the body of a clause.
+    fn expr(&mut self, expr: &Expr, bindings: &Bindings) -> TranslateResult<Expr> {
+        match expr {
+            Expr::Var(Var(_, var)) => match bindings.vars.get(&var.name) {
+                Some(name) => Ok(atom_from_sym!(self.nid, *name)),
+                None => Ok(tuple!(self.nid, atom!(self.nid, "const"), expr.clone())),
+            },
+            Expr::Literal(_) | Expr::Nil(_) => Ok(expr.clone()),
+            // Tuples are wrapped in another tuple to distinguish them
+            // from calls
+            Expr::Tuple(tuple) => {
+                let mut elements = Vec::with_capacity(tuple.elements.len());
+                for element in tuple.elements.iter() {
+                    elements.push(self.expr(element, bindings)?);
+                }
+                let inner = Expr::Tuple(Tuple {
+                    span: tuple.span,
+                    id: self.nid.next(),
+                    elements,
+                });
+                Ok(tuple!(self.nid, inner))
+            }
+            Expr::Cons(cons) => {
+                let head = self.expr(&cons.head, bindings)?;
+                let tail = self.expr(&cons.tail, bindings)?;
+                Ok(cons!(self.nid, head, tail))
+            }
+            Expr::BinaryExpr(bin) => match bin.op {
+                BinaryOp::Send | BinaryOp::Append | BinaryOp::Remove => Err((
+                    bin.span,
+                    format!(
+                        "the operator {} is not allowed in match specifications",
+                        bin.op
+                    ),
+                )),
+                op => {
+                    let lhs = self.expr(&bin.lhs, bindings)?;
+                    let rhs = self.expr(&bin.rhs, bindings)?;
+                    Ok(tuple!(self.nid, atom!(self.nid, &op.to_string()), lhs, rhs))
+                }
+            },
+            Expr::UnaryExpr(un) => {
+                let operand = self.expr(&un.operand, bindings)?;
+                Ok(tuple!(
+                    self.nid,
+                    atom!(self.nid, &un.op.to_string()),
+                    operand
+                ))
+            }
+            Expr::Apply(apply) => {
+                let function = match &*apply.callee {
+                    Expr::Literal(Literal::Atom(_, function)) => *function,
+                    Expr::Remote(Remote {
+                        module, function, ..
+                    }) => match (&**module, &**function) {
+                        (
+                            Expr::Literal(Literal::Atom(_, module)),
+                            Expr::Literal(Literal::Atom(_, function)),
+                        ) if &*module.as_str() == "erlang" => *function,
+                        _ => return Err(unsupported_call(apply.span)),
+                    },
+                    _ => return Err(unsupported_call(apply.span)),
+                };
+                let mut elements = vec![atom_from_sym!(self.nid, function.name)];
+                for arg in apply.args.iter() {
+                    elements.push(self.expr(arg, bindings)?);
+                }
+                Ok(Expr::Tuple(Tuple {
+                    span: apply.span,
+                    id: self.nid.next(),
+                    elements,
+                }))
+            }
+            Expr::Record(rec) => {
+                let def = self.record(rec.name)?;
+                for field in rec.fields.iter() {
+                    if !def.field_idx_map.contains_key(&field.name) {
+                        return Err(undefined_field(rec.name, field.name));
+                    }
+                }
+
+                let mut elements = vec![atom_from_sym!(self.nid, rec.name.name)];
+                for def_field in def.record.fields.iter() {
+                    let field = rec.fields.iter().find(|f| f.name == def_field.name);
+                    match field.and_then(|f| f.value.as_ref()) {
+                        Some(value) => elements.push(self.expr(value, bindings)?),
+                        None => match def_field.value.as_ref() {
+                            Some(value) => elements.push(self.expr(value, &Bindings::new())?),
+                            // Fields without a default are `undefined`
+                            None => elements.push(atom!(self.nid, undefined)),
+                        },
+                    }
+                }
+                let inner = Expr::Tuple(Tuple {
+                    span: rec.span,
+                    id: self.nid.next(),
+                    elements,
+                });
+                Ok(tuple!(self.nid, inner))
+            }
+            Expr::RecordAccess(access) => {
+                let index = self.field_index(access.name, access.field)?;
+                let record = self.expr(&access.record, bindings)?;
+                Ok(tuple!(
+                    self.nid,
+                    atom!(self.nid, element),
+                    int!(self.nid, index),
+                    record
+                ))
+            }
+            Expr::RecordIndex(index) => {
+                let index = self.field_index(index.name, index.field)?;
+                Ok(int!(self.nid, index))
+            }
+            other => Err((
+                other.span(),
+                "this expression is not allowed in match specifications".to_string(),
+            )),
+        }
+    }
+
+    fn record(&self, name: Ident) -> TranslateResult<&'a DefinedRecord> {
+        self.records
+            .get(&name.name)
+            .ok_or_else(|| (name.span, format!("record {} undefined", name)))
+    }
+
+    /// The index of a record field in the tuple representing the record.
+    fn field_index(&self, name: Ident, field: Ident) -> TranslateResult<Integer> {
+        let def = self.record(name)?;
+        match def.field_idx_map.get(&field) {
+            Some(idx) => Ok(Integer::from(*idx as i64 + 2)),
+            None => Err(undefined_field(name, field)),
+        }
+    }
+
+    fn list(&mut self, elements: Vec<Expr>) -> Expr {
+        let mut list = nil!(self.nid);
+        for element in elements.into_iter().rev() {
+            list = cons!(self.nid, element, list);
+        }
+        list
+    }
+}
+
+fn undefined_field(record: Ident, field: Ident) -> (SourceSpan, String) {
+    (
+        field.span,
+        format!("field {} undefined in record {}", field, record),
+    )
+}
+
+fn unsupported_call(span: SourceSpan) -> (SourceSpan, String) {
+    (
+        span,
+        "only calls to guard functions are allowed in match specifications".to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    use libeir_diagnostics::{CodeMap, SourceSpan};
+    use libeir_util_parse::Errors;
+
+    use crate::parser::ast::{LocalFunctionName, Module};
+    use crate::parser::{
+        apply_transforms, visit_module_exprs_ref, ParseConfig, Parser, ParserError,
+        TransformRegistry,
+    };
+
+    fn transform(input: &str) -> Result<Module, Vec<String>> {
+        let parser = Parser::new(ParseConfig::default(), Arc::new(CodeMap::new()));
+        let mut errors: Errors<ParserError, ParserError> = Errors::new();
+        let mut module = parser
+            .parse_string::<Module, _>(&mut errors, input)
+            .unwrap();
+
+        let registry = TransformRegistry::default();
+        match apply_transforms(&registry, &mut errors, &mut module) {
+            Ok(()) => Ok(module),
+            Err(()) => Err(errors.iter_diagnostics().map(|d| d.message).collect()),
+        }
+    }
+
+    fn assert_same_body(transformed: &Module, expected: &Module, function: &str) {
+        let name = LocalFunctionName {
+            span: SourceSpan::UNKNOWN,
+            function: ident!(function),
+            arity: 0,
+        };
+        assert_eq!(
+            transformed.functions[&name].clauses[0].body,
+            expected.functions[&name].clauses[0].body
+        );
+    }
+
+    #[test]
+    fn ets_fun2ms() {
+        let transformed = transform(
+            "-module(foo).
+-compile({parse_transform, ms_transform}).
+-record(person, {name, age, city}).
+heads() -> ets:fun2ms(fun({A, _, A}) -> A end).
+guards() -> ets:fun2ms(fun({K, V}) when K > 1, is_atom(V); K =:= 0 -> {K, V} end).
+whole() -> ets:fun2ms(fun(P = #person{age = Age}) when Age >= 18 -> P end).
+fields() -> ets:fun2ms(fun(#person{city = stockholm} = P) -> P#person.name end).
+consts() -> Max = 10, ets:fun2ms(fun({X}) when X < Max -> X end).
+",
+        )
+        .unwrap();
+
+        let expected = transform(
+            "-module(foo).
+heads() -> [{{'$1', '_', '$1'}, [], ['$1']}].
+guards() -> [{{'$1', '$2'}, [{'>', '$1', 1}, {is_atom, '$2'}], [{{'$1', '$2'}}]},
+             {{'$1', '$2'}, [{'=:=', '$1', 0}], [{{'$1', '$2'}}]}].
+whole() -> [{{person, '_', '$1', '_'}, [{'>=', '$1', 18}], ['$_']}].
+fields() -> [{{person, '_', '_', stockholm}, [], [{element, 2, '$_'}]}].
+consts() -> Max = 10, [{{'$1'}, [{'<', '$1', {const, Max}}], ['$1']}].
+",
+        )
+        .unwrap();
+
+        for function in &["heads", "guards", "whole", "fields", "consts"] {
+            assert_same_body(&transformed, &expected, function);
+        }
+    }
+
+    #[test]
+    fn dbg_fun2ms() {
+        let transformed = transform(
+            "-module(foo).
+-compile({parse_transform, ms_transform}).
+trace() -> dbg:fun2ms(fun([A, _]) when is_integer(A) -> return_trace() end).
+",
+        )
+        .unwrap();
+
+        let expected = transform(
+            "-module(foo).
+trace() -> [{['$1', '_'], [{is_integer, '$1'}], [{return_trace}]}].
+",
+        )
+        .unwrap();
+
+        assert_same_body(&transformed, &expected, "trace");
+    }
+
+    #[test]
+    fn fresh_node_ids() {
+        let transformed = transform(
+            "-module(foo).
+-compile({parse_transform, ms_transform}).
+other() -> {a, b, c, d, e, f, g, h, i, j, k, l}.
+heads() -> ets:fun2ms(fun({A, _, A}) -> A end).
+",
+        )
+        .unwrap();
+
+        let mut ids = HashSet::new();
+        visit_module_exprs_ref(&transformed, &mut |expr| {
+            assert!(ids.insert(expr.id()), "duplicate node id {:?}", expr.id());
+        });
+    }
+
+    #[test]
+    fn record_defaults() {
+        let transformed = transform(
+            "-module(foo).
+-compile({parse_transform, ms_transform}).
+-record(person, {name, age = 0, city}).
+build() -> ets:fun2ms(fun({X}) -> #person{name = X} end).
+",
+        )
+        .unwrap();
+
+        let expected = transform(
+            "-module(foo).
+build() -> [{{'$1'}, [], [{{person, '$1', 0, undefined}}]}].
+",
+        )
+        .unwrap();
+
+        assert_same_body(&transformed, &expected, "build");
+    }
+
+    #[test]
+    fn unsupported_expression() {
+        let errors = transform(
+            "-module(foo).
+-compile({parse_transform, ms_transform}).
+bad() -> ets:fun2ms(fun({A}) -> A ! hello end).
+",
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors,
+            vec!["parse transform ms_transform failed".to_string()]
+        );
+    }
+
+    #[test]
+    fn unknown_transform() {
+        let errors = transform(
+            "-module(foo).
+-compile({parse_transform, lager_transform}).
+",
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors,
+            vec!["unknown parse transform 'lager_transform'".to_string()]
+        );
+    }
+}
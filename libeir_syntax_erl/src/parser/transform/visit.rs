@@ -0,0 +1,246 @@
+//! Walking the expressions of the AST.
+//!
+//! The visitors come in two flavours generated from the same code, one
+//! over mutable references for transforms that rewrite the AST, and one
+//! over shared references for checks that only look at it.
+
+use crate::parser::ast::Expr;
+use crate::parser::ast::Module;
+
+macro_rules! expr_visitor {
+    ($name:ident, [$($m:tt)*], $iter:ident, $values:ident, $as_opt:ident) => {
+        mod $name {
+            use crate::parser::ast::{
+                Clause, Expr, Function, FunctionClause, Guard, MapField, Module, RecordField,
+            };
+
+            pub fn visit_module_exprs(module: &$($m)* Module, fun: &mut dyn FnMut(&$($m)* Expr)) {
+                for function in module.functions.$values() {
+                    for clause in function.clauses.$iter() {
+                        visit_function_clause(clause, fun);
+                    }
+                }
+            }
+
+            pub fn visit_expr(expr: &$($m)* Expr, fun: &mut dyn FnMut(&$($m)* Expr)) {
+                match expr {
+                    Expr::Var(_)
+                    | Expr::Literal(_)
+                    | Expr::FunctionName(_)
+                    | Expr::DelayedSubstitution(..)
+                    | Expr::Nil(_)
+                    | Expr::RecordIndex(_) => (),
+                    Expr::Cons(cons) => {
+                        visit_expr(&$($m)* cons.head, fun);
+                        visit_expr(&$($m)* cons.tail, fun);
+                    }
+                    Expr::Tuple(tuple) => visit_exprs(&$($m)* tuple.elements, fun),
+                    Expr::Map(map) => visit_map_fields(&$($m)* map.fields, fun),
+                    Expr::MapUpdate(map) => {
+                        visit_expr(&$($m)* map.map, fun);
+                        visit_map_fields(&$($m)* map.updates, fun);
+                    }
+                    Expr::MapProjection(map) => {
+                        visit_expr(&$($m)* map.map, fun);
+                        visit_map_fields(&$($m)* map.fields, fun);
+                    }
+                    Expr::Binary(bin) => {
+                        for element in bin.elements.$iter() {
+                            visit_expr(&$($m)* element.bit_expr, fun);
+                            if let Some(size) = element.bit_size.$as_opt() {
+                                visit_expr(size, fun);
+                            }
+                        }
+                    }
+                    Expr::Record(rec) => visit_record_fields(&$($m)* rec.fields, fun),
+                    Expr::RecordAccess(rec) => visit_expr(&$($m)* rec.record, fun),
+                    Expr::RecordUpdate(rec) => {
+                        visit_expr(&$($m)* rec.record, fun);
+                        visit_record_fields(&$($m)* rec.updates, fun);
+                    }
+                    Expr::ListComprehension(compr) => {
+                        visit_expr(&$($m)* compr.body, fun);
+                        visit_exprs(&$($m)* compr.qualifiers, fun);
+                    }
+                    Expr::BinaryComprehension(compr) => {
+                        visit_expr(&$($m)* compr.body, fun);
+                        visit_exprs(&$($m)* compr.qualifiers, fun);
+                    }
+                    Expr::MapComprehension(compr) => {
+                        visit_expr(&$($m)* compr.key, fun);
+                        visit_expr(&$($m)* compr.value, fun);
+                        visit_exprs(&$($m)* compr.qualifiers, fun);
+                    }
+                    Expr::Generator(gen) => {
+                        visit_expr(&$($m)* gen.pattern, fun);
+                        visit_expr(&$($m)* gen.expr, fun);
+                    }
+                    Expr::BinaryGenerator(gen) => {
+                        visit_expr(&$($m)* gen.pattern, fun);
+                        visit_expr(&$($m)* gen.expr, fun);
+                    }
+                    Expr::MapGenerator(gen) => {
+                        visit_expr(&$($m)* gen.key, fun);
+                        visit_expr(&$($m)* gen.value, fun);
+                        visit_expr(&$($m)* gen.expr, fun);
+                    }
+                    Expr::Begin(begin) => visit_exprs(&$($m)* begin.body, fun),
+                    Expr::Apply(apply) => {
+                        visit_expr(&$($m)* apply.callee, fun);
+                        visit_exprs(&$($m)* apply.args, fun);
+                    }
+                    Expr::Remote(remote) => {
+                        visit_expr(&$($m)* remote.module, fun);
+                        visit_expr(&$($m)* remote.function, fun);
+                    }
+                    Expr::BinaryExpr(bin) => {
+                        visit_expr(&$($m)* bin.lhs, fun);
+                        visit_expr(&$($m)* bin.rhs, fun);
+                    }
+                    Expr::UnaryExpr(un) => visit_expr(&$($m)* un.operand, fun),
+                    Expr::Match(mat) => {
+                        visit_expr(&$($m)* mat.pattern, fun);
+                        visit_expr(&$($m)* mat.expr, fun);
+                    }
+                    Expr::If(expr) => {
+                        for clause in expr.clauses.$iter() {
+                            visit_guards(&$($m)* clause.guards, fun);
+                            visit_exprs(&$($m)* clause.body, fun);
+                        }
+                    }
+                    Expr::Catch(catch) => visit_expr(&$($m)* catch.expr, fun),
+                    Expr::Case(case) => {
+                        visit_expr(&$($m)* case.expr, fun);
+                        visit_clauses(&$($m)* case.clauses, fun);
+                    }
+                    Expr::Receive(rec) => {
+                        if let Some(clauses) = rec.clauses.$as_opt() {
+                            visit_clauses(clauses, fun);
+                        }
+                        if let Some(after) = rec.after.$as_opt() {
+                            visit_expr(&$($m)* after.timeout, fun);
+                            visit_exprs(&$($m)* after.body, fun);
+                        }
+                    }
+                    Expr::Try(tr) => {
+                        visit_exprs(&$($m)* tr.exprs, fun);
+                        if let Some(clauses) = tr.clauses.$as_opt() {
+                            visit_clauses(clauses, fun);
+                        }
+                        if let Some(clauses) = tr.catch_clauses.$as_opt() {
+                            for clause in clauses.$iter() {
+                                visit_expr(&$($m)* clause.error, fun);
+                                if let Some(guards) = clause.guard.$as_opt() {
+                                    visit_guards(guards, fun);
+                                }
+                                visit_exprs(&$($m)* clause.body, fun);
+                            }
+                        }
+                        if let Some(after) = tr.after.$as_opt() {
+                            visit_exprs(after, fun);
+                        }
+                    }
+                    Expr::Fun(Function::Named(named)) => {
+                        for clause in named.clauses.$iter() {
+                            visit_function_clause(clause, fun);
+                        }
+                    }
+                    Expr::Fun(Function::Unnamed(lambda)) => {
+                        for clause in lambda.clauses.$iter() {
+                            visit_function_clause(clause, fun);
+                        }
+                    }
+                    Expr::Maybe(maybe) => {
+                        visit_exprs(&$($m)* maybe.body, fun);
+                        if let Some(clauses) = maybe.else_clauses.$as_opt() {
+                            visit_clauses(clauses, fun);
+                        }
+                    }
+                    Expr::MaybeMatch(mat) => {
+                        visit_expr(&$($m)* mat.pattern, fun);
+                        visit_expr(&$($m)* mat.expr, fun);
+                    }
+                }
+                fun(expr);
+            }
+
+            fn visit_exprs(exprs: &$($m)* [Expr], fun: &mut dyn FnMut(&$($m)* Expr)) {
+                for expr in exprs.$iter() {
+                    visit_expr(expr, fun);
+                }
+            }
+
+            fn visit_map_fields(fields: &$($m)* [MapField], fun: &mut dyn FnMut(&$($m)* Expr)) {
+                for field in fields.$iter() {
+                    match field {
+                        MapField::Assoc { key, value, .. } | MapField::Exact { key, value, .. } => {
+                            visit_expr(key, fun);
+                            visit_expr(value, fun);
+                        }
+                    }
+                }
+            }
+
+            fn visit_record_fields(fields: &$($m)* [RecordField], fun: &mut dyn FnMut(&$($m)* Expr)) {
+                for field in fields.$iter() {
+                    if let Some(value) = field.value.$as_opt() {
+                        visit_expr(value, fun);
+                    }
+                }
+            }
+
+            fn visit_guards(guards: &$($m)* [Guard], fun: &mut dyn FnMut(&$($m)* Expr)) {
+                for guard in guards.$iter() {
+                    visit_exprs(&$($m)* guard.conditions, fun);
+                }
+            }
+
+            fn visit_clauses(clauses: &$($m)* [Clause], fun: &mut dyn FnMut(&$($m)* Expr)) {
+                for clause in clauses.$iter() {
+                    visit_expr(&$($m)* clause.pattern, fun);
+                    if let Some(guards) = clause.guard.$as_opt() {
+                        visit_guards(guards, fun);
+                    }
+                    visit_exprs(&$($m)* clause.body, fun);
+                }
+            }
+
+            fn visit_function_clause(clause: &$($m)* FunctionClause, fun: &mut dyn FnMut(&$($m)* Expr)) {
+                visit_exprs(&$($m)* clause.params, fun);
+                if let Some(guards) = clause.guard.$as_opt() {
+                    visit_guards(guards, fun);
+                }
+                visit_exprs(&$($m)* clause.body, fun);
+            }
+        }
+    };
+}
+
+expr_visitor!(by_mut, [mut], iter_mut, values_mut, as_mut);
+expr_visitor!(by_ref, [], iter, values, as_ref);
+
+/// Calls `fun` on every expression in the functions of `module`,
+/// children before their parents.
+///
+/// `fun` may replace the expression it is given, the replacement is not
+/// visited again.
+pub fn visit_module_exprs(module: &mut Module, fun: &mut dyn FnMut(&mut Expr)) {
+    by_mut::visit_module_exprs(module, fun)
+}
+
+/// Calls `fun` on `expr` and every expression contained in it, children
+/// before their parents.
+pub fn visit_expr(expr: &mut Expr, fun: &mut dyn FnMut(&mut Expr)) {
+    by_mut::visit_expr(expr, fun)
+}
+
+/// Like `visit_module_exprs`, for when the expressions are only looked
+/// at.
+pub fn visit_module_exprs_ref(module: &Module, fun: &mut dyn FnMut(&Expr)) {
+    by_ref::visit_module_exprs(module, fun)
+}
+
+/// Like `visit_expr`, for when the expressions are only looked at.
+pub fn visit_expr_ref(expr: &Expr, fun: &mut dyn FnMut(&Expr)) {
+    by_ref::visit_expr(expr, fun)
+}
@@ -26,6 +26,7 @@ pub enum Directive {
     Error(directives::Error),
     Warning(directives::Warning),
     File(directives::File),
+    Feature(directives::Feature),
 }
 impl Directive {
     pub fn span(&self) -> SourceSpan {
@@ -44,6 +45,7 @@ impl Directive {
             Directive::Error(ref t) => t.span(),
             Directive::Warning(ref t) => t.span(),
             Directive::File(ref t) => t.span(),
+            Directive::Feature(ref t) => t.span(),
         }
     }
 }
@@ -64,9 +66,36 @@ impl fmt::Display for Directive {
             Directive::Error(ref t) => t.fmt(f),
             Directive::Warning(ref t) => t.fmt(f),
             Directive::File(ref t) => t.fmt(f),
+            Directive::Feature(ref t) => t.fmt(f),
         }
     }
 }
+
+/// The keyword token that replaces the name of an attribute with a
+/// dedicated rule in the grammar, like `spec` in `-spec`.
+pub(crate) fn attribute_keyword(name: &str) -> Option<Token> {
+    let keyword = match name {
+        "compile" => Token::Compile,
+        "record" => Token::Record,
+        "spec" => Token::Spec,
+        "callback" => Token::Callback,
+        "optional_callback" => Token::OptionalCallback,
+        "import" => Token::Import,
+        "export" => Token::Export,
+        "export_type" => Token::ExportType,
+        "removed" => Token::Removed,
+        "vsn" => Token::Vsn,
+        "author" => Token::Author,
+        "on_load" => Token::OnLoad,
+        "behaviour" => Token::Behaviour,
+        "deprecated" => Token::Deprecated,
+        "type" => Token::Type,
+        "opaque" => Token::Opaque,
+        _ => return None,
+    };
+    Some(keyword)
+}
+
 impl ReadFrom for Directive {
     fn try_read_from<R, S>(reader: &mut R) -> Result<Option<Self>>
     where
@@ -93,33 +122,13 @@ impl ReadFrom for Directive {
             return Ok(None);
         };
 
-        let name_sym = name.symbol().as_str().get();
         // Replace atoms with more concrete tokens for special attributes,
         // but otherwise do nothing else with them
-        match name_sym {
-            "compile" => unread_token!(reader, _hyphen.into(), name, Token::Compile),
-            "record" => unread_token!(reader, _hyphen.into(), name, Token::Record),
-            "spec" => unread_token!(reader, _hyphen.into(), name, Token::Spec),
-            "callback" => unread_token!(reader, _hyphen.into(), name, Token::Callback),
-            "optional_callback" => {
-                unread_token!(reader, _hyphen.into(), name, Token::OptionalCallback)
-            }
-            "import" => unread_token!(reader, _hyphen.into(), name, Token::Import),
-            "export" => unread_token!(reader, _hyphen.into(), name, Token::Export),
-            "export_type" => unread_token!(reader, _hyphen.into(), name, Token::ExportType),
-            "removed" => unread_token!(reader, _hyphen.into(), name, Token::Removed),
-            "vsn" => unread_token!(reader, _hyphen.into(), name, Token::Vsn),
-            "author" => unread_token!(reader, _hyphen.into(), name, Token::Author),
-            "on_load" => unread_token!(reader, _hyphen.into(), name, Token::OnLoad),
-            "behaviour" => unread_token!(reader, _hyphen.into(), name, Token::Behaviour),
-            "deprecated" => unread_token!(reader, _hyphen.into(), name, Token::Deprecated),
-            "type" => unread_token!(reader, _hyphen.into(), name, Token::Type),
-            "opaque" => unread_token!(reader, _hyphen.into(), name, Token::Opaque),
-            _ => {
-                reader.unread_token(name.clone().into());
-                reader.unread_token(_hyphen.into());
-            }
+        if let Some(keyword) = attribute_keyword(name.symbol().as_str().get()) {
+            unread_token!(reader, _hyphen.into(), name, keyword);
         }
+        reader.unread_token(name.clone().into());
+        reader.unread_token(_hyphen.into());
 
         match name.symbol().as_str().get() {
             // -module(name) is treated as equivalent to -define(?MODULE, name)
@@ -138,6 +147,7 @@ impl ReadFrom for Directive {
             "error" => reader.read().map(Directive::Error).map(Some),
             "warning" => reader.read().map(Directive::Warning).map(Some),
             "file" => reader.read().map(Directive::File).map(Some),
+            "feature" => reader.read().map(Directive::Feature).map(Some),
             _ => Ok(None),
         }
     }
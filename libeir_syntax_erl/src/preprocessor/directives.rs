@@ -10,6 +10,7 @@ use libeir_util_parse::PathVariableSubstituteError;
 
 use crate::lexer::{symbols, Lexed, LexicalToken, Symbol, Token};
 use crate::lexer::{AtomToken, IntegerToken, StringToken, SymbolToken};
+use crate::parser::LookupRecorder;
 
 use super::token_reader::{ReadFrom, TokenReader};
 use super::types::{MacroName, MacroVariables};
@@ -178,11 +179,12 @@ impl ReadFrom for Module {
     }
 }
 
-fn do_include(subs_path: &PathBuf, include_paths: &VecDeque<PathBuf>) -> std::result::Result<PathBuf, Vec<String>> {
+fn do_include(subs_path: &PathBuf, include_paths: &VecDeque<PathBuf>, lookups: &LookupRecorder) -> std::result::Result<PathBuf, Vec<String>> {
     let mut tmp_path = PathBuf::new();
     for include_path in include_paths.iter() {
         tmp_path.push(include_path);
         tmp_path.push(subs_path);
+        lookups.record(&tmp_path);
         if tmp_path.exists() {
             return Ok(tmp_path);
         }
@@ -215,14 +217,14 @@ pub struct Include {
 }
 impl Include {
     /// Executes file inclusion.
-    pub fn include(&self, include_paths: &VecDeque<PathBuf>) -> DirectiveResult<PathBuf> {
+    pub fn include(&self, include_paths: &VecDeque<PathBuf>, lookups: &LookupRecorder) -> DirectiveResult<PathBuf> {
         let path = substitute_path_variables(self.path.symbol().as_str().get()).context(
             PathSubstitute {
                 span: self.path.span(),
             },
         )?;
 
-        match do_include(&path, include_paths) {
+        match do_include(&path, include_paths, lookups) {
             Ok(path) => Ok(path),
             Err(searched) => Err(DirectiveError::FileNotFound {
                 span: self.span(),
@@ -280,14 +282,14 @@ pub struct IncludeLib {
 }
 impl IncludeLib {
     /// Executes file inclusion.
-    pub fn include_lib(&self, include_paths: &VecDeque<PathBuf>, code_paths: &VecDeque<PathBuf>) -> DirectiveResult<PathBuf> {
+    pub fn include_lib(&self, include_paths: &VecDeque<PathBuf>, code_paths: &VecDeque<PathBuf>, lookups: &LookupRecorder) -> DirectiveResult<PathBuf> {
         let path = substitute_path_variables(self.path.symbol().as_str().get()).context(
             PathSubstitute {
                 span: self.path.span(),
             },
         )?;
 
-        let first_searched = match do_include(&path, include_paths) {
+        let first_searched = match do_include(&path, include_paths, lookups) {
             Ok(path) => return Ok(path),
             Err(searched) => searched,
         };
@@ -298,6 +300,7 @@ impl IncludeLib {
         if let Component::Normal(_app_name) = &components[0] {
             for root in code_paths.iter() {
                 let full_path = root.join(&path);
+                lookups.record(&full_path);
                 if full_path.exists() {
                     return Ok(full_path);
                 }
@@ -964,3 +967,59 @@ impl ReadFrom for File {
         })
     }
 }
+
+/// `feature` directive.
+///
+/// Enables or disables an optional language feature, e.g.
+/// `-feature(maybe_expr, enable).`
+#[derive(Debug, Clone)]
+pub struct Feature {
+    pub _hyphen: SymbolToken,
+    pub _feature: AtomToken,
+    pub _open_paren: SymbolToken,
+    pub name: AtomToken,
+    pub _comma: SymbolToken,
+    pub action: AtomToken,
+    pub _close_paren: SymbolToken,
+    pub _dot: SymbolToken,
+}
+impl Feature {
+    pub fn span(&self) -> SourceSpan {
+        let start = self._hyphen.0;
+        let end = self._dot.2;
+        SourceSpan::new(start, end)
+    }
+}
+impl Eq for Feature {}
+impl PartialEq for Feature {
+    fn eq(&self, other: &Self) -> bool {
+        self.name.symbol() == other.name.symbol() && self.action.symbol() == other.action.symbol()
+    }
+}
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "-feature({}, {}).",
+            self.name.symbol(),
+            self.action.symbol()
+        )
+    }
+}
+impl ReadFrom for Feature {
+    fn read_from<R, S>(reader: &mut R) -> Result<Self>
+    where
+        R: TokenReader<Source = S>,
+    {
+        Ok(Feature {
+            _hyphen: reader.read_expected(&Token::Minus)?,
+            _feature: reader.read_expected(&Symbol::intern("feature"))?,
+            _open_paren: reader.read_expected(&Token::LParen)?,
+            name: reader.read()?,
+            _comma: reader.read_expected(&Token::Comma)?,
+            action: reader.read()?,
+            _close_paren: reader.read_expected(&Token::RParen)?,
+            _dot: reader.read_expected(&Token::Dot)?,
+        })
+    }
+}
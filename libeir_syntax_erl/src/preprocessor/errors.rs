@@ -58,6 +58,9 @@ pub enum PreprocessorError {
     #[snafu(display("invalid conditional expression"))]
     InvalidConditional { span: SourceSpan },
 
+    #[snafu(display("invalid feature directive: {}", reason))]
+    InvalidFeature { span: SourceSpan, reason: String },
+
     #[snafu(visibility(pub), display("call to builtin function failed"))]
     BuiltinFailed {
         span: SourceSpan,
@@ -161,6 +164,13 @@ impl PreprocessorError {
                         Label::primary(span.source_id(), *span)
                             .with_message("expected 'true', 'false', or an expression which can be evaluated to 'true' or 'false'")
                     ]),
+            PreprocessorError::InvalidFeature { span, reason } =>
+                Diagnostic::error()
+                    .with_message("invalid feature directive")
+                    .with_labels(vec![
+                        Label::primary(span.source_id(), *span)
+                            .with_message(reason)
+                    ]),
             PreprocessorError::BuiltinFailed { span, source } =>
                 Diagnostic::error()
                     .with_message(self.to_string())
@@ -200,7 +210,7 @@ impl PreprocessorError {
                         Label::primary(span.source_id(), span)
                     ])
             }
-            PreprocessorError::BadMacroCall { call, def: MacroDef::String(_), reason, .. } => {
+            PreprocessorError::BadMacroCall { call, def: MacroDef::String(_) | MacroDef::Predefined, reason, .. } => {
                 let span = call.span();
                 Diagnostic::error()
                     .with_message(self.to_string())
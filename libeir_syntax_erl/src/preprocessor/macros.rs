@@ -130,6 +130,9 @@ pub enum MacroDef {
     Static(Define),
     Dynamic(Vec<LexicalToken>),
     DelayedSubstitution(DelayedSubstitution),
+    /// Built-in macro that is expanded by the preprocessor at the call
+    /// site, like `?OTP_RELEASE` or `?FEATURE_ENABLED(Feature)`.
+    Predefined,
 }
 impl MacroDef {
     /// Returns `true` if this macro has variables, otherwise `false`.
@@ -140,6 +143,7 @@ impl MacroDef {
             MacroDef::String(_) => false,
             MacroDef::Boolean(_) => false,
             MacroDef::DelayedSubstitution(_) => false,
+            MacroDef::Predefined => false,
         }
     }
 }
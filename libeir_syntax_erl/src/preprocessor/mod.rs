@@ -3,12 +3,14 @@ mod errors;
 //mod evaluator;
 mod macros;
 mod preprocessor;
+mod render;
 mod token_reader;
 mod token_stream;
 
 pub mod directives;
 pub mod types;
 
+pub(crate) use self::directive::attribute_keyword;
 pub use self::directive::Directive;
 pub use self::errors::PreprocessorError;
 pub use self::macros::{MacroCall, MacroContainer, MacroDef, MacroIdent};
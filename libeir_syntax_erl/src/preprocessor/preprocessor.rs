@@ -1,4 +1,4 @@
-use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::convert::TryFrom;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -6,21 +6,25 @@ use std::sync::Arc;
 use snafu::ResultExt;
 
 use libeir_diagnostics::*;
-use libeir_util_parse::{ErrorReceiver, ErrorReceiverTee, Source};
+use libeir_util_parse::{ErrorReceiver, ErrorReceiverTee, FileMapSource, Scanner, Source};
 
 use crate::evaluator;
 use crate::lexer::Lexer;
 use crate::lexer::{symbols, DelayedSubstitution, IdentToken, Lexed, LexicalToken, Symbol, Token};
-use crate::parser::Parser;
+use crate::parser::{LookupRecorder, MacroValue, Parser};
 
 use super::errors;
 use super::macros::Stringify;
+use super::render::Renderer;
 use super::token_reader::{TokenBufferReader, TokenReader, TokenStreamReader};
 use super::{Directive, MacroCall, MacroContainer, MacroDef, MacroIdent};
 use super::{Preprocessed, PreprocessorError, Result as PResult};
 
 type Errors<'a> = ErrorReceiverTee<'a, PreprocessorError, PreprocessorError>;
 
+/// Optional language features that can be toggled with `-feature(..)`.
+const FEATURES: &[&str] = &["maybe_expr"];
+
 macro_rules! error_into {
     ($errors:expr, $result:expr) => {
         match $result {
@@ -41,10 +45,13 @@ pub struct Preprocessor<'a, Reader: TokenReader> {
     directives: BTreeMap<SourceIndex, Directive>,
     code_paths: VecDeque<PathBuf>,
     include_paths: VecDeque<PathBuf>,
+    lookups: LookupRecorder,
     branches: Vec<Branch>,
     macros: MacroContainer,
     macro_calls: BTreeMap<SourceIndex, MacroCall>,
     expanded_tokens: VecDeque<LexicalToken>,
+    features: HashSet<Symbol>,
+    otp_release: u32,
     warnings_as_errors: bool,
     no_warn: bool,
 }
@@ -57,10 +64,8 @@ where
         let code_paths = parser.config.code_paths.clone();
         let include_paths = parser.config.include_paths.clone();
 
-        let mut macros = match parser.config.macros {
-            None => MacroContainer::new(),
-            Some(ref macros) => macros.clone(),
-        };
+        let mut errors = errors;
+        let mut macros = MacroContainer::new();
         macros.insert(
             MacroIdent::Const(Symbol::intern("FUNCTION_NAME")),
             MacroDef::DelayedSubstitution(DelayedSubstitution::FunctionName),
@@ -69,6 +74,38 @@ where
             MacroIdent::Const(Symbol::intern("FUNCTION_ARITY")),
             MacroDef::DelayedSubstitution(DelayedSubstitution::FunctionArity),
         );
+        macros.insert(
+            MacroIdent::Const(Symbol::intern("OTP_RELEASE")),
+            MacroDef::Predefined,
+        );
+        macros.insert(
+            MacroIdent::Func(Symbol::intern("FEATURE_AVAILABLE"), 1),
+            MacroDef::Predefined,
+        );
+        macros.insert(
+            MacroIdent::Func(Symbol::intern("FEATURE_ENABLED"), 1),
+            MacroDef::Predefined,
+        );
+        for (name, value) in parser.config.macros.iter() {
+            let def = match value {
+                MacroValue::Defined => MacroDef::Boolean(true),
+                MacroValue::Tokens(source) => {
+                    let id = parser.codemap.add(format!("-D{}", name), source.clone());
+                    let file = parser.codemap.get(id).unwrap();
+                    let lexer = Lexer::new(Scanner::new(FileMapSource::new(file)));
+                    let mut tokens = Vec::new();
+                    for lexed in lexer {
+                        match lexed {
+                            Ok(LexicalToken(_, Token::EOF, _)) => break,
+                            Ok(token) => tokens.push(token),
+                            Err(source) => errors.error(PreprocessorError::Lexical { source }),
+                        }
+                    }
+                    MacroDef::Dynamic(tokens)
+                }
+            };
+            macros.insert(MacroIdent::Const(Symbol::intern(name)), def);
+        }
 
         Preprocessor {
             errors,
@@ -78,10 +115,13 @@ where
             directives: BTreeMap::new(),
             code_paths,
             include_paths,
+            lookups: parser.config.lookups.clone(),
             branches: Vec::new(),
             macros,
             macro_calls: BTreeMap::new(),
             expanded_tokens: VecDeque::new(),
+            features: HashSet::new(),
+            otp_release: parser.config.otp_release,
             warnings_as_errors: parser.config.warnings_as_errors,
             no_warn: parser.config.no_warn,
         }
@@ -102,10 +142,13 @@ where
             directives: BTreeMap::new(),
             code_paths: self.code_paths.clone(),
             include_paths: self.include_paths.clone(),
+            lookups: self.lookups.clone(),
             branches: Vec::new(),
             macros: self.macros.clone(),
             macro_calls: BTreeMap::new(),
             expanded_tokens: VecDeque::new(),
+            features: self.features.clone(),
+            otp_release: self.otp_release,
             warnings_as_errors: self.warnings_as_errors,
             no_warn: self.no_warn,
         }
@@ -116,9 +159,15 @@ where
     }
 
     fn next_token(&mut self) -> Result<Option<LexicalToken>, ()> {
+        Ok(self.next_token_origin()?.map(|(token, _expanded)| token))
+    }
+
+    /// Like `next_token`, but also tells whether the token was produced
+    /// by expanding a macro rather than read from the source.
+    fn next_token_origin(&mut self) -> Result<Option<(LexicalToken, bool)>, ()> {
         loop {
             if let Some(token) = self.expanded_tokens.pop_front() {
-                return Ok(Some(token));
+                return Ok(Some((token, true)));
             }
             if self.can_directive_start {
                 match self.try_read_directive()? {
@@ -155,7 +204,7 @@ where
                 } else {
                     self.can_directive_start = false;
                 }
-                return Ok(Some(token));
+                return Ok(Some((token, false)));
             } else {
                 break;
             }
@@ -172,6 +221,9 @@ where
     }
 
     fn try_expand_predefined_macro(&self, call: &MacroCall) -> PResult<Option<LexicalToken>> {
+        if let Some(MacroDef::Predefined) = self.macros.get(call) {
+            return self.expand_builtin_macro(call).map(Some);
+        }
         let expanded = match call.name().as_str().get() {
             "FILE" => {
                 let span = call.span();
@@ -203,6 +255,34 @@ where
         Ok(Some(expanded))
     }
 
+    fn expand_builtin_macro(&self, call: &MacroCall) -> PResult<LexicalToken> {
+        let span = call.span();
+        let token = match call.name().as_str().get() {
+            "OTP_RELEASE" => Token::Integer((self.otp_release as i64).into()),
+            name @ "FEATURE_AVAILABLE" | name @ "FEATURE_ENABLED" => {
+                let args = call.args.as_ref().unwrap();
+                let feature = match &args.iter().next().unwrap().tokens[..] {
+                    [LexicalToken(_, Token::Atom(feature), _)] => *feature,
+                    _ => {
+                        return Err(PreprocessorError::BadMacroCall {
+                            call: call.clone(),
+                            def: MacroDef::Predefined,
+                            reason: "expected the name of a feature".to_string(),
+                        })
+                    }
+                };
+                let value = if name == "FEATURE_AVAILABLE" {
+                    FEATURES.contains(&&*feature.as_str())
+                } else {
+                    self.features.contains(&feature)
+                };
+                Token::Atom(if value { symbols::True } else { symbols::False })
+            }
+            _ => unreachable!(),
+        };
+        Ok(LexicalToken(span.start(), token, span.end()))
+    }
+
     fn expand_userdefined_macro(&self, call: MacroCall) -> PResult<VecDeque<LexicalToken>> {
         let span = call.span();
         let definition = match self.macros.get(&call) {
@@ -258,6 +338,7 @@ where
                 span.end(),
             )]
             .into()),
+            MacroDef::Predefined => unreachable!(),
         }
     }
 
@@ -322,7 +403,7 @@ where
             Directive::Module(ref d) => {
                 self.macros.insert(
                     MacroIdent::Const(symbols::ModuleCapital),
-                    MacroDef::String(d.name.symbol()),
+                    MacroDef::Dynamic(vec![d.name.clone().into()]),
                 );
                 self.macros.insert(
                     MacroIdent::Const(symbols::ModuleStringCapital),
@@ -332,14 +413,15 @@ where
             Directive::Include(ref d) if !ignore => {
                 let path = error_into!(
                     self.errors,
-                    d.include(&self.include_paths).context(errors::BadDirective)
+                    d.include(&self.include_paths, &self.lookups)
+                        .context(errors::BadDirective)
                 )?;
                 error_into!(self.errors, self.reader.inject_include(path, d.span()))?;
             }
             Directive::IncludeLib(ref d) if !ignore => {
                 let path = error_into!(
                     self.errors,
-                    d.include_lib(&self.include_paths, &self.code_paths)
+                    d.include_lib(&self.include_paths, &self.code_paths, &self.lookups)
                         .context(errors::BadDirective)
                 )?;
                 error_into!(self.errors, self.reader.inject_include(path, d.span()))?;
@@ -355,7 +437,9 @@ where
                 self.branches.push(Branch::new(entered));
             }
             Directive::If(ref d) => {
-                let entered = self.eval_conditional(d.span(), d.condition.clone())?;
+                // The condition of a skipped -if may refer to macros
+                // that are not defined, so it is not evaluated.
+                let entered = !ignore && self.eval_conditional(d.span(), d.condition.clone())?;
                 self.branches.push(Branch::new(entered));
             }
             Directive::Ifndef(ref d) => {
@@ -382,19 +466,25 @@ where
                 }
             },
             Directive::Elif(ref d) => {
-                // Treat this like -endif followed by -if(Cond)
-                match self.branches.pop() {
-                    None => {
+                // Only entered if none of the previous branches were
+                let taken = match self.branches.last() {
+                    Some(branch) if branch.then_branch => branch.taken,
+                    _ => {
                         return error_into!(
                             self.errors,
                             Err(PreprocessorError::OrphanedElse { directive })
                         )
                     }
-                    Some(_) => {
-                        let entered = self.eval_conditional(d.span(), d.condition.clone())?;
-                        self.branches.push(Branch::new(entered));
-                    }
-                }
+                };
+                let outer_ignored = self.branches[..self.branches.len() - 1]
+                    .iter()
+                    .any(|b| !b.entered);
+                let entered = !taken
+                    && !outer_ignored
+                    && self.eval_conditional(d.span(), d.condition.clone())?;
+                let branch = self.branches.last_mut().unwrap();
+                branch.entered = entered;
+                branch.taken |= entered;
             }
             Directive::Endif(_) => match self.branches.pop() {
                 None => {
@@ -437,6 +527,38 @@ where
                     self.errors.warning(err);
                 }
             }
+            Directive::Feature(ref d) if !ignore => {
+                let name = d.name.symbol();
+                if !FEATURES.contains(&&*name.as_str()) {
+                    return error_into!(
+                        self.errors,
+                        Err(PreprocessorError::InvalidFeature {
+                            span: d.span(),
+                            reason: format!("unknown feature '{}'", name),
+                        })
+                    );
+                }
+                match &*d.action.symbol().as_str() {
+                    "enable" => {
+                        self.features.insert(name);
+                    }
+                    "disable" => {
+                        self.features.remove(&name);
+                    }
+                    action => {
+                        return error_into!(
+                            self.errors,
+                            Err(PreprocessorError::InvalidFeature {
+                                span: d.span(),
+                                reason: format!(
+                                    "invalid feature action '{}', expected 'enable' or 'disable'",
+                                    action
+                                ),
+                            })
+                        );
+                    }
+                }
+            }
             Directive::File(ref f) if !ignore => {
                 // TODO
                 println!("TODO file directive {}", f);
@@ -455,6 +577,7 @@ where
         use crate::parser::ast::{Expr, Literal};
         use crate::parser::Parse;
 
+        let condition = self.resolve_defined(condition);
         let result = {
             let mut adapter = self.errors.make_adapter(
                 move |v| PreprocessorError::ParseError {
@@ -489,6 +612,84 @@ where
     }
 }
 
+impl<'a, R, S> Preprocessor<'a, R>
+where
+    R: TokenReader<Source = S>,
+{
+    /// Replaces `defined(Name)` in a condition with whether the macro
+    /// `Name` is defined. This has to be done before the condition is
+    /// parsed, as the name is not a valid expression on its own.
+    fn resolve_defined(&self, condition: VecDeque<Lexed>) -> VecDeque<Lexed> {
+        fn token(lexed: &Lexed) -> Option<&Token> {
+            lexed.as_ref().ok().map(|LexicalToken(_, token, _)| token)
+        }
+
+        let mut tokens: Vec<Lexed> = condition.into_iter().collect();
+        let mut idx = 0;
+        while idx + 3 < tokens.len() {
+            let is_defined = match (
+                token(&tokens[idx]),
+                token(&tokens[idx + 1]),
+                token(&tokens[idx + 3]),
+            ) {
+                (Some(Token::Atom(fun)), Some(Token::LParen), Some(Token::RParen)) => {
+                    &*fun.as_str() == "defined"
+                }
+                _ => false,
+            };
+            let name = match token(&tokens[idx + 2]) {
+                Some(Token::Atom(name)) | Some(Token::Ident(name)) => Some(*name),
+                _ => None,
+            };
+            if let (true, Some(name)) = (is_defined, name) {
+                let start = tokens[idx].as_ref().unwrap().0;
+                let end = tokens[idx + 3].as_ref().unwrap().2;
+                let value = if self.macros.defined(&name) {
+                    symbols::True
+                } else {
+                    symbols::False
+                };
+                let token = LexicalToken(start, Token::Atom(value), end);
+                tokens.splice(idx..idx + 4, Some(Ok(token)));
+            }
+            idx += 1;
+        }
+        tokens.into()
+    }
+
+    /// The keywords of disabled features are plain atoms.
+    fn apply_features(&self, token: LexicalToken) -> LexicalToken {
+        match token {
+            LexicalToken(start, Token::Maybe, end)
+                if !self.features.contains(&Symbol::intern("maybe_expr")) =>
+            {
+                LexicalToken(start, Token::Atom(Symbol::intern("maybe")), end)
+            }
+            LexicalToken(start, Token::Else, end)
+                if !self.features.contains(&Symbol::intern("maybe_expr")) =>
+            {
+                LexicalToken(start, Token::Atom(symbols::Else), end)
+            }
+            token => token,
+        }
+    }
+}
+
+impl<'a, R, S> Preprocessor<'a, R>
+where
+    R: TokenReader<Source = S>,
+{
+    /// Consumes the whole token stream and renders it back to Erlang
+    /// source, with `-file` attributes marking include boundaries.
+    pub fn render(mut self) -> Result<String, ()> {
+        let mut renderer = Renderer::new(self.codemap.clone());
+        while let Some((token, expanded)) = self.next_token_origin()? {
+            renderer.push(self.apply_features(token), expanded);
+        }
+        Ok(renderer.finish())
+    }
+}
+
 impl<'a, R, S> Iterator for Preprocessor<'a, R>
 where
     R: TokenReader<Source = S>,
@@ -499,7 +700,7 @@ where
         match self.next_token() {
             Err(()) => Some(Err(())),
             Ok(None) => None,
-            Ok(Some(token)) => Some(Ok(token.into())),
+            Ok(Some(token)) => Some(Ok(self.apply_features(token).into())),
         }
     }
 }
@@ -508,12 +709,15 @@ where
 struct Branch {
     pub then_branch: bool,
     pub entered: bool,
+    /// Whether this or any previous `-elif` branch was entered.
+    pub taken: bool,
 }
 impl Branch {
     pub fn new(entered: bool) -> Self {
         Branch {
             then_branch: true,
             entered,
+            taken: entered,
         }
     }
     pub fn switch_to_else_branch(&mut self) -> Result<(), ()> {
@@ -521,7 +725,8 @@ impl Branch {
             return Err(());
         }
         self.then_branch = false;
-        self.entered = !self.entered;
+        self.entered = !self.taken;
+        self.taken = true;
         Ok(())
     }
 }
@@ -0,0 +1,250 @@
+use std::fmt::Write;
+use std::mem;
+use std::sync::Arc;
+
+use libeir_diagnostics::{CodeMap, SourceId};
+
+use crate::lexer::{DelayedSubstitution, LexicalToken, Symbol, Token};
+
+/// Renders a preprocessed token stream back to Erlang source.
+///
+/// Tokens read from a file are kept on the line they were written on,
+/// and a `-file(Name, Line).` attribute is inserted whenever the
+/// tokens start coming from another file, like `erlc -P` does. Tokens
+/// produced by a macro expansion have the position of the macro
+/// definition, so they are placed after the token before them instead.
+///
+/// Tokens are buffered until the end of each form, so that
+/// `?FUNCTION_NAME` and `?FUNCTION_ARITY` can be expanded to the name
+/// and arity of the function the form defines.
+pub(super) struct Renderer {
+    codemap: Arc<CodeMap>,
+    out: String,
+    form: Vec<(LexicalToken, bool)>,
+    position: Option<(SourceId, usize)>,
+    previous: Option<Token>,
+    /// Whether the next token starts a new form.
+    form_start: bool,
+    /// Whether the previous token is the `-` of an attribute.
+    attribute_hyphen: bool,
+}
+impl Renderer {
+    pub fn new(codemap: Arc<CodeMap>) -> Self {
+        Renderer {
+            codemap,
+            out: String::new(),
+            form: Vec::new(),
+            position: None,
+            previous: None,
+            form_start: true,
+            attribute_hyphen: false,
+        }
+    }
+
+    pub fn push(&mut self, token: LexicalToken, expanded: bool) {
+        let form_end = token.1 == Token::Dot;
+        self.form.push((token, expanded));
+        if form_end {
+            self.flush_form();
+        }
+    }
+
+    pub fn finish(mut self) -> String {
+        self.flush_form();
+        self.newlines(1);
+        self.out
+    }
+
+    fn flush_form(&mut self) {
+        let form = mem::replace(&mut self.form, Vec::new());
+        let function = function_head(form.iter().map(|(token, _)| &token.1));
+        for (token, expanded) in form {
+            let LexicalToken(start, tok, end) = token;
+            let tok = match (tok, function) {
+                (
+                    Token::DelayedSubstitution(DelayedSubstitution::FunctionName),
+                    Some((name, _)),
+                ) => Token::Atom(name),
+                (
+                    Token::DelayedSubstitution(DelayedSubstitution::FunctionArity),
+                    Some((_, arity)),
+                ) => Token::Integer((arity as i64).into()),
+                (tok, _) => tok,
+            };
+            self.write(&LexicalToken(start, tok, end), expanded);
+        }
+    }
+
+    fn write(&mut self, token: &LexicalToken, expanded: bool) {
+        let LexicalToken(start, ref tok, _) = *token;
+
+        if !expanded || self.position.is_none() {
+            let source_id = start.source_id();
+            let file = self.codemap.get(source_id).unwrap();
+            let line = file.line_index(start.index()).to_usize() + 1;
+
+            match self.position {
+                Some((current_id, current_line)) if current_id == source_id => {
+                    if line > current_line {
+                        // Keep at most one empty line between forms
+                        let newlines = if line - current_line > 1 { 2 } else { 1 };
+                        self.newlines(newlines);
+                    }
+                }
+                _ => {
+                    self.newlines(1);
+                    if !self.out.is_empty() {
+                        self.out.push('\n');
+                    }
+                    writeln!(self.out, "-file(\"{}\", {}).", file.name(), line).unwrap();
+                }
+            }
+            self.position = Some((source_id, line));
+        }
+
+        if self.needs_space(tok) {
+            self.out.push(' ');
+        }
+        write_token(&mut self.out, tok);
+        self.previous = Some(tok.clone());
+        self.attribute_hyphen = self.form_start && *tok == Token::Minus;
+        self.form_start = *tok == Token::Dot;
+    }
+
+    fn newlines(&mut self, count: usize) {
+        if self.out.is_empty() || self.out.ends_with('\n') {
+            return;
+        }
+        for _ in 0..count {
+            self.out.push('\n');
+        }
+        self.previous = None;
+    }
+
+    fn needs_space(&self, next: &Token) -> bool {
+        let previous = match &self.previous {
+            None => return false,
+            Some(previous) => previous,
+        };
+        if self.attribute_hyphen {
+            return false;
+        }
+        match previous {
+            Token::LParen
+            | Token::LBracket
+            | Token::LBrace
+            | Token::BinaryStart
+            | Token::Pound
+            | Token::Colon
+            | Token::Question => return false,
+            _ => (),
+        }
+        match next {
+            Token::RParen
+            | Token::RBracket
+            | Token::RBrace
+            | Token::BinaryEnd
+            | Token::Comma
+            | Token::Semicolon
+            | Token::Dot
+            | Token::Colon => false,
+            // `f(X)` and `-export(...)` rather than `f (X)`
+            Token::LParen => match previous {
+                Token::Atom(_)
+                | Token::Ident(_)
+                | Token::RParen
+                | Token::Fun
+                | Token::Module
+                | Token::Export
+                | Token::ExportType
+                | Token::Import
+                | Token::Record
+                | Token::Spec
+                | Token::Callback
+                | Token::OptionalCallback
+                | Token::Type
+                | Token::Opaque
+                | Token::Compile
+                | Token::Vsn
+                | Token::Author
+                | Token::OnLoad
+                | Token::Behaviour
+                | Token::Deprecated
+                | Token::Removed
+                | Token::File => false,
+                _ => true,
+            },
+            _ => true,
+        }
+    }
+}
+
+/// The name and arity of the function defined by a form, given its
+/// tokens. Returns `None` if the form is not a function.
+fn function_head<'a, I>(mut tokens: I) -> Option<(Symbol, usize)>
+where
+    I: Iterator<Item = &'a Token>,
+{
+    let name = match (tokens.next(), tokens.next()) {
+        (Some(Token::Atom(name)), Some(Token::LParen)) => *name,
+        _ => return None,
+    };
+    let mut depth = 0;
+    let mut arity = 0;
+    for token in tokens {
+        if depth == 0 && *token == Token::RParen {
+            return Some((name, arity));
+        }
+        arity = arity.max(1);
+        match token {
+            Token::LParen | Token::LBracket | Token::LBrace | Token::BinaryStart => depth += 1,
+            Token::RParen | Token::RBracket | Token::RBrace | Token::BinaryEnd => depth -= 1,
+            Token::Comma if depth == 0 => arity += 1,
+            _ => (),
+        }
+    }
+    None
+}
+
+fn write_token(out: &mut String, token: &Token) {
+    match token {
+        Token::Char(c) => {
+            if c.is_alphanumeric() || (c.is_ascii_punctuation() && *c != '\\') {
+                write!(out, "${}", c).unwrap();
+            } else {
+                write!(out, "$\\x{{{:X}}}", *c as u32).unwrap();
+            }
+        }
+        Token::Float(float) => {
+            // Erlang requires a fraction before the exponent, `1.0e100`
+            let repr = format!("{:?}", float.inner());
+            match repr.find('e') {
+                Some(idx) if !repr[..idx].contains('.') => {
+                    write!(out, "{}.0{}", &repr[..idx], &repr[idx..]).unwrap()
+                }
+                _ => out.push_str(&repr),
+            }
+        }
+        // Atoms only need quotes when they would not lex as an atom
+        Token::Atom(atom) if !atom.is_keyword() && is_plain_atom(&atom.as_str()) => {
+            out.push_str(&atom.as_str())
+        }
+        // Outside of a function there is nothing to expand them to
+        Token::DelayedSubstitution(DelayedSubstitution::FunctionName) => {
+            out.push_str("?FUNCTION_NAME")
+        }
+        Token::DelayedSubstitution(DelayedSubstitution::FunctionArity) => {
+            out.push_str("?FUNCTION_ARITY")
+        }
+        token => write!(out, "{}", token).unwrap(),
+    }
+}
+
+fn is_plain_atom(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_lowercase() => (),
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '@')
+}
@@ -0,0 +1,63 @@
+use crate::run;
+
+#[test]
+fn binary_comprehension() {
+    run(
+        "bin_compr",
+        "-module(bin_compr).
+
+run() ->
+    <<2, 4, 6>> = double(<<1, 2, 3>>),
+    <<>> = double(<<>>),
+    <<1, 2>> = identity(<<1, 2>>),
+    <<1, 0, 2, 0>> = << <<X:16/little>> || X <- [1, 2] >>,
+    true.
+
+double(Bin) -> << <<(X * 2)>> || <<X:8>> <= Bin >>.
+
+identity(Bin) -> << <<X>> || <<X>> <= Bin >>.
+",
+    );
+}
+
+#[test]
+fn binary_generator_in_list_comprehension() {
+    run(
+        "bin_gen",
+        "-module(bin_gen).
+
+run() ->
+    [258, 772] = words(<<1, 2, 3, 4>>),
+    % Trailing bits that do not fill a whole element are ignored
+    [258] = words(<<1, 2, 3>>),
+    [] = words(<<>>),
+    [{1, 2}, {3, 4}] = [{A, B} || <<A, B>> <= <<1, 2, 3, 4>>],
+    true.
+
+words(Bin) -> [X || <<X:16>> <= Bin].
+",
+    );
+}
+
+#[test]
+fn binary_generator_sizes_and_filters() {
+    run(
+        "bin_gen_sizes",
+        "-module(bin_gen_sizes).
+
+run() ->
+    [1, 2, 3] = sized(8, <<1, 2, 3>>),
+    [258] = sized(16, <<1, 2>>),
+    % Size bound earlier in the same pattern
+    [1, 770] = [X || <<N:8, X:N>> <= <<8, 1, 16, 3, 2>>],
+    [2, 4] = [X || <<X>> <= <<1, 2, 3, 4>>, X rem 2 =:= 0],
+    <<3, 4>> = << <<X>> || <<X>> <= <<1, 2, 3, 4>>, X > 2 >>,
+    % Elements that do not match the pattern are skipped
+    [1, 3] = [X || <<0, X>> <= <<0, 1, 5, 2, 0, 3>>],
+    [{1, a}, {1, b}, {2, a}, {2, b}] = [{X, Y} || <<X>> <= <<1, 2>>, Y <- [a, b]],
+    true.
+
+sized(Size, Bin) -> [X || <<X:Size>> <= Bin].
+",
+    );
+}
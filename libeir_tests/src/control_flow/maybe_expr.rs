@@ -0,0 +1,77 @@
+use crate::run;
+
+#[test]
+fn maybe_expr() {
+    run(
+        "maybe_expr",
+        "-module(maybe_expr).
+-feature(maybe_expr, enable).
+
+run() ->
+    {ok, 3} = sum({ok, 1}, {ok, 2}),
+    {error, a} = sum({error, a}, {ok, 2}),
+    {error, b} = sum({ok, 1}, {error, b}),
+
+    3 = with_else({ok, 1}, {ok, 2}),
+    error = with_else({error, a}, {ok, 2}),
+    other = with_else({ok, 1}, other),
+    ok = try with_else(nope, {ok, 2})
+         catch error:{else_clause, nope} -> ok
+         end,
+
+    ok = last(ok),
+    nope = last(nope),
+
+    {same, 1} = same(1, 1),
+    {different, 2} = same(1, 2),
+    true.
+
+sum(A, B) ->
+    maybe
+        {ok, X} ?= A,
+        {ok, Y} ?= B,
+        {ok, X + Y}
+    end.
+
+with_else(A, B) ->
+    maybe
+        {ok, X} ?= A,
+        {ok, Y} ?= B,
+        X + Y
+    else
+        {error, _} -> error;
+        other -> other
+    end.
+
+last(A) ->
+    maybe
+        ok ?= A
+    end.
+
+same(A, B) ->
+    maybe
+        X ?= A,
+        X ?= B,
+        {same, X}
+    else
+        Other -> {different, Other}
+    end.
+",
+    );
+}
+
+#[test]
+fn maybe_expr_disabled() {
+    // Without the feature, `maybe` and `else` are plain atoms
+    run(
+        "maybe_atoms",
+        "-module(maybe_atoms).
+
+run() ->
+    [maybe, else] = atoms(),
+    true.
+
+atoms() -> [maybe, 'else'].
+",
+    );
+}
@@ -2,4 +2,5 @@ mod fib;
 //mod nth_root;
 mod accumulate_list;
 mod get_values;
+mod maybe_expr;
 mod shadowing;
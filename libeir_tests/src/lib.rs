@@ -5,18 +5,24 @@ use std::path::Path;
 use std::sync::Arc;
 
 use libeir_diagnostics::*;
+use libeir_intern::Ident;
 use libeir_ir::{FunctionIdent, Module};
-use libeir_syntax_erl::lower_module;
+use libeir_syntax_erl::{apply_transforms, lower_module, TransformRegistry};
 use libeir_syntax_erl::{ErlangError, Parse, ParseConfig, Parser, ParserError};
 use libeir_util_parse::{error_tee, Errors};
 
 use libeir_util_dot_graph::GraphPrinter;
 
+use libeir_interpreter::VMState;
+
+mod binary_comprehensions;
 mod control_flow;
 mod ct_runner;
 mod errors;
 mod list_comprehensions;
+mod map_comprehensions;
 mod otp;
+mod parse_transforms;
 mod patterns;
 mod records;
 
@@ -28,7 +34,12 @@ where
     let codemap = Arc::new(CodeMap::new());
     let eir_res = error_tee(&mut errors, |mut errors| {
         let parser = Parser::new(config, codemap.clone());
-        let ast = parser.parse_file(&mut errors.make_into_adapter(), path)?;
+        let mut ast = parser.parse_file(&mut errors.make_into_adapter(), path)?;
+        apply_transforms(
+            &TransformRegistry::default(),
+            &mut errors.make_into_adapter(),
+            &mut ast,
+        )?;
         let eir = lower_module(&mut errors.make_into_adapter(), codemap.clone(), &ast)?;
         Ok(eir)
     });
@@ -46,7 +57,12 @@ where
     let codemap = Arc::new(CodeMap::new());
     let eir_res = error_tee(&mut errors, |mut errors| {
         let parser = Parser::new(config, codemap.clone());
-        let ast = parser.parse_string(&mut errors.make_into_adapter(), input)?;
+        let mut ast = parser.parse_string(&mut errors.make_into_adapter(), input)?;
+        apply_transforms(
+            &TransformRegistry::default(),
+            &mut errors.make_into_adapter(),
+            &mut ast,
+        )?;
         let eir = lower_module(&mut errors.make_into_adapter(), codemap.clone(), &ast)?;
         Ok(eir)
     });
@@ -56,6 +72,35 @@ where
     eir_res
 }
 
+/// Lowers `source`, runs the default pass pipeline over it and checks
+/// that `module:run/0` returns `true` in the interpreter.
+fn run(module: &str, source: &str) {
+    let _ = env_logger::try_init();
+
+    let mut eir_mod = lower(source, ParseConfig::default()).unwrap();
+
+    for fun_def in eir_mod.function_iter() {
+        let fun = fun_def.function();
+        let mut out = Vec::new();
+        fun.validate(&mut out);
+        assert!(out.len() == 0);
+    }
+
+    let mut pass_manager = libeir_passes::PassManager::default();
+    pass_manager.run(&mut eir_mod);
+
+    let mut vm = VMState::new();
+    vm.add_builtin_modules();
+    vm.add_erlang_module(eir_mod);
+
+    let run_fun = FunctionIdent {
+        module: Ident::from_str(module),
+        name: Ident::from_str("run"),
+        arity: 0,
+    };
+    assert!(vm.call(&run_fun, &[]).unwrap().as_boolean() == Some(true));
+}
+
 pub fn write_dot(module: &Module, ident: Option<FunctionIdent>) {
     if let Some(ident) = ident {
         let idx = module.ident_index(&ident).unwrap();
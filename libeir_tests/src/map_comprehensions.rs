@@ -0,0 +1,38 @@
+use crate::run;
+
+#[test]
+fn map_comprehension() {
+    run(
+        "map_compr",
+        "-module(map_compr).
+
+run() ->
+    M = #{a => 1, b => 2},
+    true = #{a => 2, b => 4} =:= double(M),
+    true = #{} =:= double(#{}),
+    true = #{1 => a, 2 => b} =:= #{V => K || {K, V} <- [{a, 1}, {b, 2}]},
+    true = #{b => 2} =:= #{K => V || K := V <- M, V > 1},
+    true.
+
+double(M) -> #{K => V * 2 || K := V <- M}.
+",
+    );
+}
+
+#[test]
+fn map_generator_in_list_comprehension() {
+    run(
+        "map_gen",
+        "-module(map_gen).
+
+run() ->
+    M = #{a => 1, b => 2, c => 1},
+    [{a, 1}, {b, 2}, {c, 1}] = [{K, V} || K := V <- M],
+    [] = [K || K := _ <- #{}],
+    % Associations that do not match the pattern are skipped
+    [a, c] = [K || K := 1 <- M],
+    [{a, x}, {a, y}] = [{K, X} || K := 1 <- #{a => 1}, X <- [x, y]],
+    true.
+",
+    );
+}
@@ -0,0 +1,17 @@
+use crate::run;
+
+#[test]
+fn ets_fun2ms() {
+    run(
+        "ms",
+        "
+-module(ms).
+-compile({parse_transform, ms_transform}).
+
+run() ->
+    Min = 1,
+    Spec = ets:fun2ms(fun({A, B}) when A > Min -> {B, A} end),
+    Spec =:= [{{'$1', '$2'}, [{'>', '$1', {const, 1}}], [{{'$2', '$1'}}]}].
+",
+    );
+}
@@ -263,3 +263,49 @@ end.
         ])));
     }
 }
+
+#[test]
+fn test_binary_pattern_merge() {
+    let _ = env_logger::try_init();
+
+    let mut eir_mod = lower(
+        "
+-module(woo).
+
+run() ->
+    {1, 1} = heads(<<1, 2, 3>>),
+    {1, <<2>>} = tagged(<<1, 2>>),
+    error = tagged(<<2, 2>>),
+    {<<7, 8>>, 8} = whole(<<7, 8>>),
+    error = whole(<<7>>),
+    true.
+
+heads(Bin) ->
+    <<A:8, _/binary>> = <<B:8, _/binary>> = Bin,
+    {A, B}.
+
+tagged(<<1, _/binary>> = <<Tag:8, Rest/binary>>) -> {Tag, Rest};
+tagged(_) -> error.
+
+whole(<<All/binary>> = <<_:8, Last:8>>) -> {All, Last};
+whole(_) -> error.
+",
+        ParseConfig::default(),
+    )
+    .unwrap();
+
+    let mut pass_manager = PassManager::default();
+    pass_manager.run(&mut eir_mod);
+
+    let fun = FunctionIdent {
+        module: Ident::from_str("woo"),
+        name: Ident::from_str("run"),
+        arity: 0,
+    };
+
+    let mut vm = VMState::new();
+    vm.add_builtin_modules();
+    vm.add_erlang_module(eir_mod);
+
+    assert!(vm.call(&fun, &[]).unwrap().as_boolean() == Some(true));
+}
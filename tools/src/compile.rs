@@ -1,40 +1,58 @@
 use std::io::Write;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::str::FromStr;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Instant;
 
 use clap::{arg_enum, value_t, values_t, App, Arg, ArgMatches};
 
-use libeir_diagnostics::term::{
-    self,
-    termcolor::{ColorChoice, StandardStream},
-};
-use libeir_diagnostics::CodeMap;
-use libeir_frontend::{
-    abstr_erlang::AbstrErlangFrontend, eir::EirFrontend, erlang::ErlangFrontend, AnyFrontend,
-    DynFrontend,
+use libeir_diagnostics::{CodeMap, Diagnostic, Severity};
+use libeir_frontend::{cache::CachingErlangFrontend, erlang::ErlangFrontend, DynFrontend};
+use libeir_ir::text::printer::StandardFormatConfig;
+use libeir_ir::text::{function_to_dot, function_to_dot_by_source_line};
+use libeir_ir::{FunctionIdent, Module};
+use libeir_passes::{DumpConfig, PassManager};
+use libeir_syntax_erl::{ParseConfig, Parser, ParserError};
+use libeir_util_parse::Errors;
+
+use tools::frontend::{
+    emit_diagnostics, frontend_args, make_frontend, make_parse_config, InputType,
 };
-use libeir_ir::FunctionIdent;
-use libeir_passes::PassManager;
+use tools::project::Project;
 
-arg_enum! {
-    #[derive(Debug, PartialEq, Eq)]
-    pub enum OutputType {
-        Eir,
-        Dot,
+#[derive(Debug, PartialEq, Eq)]
+pub enum OutputType {
+    Eir,
+    Dot,
+    Html,
+    /// Preprocessed Erlang source, like `erlc -P`.
+    ErlPp,
+}
+impl OutputType {
+    fn variants() -> [&'static str; 4] {
+        ["eir", "dot", "html", "erl-pp"]
     }
 }
-
-arg_enum! {
-    #[derive(Debug, PartialEq, Eq)]
-    pub enum InputType {
-        Eir,
-        Abstr,
-        Erl,
+impl FromStr for OutputType {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        match &*s.to_lowercase() {
+            "eir" => Ok(OutputType::Eir),
+            "dot" => Ok(OutputType::Dot),
+            "html" => Ok(OutputType::Html),
+            "erl-pp" => Ok(OutputType::ErlPp),
+            _ => Err(format!(
+                "valid values: {}",
+                OutputType::variants().join(", ")
+            )),
+        }
     }
 }
 
 arg_enum! {
-    #[derive(Debug)]
+    #[derive(Debug, Copy, Clone)]
     pub enum CompileLevel {
         High,
         Normal,
@@ -43,7 +61,7 @@ arg_enum! {
 }
 
 arg_enum! {
-    #[derive(Debug)]
+    #[derive(Debug, Copy, Clone)]
     pub enum CompilePass {
         CompilePatterns,
         SimplifyCfg,
@@ -74,33 +92,322 @@ impl LogLevel {
     }
 }
 
-fn make_erlang_frontend(codemap: Arc<CodeMap>, matches: &ArgMatches) -> ErlangFrontend {
-    use libeir_syntax_erl::ParseConfig;
+/// The pass pipeline selected on the command line. Unlike a
+/// `PassManager`, it can be sent to the threads compiling a project.
+#[derive(Debug, Clone)]
+struct Pipeline {
+    level: CompileLevel,
+    passes: Vec<CompilePass>,
+}
+impl Pipeline {
+    fn from_matches(matches: &ArgMatches) -> Self {
+        let passes = if matches.is_present("PASSES") {
+            values_t!(matches.values_of("PASSES"), CompilePass).unwrap()
+        } else {
+            Vec::new()
+        };
+        Pipeline {
+            level: value_t!(matches, "COMPILE_LEVEL", CompileLevel).unwrap(),
+            passes,
+        }
+    }
+
+    fn pass_manager(&self) -> PassManager {
+        match self.level {
+            CompileLevel::High => PassManager::new(),
+            CompileLevel::Normal => PassManager::default(),
+            CompileLevel::Custom => {
+                let mut pass_manager = PassManager::new();
+                for pass_type in self.passes.iter() {
+                    match pass_type {
+                        CompilePass::CompilePatterns => {
+                            pass_manager
+                                .push_function_pass(libeir_passes::CompilePatternPass::new());
+                        }
+                        CompilePass::SimplifyCfg => {
+                            pass_manager.push_function_pass(libeir_passes::SimplifyCfgPass::new());
+                        }
+                        CompilePass::NaiveInlineClosures => {
+                            pass_manager
+                                .push_function_pass(libeir_passes::NaiveInlineClosuresPass::new());
+                        }
+                        CompilePass::Validate => {
+                            pass_manager.push_function_pass(libeir_passes::ValidatePass::new());
+                        }
+                    }
+                }
+                pass_manager
+            }
+        }
+    }
+}
+
+/// Writes the preprocessed input file instead of compiling it.
+fn preprocess(codemap: Arc<CodeMap>, matches: &ArgMatches) {
+    let in_file_name = matches.value_of("IN_FILE").unwrap();
+
+    let parser = Parser::new(make_parse_config(matches), codemap.clone());
+    let mut errors: Errors<ParserError, ParserError> = Errors::new();
+    let res = libeir_syntax_erl::preprocess_file(&parser, &mut errors, in_file_name);
+    errors.print(&codemap);
+
+    let out_data = match res {
+        Ok(out_data) => out_data,
+        Err(()) => return,
+    };
+    let out_file_name = matches
+        .value_of("OUT_FILE")
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| format!("{}.P", in_file_name));
 
-    let mut config = ParseConfig::default();
+    println!("Writing to {}", out_file_name);
+    let mut out = ::std::fs::File::create(&out_file_name).unwrap();
+    out.write(out_data.as_bytes()).unwrap();
+}
+
+/// A lowered module of a project, waiting for the pass pipeline.
+struct ProjectJob {
+    module: Module,
+    out_file: PathBuf,
+}
 
-    if let Some(includes) = matches.values_of("INCLUDE_PATHS") {
-        for include in includes {
-            config.include_paths.push_front(PathBuf::from(include));
+/// Runs the pass pipeline on the module of `job` and writes it out,
+/// returning the name of the module and the written file.
+fn run_project_job(pipeline: &Pipeline, job: ProjectJob) -> (String, Result<PathBuf, String>) {
+    let ProjectJob {
+        mut module,
+        out_file,
+    } = job;
+    let name = module.name().to_string();
+
+    // A panic in one module should not take down the whole project.
+    let res = panic::catch_unwind(AssertUnwindSafe(|| {
+        pipeline.pass_manager().run(&mut module);
+        module.to_text_standard()
+    }));
+    let out_data = match res {
+        Ok(out_data) => out_data,
+        Err(_) => return (name, Err("panicked in the pass pipeline".to_string())),
+    };
+
+    if let Some(dir) = out_file.parent() {
+        if let Err(err) = std::fs::create_dir_all(dir) {
+            return (
+                name,
+                Err(format!("{} occurred when creating {:?}", err, dir)),
+            );
+        }
+    }
+    match std::fs::write(&out_file, out_data) {
+        Ok(()) => (name, Ok(out_file)),
+        Err(err) => (
+            name,
+            Err(format!("{} occurred when writing {:?}", err, out_file)),
+        ),
+    }
+}
+
+/// What `build_project` needs besides the project itself.
+struct ProjectOptions {
+    /// The configuration shared by all groups, which add their own
+    /// include paths, code paths and macros to it.
+    config: ParseConfig,
+    /// Overrides the `outdir` of every group.
+    out_dir: Option<PathBuf>,
+    cache_dir: Option<PathBuf>,
+    jobs: usize,
+    pipeline: Pipeline,
+}
+
+/// The outcome of `build_project`.
+struct ProjectReport {
+    diagnostics: Vec<Diagnostic>,
+    compiled: usize,
+    /// The files or modules that failed, with the reason.
+    failed: Vec<(String, String)>,
+}
+
+/// Compiles every module of `project`.
+///
+/// All modules are parsed and lowered with a shared `CodeMap` first, the
+/// pass pipeline is then run on `options.jobs` threads, writing one
+/// output file per module. With a cache directory, modules whose
+/// sources, headers and configuration did not change are loaded from
+/// the cache instead of being lowered again.
+fn build_project(
+    codemap: &Arc<CodeMap>,
+    project: &Project,
+    options: &ProjectOptions,
+) -> ProjectReport {
+    let mut diagnostics = Vec::new();
+    let mut jobs = Vec::new();
+    let mut failed: Vec<(String, String)> = Vec::new();
+    for group in project.groups.iter() {
+        let mut config = options.config.clone();
+        config
+            .include_paths
+            .extend(group.include_paths.iter().cloned());
+        config.code_paths.extend(project.code_paths.iter().cloned());
+        for (name, value) in group.defines.iter() {
+            config.define_macro(name.clone(), value.clone());
+        }
+        let frontend = ErlangFrontend::new(config, codemap.clone());
+        let frontend: Box<dyn DynFrontend> = match options.cache_dir.as_ref() {
+            Some(dir) => Box::new(CachingErlangFrontend::new(frontend, dir.clone())),
+            None => Box::new(frontend),
+        };
+
+        for file in group.files.iter() {
+            let file_name = file.display().to_string();
+            let res = panic::catch_unwind(AssertUnwindSafe(|| frontend.parse_file_dyn(file)));
+            let module = match res {
+                Ok((res, file_diagnostics)) => {
+                    diagnostics.extend(file_diagnostics);
+                    res
+                }
+                Err(_) => {
+                    failed.push((file_name, "panicked in the frontend".to_string()));
+                    continue;
+                }
+            };
+            let module = match module {
+                Ok(module) => module,
+                Err(()) => {
+                    failed.push((file_name, "failed to compile".to_string()));
+                    continue;
+                }
+            };
+
+            let out_file = match options.out_dir.as_ref().or(group.outdir.as_ref()) {
+                Some(dir) => dir.join(format!("{}.eir", module.name())),
+                None => PathBuf::from(format!("{}.eir", file_name)),
+            };
+            jobs.push(ProjectJob { module, out_file });
         }
     }
-    if let Some(includes) = matches.values_of("CODE_PATHS") {
-        for include in includes {
-            config.code_paths.push_front(PathBuf::from(include));
+
+    let queue = Arc::new(Mutex::new(jobs));
+    let (sender, receiver) = mpsc::channel();
+    let workers: Vec<_> = (0..options.jobs.max(1))
+        .map(|_| {
+            let queue = queue.clone();
+            let sender = sender.clone();
+            let pipeline = options.pipeline.clone();
+            thread::spawn(move || loop {
+                let job = queue.lock().unwrap().pop();
+                match job {
+                    Some(job) => sender.send(run_project_job(&pipeline, job)).unwrap(),
+                    None => break,
+                }
+            })
+        })
+        .collect();
+    drop(sender);
+
+    let mut compiled = 0;
+    for (name, result) in receiver.iter() {
+        match result {
+            Ok(_) => compiled += 1,
+            Err(reason) => failed.push((name, reason)),
         }
     }
+    for worker in workers {
+        worker.join().unwrap();
+    }
 
-    ErlangFrontend::new(config, codemap)
+    failed.sort();
+    ProjectReport {
+        diagnostics,
+        compiled,
+        failed,
+    }
 }
 
-fn make_frontend(codemap: Arc<CodeMap>, matches: &ArgMatches) -> AnyFrontend {
-    match value_t!(matches, "IN_FORMAT", InputType).unwrap() {
-        InputType::Erl => make_erlang_frontend(codemap, matches).into(),
-        InputType::Abstr => AbstrErlangFrontend::new(codemap).into(),
-        InputType::Eir => EirFrontend::new(codemap).into(),
+/// Compiles the project given on the command line, a directory or an
+/// `Emakefile`, and exits with an error if any module failed.
+fn compile_project(codemap: Arc<CodeMap>, matches: &ArgMatches) {
+    let start = Instant::now();
+
+    let project_path = Path::new(matches.value_of("PROJECT").unwrap());
+    let mut diagnostics = Vec::new();
+    let project = match Project::load(&codemap, project_path, &mut diagnostics) {
+        Ok(project) => project,
+        Err(()) => {
+            emit_diagnostics(&codemap, &diagnostics);
+            std::process::exit(1);
+        }
+    };
+    emit_diagnostics(&codemap, &diagnostics);
+
+    let options = ProjectOptions {
+        config: make_parse_config(matches),
+        out_dir: matches.value_of("OUT_DIR").map(PathBuf::from),
+        cache_dir: matches.value_of("CACHE_DIR").map(PathBuf::from),
+        jobs: value_t!(matches, "JOBS", usize).unwrap().max(1),
+        pipeline: Pipeline::from_matches(matches),
+    };
+    let report = build_project(&codemap, &project, &options);
+    emit_diagnostics(&codemap, &report.diagnostics);
+
+    let count = |severity| {
+        report
+            .diagnostics
+            .iter()
+            .filter(|diag| diag.severity == severity)
+            .count()
+    };
+    println!(
+        "Compiled {} of {} modules in {:.2?} using {} threads, {} errors, {} warnings",
+        report.compiled,
+        project.num_files(),
+        start.elapsed(),
+        options.jobs,
+        count(Severity::Error),
+        count(Severity::Warning),
+    );
+    if !report.failed.is_empty() {
+        println!("Failed:");
+        for (name, reason) in report.failed.iter() {
+            println!("    {}: {}", name, reason);
+        }
+        std::process::exit(1);
+    }
+}
+
+/// Renders a graph to svg with `dot`. Returns `None` if `dot` can not
+/// be run, the html export then shows the dot source.
+fn render_svg(dot: &str) -> Option<String> {
+    use std::process::{Command, Stdio};
+
+    let run = || -> std::io::Result<std::process::Output> {
+        let mut child = Command::new("dot")
+            .arg("-Tsvg")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        child.stdin.take().unwrap().write_all(dot.as_bytes())?;
+        child.wait_with_output()
+    };
+    match run() {
+        Ok(res) if res.status.success() => Some(String::from_utf8_lossy(&res.stdout).into_owned()),
+        Ok(res) => {
+            println!("dot failed: {}", String::from_utf8_lossy(&res.stderr));
+            None
+        }
+        Err(err) => {
+            println!("Failed to run dot: {}", err);
+            None
+        }
     }
 }
 
+/// Exits with an error about an invalid combination of arguments, in
+/// the way clap reports usage errors.
+fn usage_error(message: &str) -> ! {
+    clap::Error::with_description(message, clap::ErrorKind::ArgumentConflict).exit()
+}
+
 fn setup_logger(level: log::LevelFilter) {
     fern::Dispatch::new()
         .format(|out, message, record| {
@@ -125,15 +432,34 @@ fn main() {
         .arg(
             Arg::with_name("IN_FILE")
                 .help("Input file for compiler")
-                .required(true),
+                .required_unless("PROJECT"),
         )
         .arg(
-            Arg::from_usage("<IN_FORMAT> -f,--in-format <IN_FORMAT> 'input format'")
-                .default_value("erl")
-                .required(true)
-                .case_insensitive(true)
-                .possible_values(&InputType::variants()),
+            Arg::from_usage(
+                "<PROJECT> --project <PATH> 'compile every module of a directory or Emakefile'",
+            )
+            .required(false)
+            .conflicts_with("IN_FILE"),
         )
+        .arg(
+            Arg::from_usage("<JOBS> -j,--jobs <N> 'number of modules compiled in parallel'")
+                .default_value("1")
+                .required(false)
+                .requires("PROJECT"),
+        )
+        .arg(
+            Arg::from_usage("<OUT_DIR> --outdir <DIR> 'output directory for project modules'")
+                .required(false)
+                .requires("PROJECT"),
+        )
+        .arg(
+            Arg::from_usage(
+                "<CACHE_DIR> --cache-dir <DIR> 'reuse lowered modules of unchanged sources'",
+            )
+            .required(false)
+            .requires("PROJECT"),
+        )
+        .args(&frontend_args())
         .arg(
             Arg::from_usage("<OUT_FORMAT> -p,--out-format <OUT_FORMAT> 'output format'")
                 .default_value("eir")
@@ -162,24 +488,40 @@ fn main() {
         ))
         .arg(
             Arg::from_usage(
-                "<INCLUDE_PATHS> -I <INCLUDE_PATH> 'add include path for the erlang preprocessor'",
+                "[ANNOTATE_SOURCE] --annotate-source 'annotate eir and dot output with the source lines blocks were lowered from'",
             )
-            .required(false)
-            .multiple(true),
+            .conflicts_with("PROJECT"),
+        )
+        .arg(
+            Arg::from_usage("<PASSES> --pass <PASS> 'run the given compilation pass'")
+                .required(false)
+                .multiple(true)
+                .number_of_values(1)
+                .possible_values(&CompilePass::variants()),
         )
         .arg(
             Arg::from_usage(
-                "<CODE_PATHS> -C <CODE_PATH> 'add code path for the erlang preprocessor'",
+                "<DUMP_DIR> --dump-dir <DIR> 'write the ir of functions after every pass to DIR'",
             )
-            .required(false)
-            .multiple(true),
+            .required(false),
         )
         .arg(
-            Arg::from_usage("<PASSES> --pass <PASS> 'run the given compilation pass'")
+            Arg::from_usage("<DUMP_FUN> --dump-fun <IDENT> 'only dump the given function'")
                 .required(false)
                 .multiple(true)
                 .number_of_values(1)
-                .possible_values(&CompilePass::variants()),
+                .requires("DUMP_DIR"),
+        )
+        .arg(
+            Arg::from_usage("<DUMP_PASS> --dump-pass <PASS> 'only dump after the given pass'")
+                .required(false)
+                .multiple(true)
+                .number_of_values(1)
+                .requires("DUMP_DIR"),
+        )
+        .arg(
+            Arg::from_usage("[DUMP_DIFF] --dump-diff 'write diffs between consecutive dumps'")
+                .requires("DUMP_DIR"),
         )
         .arg(
             Arg::from_usage("<LOG_LEVEL> -L,--log-level <LOG_LEVEL> 'log level'")
@@ -197,60 +539,82 @@ fn main() {
     );
 
     let codemap = Arc::new(CodeMap::new());
+    let out_type = value_t!(matches, "OUT_FORMAT", OutputType).unwrap();
+
+    let in_type = value_t!(matches, "IN_FORMAT", InputType).unwrap();
+
+    if matches.is_present("PROJECT") {
+        if in_type != InputType::Erl {
+            usage_error("project compilation requires erl input");
+        }
+        if out_type != OutputType::Eir {
+            usage_error("project compilation requires eir output");
+        }
+        compile_project(codemap, &matches);
+        return;
+    }
+
+    let annotate_source = matches.is_present("ANNOTATE_SOURCE");
+    if annotate_source && out_type != OutputType::Eir && out_type != OutputType::Dot {
+        usage_error("--annotate-source requires eir or dot output");
+    }
+
+    if out_type == OutputType::ErlPp {
+        if in_type != InputType::Erl {
+            usage_error("erl-pp output requires erl input");
+        }
+        preprocess(codemap, &matches);
+        return;
+    }
+
     let frontend = make_frontend(codemap.clone(), &matches);
 
     let in_file_name = matches.value_of("IN_FILE").unwrap();
     let in_file_path = Path::new(in_file_name);
 
     let (eir_res, diagnostics) = frontend.parse_file_dyn(&in_file_path);
-    {
-        let term_config = term::Config::default();
-        let mut out = StandardStream::stderr(ColorChoice::Auto);
-        for diag in diagnostics.iter() {
-            term::emit(&mut out, &term_config, &*codemap, diag).unwrap();
-        }
-    }
+    emit_diagnostics(&codemap, &diagnostics);
 
     if eir_res.is_err() {
         return;
     }
     let mut eir = eir_res.unwrap();
 
-    match value_t!(matches, "COMPILE_LEVEL", CompileLevel).unwrap() {
-        CompileLevel::High => {}
-        CompileLevel::Normal => {
-            let mut pass_manager = PassManager::default();
-            pass_manager.run(&mut eir);
+    let selected_function = matches
+        .value_of("FUN_IDENT")
+        .map(|val| FunctionIdent::parse_with_module(val, eir.name().clone()).unwrap());
+
+    let mut pass_manager = Pipeline::from_matches(&matches).pass_manager();
+
+    if let Some(dir) = matches.value_of("DUMP_DIR") {
+        let mut dump = DumpConfig::new(dir);
+        if let Some(funs) = matches.values_of("DUMP_FUN") {
+            dump.functions = funs
+                .map(|val| FunctionIdent::parse_with_module(val, eir.name().clone()).unwrap())
+                .collect();
         }
-        CompileLevel::Custom => {
-            let mut pass_manager = PassManager::new();
-            if matches.is_present("PASSES") {
-                for pass_type in values_t!(matches.values_of("PASSES"), CompilePass).unwrap() {
-                    match pass_type {
-                        CompilePass::CompilePatterns => {
-                            pass_manager
-                                .push_function_pass(libeir_passes::CompilePatternPass::new());
-                        }
-                        CompilePass::SimplifyCfg => {
-                            pass_manager.push_function_pass(libeir_passes::SimplifyCfgPass::new());
-                        }
-                        CompilePass::NaiveInlineClosures => {
-                            pass_manager
-                                .push_function_pass(libeir_passes::NaiveInlineClosuresPass::new());
-                        }
-                        CompilePass::Validate => {
-                            pass_manager.push_function_pass(libeir_passes::ValidatePass::new());
-                        }
-                    }
-                }
-            }
-            pass_manager.run(&mut eir);
+        if let Some(passes) = matches.values_of("DUMP_PASS") {
+            dump.passes = passes.map(|pass| pass.to_string()).collect();
         }
+        dump.diff = matches.is_present("DUMP_DIFF");
+        pass_manager.set_dump(dump);
     }
 
-    let selected_function = matches
-        .value_of("FUN_IDENT")
-        .map(|val| FunctionIdent::parse_with_module(val, eir.name().clone()).unwrap());
+    // The html export shows the selected function before and after
+    // every pass, so only that function is run through the pipeline.
+    let mut html_export = None;
+    if out_type == OutputType::Html {
+        let selected = selected_function
+            .as_ref()
+            .expect("Expected function ident with -i <FUN_IDENT>");
+        let fun_def = eir
+            .function_iter_mut()
+            .find(|fun_def| fun_def.function().ident() == selected)
+            .expect("Selected function not found in module");
+        html_export = Some(pass_manager.run_html_export(fun_def.function_mut()));
+    } else {
+        pass_manager.run(&mut eir);
+    }
 
     //if matches.is_present("ANNOTATE_LIVE") {
     //    print_ctx.add_annotator(EirLiveValuesAnnotator::new());
@@ -258,14 +622,17 @@ fn main() {
 
     let out_data;
     let out_ext;
-    let out_type = value_t!(matches, "OUT_FORMAT", OutputType).unwrap();
     match out_type {
         OutputType::Eir => {
-            if let Some(selected) = selected_function {
-                out_data = eir[&selected].function().to_text_standard();
-            } else {
-                out_data = eir.to_text_standard();
-            }
+            let mut config = StandardFormatConfig::default();
+            out_data = match (selected_function, annotate_source) {
+                (Some(selected), false) => eir[&selected].function().to_text(&mut config),
+                (Some(selected), true) => eir[&selected]
+                    .function()
+                    .to_text_annotated(&mut config, &codemap),
+                (None, false) => eir.to_text(&mut config),
+                (None, true) => eir.to_text_annotated(&mut config, &codemap),
+            };
             out_ext = "eir";
         }
         OutputType::Dot => {
@@ -274,10 +641,19 @@ fn main() {
             let fun_def = &eir[&selected_function];
             let fun = fun_def.function();
 
-            out_data = ::libeir_ir::text::function_to_dot(&fun);
+            out_data = if annotate_source {
+                function_to_dot_by_source_line(&fun, &codemap)
+            } else {
+                function_to_dot(&fun)
+            };
 
             out_ext = "dot";
         }
+        OutputType::Html => {
+            out_data = html_export.unwrap().finish(render_svg);
+            out_ext = "html";
+        }
+        OutputType::ErlPp => unreachable!(),
     }
 
     let out_file_name = matches
@@ -303,3 +679,92 @@ fn main() {
         assert!(res.status.success(), "Failed to run dot");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use libeir_diagnostics::CodeMap;
+    use libeir_syntax_erl::ParseConfig;
+
+    use super::{build_project, CompileLevel, Pipeline, ProjectOptions};
+    use tools::project::Project;
+
+    struct TempDir(PathBuf);
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("eir_compile_{}_{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+
+        fn write(&self, path: &str, contents: &str) {
+            let path = self.0.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, contents).unwrap();
+        }
+    }
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn build_emakefile_project(jobs: usize) {
+        let dir = TempDir::new(&format!("jobs_{}", jobs));
+        dir.write(
+            "Emakefile",
+            "{'src/*', [{i, \"include\"}, {d, 'GREETING', \"hello\"}, {outdir, \"ebin\"}]}.\n",
+        );
+        dir.write("include/app.hrl", "-define(ANSWER, 42).\n");
+        for name in ["one", "two", "three", "four", "five"].iter() {
+            dir.write(
+                &format!("src/{}.erl", name),
+                &format!(
+                    "-module({}).\n-include(\"app.hrl\").\n\
+                     -export([f/0]).\nf() -> {{?GREETING, ?ANSWER}}.\n",
+                    name
+                ),
+            );
+        }
+        dir.write("src/broken.erl", "-module(broken).\nf( -> .\n");
+
+        let codemap = Arc::new(CodeMap::new());
+        let mut diagnostics = Vec::new();
+        let project =
+            Project::from_emakefile(&codemap, &dir.0.join("Emakefile"), &mut diagnostics).unwrap();
+        let options = ProjectOptions {
+            config: ParseConfig::default(),
+            out_dir: None,
+            cache_dir: None,
+            jobs,
+            pipeline: Pipeline {
+                level: CompileLevel::Normal,
+                passes: Vec::new(),
+            },
+        };
+        let report = build_project(&codemap, &project, &options);
+
+        assert_eq!(report.compiled, 5);
+        assert_eq!(report.failed.len(), 1);
+        assert!(report.failed[0].0.ends_with("broken.erl"));
+        assert!(!report.diagnostics.is_empty());
+        for name in ["one", "two", "three", "four", "five"].iter() {
+            assert!(dir.0.join(format!("ebin/{}.eir", name)).is_file());
+        }
+        assert!(!dir.0.join("ebin/broken.eir").exists());
+    }
+
+    #[test]
+    fn build_sequential() {
+        build_emakefile_project(1);
+    }
+
+    #[test]
+    fn build_parallel() {
+        build_emakefile_project(4);
+    }
+}
@@ -0,0 +1,81 @@
+//! Formats Erlang source files in place, or checks that they are
+//! formatted.
+
+use std::path::PathBuf;
+
+use clap::{App, Arg};
+
+use libeir_diagnostics::{CodeMap, ToDiagnostic};
+use libeir_syntax_erl::{format, FormatConfig};
+
+use tools::frontend::emit_diagnostics;
+
+fn main() {
+    let matches = App::new("Eir Format")
+        .version("alpha")
+        .author("Hans Elias B. Josephsen")
+        .about("Formats Erlang source files")
+        .arg(
+            Arg::with_name("IN_FILES")
+                .help("Files to format")
+                .required(true)
+                .multiple(true),
+        )
+        .arg(Arg::from_usage(
+            "[CHECK] --check 'do not write files, list the ones that are not formatted and fail if there are any'",
+        ))
+        .arg(
+            Arg::from_usage("[WIDTH] -w,--width <COLUMNS> 'the width lines are fit into'")
+                .default_value("100"),
+        )
+        .get_matches();
+
+    let width = match matches.value_of("WIDTH").unwrap().parse() {
+        Ok(width) => width,
+        Err(_) => {
+            eprintln!("invalid width");
+            std::process::exit(1);
+        }
+    };
+    let config = FormatConfig { width };
+    let check = matches.is_present("CHECK");
+
+    let codemap = CodeMap::new();
+    let mut failed = false;
+    for in_file in matches.values_of("IN_FILES").unwrap() {
+        let path = PathBuf::from(in_file);
+        let source = match std::fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("{}: {}", path.display(), err);
+                failed = true;
+                continue;
+            }
+        };
+
+        let id = codemap.add(path.clone(), source.clone());
+        let formatted = match format(&config, codemap.get(id).unwrap()) {
+            Ok(formatted) => formatted,
+            Err(err) => {
+                emit_diagnostics(&codemap, &[err.to_diagnostic()]);
+                failed = true;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+
+        if check {
+            println!("{}", path.display());
+            failed = true;
+        } else if let Err(err) = std::fs::write(&path, formatted) {
+            eprintln!("{}: {}", path.display(), err);
+            failed = true;
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
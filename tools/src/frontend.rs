@@ -0,0 +1,121 @@
+//! Frontend setup shared by the tools, selecting the input format and
+//! configuring the Erlang preprocessor from the command line.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use clap::{arg_enum, value_t, Arg, ArgMatches};
+
+use libeir_diagnostics::term::{
+    self,
+    termcolor::{ColorChoice, StandardStream},
+};
+use libeir_diagnostics::{CodeMap, Diagnostic};
+use libeir_frontend::{
+    abstr_erlang::AbstrErlangFrontend, eir::EirFrontend, erlang::ErlangFrontend, AnyFrontend,
+};
+use libeir_syntax_erl::ParseConfig;
+
+arg_enum! {
+    #[derive(Debug, PartialEq, Eq)]
+    pub enum InputType {
+        Eir,
+        Abstr,
+        Erl,
+    }
+}
+
+/// The arguments read by `make_frontend`.
+pub fn frontend_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    let mut args = vec![
+        Arg::from_usage("<IN_FORMAT> -f,--in-format <IN_FORMAT> 'input format'")
+            .default_value("erl")
+            .required(true)
+            .case_insensitive(true)
+            .possible_values(&InputType::variants()),
+    ];
+    args.extend(parse_config_args());
+    args
+}
+
+/// The arguments read by `make_parse_config`.
+pub fn parse_config_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::from_usage(
+            "<INCLUDE_PATHS> -I <INCLUDE_PATH> 'add include path for the erlang preprocessor'",
+        )
+        .required(false)
+        .multiple(true),
+        Arg::from_usage("<CODE_PATHS> -C <CODE_PATH> 'add code path for the erlang preprocessor'")
+            .required(false)
+            .multiple(true),
+        Arg::from_usage(
+            "<DEFINES> -D <MACRO> 'define a macro for the erlang preprocessor, NAME or NAME=VALUE'",
+        )
+        .required(false)
+        .multiple(true)
+        .number_of_values(1),
+        Arg::from_usage("<UNDEFS> -U <NAME> 'undefine a macro for the erlang preprocessor'")
+            .required(false)
+            .multiple(true)
+            .number_of_values(1),
+    ]
+}
+
+pub fn make_parse_config(matches: &ArgMatches) -> ParseConfig {
+    let mut config = ParseConfig::default();
+
+    if let Some(includes) = matches.values_of("INCLUDE_PATHS") {
+        for include in includes {
+            config.include_paths.push_front(PathBuf::from(include));
+        }
+    }
+    if let Some(includes) = matches.values_of("CODE_PATHS") {
+        for include in includes {
+            config.code_paths.push_front(PathBuf::from(include));
+        }
+    }
+
+    // Like erlc, definitions and undefinitions apply in the order they
+    // are given on the command line.
+    let mut macro_args: Vec<(usize, bool, &str)> = Vec::new();
+    if let (Some(indices), Some(values)) =
+        (matches.indices_of("DEFINES"), matches.values_of("DEFINES"))
+    {
+        macro_args.extend(indices.zip(values).map(|(idx, val)| (idx, true, val)));
+    }
+    if let (Some(indices), Some(values)) =
+        (matches.indices_of("UNDEFS"), matches.values_of("UNDEFS"))
+    {
+        macro_args.extend(indices.zip(values).map(|(idx, val)| (idx, false, val)));
+    }
+    macro_args.sort_by_key(|(idx, _, _)| *idx);
+    for (_, define, arg) in macro_args {
+        if define {
+            let mut split = arg.splitn(2, '=');
+            let name = split.next().unwrap();
+            config.define_macro(name, split.next().map(|value| value.to_string()));
+        } else {
+            config.undefine_macro(arg);
+        }
+    }
+
+    config
+}
+
+pub fn make_frontend(codemap: Arc<CodeMap>, matches: &ArgMatches) -> AnyFrontend {
+    match value_t!(matches, "IN_FORMAT", InputType).unwrap() {
+        InputType::Erl => ErlangFrontend::new(make_parse_config(matches), codemap).into(),
+        InputType::Abstr => AbstrErlangFrontend::new(codemap).into(),
+        InputType::Eir => EirFrontend::new(codemap).into(),
+    }
+}
+
+/// Prints `diagnostics` to stderr.
+pub fn emit_diagnostics(codemap: &CodeMap, diagnostics: &[Diagnostic]) {
+    let term_config = term::Config::default();
+    let mut out = StandardStream::stderr(ColorChoice::Auto);
+    for diag in diagnostics.iter() {
+        term::emit(&mut out, &term_config, codemap, diag).unwrap();
+    }
+}
@@ -0,0 +1,4 @@
+//! Code shared by the command line tools.
+
+pub mod frontend;
+pub mod project;
@@ -0,0 +1,400 @@
+//! Analysis of a single document, runs the Erlang frontend up to and
+//! including lowering and answers queries against the resulting AST.
+
+use std::fmt::Write as _;
+use std::ops::Range as ByteRange;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+
+use libeir_diagnostics::{
+    CodeMap, Diagnostic, FileName, LabelStyle, Severity, SourceFile, SourceId, SourceSpan,
+};
+use libeir_frontend::erlang::ErlangFrontend;
+use libeir_intern::Symbol;
+use libeir_ir::ToPrimitive;
+use libeir_syntax_erl::ast::{Module as ModuleAst, NamedFunction};
+use libeir_syntax_erl::{
+    lower_module, Lexer, LexicalToken, LowerError, ParseConfig, ParserError, Token,
+};
+use libeir_util_parse::{Errors, FileMapSource, Scanner};
+
+use crate::protocol::{path_to_uri, uri_to_path, Location, Range};
+
+/// What the name under the cursor refers to.
+enum Reference {
+    /// A local function, with the arity if it is known from the call
+    /// site.
+    Function(Symbol, Option<usize>),
+    Record(Symbol),
+    Macro(Symbol),
+}
+
+pub struct Analysis {
+    codemap: Arc<CodeMap>,
+    file: Arc<SourceFile>,
+    uri: String,
+    /// The tokens of the document before preprocessing, without comments.
+    tokens: Vec<LexicalToken>,
+    /// `None` if the document could not be parsed.
+    ast: Option<ModuleAst>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Analysis {
+    /// Analyzes `text`, the contents of the document at `uri`. Documents
+    /// with a `file` URI are given their path, so that includes are
+    /// resolved relative to it.
+    pub fn new(config: &ParseConfig, uri: &str, text: String) -> Analysis {
+        let codemap = Arc::new(CodeMap::new());
+        let id = match uri_to_path(uri) {
+            Some(path) => codemap.add(path, text),
+            None => codemap.add(uri.to_string(), text),
+        };
+        let file = codemap.get(id).unwrap();
+        let frontend = ErlangFrontend::new(config.clone(), codemap.clone());
+
+        let mut tokens = Vec::new();
+        let mut diagnostics = Vec::new();
+        // A half written document is the common case here, a panic in the
+        // frontend must not take down the server.
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            tokens = lex(&file);
+
+            let mut parse_errors: Errors<ParserError, ParserError> = Errors::new();
+            let ast = frontend.parse_source_ast(&mut parse_errors, file.clone());
+            diagnostics.extend(parse_errors.iter_diagnostics());
+            let ast = ast.ok()?;
+
+            let mut lower_errors: Errors<LowerError, LowerError> = Errors::new();
+            let _ = lower_module(&mut lower_errors, codemap.clone(), &ast);
+            diagnostics.extend(lower_errors.iter_diagnostics());
+            Some(ast)
+        }));
+        let ast = match res {
+            Ok(ast) => ast,
+            Err(_) => {
+                diagnostics.push(
+                    Diagnostic::error().with_message("the compiler panicked analyzing this file"),
+                );
+                None
+            }
+        };
+
+        Analysis {
+            codemap,
+            file,
+            uri: uri.to_string(),
+            tokens,
+            ast,
+            diagnostics,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        self.file.source()
+    }
+
+    /// The diagnostics of the document as LSP `Diagnostic`s.
+    pub fn diagnostics(&self) -> Vec<Value> {
+        self.diagnostics
+            .iter()
+            .map(|diag| {
+                let label = diag
+                    .labels
+                    .iter()
+                    .find(|label| label.style == LabelStyle::Primary)
+                    .or_else(|| diag.labels.first());
+
+                let mut message = diag.message.clone();
+                let mut range = 0..0;
+                if let Some(label) = label {
+                    if !label.message.is_empty() {
+                        write!(message, "\n{}", label.message).unwrap();
+                    }
+                    if let Some((label_range, included)) =
+                        self.document_range(label.file_id, label.range.clone())
+                    {
+                        range = label_range;
+                        if let Some(included) = included {
+                            write!(message, "\nin {}", included).unwrap();
+                        }
+                    }
+                }
+
+                json!({
+                    "range": Range::from_offsets(self.text(), range),
+                    "severity": severity(diag.severity),
+                    "source": "eir",
+                    "message": message,
+                })
+            })
+            .collect()
+    }
+
+    /// Maps `range` in the file `id` to the document. A range in an
+    /// included file is mapped to the include directive it came from,
+    /// and the name of the included file is returned with it.
+    fn document_range(
+        &self,
+        mut id: SourceId,
+        range: ByteRange<usize>,
+    ) -> Option<(ByteRange<usize>, Option<FileName>)> {
+        if id == self.file.id() {
+            return Some((range, None));
+        }
+        let name = self.codemap.name(id)?;
+        while let Some(parent) = self.codemap.parent(id) {
+            if parent.source_id() == self.file.id() {
+                return Some((parent.into(), Some(name)));
+            }
+            id = parent.source_id();
+        }
+        None
+    }
+
+    /// Whether the file `id` was included by the document, directly or
+    /// through other headers.
+    fn is_included(&self, mut id: SourceId) -> bool {
+        while let Some(parent) = self.codemap.parent(id) {
+            if parent.source_id() == self.file.id() {
+                return true;
+            }
+            id = parent.source_id();
+        }
+        false
+    }
+
+    fn location(&self, span: SourceSpan) -> Option<Location> {
+        let file = self.codemap.get(span.source_id())?;
+        let uri = if file.id() == self.file.id() {
+            self.uri.clone()
+        } else {
+            match file.name() {
+                FileName::Real(path) => path_to_uri(path),
+                FileName::Virtual(_) => return None,
+            }
+        };
+        Some(Location {
+            uri,
+            range: Range::from_offsets(file.source(), span.into()),
+        })
+    }
+
+    /// Finds the name at `offset` and what it refers to, returns the
+    /// index of its token along with it.
+    fn reference_at(&self, offset: usize) -> Option<(usize, Reference)> {
+        let idx = self.tokens.iter().position(|token| {
+            let range: ByteRange<usize> = token.span().into();
+            range.start <= offset && offset <= range.end && token_name(&token.1).is_some()
+        })?;
+        let name = token_name(&self.tokens[idx].1)?;
+        let prev = idx.checked_sub(1).map(|prev| &self.tokens[prev].1);
+        let rest = &self.tokens[idx + 1..];
+
+        let reference = match (prev, &self.tokens[idx].1) {
+            (Some(Token::Question), _) => Reference::Macro(name),
+            (Some(Token::Pound), _) => Reference::Record(name),
+            // Remote calls and module names.
+            (Some(Token::Colon), _) => return None,
+            (_, Token::Atom(_)) => match rest.first().map(|token| &token.1) {
+                Some(Token::Colon) => return None,
+                _ => Reference::Function(name, reference_arity(rest)),
+            },
+            _ => return None,
+        };
+        Some((idx, reference))
+    }
+
+    /// The local functions named `name`. If `arity` is given and there is
+    /// a function with it, only that one is returned.
+    fn functions(&self, name: Symbol, arity: Option<usize>) -> Vec<&NamedFunction> {
+        let ast = match self.ast.as_ref() {
+            Some(ast) => ast,
+            None => return Vec::new(),
+        };
+        let named: Vec<&NamedFunction> = ast
+            .functions
+            .values()
+            .filter(|function| function.name.symbol() == name)
+            .collect();
+        let exact: Vec<&NamedFunction> = named
+            .iter()
+            .cloned()
+            .filter(|function| Some(function.arity) == arity)
+            .collect();
+        if exact.is_empty() {
+            named
+        } else {
+            exact
+        }
+    }
+
+    /// The `-define`s of the macro `name` in the document and in the
+    /// files it includes.
+    fn macro_definitions(&self, name: Symbol) -> Vec<Location> {
+        let define = Symbol::intern("define");
+        let mut included: Vec<Arc<SourceFile>> = self
+            .codemap
+            .iter()
+            .filter(|file| self.is_included(file.id()))
+            .collect();
+        included.sort_by_key(|file| file.id());
+
+        let mut spans = Vec::new();
+        let mut find_defines = |tokens: &[LexicalToken]| {
+            for window in tokens.windows(4) {
+                let is_define = match (&window[0].1, &window[1].1, &window[2].1) {
+                    (Token::Minus, Token::Atom(atom), Token::LParen) => *atom == define,
+                    _ => false,
+                };
+                if is_define && token_name(&window[3].1) == Some(name) {
+                    spans.push(window[3].span());
+                }
+            }
+        };
+        find_defines(&self.tokens);
+        for file in included {
+            find_defines(&lex(&file));
+        }
+
+        spans
+            .into_iter()
+            .filter_map(|span| self.location(span))
+            .collect()
+    }
+
+    /// The definitions of the name at `offset`.
+    pub fn definition(&self, offset: usize) -> Vec<Location> {
+        let reference = match self.reference_at(offset) {
+            Some((_, reference)) => reference,
+            None => return Vec::new(),
+        };
+        match reference {
+            Reference::Function(name, arity) => self
+                .functions(name, arity)
+                .iter()
+                .filter_map(|function| self.location(function.span))
+                .collect(),
+            Reference::Record(name) => self
+                .ast
+                .as_ref()
+                .and_then(|ast| ast.records.get(&name))
+                .and_then(|record| self.location(record.record.span))
+                .into_iter()
+                .collect(),
+            Reference::Macro(name) => self.macro_definitions(name),
+        }
+    }
+
+    /// A LSP `Hover` for the local function at `offset`, giving its arity
+    /// and spec.
+    pub fn hover(&self, offset: usize) -> Option<Value> {
+        let (idx, reference) = self.reference_at(offset)?;
+        let (name, arity) = match reference {
+            Reference::Function(name, arity) => (name, arity),
+            _ => return None,
+        };
+        let functions = self.functions(name, arity);
+        if functions.is_empty() {
+            return None;
+        }
+
+        let mut value = String::new();
+        for function in functions {
+            if !value.is_empty() {
+                value.push_str("\n---\n");
+            }
+            write!(value, "```erlang\n{}/{}\n```", name, function.arity).unwrap();
+            let spec = function.spec.as_ref().and_then(|spec| {
+                let file = self.codemap.get(spec.span.source_id())?;
+                file.source_slice(spec.span).ok().map(str::to_owned)
+            });
+            if let Some(spec) = spec {
+                write!(value, "\n```erlang\n{}\n```", spec).unwrap();
+            }
+        }
+
+        Some(json!({
+            "contents": { "kind": "markdown", "value": value },
+            "range": Range::from_offsets(self.text(), self.tokens[idx].span().into()),
+        }))
+    }
+}
+
+fn lex(file: &Arc<SourceFile>) -> Vec<LexicalToken> {
+    Lexer::new(Scanner::new(FileMapSource::new(file.clone())))
+        .filter_map(Result::ok)
+        .collect()
+}
+
+fn token_name(token: &Token) -> Option<Symbol> {
+    match token {
+        Token::Atom(name) | Token::Ident(name) => Some(*name),
+        _ => None,
+    }
+}
+
+/// The arity of a reference to a function, from the tokens that follow
+/// its name: either `/Arity` or the arguments of a call.
+fn reference_arity(rest: &[LexicalToken]) -> Option<usize> {
+    match (rest.get(0).map(|t| &t.1), rest.get(1).map(|t| &t.1)) {
+        (Some(Token::Slash), Some(Token::Integer(arity))) => arity.to_usize(),
+        (Some(Token::LParen), _) => call_arity(rest),
+        _ => None,
+    }
+}
+
+/// Counts the arguments of a call, `tokens` starts at the opening
+/// parenthesis. Commas are only counted outside of nested brackets and
+/// `... end` blocks.
+fn call_arity(tokens: &[LexicalToken]) -> Option<usize> {
+    let mut depth = 0;
+    let mut commas = 0;
+    for (idx, token) in tokens.iter().enumerate() {
+        match &token.1 {
+            Token::LParen
+            | Token::LBrace
+            | Token::LBracket
+            | Token::BinaryStart
+            | Token::Begin
+            | Token::Case
+            | Token::If
+            | Token::Receive
+            | Token::Try
+            | Token::Maybe => depth += 1,
+            // Only funs with clauses are closed by `end`, not `fun f/1`.
+            Token::Fun => {
+                let next = tokens.get(idx + 1).map(|t| &t.1);
+                let after = tokens.get(idx + 2).map(|t| &t.1);
+                match (next, after) {
+                    (Some(Token::LParen), _) | (Some(Token::Ident(_)), Some(Token::LParen)) => {
+                        depth += 1
+                    }
+                    _ => (),
+                }
+            }
+            Token::RParen | Token::RBrace | Token::RBracket | Token::BinaryEnd | Token::End => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(if idx == 1 { 0 } else { commas + 1 });
+                }
+            }
+            Token::Comma if depth == 1 => commas += 1,
+            // The end of the form, the call was never closed.
+            Token::Dot => return None,
+            _ => (),
+        }
+    }
+    None
+}
+
+fn severity(severity: Severity) -> u8 {
+    match severity {
+        Severity::Bug | Severity::Error => 1,
+        Severity::Warning => 2,
+        Severity::Note => 3,
+        Severity::Help => 4,
+    }
+}
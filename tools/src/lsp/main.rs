@@ -0,0 +1,35 @@
+//! A language server for Erlang, speaking LSP over stdio.
+//!
+//! Diagnostics from parsing, preprocessing and lowering are published
+//! whenever a document changes. Go-to-definition is supported for local
+//! functions, records and macros, and hovering a local function shows
+//! its arity and spec.
+
+mod analysis;
+mod protocol;
+mod server;
+
+use std::io;
+
+use clap::App;
+
+use tools::frontend::{make_parse_config, parse_config_args};
+
+use crate::server::Server;
+
+fn main() {
+    let matches = App::new("Eir Language Server")
+        .version("alpha")
+        .author("Hans Elias B. Josephsen")
+        .about("Erlang language server, communicates over stdio")
+        .args(&parse_config_args())
+        .get_matches();
+
+    let config = make_parse_config(&matches);
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut server = Server::new(config, stdout.lock());
+    let code = server.run(&mut stdin.lock()).unwrap();
+    std::process::exit(code);
+}
@@ -0,0 +1,294 @@
+//! The base protocol of the language server protocol: JSON-RPC messages
+//! framed by a `Content-Length` header, and the few LSP types the server
+//! needs.
+
+use std::io::{self, BufRead, Read, Write};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const SERVER_NOT_INITIALIZED: i64 = -32002;
+
+/// The largest message content accepted. Larger messages are skipped
+/// instead of being read into memory.
+pub const MAX_CONTENT_LENGTH: usize = 64 * 1024 * 1024;
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads the next message from `reader`. Returns `None` at the end of
+/// the input.
+///
+/// A message with a missing, invalid or too large `Content-Length`
+/// gives an `InvalidData` error. The rest of that message has been
+/// consumed, so reading can continue with the next one.
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut headers = false;
+    let mut content_length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            // Blank lines before the headers of a message are tolerated.
+            if headers {
+                break;
+            }
+            continue;
+        }
+        headers = true;
+        let mut parts = line.splitn(2, ':');
+        let name = parts.next().unwrap().trim();
+        let value = parts.next().unwrap_or("").trim();
+        if name.eq_ignore_ascii_case("Content-Length") {
+            content_length = Some(value.parse::<usize>().map_err(|_| value.to_string()));
+        }
+    }
+
+    let content_length = match content_length {
+        Some(Ok(length)) => length,
+        Some(Err(value)) => {
+            return Err(invalid_data(format!("invalid Content-Length {:?}", value)))
+        }
+        None => return Err(invalid_data("missing Content-Length header".to_string())),
+    };
+    if content_length > MAX_CONTENT_LENGTH {
+        io::copy(
+            &mut reader.by_ref().take(content_length as u64),
+            &mut io::sink(),
+        )?;
+        return Err(invalid_data(format!(
+            "Content-Length {} exceeds the limit of {} bytes",
+            content_length, MAX_CONTENT_LENGTH
+        )));
+    }
+
+    let mut content = vec![0; content_length];
+    reader.read_exact(&mut content)?;
+    Ok(Some(content))
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> io::Result<()> {
+    let content = message.to_string();
+    write!(
+        writer,
+        "Content-Length: {}\r\n\r\n{}",
+        content.len(),
+        content
+    )?;
+    writer.flush()
+}
+
+pub fn response(id: Value, result: Value) -> Value {
+    serde_json::json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+pub fn error_response(id: Value, code: i64, message: &str) -> Value {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+pub fn notification(method: &str, params: Value) -> Value {
+    serde_json::json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+/// A position in a document. `character` counts UTF-16 code units, as
+/// the protocol requires.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Position {
+    pub line: usize,
+    pub character: usize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Location {
+    pub uri: String,
+    pub range: Range,
+}
+
+impl Position {
+    pub fn from_offset(text: &str, offset: usize) -> Position {
+        let mut offset = offset.min(text.len());
+        while !text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let before = &text[..offset];
+        let line_start = before.rfind('\n').map(|idx| idx + 1).unwrap_or(0);
+        Position {
+            line: before.matches('\n').count(),
+            character: before[line_start..].encode_utf16().count(),
+        }
+    }
+
+    /// The byte offset of the position in `text`. Positions past the end
+    /// of a line are clamped to the end of the line.
+    pub fn to_offset(self, text: &str) -> usize {
+        let mut line_start = 0;
+        for _ in 0..self.line {
+            match text[line_start..].find('\n') {
+                Some(idx) => line_start += idx + 1,
+                None => return text.len(),
+            }
+        }
+        let line_end = text[line_start..]
+            .find('\n')
+            .map(|idx| line_start + idx)
+            .unwrap_or_else(|| text.len());
+
+        let mut units = 0;
+        for (idx, c) in text[line_start..line_end].char_indices() {
+            if units >= self.character {
+                return line_start + idx;
+            }
+            units += c.len_utf16();
+        }
+        line_end
+    }
+}
+
+impl Range {
+    pub fn from_offsets(text: &str, range: std::ops::Range<usize>) -> Range {
+        Range {
+            start: Position::from_offset(text, range.start),
+            end: Position::from_offset(text, range.end),
+        }
+    }
+}
+
+/// Converts a `file` URI to a path. Returns `None` for other schemes.
+pub fn uri_to_path(uri: &str) -> Option<std::path::PathBuf> {
+    if !uri.starts_with("file://") {
+        return None;
+    }
+    let encoded = uri["file://".len()..].as_bytes();
+
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut idx = 0;
+    while idx < encoded.len() {
+        let hex = encoded
+            .get(idx + 1..idx + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (encoded[idx], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                idx += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                idx += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok().map(Into::into)
+}
+
+pub fn path_to_uri(path: &std::path::Path) -> String {
+    let mut uri = "file://".to_string();
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, BufReader, Cursor, ErrorKind, Read};
+    use std::path::Path;
+
+    use super::{path_to_uri, read_message, uri_to_path, Position, MAX_CONTENT_LENGTH};
+
+    #[test]
+    fn framing() {
+        let too_large = MAX_CONTENT_LENGTH + 1;
+        let head = format!(
+            "\r\nContent-Length: 2\r\n\r\n{{}}\
+             Content-Type: application/json\r\n\r\n\
+             Content-Length: {}\r\n\r\n",
+            too_large,
+        );
+        let tail = "Content-Length: x\r\n\r\ncontent-length: 4\r\n\r\nnull";
+        // The oversized content is streamed, never held in memory.
+        let mut input = BufReader::new(
+            Cursor::new(head)
+                .chain(io::repeat(b' ').take(too_large as u64))
+                .chain(Cursor::new(tail)),
+        );
+
+        assert_eq!(read_message(&mut input).unwrap(), Some(b"{}".to_vec()));
+        let errors = [
+            "missing Content-Length header",
+            "Content-Length 67108865 exceeds the limit of 67108864 bytes",
+            "invalid Content-Length \"x\"",
+        ];
+        for error in errors.iter() {
+            let err = read_message(&mut input).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+            assert_eq!(err.to_string(), *error);
+        }
+        assert_eq!(read_message(&mut input).unwrap(), Some(b"null".to_vec()));
+        assert_eq!(read_message(&mut input).unwrap(), None);
+    }
+
+    #[test]
+    fn positions() {
+        let text = "a\n\u{1F600}b\nc";
+        let b = text.find('b').unwrap();
+        let pos = Position::from_offset(text, b);
+        assert_eq!(
+            pos,
+            Position {
+                line: 1,
+                character: 2
+            }
+        );
+        assert_eq!(pos.to_offset(text), b);
+        assert_eq!(
+            Position {
+                line: 0,
+                character: 10
+            }
+            .to_offset(text),
+            1
+        );
+        assert_eq!(
+            Position {
+                line: 5,
+                character: 0
+            }
+            .to_offset(text),
+            text.len()
+        );
+    }
+
+    #[test]
+    fn uris() {
+        let path = Path::new("/home/me/my app/src/a.erl");
+        let uri = path_to_uri(path);
+        assert_eq!(uri, "file:///home/me/my%20app/src/a.erl");
+        assert_eq!(uri_to_path(&uri), Some(path.to_owned()));
+        assert_eq!(uri_to_path("untitled:1"), None);
+    }
+}
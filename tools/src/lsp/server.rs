@@ -0,0 +1,446 @@
+//! Dispatch of LSP requests and notifications. Documents are analyzed
+//! as a whole whenever they change and the analysis is kept around to
+//! answer queries.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use serde_json::{json, Value};
+
+use libeir_syntax_erl::ParseConfig;
+
+use crate::analysis::Analysis;
+use crate::protocol::{self, Position};
+
+/// An error response to a request.
+struct ResponseError(i64, String);
+
+pub struct Server<W> {
+    out: W,
+    config: ParseConfig,
+    documents: HashMap<String, Analysis>,
+    initialized: bool,
+    shutdown: bool,
+}
+
+impl<W: Write> Server<W> {
+    pub fn new(config: ParseConfig, out: W) -> Self {
+        Server {
+            out,
+            config,
+            documents: HashMap::new(),
+            initialized: false,
+            shutdown: false,
+        }
+    }
+
+    /// Serves messages from `input` until the client sends `exit` or the
+    /// input ends. Returns the exit code of the server, which is only
+    /// successful if the client asked for a shutdown first.
+    pub fn run<R: BufRead>(&mut self, input: &mut R) -> io::Result<i32> {
+        loop {
+            let content = match protocol::read_message(input) {
+                Ok(Some(content)) => content,
+                Ok(None) => break,
+                // The malformed message has been skipped, keep serving.
+                Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                    let response = protocol::error_response(
+                        Value::Null,
+                        protocol::INVALID_REQUEST,
+                        &err.to_string(),
+                    );
+                    protocol::write_message(&mut self.out, &response)?;
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            let message: Value = match serde_json::from_slice(&content) {
+                Ok(message) => message,
+                Err(err) => {
+                    let response = protocol::error_response(
+                        Value::Null,
+                        protocol::PARSE_ERROR,
+                        &err.to_string(),
+                    );
+                    protocol::write_message(&mut self.out, &response)?;
+                    continue;
+                }
+            };
+
+            let method = message.get("method").and_then(Value::as_str);
+            let params = message.get("params").cloned().unwrap_or(Value::Null);
+            match (method, message.get("id").cloned()) {
+                (Some("exit"), None) => break,
+                (Some(method), Some(id)) => {
+                    let response = match self.request(method, params) {
+                        Ok(result) => protocol::response(id, result),
+                        Err(ResponseError(code, message)) => {
+                            protocol::error_response(id, code, &message)
+                        }
+                    };
+                    protocol::write_message(&mut self.out, &response)?;
+                }
+                (Some(method), None) => self.notification(method, params)?,
+                // We send no requests, so there are no responses to handle.
+                (None, _) => (),
+            }
+        }
+        Ok(if self.shutdown { 0 } else { 1 })
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Result<Value, ResponseError> {
+        if self.shutdown {
+            return Err(ResponseError(
+                protocol::INVALID_REQUEST,
+                "the server is shutting down".to_string(),
+            ));
+        }
+        if !self.initialized && method != "initialize" {
+            return Err(ResponseError(
+                protocol::SERVER_NOT_INITIALIZED,
+                "the server is not initialized".to_string(),
+            ));
+        }
+
+        match method {
+            "initialize" => {
+                self.initialized = true;
+                Ok(json!({
+                    "capabilities": {
+                        // Full document sync.
+                        "textDocumentSync": 1,
+                        "definitionProvider": true,
+                        "hoverProvider": true,
+                    },
+                    "serverInfo": {
+                        "name": "eir-lsp",
+                        "version": env!("CARGO_PKG_VERSION"),
+                    },
+                }))
+            }
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/definition" => {
+                let (analysis, offset) = self.document_position(&params)?;
+                Ok(serde_json::to_value(analysis.definition(offset)).unwrap())
+            }
+            "textDocument/hover" => {
+                let (analysis, offset) = self.document_position(&params)?;
+                Ok(analysis.hover(offset).unwrap_or(Value::Null))
+            }
+            _ => Err(ResponseError(
+                protocol::METHOD_NOT_FOUND,
+                format!("unsupported method {}", method),
+            )),
+        }
+    }
+
+    /// The analysis of the document and the offset of the position in a
+    /// `TextDocumentPositionParams`.
+    fn document_position(&self, params: &Value) -> Result<(&Analysis, usize), ResponseError> {
+        let invalid = || ResponseError(protocol::INVALID_PARAMS, "invalid params".to_string());
+        let uri = params["textDocument"]["uri"].as_str().ok_or_else(invalid)?;
+        let position: Position =
+            serde_json::from_value(params["position"].clone()).map_err(|_| invalid())?;
+        let analysis = self.documents.get(uri).ok_or_else(|| {
+            ResponseError(
+                protocol::INVALID_PARAMS,
+                format!("document {} is not open", uri),
+            )
+        })?;
+        Ok((analysis, position.to_offset(analysis.text())))
+    }
+
+    fn notification(&mut self, method: &str, params: Value) -> io::Result<()> {
+        let uri = match params["textDocument"]["uri"].as_str() {
+            Some(uri) => uri.to_string(),
+            None => return Ok(()),
+        };
+        match method {
+            "textDocument/didOpen" => {
+                if let Some(text) = params["textDocument"]["text"].as_str() {
+                    self.update(uri, text.to_string())?;
+                }
+            }
+            "textDocument/didChange" => {
+                // With full sync the last change holds the whole document.
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str());
+                if let Some(text) = text {
+                    self.update(uri, text.to_string())?;
+                }
+            }
+            // Headers the document includes may have changed on disk.
+            "textDocument/didSave" => {
+                if let Some(text) = self.documents.get(&uri).map(|doc| doc.text().to_string()) {
+                    self.update(uri, text)?;
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                self.publish_diagnostics(&uri, Vec::new())?;
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn update(&mut self, uri: String, text: String) -> io::Result<()> {
+        let analysis = Analysis::new(&self.config, &uri, text);
+        self.publish_diagnostics(&uri, analysis.diagnostics())?;
+        self.documents.insert(uri, analysis);
+        Ok(())
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str, diagnostics: Vec<Value>) -> io::Result<()> {
+        let message = protocol::notification(
+            "textDocument/publishDiagnostics",
+            json!({ "uri": uri, "diagnostics": diagnostics }),
+        );
+        protocol::write_message(&mut self.out, &message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use serde_json::{json, Value};
+
+    use libeir_syntax_erl::ParseConfig;
+
+    use super::Server;
+    use crate::protocol::{self, path_to_uri};
+
+    /// Runs a session with the scripted client `messages` and returns
+    /// the exit code along with everything the server sent.
+    fn session(config: ParseConfig, messages: &[Value]) -> (i32, Vec<Value>) {
+        let mut input = Vec::new();
+        for message in messages {
+            protocol::write_message(&mut input, message).unwrap();
+        }
+        raw_session(config, input)
+    }
+
+    /// Like `session`, with the client input given as bytes.
+    fn raw_session(config: ParseConfig, input: Vec<u8>) -> (i32, Vec<Value>) {
+        let mut output = Vec::new();
+        let code = Server::new(config, &mut output)
+            .run(&mut Cursor::new(input))
+            .unwrap();
+
+        let mut output = Cursor::new(output);
+        let mut sent = Vec::new();
+        while let Some(content) = protocol::read_message(&mut output).unwrap() {
+            sent.push(serde_json::from_slice(&content).unwrap());
+        }
+        (code, sent)
+    }
+
+    fn request(id: u64, method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    fn notification(method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "method": method, "params": params })
+    }
+
+    fn response(sent: &[Value], id: u64) -> &Value {
+        sent.iter()
+            .find(|message| message["id"] == json!(id))
+            .unwrap()
+    }
+
+    fn published(sent: &[Value]) -> Vec<&Value> {
+        sent.iter()
+            .filter(|message| message["method"] == "textDocument/publishDiagnostics")
+            .map(|message| &message["params"]["diagnostics"])
+            .collect()
+    }
+
+    fn did_open(uri: &str, text: &str) -> Value {
+        notification(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": "erlang",
+                    "version": 1,
+                    "text": text,
+                },
+            }),
+        )
+    }
+
+    fn position(id: u64, method: &str, uri: &str, line: usize, character: usize) -> Value {
+        request(
+            id,
+            method,
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": line, "character": character },
+            }),
+        )
+    }
+
+    #[test]
+    fn lifecycle() {
+        let (code, sent) = session(
+            ParseConfig::default(),
+            &[
+                request(1, "textDocument/hover", json!({})),
+                request(2, "initialize", json!({ "capabilities": {} })),
+                notification("initialized", json!({})),
+                request(3, "workspace/symbol", json!({ "query": "" })),
+                request(4, "shutdown", Value::Null),
+                notification("exit", Value::Null),
+            ],
+        );
+        assert_eq!(code, 0);
+        assert_eq!(response(&sent, 1)["error"]["code"], json!(-32002));
+        let capabilities = &response(&sent, 2)["result"]["capabilities"];
+        assert_eq!(capabilities["textDocumentSync"], json!(1));
+        assert_eq!(capabilities["definitionProvider"], json!(true));
+        assert_eq!(capabilities["hoverProvider"], json!(true));
+        assert_eq!(response(&sent, 3)["error"]["code"], json!(-32601));
+        assert_eq!(response(&sent, 4)["result"], Value::Null);
+
+        // Without a shutdown the exit is unclean.
+        let (code, _sent) = session(ParseConfig::default(), &[notification("exit", Value::Null)]);
+        assert_eq!(code, 1);
+    }
+
+    #[test]
+    fn malformed_headers() {
+        let mut input = b"Content-Type: application/json\r\n\r\n".to_vec();
+        input.extend_from_slice(b"Content-Length: 99999999999\r\n\r\n");
+        protocol::write_message(&mut input, &request(1, "shutdown", Value::Null)).unwrap();
+        protocol::write_message(&mut input, &notification("exit", Value::Null)).unwrap();
+
+        // Skipping the oversized content consumes the rest of the input,
+        // so the server never sees the shutdown.
+        let (code, sent) = raw_session(ParseConfig::default(), input);
+        assert_eq!(code, 1);
+        assert_eq!(sent.len(), 2);
+        for message in sent.iter() {
+            assert_eq!(message["id"], Value::Null);
+            assert_eq!(message["error"]["code"], json!(-32600));
+        }
+        assert_eq!(
+            sent[0]["error"]["message"],
+            json!("missing Content-Length header")
+        );
+    }
+
+    #[test]
+    fn diagnostics_on_change() {
+        let uri = "untitled:diagnostics";
+        let (_code, sent) = session(
+            ParseConfig::default(),
+            &[
+                request(1, "initialize", json!({ "capabilities": {} })),
+                did_open(uri, "-module(diag).\n-export([foo/0]).\nfoo() -> X.\n"),
+                notification(
+                    "textDocument/didChange",
+                    json!({
+                        "textDocument": { "uri": uri, "version": 2 },
+                        "contentChanges": [{ "text": "-module(diag).\n-export([foo/0]).\nfoo( -> ok.\n" }],
+                    }),
+                ),
+                notification(
+                    "textDocument/didChange",
+                    json!({
+                        "textDocument": { "uri": uri, "version": 3 },
+                        "contentChanges": [{ "text": "-module(diag).\n-export([foo/0]).\nfoo() -> ok.\n" }],
+                    }),
+                ),
+            ],
+        );
+        let published = published(&sent);
+        assert_eq!(published.len(), 3);
+
+        // Unbound variables are reported by lowering.
+        let unbound = published[0].as_array().unwrap();
+        assert!(unbound.iter().any(|diag| diag["severity"] == json!(1)
+            && diag["range"]["start"] == json!({ "line": 2, "character": 9 })));
+
+        let syntax = published[1].as_array().unwrap();
+        assert!(!syntax.is_empty());
+        assert_eq!(syntax[0]["range"]["start"]["line"], json!(2));
+
+        assert_eq!(published[2], &json!([]));
+    }
+
+    #[test]
+    fn definition_and_hover() {
+        let dir = std::env::temp_dir().join(format!("eir_lsp_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("defs.hrl"), "-define(LIMIT, 10).\n").unwrap();
+        let path = dir.join("nav.erl");
+        let text = "-module(nav).
+-export([run/1]).
+-include(\"defs.hrl\").
+-record(state, {count = 0}).
+
+-spec run(integer()) -> integer().
+run(N) -> step(N, #state{}).
+
+step(N, _State) when N > ?LIMIT -> N;
+step(N, State) -> step(N + 1, State).
+";
+        std::fs::write(&path, text).unwrap();
+        let uri = path_to_uri(&path);
+
+        let (_code, sent) = session(
+            ParseConfig::default(),
+            &[
+                request(1, "initialize", json!({ "capabilities": {} })),
+                did_open(&uri, text),
+                // `step` in the body of `run/1`
+                position(2, "textDocument/definition", &uri, 6, 11),
+                // `state` in `#state{}`
+                position(3, "textDocument/definition", &uri, 6, 20),
+                // `LIMIT` in the guard of `step/2`
+                position(4, "textDocument/definition", &uri, 8, 29),
+                // `run` in the export list
+                position(5, "textDocument/hover", &uri, 1, 10),
+                // Whitespace
+                position(6, "textDocument/definition", &uri, 4, 0),
+            ],
+        );
+        assert_eq!(published(&sent), vec![&json!([])]);
+
+        let step = &response(&sent, 2)["result"];
+        assert_eq!(step.as_array().unwrap().len(), 1);
+        assert_eq!(step[0]["uri"], json!(uri));
+        assert_eq!(
+            step[0]["range"]["start"],
+            json!({ "line": 8, "character": 0 })
+        );
+
+        let state = &response(&sent, 3)["result"];
+        assert_eq!(state[0]["uri"], json!(uri));
+        assert_eq!(state[0]["range"]["start"]["line"], json!(3));
+
+        let limit = &response(&sent, 4)["result"];
+        assert_eq!(limit[0]["uri"], json!(path_to_uri(&dir.join("defs.hrl"))));
+        assert_eq!(
+            limit[0]["range"]["start"],
+            json!({ "line": 0, "character": 8 })
+        );
+
+        let hover = response(&sent, 5)["result"]["contents"]["value"]
+            .as_str()
+            .unwrap();
+        assert!(hover.contains("run/1"));
+        assert!(hover.contains("run(integer()) -> integer()"));
+
+        assert_eq!(response(&sent, 6)["result"], json!([]));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,434 @@
+//! Loading of multi-module projects, either from a directory or from an
+//! `Emakefile`.
+//!
+//! A project consists of groups of source files that share compile
+//! options. An `Emakefile` gives one group per entry, a directory is a
+//! single group.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use libeir_diagnostics::{CodeMap, Diagnostic, Label, SourceSpan};
+use libeir_util_parse::{Errors, Parser};
+use libeir_util_parse_listing::ast::{Item, Root};
+use libeir_util_parse_listing::parser::ParseError;
+
+#[derive(Debug, Default)]
+pub struct SourceGroup {
+    pub files: Vec<PathBuf>,
+    pub include_paths: Vec<PathBuf>,
+    /// Macro definitions, `NAME` or `NAME=VALUE` as with `erlc -D`.
+    pub defines: Vec<(String, Option<String>)>,
+    pub outdir: Option<PathBuf>,
+}
+
+#[derive(Debug, Default)]
+pub struct Project {
+    pub groups: Vec<SourceGroup>,
+    /// Roots `-include_lib` is resolved against.
+    pub code_paths: Vec<PathBuf>,
+}
+
+impl Project {
+    /// Loads the project at `path`, a directory or an `Emakefile`.
+    pub fn load(
+        codemap: &Arc<CodeMap>,
+        path: &Path,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<Project, ()> {
+        if path.is_dir() {
+            Project::from_dir(path, diagnostics)
+        } else {
+            Project::from_emakefile(codemap, path, diagnostics)
+        }
+    }
+
+    /// Loads a project laid out like an OTP application, with sources in
+    /// `src` and headers in `include`. If there is no `src` directory,
+    /// the sources are taken from `dir` itself.
+    ///
+    /// The parent of `dir` is used as a code path, so that an application
+    /// within an OTP `lib` directory can `-include_lib` its siblings.
+    pub fn from_dir(dir: &Path, diagnostics: &mut Vec<Diagnostic>) -> Result<Project, ()> {
+        let src = dir.join("src");
+        let src = if src.is_dir() { src } else { dir.to_owned() };
+
+        let mut group = SourceGroup::default();
+        if let Err(err) = collect_sources(&src, &mut group.files) {
+            diagnostics.push(
+                Diagnostic::error()
+                    .with_message(format!("{} occurred when reading {:?}", err, src)),
+            );
+            return Err(());
+        }
+        group.files.sort();
+
+        let include = dir.join("include");
+        if include.is_dir() {
+            group.include_paths.push(include);
+        }
+        group.include_paths.push(src);
+
+        let mut project = Project::default();
+        if let Some(parent) = dir.parent() {
+            project.code_paths.push(parent.to_owned());
+        }
+        project.groups.push(group);
+        Ok(project)
+    }
+
+    /// Loads a project from an `Emakefile`. Every entry is a tuple
+    /// `{Modules, Options}`, where `Modules` is a module or a list of
+    /// modules and may use `*` wildcards in the file name. The `i`, `d`
+    /// and `outdir` options are supported, others are ignored.
+    pub fn from_emakefile(
+        codemap: &Arc<CodeMap>,
+        path: &Path,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<Project, ()> {
+        let parser = Parser::new((), codemap.clone());
+        let mut errors: Errors<ParseError, ParseError> = Errors::new();
+        let res = parser.parse_file::<Root, _>(&mut errors, path);
+        diagnostics.extend(errors.iter_diagnostics());
+        let root = res?;
+
+        let base = path.parent().unwrap_or_else(|| Path::new("."));
+
+        let mut failed = false;
+        let mut project = Project::default();
+        for item in root.items.iter() {
+            match emakefile_entry(base, item) {
+                Ok(group) => project.groups.push(group),
+                Err(diagnostic) => {
+                    diagnostics.push(diagnostic);
+                    failed = true;
+                }
+            }
+        }
+
+        if failed {
+            Err(())
+        } else {
+            Ok(project)
+        }
+    }
+
+    pub fn num_files(&self) -> usize {
+        self.groups.iter().map(|group| group.files.len()).sum()
+    }
+}
+
+fn invalid(span: SourceSpan, message: &str) -> Diagnostic {
+    Diagnostic::error()
+        .with_message("invalid Emakefile entry")
+        .with_labels(vec![
+            Label::primary(span.source_id(), span).with_message(message.to_string())
+        ])
+}
+
+fn emakefile_entry(base: &Path, item: &Item) -> Result<SourceGroup, Diagnostic> {
+    let entries = match item.tuple() {
+        Some(tuple) if tuple.entries.len() == 1 || tuple.entries.len() == 2 => &tuple.entries,
+        _ => return Err(invalid(item.span(), "expected {Modules, Options}")),
+    };
+
+    let mut group = SourceGroup::default();
+
+    let modules: Vec<&Item> = match entries[0].list_iter() {
+        Some(iter) => iter.collect(),
+        None => vec![&entries[0]],
+    };
+    for module in modules {
+        let pattern = module
+            .atom()
+            .or_else(|| module.string())
+            .ok_or_else(|| invalid(module.span(), "expected a module name or pattern"))?;
+        expand_module_pattern(base, &pattern.as_str(), &mut group.files)
+            .map_err(|err| invalid(module.span(), &err.to_string()))?;
+    }
+
+    if let Some(options) = entries.get(1) {
+        let options = options
+            .list_iter()
+            .ok_or_else(|| invalid(options.span(), "expected a list of options"))?;
+        for option in options {
+            emakefile_option(base, option, &mut group)?;
+        }
+    }
+
+    Ok(group)
+}
+
+fn emakefile_option(base: &Path, option: &Item, group: &mut SourceGroup) -> Result<(), Diagnostic> {
+    let tuple = match option.tuple() {
+        Some(tuple) if !tuple.entries.is_empty() => tuple,
+        // Flags like `debug_info` do not affect us.
+        _ => return Ok(()),
+    };
+    let name = match tuple.entries[0].atom() {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+
+    let path = |item: &Item| {
+        item.string()
+            .or_else(|| item.atom())
+            .map(|path| base.join(&*path.as_str()))
+            .ok_or_else(|| invalid(item.span(), "expected a path"))
+    };
+
+    match (&*name.as_str(), &tuple.entries[1..]) {
+        ("i", [dir]) => group.include_paths.push(path(dir)?),
+        ("outdir", [dir]) => group.outdir = Some(path(dir)?),
+        ("d", [macro_name]) => {
+            let macro_name = macro_name
+                .atom()
+                .ok_or_else(|| invalid(macro_name.span(), "expected a macro name"))?;
+            group.defines.push((macro_name.to_string(), None));
+        }
+        ("d", [macro_name, value]) => {
+            let macro_name = macro_name
+                .atom()
+                .ok_or_else(|| invalid(macro_name.span(), "expected a macro name"))?;
+            let value = match value {
+                Item::Atom(atom) => erlang_quote(&atom.as_str(), '\''),
+                Item::String(string) => erlang_quote(&string.as_str(), '"'),
+                Item::Int(int) => int.integer.to_string(),
+                _ => return Err(invalid(value.span(), "unsupported macro value")),
+            };
+            group.defines.push((macro_name.to_string(), Some(value)));
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+/// Quotes `string` with `quote`, giving an Erlang atom or string literal
+/// that reads back as `string`.
+fn erlang_quote(string: &str, quote: char) -> String {
+    let mut out = String::with_capacity(string.len() + 2);
+    out.push(quote);
+    for c in string.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c == quote => {
+                out.push('\\');
+                out.push(c);
+            }
+            c if c.is_control() => out.push_str(&format!("\\x{{{:X}}}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push(quote);
+    out
+}
+
+/// Adds the files matching `pattern`, a path without the `.erl`
+/// extension, to `files`. A `*` in the last path component matches any
+/// sequence of characters.
+fn expand_module_pattern(
+    base: &Path,
+    pattern: &str,
+    files: &mut Vec<PathBuf>,
+) -> std::io::Result<()> {
+    let pattern = base.join(pattern);
+    let file_pattern = pattern
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    if !file_pattern.contains('*') {
+        files.push(pattern.with_extension("erl"));
+        return Ok(());
+    }
+
+    let dir = pattern.parent().unwrap_or(base);
+    let mut matched = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().map(|ext| ext == "erl") != Some(true) {
+            continue;
+        }
+        let stem = path.file_stem().unwrap().to_string_lossy();
+        if wildcard_match(&file_pattern, &stem) {
+            matched.push(path);
+        }
+    }
+    matched.sort();
+    files.extend(matched);
+    Ok(())
+}
+
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    match pattern.find('*') {
+        None => pattern == name,
+        Some(idx) => {
+            let (prefix, rest) = (&pattern[..idx], &pattern[idx + 1..]);
+            if !name.starts_with(prefix) {
+                return false;
+            }
+            let name = &name[prefix.len()..];
+            (0..=name.len())
+                .filter(|n| name.is_char_boundary(*n))
+                .any(|n| wildcard_match(rest, &name[n..]))
+        }
+    }
+}
+
+fn collect_sources(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_sources(&path, files)?;
+        } else if path.extension().map(|ext| ext == "erl") == Some(true) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    use libeir_diagnostics::CodeMap;
+
+    use super::{erlang_quote, wildcard_match, Project};
+
+    struct TempDir(PathBuf);
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("eir_project_{}_{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+
+        fn write(&self, path: &str, contents: &str) -> PathBuf {
+            let path = self.0.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, contents).unwrap();
+            path
+        }
+    }
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn load_emakefile(path: &Path) -> Result<Project, Vec<String>> {
+        let codemap = Arc::new(CodeMap::new());
+        let mut diagnostics = Vec::new();
+        Project::from_emakefile(&codemap, path, &mut diagnostics).map_err(|()| {
+            diagnostics
+                .iter()
+                .map(|diag| diag.message.clone())
+                .collect()
+        })
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(wildcard_match("*", "lists"));
+        assert!(wildcard_match("erl_*", "erl_lint"));
+        assert!(wildcard_match("*_SUITE", "lists_SUITE"));
+        assert!(wildcard_match("a*b*c", "aXbYc"));
+        assert!(!wildcard_match("erl_*", "lists"));
+        assert!(!wildcard_match("a*b", "ab_"));
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(erlang_quote("ok", '\''), "'ok'");
+        assert_eq!(erlang_quote("it's", '\''), "'it\\'s'");
+        assert_eq!(erlang_quote("say \"hi\"", '"'), "\"say \\\"hi\\\"\"");
+        assert_eq!(erlang_quote("a\\b\n", '"'), "\"a\\\\b\\n\"");
+        assert_eq!(erlang_quote("\u{1}\u{7f}", '"'), "\"\\x{1}\\x{7F}\"");
+        assert_eq!(erlang_quote("h\u{e9}", '\''), "'h\u{e9}'");
+    }
+
+    #[test]
+    fn from_dir() {
+        let dir = TempDir::new("from_dir");
+        let app = dir.0.join("app");
+        dir.write("app/src/b.erl", "");
+        dir.write("app/src/sub/a.erl", "");
+        dir.write("app/src/notes.txt", "");
+        dir.write("app/include/app.hrl", "");
+
+        let mut diagnostics = Vec::new();
+        let project = Project::from_dir(&app, &mut diagnostics).unwrap();
+        assert!(diagnostics.is_empty());
+        assert_eq!(project.code_paths, vec![dir.0.clone()]);
+        assert_eq!(project.groups.len(), 1);
+        let group = &project.groups[0];
+        assert_eq!(
+            group.files,
+            vec![app.join("src/b.erl"), app.join("src/sub/a.erl")]
+        );
+        assert_eq!(
+            group.include_paths,
+            vec![app.join("include"), app.join("src")]
+        );
+
+        // Without `src`, the sources are in the directory itself.
+        let flat = dir.0.join("flat");
+        dir.write("flat/c.erl", "");
+        let project = Project::from_dir(&flat, &mut diagnostics).unwrap();
+        assert_eq!(project.groups[0].files, vec![flat.join("c.erl")]);
+        assert_eq!(project.groups[0].include_paths, vec![flat.clone()]);
+    }
+
+    #[test]
+    fn from_emakefile() {
+        let dir = TempDir::new("from_emakefile");
+        dir.write("src/a_one.erl", "");
+        dir.write("src/a_two.erl", "");
+        dir.write("src/b.erl", "");
+        dir.write("other/c.erl", "");
+        let emakefile = dir.write(
+            "Emakefile",
+            "{'src/a_*', [debug_info, {i, \"include\"}, {outdir, \"ebin\"}, \
+             {d, 'DEBUG'}, {d, 'LEVEL', 3}, {d, 'NAME', \"tab\there\"}, {d, 'MODE', fast}]}.\n\
+             {[\"src/b\", 'other/c']}.\n",
+        );
+
+        let project = load_emakefile(&emakefile).unwrap();
+        assert_eq!(project.num_files(), 4);
+        let group = &project.groups[0];
+        assert_eq!(
+            group.files,
+            vec![dir.0.join("src/a_one.erl"), dir.0.join("src/a_two.erl")]
+        );
+        assert_eq!(group.include_paths, vec![dir.0.join("include")]);
+        assert_eq!(group.outdir, Some(dir.0.join("ebin")));
+        assert_eq!(
+            group.defines,
+            vec![
+                ("DEBUG".to_string(), None),
+                ("LEVEL".to_string(), Some("3".to_string())),
+                ("NAME".to_string(), Some("\"tab\\there\"".to_string())),
+                ("MODE".to_string(), Some("'fast'".to_string())),
+            ]
+        );
+        let group = &project.groups[1];
+        assert_eq!(
+            group.files,
+            vec![dir.0.join("src/b.erl"), dir.0.join("other/c.erl")]
+        );
+        assert_eq!(group.outdir, None);
+
+        let emakefile = dir.write("Bad", "{'src/b', [{d, 1}]}.\nb.\n");
+        let errors = load_emakefile(&emakefile).unwrap_err();
+        assert_eq!(
+            errors,
+            vec!["invalid Emakefile entry", "invalid Emakefile entry"]
+        );
+    }
+}
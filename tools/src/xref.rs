@@ -0,0 +1,461 @@
+//! Cross reference analysis over a set of modules, in the spirit of
+//! Erlang's `xref`.
+//!
+//! Every module is loaded through the selected frontend, and the call
+//! targets found in its IR are checked against the exports of the other
+//! modules in the set.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::sync::Arc;
+
+use clap::{App, Arg};
+use serde::Serialize;
+
+use libeir_diagnostics::{CodeMap, Diagnostic, Label, SourceSpan};
+use libeir_frontend::DynFrontend;
+use libeir_intern::Symbol;
+use libeir_ir::{CallTarget, FunctionIdent, Module};
+use libeir_syntax_erl::{DeprecatedFunction, DeprecatedFunctions};
+
+use tools::frontend::{emit_diagnostics, frontend_args, make_frontend};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    /// A call to a module that is neither loaded nor ignored.
+    UndefinedModule,
+    /// A call to a function that does not exist in a loaded module.
+    UndefinedFunction,
+    /// A call from another module to a function that exists, but is not
+    /// exported.
+    UnexportedFunction,
+    /// An exported function that is not called from any other module.
+    UnusedExport,
+    DeprecatedCall,
+    RemovedCall,
+}
+
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub kind: IssueKind,
+    /// The calling function, `None` for unused exports.
+    pub caller: Option<FunctionIdent>,
+    /// The called function, or the unused export.
+    pub target: CallTarget,
+    pub span: SourceSpan,
+    /// The advice given by the deprecation database.
+    pub advice: Option<String>,
+}
+
+impl Issue {
+    pub fn message(&self) -> String {
+        let target = format_target(&self.target);
+        match self.kind {
+            IssueKind::UndefinedModule => {
+                format!(
+                    "call to {} in undefined module {}",
+                    target, self.target.module
+                )
+            }
+            IssueKind::UndefinedFunction => format!("call to undefined function {}", target),
+            IssueKind::UnexportedFunction => format!("call to unexported function {}", target),
+            IssueKind::UnusedExport => format!("exported function {} is unused", target),
+            IssueKind::DeprecatedCall => format!(
+                "{} is deprecated; {}",
+                target,
+                self.advice.as_ref().unwrap()
+            ),
+            IssueKind::RemovedCall => {
+                format!("{} is removed; {}", target, self.advice.as_ref().unwrap())
+            }
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        match self.kind {
+            IssueKind::UndefinedFunction
+            | IssueKind::UnexportedFunction
+            | IssueKind::RemovedCall => true,
+            _ => false,
+        }
+    }
+
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let diag = if self.is_error() {
+            Diagnostic::error()
+        } else {
+            Diagnostic::warning()
+        };
+        let diag = diag.with_message(self.message());
+        if self.span == SourceSpan::UNKNOWN {
+            diag
+        } else {
+            diag.with_labels(vec![Label::primary(self.span.source_id(), self.span)])
+        }
+    }
+}
+
+pub fn format_target(target: &CallTarget) -> String {
+    format!("{}:{}/{}", target.module, target.function, target.arity)
+}
+
+fn format_ident(ident: &FunctionIdent) -> String {
+    format!("{}:{}/{}", ident.module, ident.name, ident.arity)
+}
+
+/// Functions that every module has, without them being in the IR.
+fn is_implicit(function: Symbol, arity: usize) -> bool {
+    match (&*function.as_str(), arity) {
+        ("module_info", 0) | ("module_info", 1) => true,
+        _ => false,
+    }
+}
+
+pub struct Xref {
+    modules: BTreeMap<Symbol, Module>,
+    /// Modules that are assumed to exist even though they are not loaded,
+    /// calls to them are not checked.
+    ignored_modules: BTreeSet<Symbol>,
+    deprecated: DeprecatedFunctions,
+}
+
+impl Xref {
+    pub fn new() -> Self {
+        let mut ignored_modules = BTreeSet::new();
+        // Operators and guard BIFs are lowered to calls into `erlang`.
+        ignored_modules.insert(Symbol::intern("erlang"));
+        Xref {
+            modules: BTreeMap::new(),
+            ignored_modules,
+            deprecated: DeprecatedFunctions::default(),
+        }
+    }
+
+    pub fn add_module(&mut self, module: Module) {
+        self.modules.insert(module.name().name, module);
+    }
+
+    pub fn ignore_module(&mut self, name: Symbol) {
+        self.ignored_modules.insert(name);
+    }
+
+    /// Every statically known call made in the loaded modules, together
+    /// with the calling function.
+    pub fn calls(&self) -> Vec<(FunctionIdent, CallTarget, SourceSpan)> {
+        let mut calls = Vec::new();
+        for module in self.modules.values() {
+            let graph = module.call_graph();
+            for (caller, call) in graph.iter() {
+                calls.push((*caller, call.target, call.span));
+            }
+        }
+        calls
+    }
+
+    pub fn analyze(&self) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        let mut used_exports = BTreeSet::new();
+
+        for (caller, target, span) in self.calls() {
+            let local = caller.module.name == target.module;
+            if !local {
+                used_exports.insert(target);
+            }
+
+            let issue = |kind, advice| Issue {
+                kind,
+                caller: Some(caller),
+                target,
+                span,
+                advice,
+            };
+
+            let target_str = target.module.as_str();
+            let function_str = target.function.as_str();
+            match self
+                .deprecated
+                .get(&target_str, &function_str, target.arity)
+            {
+                Some(DeprecatedFunction::Deprecated(advice)) => {
+                    issues.push(issue(IssueKind::DeprecatedCall, Some(advice.clone())))
+                }
+                Some(DeprecatedFunction::Removed(advice)) => {
+                    issues.push(issue(IssueKind::RemovedCall, Some(advice.clone())))
+                }
+                None => (),
+            }
+
+            if is_implicit(target.function, target.arity) {
+                continue;
+            }
+            match self.modules.get(&target.module) {
+                Some(module) => {
+                    if module
+                        .name_arity_index(target.function, target.arity)
+                        .is_none()
+                    {
+                        issues.push(issue(IssueKind::UndefinedFunction, None));
+                    } else if !local && !module.is_exported(target.function, target.arity) {
+                        issues.push(issue(IssueKind::UnexportedFunction, None));
+                    }
+                }
+                None if self.ignored_modules.contains(&target.module) => (),
+                None => issues.push(issue(IssueKind::UndefinedModule, None)),
+            }
+        }
+
+        for module in self.modules.values() {
+            let on_load = module.on_load();
+            for (function, arity) in module.export_iter() {
+                let target = CallTarget {
+                    module: module.name().name,
+                    function,
+                    arity,
+                };
+                if used_exports.contains(&target)
+                    || on_load == Some((function, arity))
+                    || is_implicit(function, arity)
+                {
+                    continue;
+                }
+                let span = module
+                    .name_arity_index(function, arity)
+                    .map(|index| module[index].function().span())
+                    .unwrap_or(SourceSpan::UNKNOWN);
+                issues.push(Issue {
+                    kind: IssueKind::UnusedExport,
+                    caller: None,
+                    target,
+                    span,
+                    advice: None,
+                });
+            }
+        }
+
+        issues
+    }
+}
+
+#[derive(Serialize)]
+struct JsonReport {
+    modules: Vec<String>,
+    calls: Vec<JsonCall>,
+    issues: Vec<JsonIssue>,
+}
+
+#[derive(Serialize)]
+struct JsonCall {
+    caller: String,
+    target: String,
+}
+
+#[derive(Serialize)]
+struct JsonIssue {
+    kind: IssueKind,
+    message: String,
+    caller: Option<String>,
+    target: String,
+    file: Option<String>,
+    line: Option<usize>,
+    column: Option<usize>,
+}
+
+fn json_report(xref: &Xref, issues: &[Issue], codemap: &CodeMap) -> JsonReport {
+    let modules = xref
+        .modules
+        .keys()
+        .map(|name| name.as_str().get().to_string())
+        .collect();
+
+    let calls = xref
+        .calls()
+        .iter()
+        .map(|(caller, target, _span)| JsonCall {
+            caller: format_ident(caller),
+            target: format_target(target),
+        })
+        .collect();
+
+    let issues = issues
+        .iter()
+        .map(|issue| {
+            let source_id = issue.span.source_id();
+            let location = codemap
+                .location(source_id, issue.span.start_index())
+                .and_then(|loc| loc.ok());
+            JsonIssue {
+                kind: issue.kind,
+                message: issue.message(),
+                caller: issue.caller.as_ref().map(format_ident),
+                target: format_target(&issue.target),
+                file: codemap.name(source_id).map(|name| name.to_string()),
+                line: location.as_ref().map(|loc| loc.line.0 as usize + 1),
+                column: location.as_ref().map(|loc| loc.column.0 as usize + 1),
+            }
+        })
+        .collect();
+
+    JsonReport {
+        modules,
+        calls,
+        issues,
+    }
+}
+
+fn main() {
+    let matches = App::new("Eir Xref")
+        .version("alpha")
+        .author("Hans Elias B. Josephsen")
+        .about("Cross reference analysis of calls between modules")
+        .arg(
+            Arg::with_name("IN_FILES")
+                .help("Modules to analyze")
+                .required(true)
+                .multiple(true),
+        )
+        .args(&frontend_args())
+        .arg(
+            Arg::from_usage(
+                "<IGNORE_MODULES> -x,--ignore-module <MODULE> 'do not report calls to MODULE as undefined'",
+            )
+            .required(false)
+            .multiple(true)
+            .number_of_values(1),
+        )
+        .arg(Arg::from_usage("[JSON] --json 'print the results as json to stdout'"))
+        .get_matches();
+
+    let codemap = Arc::new(CodeMap::new());
+    let frontend = make_frontend(codemap.clone(), &matches);
+
+    let mut xref = Xref::new();
+    if let Some(modules) = matches.values_of("IGNORE_MODULES") {
+        for module in modules {
+            xref.ignore_module(Symbol::intern(module));
+        }
+    }
+
+    let mut failed = false;
+    for in_file in matches.values_of("IN_FILES").unwrap() {
+        let (res, diagnostics) = frontend.parse_file_dyn(Path::new(in_file));
+        emit_diagnostics(&codemap, &diagnostics);
+        match res {
+            Ok(module) => xref.add_module(module),
+            Err(()) => failed = true,
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+
+    let issues = xref.analyze();
+    if matches.is_present("JSON") {
+        let report = json_report(&xref, &issues, &codemap);
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else {
+        let diagnostics: Vec<_> = issues.iter().map(Issue::to_diagnostic).collect();
+        emit_diagnostics(&codemap, &diagnostics);
+    }
+
+    if issues.iter().any(Issue::is_error) {
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use libeir_diagnostics::CodeMap;
+    use libeir_frontend::{eir::EirFrontend, DynFrontend};
+
+    use super::{format_target, IssueKind, Xref};
+
+    fn analyze(sources: &[&str]) -> Vec<(IssueKind, String)> {
+        let frontend = EirFrontend::new(Arc::new(CodeMap::new()));
+        let mut xref = Xref::new();
+        for source in sources {
+            let (res, _diagnostics) = frontend.parse_string_dyn(source);
+            xref.add_module(res.unwrap());
+        }
+        let mut issues: Vec<_> = xref
+            .analyze()
+            .iter()
+            .map(|issue| (issue.kind, format_target(&issue.target)))
+            .collect();
+        issues.sort();
+        issues
+    }
+
+    #[test]
+    fn undefined_and_unused() {
+        let issues = analyze(&[
+            "
+a'a' {
+    !export [a'start'/0];
+
+    a'start'/0 {
+        entry(%ret, %thr):
+            %f = a'b':a'run'/0;
+            %f() => block1 except %thr;
+        block1(%r):
+            %g = a'b':a'hidden'/0;
+            %g() => block2 except %thr;
+        block2(%s):
+            %h = a'b':a'nope'/0;
+            %h() => block3 except %thr;
+        block3(%t):
+            %i = a'c':a'run'/0;
+            %i() => %ret except %thr;
+    }
+}
+",
+            "
+a'b' {
+    !export [a'run'/0, a'unused'/0];
+
+    a'run'/0 {
+        entry(%ret, %thr):
+            %f = a'b':a'hidden'/0;
+            %f() => %ret except %thr;
+    }
+    a'hidden'/0 {
+        entry(%ret, %thr):
+            %ret(a'ok');
+    }
+    a'unused'/0 {
+        entry(%ret, %thr):
+            %ret(a'ok');
+    }
+}
+",
+        ]);
+
+        let expected = vec![
+            (IssueKind::UnexportedFunction, "b:hidden/0".to_string()),
+            (IssueKind::UndefinedFunction, "b:nope/0".to_string()),
+            (IssueKind::UndefinedModule, "c:run/0".to_string()),
+            (IssueKind::UnusedExport, "a:start/0".to_string()),
+            (IssueKind::UnusedExport, "b:unused/0".to_string()),
+        ];
+        assert_eq!(issues, expected);
+    }
+
+    #[test]
+    fn deprecated_calls() {
+        let issues = analyze(&["
+a'a' {
+    a'now'/0 {
+        entry(%ret, %thr):
+            %f = a'erlang':a'now'/0;
+            %f() => %ret except %thr;
+    }
+}
+"]);
+        assert_eq!(
+            issues,
+            vec![(IssueKind::DeprecatedCall, "erlang:now/0".to_string())]
+        );
+    }
+}
@@ -24,6 +24,20 @@ fn format_label(label: &str, out: &mut String) {
     }
 }
 
+/// Escapes text for use in a html label that is not inside a record.
+fn format_html_label(label: &str, out: &mut String) {
+    for c in label.chars() {
+        match c {
+            '>' => out.push_str("&gt;"),
+            '<' => out.push_str("&lt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            '\n' => out.push_str(DOT_BREAK),
+            c => out.push(c),
+        }
+    }
+}
+
 pub trait NodeId {
     fn make_id(&self, out: &mut String);
 }
@@ -67,7 +81,12 @@ pub trait ContentFormatting {
 }
 impl ContentFormatting for &str {
     fn make_raw(&self, out: &mut String) {
-        format_label(self, out);
+        format_html_label(self, out);
+    }
+}
+impl ContentFormatting for String {
+    fn make_raw(&self, out: &mut String) {
+        format_html_label(self, out);
     }
 }
 
@@ -80,16 +99,127 @@ impl Content for &str {
     }
 }
 
-pub struct StructuredNode {}
+enum Cell {
+    Text {
+        content: String,
+        port: Option<String>,
+    },
+    Vertical(Vec<Cell>),
+    Horizontal(Vec<Cell>),
+}
+
+/// A node label built from nested rows and columns of text cells.
+/// Rendered as a html-like table label, so unlike plain nodes the
+/// individual cells can be addressed as ports by `SubNid`.
+///
+/// ```ignore
+/// let mut node = StructuredNode::new();
+/// node.text("block1(%2):");
+/// node.horizontal(|row| {
+///     row.text_port("left", "a");
+///     row.text_port("right", "b");
+/// });
+/// ```
+pub struct StructuredNode {
+    root: Vec<Cell>,
+}
 impl StructuredNode {
-    pub fn text<F: ContentFormatting>(&mut self, _content: F) {}
-    pub fn vertical(&mut self, _cells: &[()]) {}
-    pub fn horizontal(&mut self, _cells: &[()]) {}
+    /// Creates an empty node. Cells added directly to the node are
+    /// stacked vertically.
+    pub fn new() -> Self {
+        StructuredNode { root: Vec::new() }
+    }
+
+    pub fn text<F: ContentFormatting>(&mut self, content: F) {
+        self.push_text(None, content);
+    }
+
+    /// Adds a text cell that can be referenced as `SubNid(node, port)`.
+    pub fn text_port<F: ContentFormatting>(&mut self, port: &str, content: F) {
+        self.push_text(Some(port.to_string()), content);
+    }
+
+    /// Adds a cell containing the cells added by `fun`, stacked on
+    /// top of each other.
+    pub fn vertical<F>(&mut self, fun: F)
+    where
+        F: FnOnce(&mut StructuredNode),
+    {
+        let mut inner = StructuredNode::new();
+        fun(&mut inner);
+        self.root.push(Cell::Vertical(inner.root));
+    }
+
+    /// Adds a cell containing the cells added by `fun`, next to each
+    /// other.
+    pub fn horizontal<F>(&mut self, fun: F)
+    where
+        F: FnOnce(&mut StructuredNode),
+    {
+        let mut inner = StructuredNode::new();
+        fun(&mut inner);
+        self.root.push(Cell::Horizontal(inner.root));
+    }
+
+    fn push_text<F: ContentFormatting>(&mut self, port: Option<String>, content: F) {
+        let mut buf = String::new();
+        content.make_raw(&mut buf);
+        self.root.push(Cell::Text { content: buf, port });
+    }
+
+    fn make_raw(&self, out: &mut String) {
+        Self::make_table(&self.root, true, out);
+    }
+
+    fn make_table(cells: &[Cell], vertical: bool, out: &mut String) {
+        out.push_str("<table border=\"0\" cellborder=\"1\" cellspacing=\"0\">");
+        if !vertical {
+            out.push_str("<tr>");
+        }
+        for cell in cells {
+            if vertical {
+                out.push_str("<tr>");
+            }
+            Self::make_cell(cell, out);
+            if vertical {
+                out.push_str("</tr>");
+            }
+        }
+        if !vertical {
+            out.push_str("</tr>");
+        }
+        out.push_str("</table>");
+    }
+
+    fn make_cell(cell: &Cell, out: &mut String) {
+        match cell {
+            Cell::Text { content, port } => {
+                out.push_str("<td align=\"left\" balign=\"left\"");
+                if let Some(port) = port {
+                    write!(out, " port=\"{}\"", port).unwrap();
+                }
+                out.push('>');
+                out.push_str(content);
+                out.push_str("</td>");
+            }
+            Cell::Vertical(cells) => {
+                out.push_str("<td border=\"0\" cellpadding=\"0\">");
+                Self::make_table(cells, true, out);
+                out.push_str("</td>");
+            }
+            Cell::Horizontal(cells) => {
+                out.push_str("<td border=\"0\" cellpadding=\"0\">");
+                Self::make_table(cells, false, out);
+                out.push_str("</td>");
+            }
+        }
+    }
 }
 
 pub struct GraphPrinter<O: Write> {
     out: O,
     id_buf: Option<String>,
+    num_clusters: usize,
     error: Result<(), Error>,
 }
 
@@ -139,6 +269,7 @@ impl<O: Write> GraphPrinter<O> {
         let mut g = GraphPrinter {
             out,
             id_buf: Some(String::new()),
+            num_clusters: 0,
             error: Ok(()),
         };
         g.w(|w| {
@@ -186,6 +317,29 @@ impl<O: Write> GraphPrinter<O> {
         self.id_buf = Some(id_buf);
     }
 
+    pub fn structured_node<I>(&mut self, id: I, node: &StructuredNode)
+    where
+        I: NodeId,
+    {
+        let mut id_buf = self.id_buf.take().unwrap();
+
+        id_buf.clear();
+        id.make_id(&mut id_buf);
+        self.w(|w| {
+            write!(w, "{} [ shape=plain, label=<", &id_buf)?;
+            Ok(())
+        });
+
+        id_buf.clear();
+        node.make_raw(&mut id_buf);
+        self.w(|w| {
+            write!(w, "{}> ];\n", &id_buf)?;
+            Ok(())
+        });
+
+        self.id_buf = Some(id_buf);
+    }
+
     pub fn edge<I1, I2>(&mut self, from: I1, to: I2, label: &str)
     where
         I1: NodeId,
@@ -217,6 +371,34 @@ impl<O: Write> GraphPrinter<O> {
         self.id_buf = Some(id_buf);
     }
 
+    /// Starts a cluster subgraph. Nodes added before the matching
+    /// `end_cluster` are drawn grouped together in a box with the
+    /// given label. Edges should be added outside of clusters, dot
+    /// places a node in the cluster it is first mentioned in.
+    pub fn begin_cluster(&mut self, label: &str) {
+        let mut id_buf = self.id_buf.take().unwrap();
+
+        id_buf.clear();
+        format_html_label(label, &mut id_buf);
+        let num = self.num_clusters;
+        self.num_clusters += 1;
+        self.w(|w| {
+            write!(w, "subgraph cluster_{} {{\n", num)?;
+            write!(w, "label=<{}>;\n", &id_buf)?;
+            write!(w, "labeljust=\"l\";\n")?;
+            Ok(())
+        });
+
+        self.id_buf = Some(id_buf);
+    }
+
+    pub fn end_cluster(&mut self) {
+        self.w(|w| {
+            write!(w, "}}\n")?;
+            Ok(())
+        });
+    }
+
     fn w<F>(&mut self, f: F)
     where
         F: FnOnce(&mut O) -> Result<(), Error>,
@@ -276,7 +458,7 @@ impl<O: Write> GraphPrinter<O> {
 
 #[cfg(test)]
 mod test {
-    use crate::{GraphPrinter, NodeId};
+    use crate::{GraphPrinter, NodeId, StructuredNode, SubNid};
     use std::path::Path;
 
     #[test]
@@ -288,6 +470,39 @@ mod test {
         let out = p.finish().unwrap();
     }
 
+    #[test]
+    fn cluster() {
+        let mut p = GraphPrinter::new();
+        p.begin_cluster("a <b>");
+        p.node("woo", "Something something");
+        p.end_cluster();
+        p.node("hoo", "Something else");
+        p.edge("woo", "hoo", "Some edge");
+        let out = p.finish().unwrap();
+        assert!(out.contains("subgraph cluster_0 {\nlabel=<a &lt;b&gt;>;"));
+    }
+
+    #[test]
+    fn structured() {
+        let mut node = StructuredNode::new();
+        node.text("head <1>");
+        node.horizontal(|row| {
+            row.text_port("l", "left");
+            row.text_port("r", "right");
+        });
+
+        let mut p = GraphPrinter::new();
+        p.structured_node("woo", &node);
+        p.node("hoo", "Something else");
+        p.edge(SubNid("woo", "l"), "hoo", "");
+        let out = p.finish().unwrap();
+
+        assert!(out.contains("woo [ shape=plain, label=<<table"));
+        assert!(out.contains("head &lt;1&gt;"));
+        assert!(out.contains("<td align=\"left\" balign=\"left\" port=\"l\">left</td>"));
+        assert!(out.contains("woo:l -> hoo"));
+    }
+
     #[test]
     fn basic_to_file() {
         let mut p = GraphPrinter::new();
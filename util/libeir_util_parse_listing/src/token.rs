@@ -104,6 +104,15 @@ where
                 continue;
             }
 
+            // Comments run until the end of the line, they are allowed in
+            // term files like `Emakefile`s.
+            if c == '%' {
+                while !(self.scanner.read().1 == '\n' || self.scanner.read().1 == '\0') {
+                    self.scanner.advance();
+                }
+                continue;
+            }
+
             break;
         }
 